@@ -0,0 +1,37 @@
+use axum::extract::{Query, State};
+use axum::http::{HeaderMap, StatusCode};
+use axum::Json;
+use serde::Deserialize;
+use std::sync::Arc;
+
+use kubellm_core::{get_budget_statuses, BudgetStatus, ErrorResponse, PromptStore};
+
+use crate::prompt::request_user;
+
+type DatabaseConnection = Arc<dyn PromptStore>;
+
+#[derive(Deserialize)]
+pub struct BudgetsQuery {
+    pub user: Option<String>,
+}
+
+// spend against each budget in its current window, limited to one user's budgets by
+// ?user= or the user header
+pub async fn get_budgets_handler(
+    State(store): State<DatabaseConnection>,
+    headers: HeaderMap,
+    Query(query): Query<BudgetsQuery>,
+) -> anyhow::Result<Json<Vec<BudgetStatus>>, (StatusCode, Json<ErrorResponse>)> {
+    let user = query.user.or_else(|| request_user(&headers));
+
+    match get_budget_statuses(store.as_ref(), user.as_deref()).await {
+        Ok(statuses) => Ok(Json(statuses)),
+        Err(e) => {
+            eprintln!("Database error: {}", e);
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ErrorResponse::new("Failed to fetch budgets")),
+            ))
+        }
+    }
+}
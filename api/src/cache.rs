@@ -0,0 +1,8 @@
+use axum::Json;
+
+use kubellm_core::{response_cache, CacheStats};
+
+// how many prompts the response cache answered since the api started
+pub async fn get_cache_stats_handler() -> Json<CacheStats> {
+    Json(response_cache().stats())
+}
@@ -0,0 +1,40 @@
+use axum::extract::{Query, State};
+use axum::http::StatusCode;
+use axum::Json;
+use std::sync::Arc;
+
+use crate::prompt::error_status;
+
+use kubellm_core::{
+    get_model_info, refresh_models, ErrorResponse, GetModelsQuery, ModelCatalogEntry, PromptStore,
+};
+
+type DatabaseConnection = Arc<dyn PromptStore>;
+
+// a provider's models with display names and creation dates, served from the model catalog
+pub async fn get_model_catalog_handler(
+    State(store): State<DatabaseConnection>,
+    Query(params): Query<GetModelsQuery>,
+) -> anyhow::Result<Json<ModelCatalogEntry>, (StatusCode, Json<ErrorResponse>)> {
+    get_model_info(&params.provider, Some(store.as_ref()))
+        .await
+        .map(Json)
+        .map_err(|e| catalog_error(&params.provider, e))
+}
+
+// fetch a provider's models now instead of waiting for its cached list to expire,
+// e.g. after a new model is released
+pub async fn refresh_models_handler(
+    State(store): State<DatabaseConnection>,
+    Query(params): Query<GetModelsQuery>,
+) -> anyhow::Result<Json<ModelCatalogEntry>, (StatusCode, Json<ErrorResponse>)> {
+    refresh_models(&params.provider, Some(store.as_ref()))
+        .await
+        .map(Json)
+        .map_err(|e| catalog_error(&params.provider, e))
+}
+
+fn catalog_error(provider: &str, e: kubellm_core::CoreError) -> (StatusCode, Json<ErrorResponse>) {
+    eprintln!("Error retrieving models for provider {}: {}", provider, e);
+    (error_status(&e), Json(ErrorResponse::from(&e)))
+}
@@ -0,0 +1,101 @@
+use axum::extract::{Path, State};
+use axum::http::{HeaderMap, StatusCode};
+use axum::Json;
+use std::sync::Arc;
+
+use crate::prompt::prompt_user;
+
+use kubellm_core::{
+    budget_config, compare_prompt, CompareRequest, CompareTarget, Comparison, ComparisonResult,
+    ErrorResponse, Prompt, PromptStore,
+};
+
+type DatabaseConnection = Arc<dyn PromptStore>;
+
+// prompt every target at once, targets that fail have an error in their result
+// rather than failing the whole comparison
+pub async fn compare_handler(
+    State(store): State<DatabaseConnection>,
+    headers: HeaderMap,
+    Json(payload): Json<CompareRequest>,
+) -> anyhow::Result<Json<Comparison>, (StatusCode, Json<ErrorResponse>)> {
+    if let Err(e) = payload.validate() {
+        return Err((StatusCode::BAD_REQUEST, Json(ErrorResponse::new(e))));
+    }
+
+    let user = prompt_user(&headers, budget_config())?;
+
+    let comparison = compare_prompt(&payload, user.as_deref(), &store).await;
+    for result in &comparison.results {
+        if let Some(error) = &result.error {
+            eprintln!(
+                "Error prompting {} in comparison {}: {}",
+                result.target, comparison.comparison_id, error.error
+            );
+        }
+    }
+
+    Ok(Json(comparison))
+}
+
+// the saved answers of a comparison, 404 if it doesn't exist.
+// only targets that answered were saved so failed targets aren't included
+pub async fn get_comparison_handler(
+    State(store): State<DatabaseConnection>,
+    Path(id): Path<String>,
+) -> anyhow::Result<Json<Comparison>, (StatusCode, Json<ErrorResponse>)> {
+    match store.get_comparison_prompts(&id).await {
+        Ok(prompts) if prompts.is_empty() => Err((
+            StatusCode::NOT_FOUND,
+            Json(ErrorResponse::new(format!("Comparison not found: {}", id))),
+        )),
+        Ok(prompts) => Ok(Json(saved_comparison(id, prompts))),
+        Err(e) => {
+            eprintln!("Database error: {}", e);
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ErrorResponse::new("Failed to fetch comparison")),
+            ))
+        }
+    }
+}
+
+// saved answers are listed under the provider and model that answered them
+fn saved_comparison(comparison_id: String, prompts: Vec<Prompt>) -> Comparison {
+    let results = prompts
+        .into_iter()
+        .map(|prompt| ComparisonResult {
+            target: CompareTarget {
+                provider: prompt.provider.clone(),
+                model: Some(prompt.model.clone()),
+            },
+            prompt: Some(prompt),
+            error: None,
+        })
+        .collect();
+
+    Comparison {
+        comparison_id,
+        results,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compare_request_body() {
+        let payload: CompareRequest = serde_json::from_str(
+            r#"{"prompt":"What is 2 + 2?","targets":[{"provider":"Anthropic","model":"claude-sonnet-4-20250514"},{"provider":"OpenAI"}]}"#,
+        )
+        .unwrap();
+        assert!(payload.validate().is_ok());
+        assert_eq!(payload.targets.len(), 2);
+        assert_eq!(payload.targets[1].model, None);
+
+        let payload: CompareRequest =
+            serde_json::from_str(r#"{"prompt":"What is 2 + 2?","targets":[]}"#).unwrap();
+        assert!(payload.validate().is_err());
+    }
+}
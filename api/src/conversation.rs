@@ -0,0 +1,155 @@
+use axum::extract::{Path, State};
+use axum::http::{HeaderMap, StatusCode};
+use axum::Json;
+use std::sync::Arc;
+
+use crate::prompt::{error_status, prompt_user};
+
+use kubellm_core::{
+    budget_config, prompt_conversation, Conversation, ConversationDetail,
+    CreateConversationRequest, CreatePromptRequest, ErrorResponse, Prompt, PromptStore,
+};
+
+type DatabaseConnection = Arc<dyn PromptStore>;
+
+// title used when a conversation is created without one
+const DEFAULT_CONVERSATION_TITLE: &str = "New conversation";
+
+pub async fn create_conversation_handler(
+    State(store): State<DatabaseConnection>, // extract the prompt store from api state (set in router declaration)
+    Json(payload): Json<CreateConversationRequest>, // extract conversation json from request
+) -> anyhow::Result<Json<Conversation>, (StatusCode, Json<ErrorResponse>)> {
+    let title = conversation_title(payload.title.as_deref());
+
+    match store.create_conversation(&title).await {
+        Ok(conversation) => Ok(Json(conversation)),
+        Err(e) => {
+            eprintln!("Database error: {}", e);
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ErrorResponse::new("Failed to create conversation")),
+            ))
+        }
+    }
+}
+
+pub async fn get_conversations_handler(
+    State(store): State<DatabaseConnection>, // extract the prompt store from api state (router declaration)
+) -> anyhow::Result<Json<Vec<Conversation>>, (StatusCode, Json<ErrorResponse>)> {
+    match store.get_all_conversations().await {
+        Ok(conversations) => Ok(Json(conversations)),
+        Err(e) => {
+            eprintln!("Database error: {}", e);
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ErrorResponse::new("Failed to fetch conversations")),
+            ))
+        }
+    }
+}
+
+// a conversation with its prompts in order, 404 if it doesn't exist
+pub async fn get_conversation_handler(
+    State(store): State<DatabaseConnection>,
+    Path(id): Path<i64>,
+) -> anyhow::Result<Json<ConversationDetail>, (StatusCode, Json<ErrorResponse>)> {
+    let conversation = find_conversation(store.as_ref(), id).await?;
+
+    match store.get_conversation_prompts(id).await {
+        Ok(prompts) => Ok(Json(ConversationDetail {
+            conversation,
+            prompts,
+        })),
+        Err(e) => {
+            eprintln!("Database error: {}", e);
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ErrorResponse::new("Failed to fetch conversation")),
+            ))
+        }
+    }
+}
+
+// continue a conversation, the provider sees every earlier turn along with the new prompt
+pub async fn create_conversation_prompt_handler(
+    State(store): State<DatabaseConnection>,
+    Path(id): Path<i64>,
+    headers: HeaderMap,
+    Json(payload): Json<CreatePromptRequest>,
+) -> anyhow::Result<Json<Prompt>, (StatusCode, Json<ErrorResponse>)> {
+    if payload.prompt.trim().is_empty() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(ErrorResponse::new("Prompt cannot be empty")),
+        ));
+    }
+    if let Err(e) = payload.params.validate() {
+        return Err((StatusCode::BAD_REQUEST, Json(ErrorResponse::new(e))));
+    }
+
+    find_conversation(store.as_ref(), id).await?;
+    let user = prompt_user(&headers, budget_config())?;
+
+    match prompt_conversation(id, &payload, user.as_deref(), &store).await {
+        Ok(prompt) => Ok(Json(prompt)),
+        Err(e) => {
+            eprintln!(
+                "Error prompting model for provider {} in conversation {}: {}",
+                &payload.provider, id, e
+            );
+            Err((error_status(&e), Json(ErrorResponse::from(&e))))
+        }
+    }
+}
+
+async fn find_conversation(
+    store: &dyn PromptStore,
+    id: i64,
+) -> anyhow::Result<Conversation, (StatusCode, Json<ErrorResponse>)> {
+    match store.get_conversation(id).await {
+        Ok(Some(conversation)) => Ok(conversation),
+        Ok(None) => Err((
+            StatusCode::NOT_FOUND,
+            Json(ErrorResponse::new(format!(
+                "Conversation not found: {}",
+                id
+            ))),
+        )),
+        Err(e) => {
+            eprintln!("Database error: {}", e);
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ErrorResponse::new("Failed to fetch conversation")),
+            ))
+        }
+    }
+}
+
+fn conversation_title(title: Option<&str>) -> String {
+    match title.map(str::trim) {
+        Some(title) if !title.is_empty() => title.to_string(),
+        _ => DEFAULT_CONVERSATION_TITLE.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_conversation_title_defaults() {
+        assert_eq!(conversation_title(None), DEFAULT_CONVERSATION_TITLE);
+        assert_eq!(conversation_title(Some("   ")), DEFAULT_CONVERSATION_TITLE);
+        assert_eq!(conversation_title(Some(" Rust help ")), "Rust help");
+    }
+
+    #[test]
+    fn test_create_conversation_request_optional_title() {
+        let payload: CreateConversationRequest = serde_json::from_str("{}").unwrap();
+        assert!(payload.title.is_none());
+
+        let payload: CreateConversationRequest =
+            serde_json::from_str(r#"{"title":"Trip planning"}"#).unwrap();
+        assert_eq!(payload.title, Some("Trip planning".to_string()));
+    }
+}
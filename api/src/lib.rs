@@ -1,5 +1,413 @@
+mod budget;
+mod cache;
+mod catalog;
+mod compare;
 mod config;
+mod conversation;
 mod prompt;
+mod task;
+mod template;
+mod usage;
 
+pub use budget::*;
+pub use cache::*;
+pub use catalog::*;
+pub use compare::*;
 pub use config::*;
+pub use conversation::*;
 pub use prompt::*;
+pub use task::*;
+pub use template::*;
+pub use usage::*;
+
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::Json,
+    routing::{get, post},
+    Router,
+};
+use kubellm_core::PromptStore;
+use serde_json::json;
+use std::sync::Arc;
+use tower_http::cors::CorsLayer;
+
+async fn health_check() -> &'static str {
+    "API is running!"
+}
+
+async fn readiness_check(
+    State(store): State<Arc<dyn PromptStore>>,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    // Test database connection
+    match store.ping().await {
+        Ok(_) => Ok(Json(json!({
+            "status": "ready",
+            "database": "connected"
+        }))),
+        Err(_) => Err(StatusCode::SERVICE_UNAVAILABLE),
+    }
+}
+
+// every route of the api, handlers share the store passed in as their DatabaseConnection state
+pub fn app(store: Arc<dyn PromptStore>) -> Router {
+    Router::new()
+        .route("/health", get(health_check))
+        .route("/ready", get(readiness_check))
+        .route("/prompt", post(create_prompt_handler))
+        .route("/prompt/stream", post(stream_prompt_handler))
+        .route("/prompt/async", post(enqueue_prompt_handler))
+        .route("/prompts", get(get_prompts_handler))
+        .route("/prompts/search", get(search_prompts_handler))
+        .route("/prompts/:id", get(get_prompt_handler))
+        .route("/tasks", get(get_tasks_handler))
+        .route("/tasks/:id", get(get_task_handler))
+        .route("/compare", post(compare_handler))
+        .route("/compare/:id", get(get_comparison_handler))
+        .route(
+            "/conversations",
+            get(get_conversations_handler).post(create_conversation_handler),
+        )
+        .route("/conversations/:id", get(get_conversation_handler))
+        .route(
+            "/conversations/:id/prompt",
+            post(create_conversation_prompt_handler),
+        )
+        .route(
+            "/templates",
+            get(get_templates_handler).post(create_template_handler),
+        )
+        .route("/templates/:id", get(get_template_handler))
+        .route("/templates/:id/run", post(run_template_handler))
+        .route("/usage", get(get_usage_handler))
+        .route("/budgets", get(get_budgets_handler))
+        .route("/cache/stats", get(get_cache_stats_handler))
+        .route("/get-models", get(get_models_handler))
+        .route("/models", get(get_model_catalog_handler))
+        .route("/models/refresh", post(refresh_models_handler))
+        .route("/get-providers", get(get_providers_handler))
+        .layer(CorsLayer::permissive()) // this is not a good idea for production
+        .with_state(store) // set the DatabaseConnection state
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum_test::TestServer;
+    use futures::StreamExt;
+    use kubellm_core::test_support::{
+        use_test_config, MemoryStore, MockFormat, MockLlmServer, MockReply,
+    };
+    use kubellm_core::{
+        register_provider, run_task, stream_prompt_model, task_policy, CreatePromptRequest,
+        PromptStreamEvent,
+    };
+    use serde_json::Value;
+    use serial_test::serial;
+    use std::time::Duration;
+
+    // the providers are registered process wide, so tests using them run one at a time
+    async fn test_app() -> (TestServer, MockLlmServer, Arc<MemoryStore>) {
+        use_test_config();
+        let mock = MockLlmServer::start().await;
+        register_provider(Arc::new(mock.anthropic_provider()));
+        register_provider(Arc::new(mock.openai_provider()));
+
+        let store = Arc::new(MemoryStore::new());
+        let server = TestServer::new(app(store.clone())).unwrap();
+        (server, mock, store)
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn test_prompt_flow() {
+        let (server, mock, store) = test_app().await;
+        mock.push(
+            MockFormat::Anthropic,
+            MockReply::text("Kafka is a distributed log")
+                .with_usage(12, 6)
+                .with_delay(Duration::from_millis(20)),
+        );
+
+        let response = server
+            .post("/prompt")
+            .add_header(USER_HEADER, "alice")
+            .json(&json!({
+                "prompt": "What is Kafka?",
+                "provider": "anthropic",
+                "system": "Be brief",
+                "tags": [" docs ", "docs"],
+            }))
+            .await;
+        response.assert_status_ok();
+        let prompt: Value = response.json();
+        assert_eq!(prompt["response"], "Kafka is a distributed log");
+        assert_eq!(prompt["provider"], "Anthropic");
+        assert_eq!(prompt["model"], "claude-mock-1");
+        assert_eq!(prompt["input_tokens"], 12);
+        assert_eq!(prompt["user_id"], "alice");
+        assert_eq!(prompt["tags"], json!(["docs"]));
+        assert!(prompt["latency_ms"].as_i64().unwrap() >= 20);
+
+        // the provider got the system prompt in its own field
+        let requests = mock.completion_requests(MockFormat::Anthropic);
+        assert_eq!(requests[0]["system"], "Be brief");
+        assert_eq!(requests[0]["messages"][0]["content"], "What is Kafka?");
+
+        assert_eq!(store.prompts().len(), 1);
+        let listed: Value = server.get("/prompts").await.json();
+        assert_eq!(listed["prompts"][0]["id"], prompt["id"]);
+        let fetched: Value = server
+            .get(&format!("/prompts/{}", prompt["id"]))
+            .await
+            .json();
+        assert_eq!(fetched["response"], "Kafka is a distributed log");
+        let hits: Value = server.get("/prompts/search?q=kafka").await.json();
+        assert_eq!(hits.as_array().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn test_stream_prompt_flow() {
+        let (server, mock, store) = test_app().await;
+        mock.push(MockFormat::OpenAI, MockReply::text("one two three"));
+
+        let response = server
+            .post("/prompt/stream")
+            .json(&json!({"prompt": "Count to three", "provider": "openai"}))
+            .await;
+        response.assert_status_ok();
+
+        let body = response.text();
+        let tokens = body.matches("event: token").count();
+        assert_eq!(tokens, 3);
+        assert!(body.contains("event: done"));
+        assert_eq!(store.prompts()[0].response, "one two three");
+        assert_eq!(store.prompts()[0].model, "gpt-4o-mock");
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn test_stream_dropped_early_is_saved() {
+        let (_server, mock, store) = test_app().await;
+        mock.push(
+            MockFormat::OpenAI,
+            MockReply::text("one two three").with_usage(5, 3),
+        );
+
+        let request: CreatePromptRequest =
+            serde_json::from_value(json!({"prompt": "Count to three", "provider": "openai"}))
+                .unwrap();
+        let prompt_store: Arc<dyn PromptStore> = store.clone();
+        let mut events = stream_prompt_model(&request, None, &prompt_store)
+            .await
+            .unwrap();
+        assert!(matches!(
+            events.next().await,
+            Some(Ok(PromptStreamEvent::Token(_)))
+        ));
+        // the client goes away after the first token
+        drop(events);
+
+        for _ in 0..100 {
+            if !store.prompts().is_empty() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        let prompts = store.prompts();
+        assert_eq!(prompts.len(), 1);
+        assert_eq!(prompts[0].response, "one two three");
+        assert_eq!(prompts[0].output_tokens, Some(3));
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn test_conversation_flow() {
+        let (server, mock, _store) = test_app().await;
+
+        let conversation: Value = server
+            .post("/conversations")
+            .json(&json!({"title": "Kafka"}))
+            .await
+            .json();
+        let path = format!("/conversations/{}/prompt", conversation["id"]);
+        for prompt in ["What is Kafka?", "And a consumer group?"] {
+            server
+                .post(&path)
+                .json(&json!({"prompt": prompt, "provider": "Anthropic"}))
+                .await
+                .assert_status_ok();
+        }
+
+        // the second prompt was sent with the first turn in front of it
+        let requests = mock.completion_requests(MockFormat::Anthropic);
+        let messages = requests[1]["messages"].as_array().unwrap();
+        assert_eq!(messages.len(), 3);
+        assert_eq!(messages[1]["content"], "Mock response to: What is Kafka?");
+
+        let detail: Value = server
+            .get(&format!("/conversations/{}", conversation["id"]))
+            .await
+            .json();
+        assert_eq!(detail["prompts"][1]["sequence"], 2);
+
+        server
+            .post("/conversations/99/prompt")
+            .json(&json!({"prompt": "hello", "provider": "Anthropic"}))
+            .await
+            .assert_status_not_found();
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn test_provider_errors() {
+        let (server, mock, store) = test_app().await;
+
+        // a failure that clears up is retried
+        mock.push(MockFormat::Anthropic, MockReply::error(529, "Overloaded"));
+        mock.push(MockFormat::Anthropic, MockReply::text("second time lucky"));
+        let prompt: Value = server
+            .post("/prompt")
+            .json(&json!({"prompt": "hello", "provider": "Anthropic"}))
+            .await
+            .json();
+        assert_eq!(prompt["response"], "second time lucky");
+        assert_eq!(prompt["attempts"], 2);
+
+        // one that doesn't is a bad gateway once the attempts run out
+        for _ in 0..3 {
+            mock.push(MockFormat::OpenAI, MockReply::error(500, "boom"));
+        }
+        let response = server
+            .post("/prompt")
+            .json(&json!({"prompt": "hello", "provider": "OpenAI"}))
+            .await;
+        response.assert_status(StatusCode::BAD_GATEWAY);
+        assert!(response.text().contains("boom"));
+
+        mock.push(
+            MockFormat::OpenAI,
+            MockReply::error(429, "slow down").with_retry_after(60),
+        );
+        server
+            .post("/prompt")
+            .json(&json!({"prompt": "hello", "provider": "OpenAI"}))
+            .await
+            .assert_status(StatusCode::TOO_MANY_REQUESTS);
+
+        let response = server
+            .post("/prompt")
+            .json(&json!({"prompt": "hello", "provider": "Anthropic", "model": "claude-mock"}))
+            .await;
+        response.assert_status_bad_request();
+        let error: Value = response.json();
+        assert_eq!(error["code"], "unknown_model");
+
+        // only the prompt that was answered was saved
+        assert_eq!(store.prompts().len(), 1);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn test_queued_prompt_flow() {
+        let (server, mock, store) = test_app().await;
+        mock.push(MockFormat::Anthropic, MockReply::text("A long answer"));
+
+        let response = server
+            .post("/prompt/async")
+            .add_header(USER_HEADER, "alice")
+            .json(&json!({"prompt": "Summarise the Kafka docs", "provider": "Anthropic"}))
+            .await;
+        response.assert_status(StatusCode::ACCEPTED);
+        let task: Value = response.json();
+        assert_eq!(task["status"], "pending");
+        assert_eq!(task["payload"]["user"], "alice");
+        assert!(store.prompts().is_empty());
+
+        // what the worker does with it
+        let queue: Arc<dyn PromptStore> = store.clone();
+        let claimed = task_policy()
+            .claim("test-worker", queue.as_ref())
+            .await
+            .unwrap()
+            .unwrap();
+        let outcome = run_task(&claimed, &queue).await;
+        assert!(task_policy()
+            .finish(&claimed, "test-worker", outcome, queue.as_ref())
+            .await
+            .unwrap());
+
+        let path = format!("/tasks/{}", task["id"]);
+        let done: Value = server.get(&path).await.json();
+        assert_eq!(done["status"], "completed");
+        assert_eq!(done["attempts"], 1);
+        let prompt: Value = server
+            .get(&format!("/prompts/{}", done["result"]["prompt_id"]))
+            .await
+            .json();
+        assert_eq!(prompt["response"], "A long answer");
+        assert_eq!(prompt["user_id"], "alice");
+
+        let completed: Value = server.get("/tasks?status=completed").await.json();
+        assert_eq!(completed[0]["id"], task["id"]);
+        server
+            .get("/tasks?limit=0")
+            .await
+            .assert_status_bad_request();
+        server.get("/tasks/99").await.assert_status_not_found();
+        server
+            .post("/prompt/async")
+            .json(&json!({"prompt": " ", "provider": "Anthropic"}))
+            .await
+            .assert_status_bad_request();
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn test_response_cache_flow() {
+        let (server, mock, store) = test_app().await;
+        let before: Value = server.get("/cache/stats").await.json();
+        assert_eq!(before["enabled"], true);
+
+        mock.push(
+            MockFormat::Anthropic,
+            MockReply::text("A topic is a log").with_usage(8, 5),
+        );
+        let ask = |cache: &str| json!({"prompt": "What is a Kafka topic?", "provider": "Anthropic", "cache": cache});
+        let first: Value = server.post("/prompt").json(&ask("use")).await.json();
+        assert_eq!(first["cache_hit"], false);
+
+        // the same question is answered without asking the provider again
+        let second: Value = server.post("/prompt").json(&ask("use")).await.json();
+        assert_eq!(second["cache_hit"], true);
+        assert_eq!(second["response"], "A topic is a log");
+        assert_eq!(second["attempts"], 0);
+        assert_eq!(second["cost"], 0.0);
+        assert_eq!(second["input_tokens"], 8);
+        assert_eq!(mock.completion_requests(MockFormat::Anthropic).len(), 1);
+
+        // refresh asks again and caches the new answer, bypass neither reads nor writes
+        mock.push(
+            MockFormat::Anthropic,
+            MockReply::text("A topic is a named log"),
+        );
+        let refreshed: Value = server.post("/prompt").json(&ask("refresh")).await.json();
+        assert_eq!(refreshed["cache_hit"], false);
+        mock.push(MockFormat::Anthropic, MockReply::text("Not cached"));
+        let bypassed: Value = server.post("/prompt").json(&ask("bypass")).await.json();
+        assert_eq!(bypassed["response"], "Not cached");
+        let cached: Value = server.post("/prompt").json(&ask("use")).await.json();
+        assert_eq!(cached["response"], "A topic is a named log");
+        assert_eq!(mock.completion_requests(MockFormat::Anthropic).len(), 3);
+        assert_eq!(store.prompts().len(), 5);
+
+        let after: Value = server.get("/cache/stats").await.json();
+        let count = |name: &str| after[name].as_u64().unwrap() - before[name].as_u64().unwrap();
+        assert_eq!(
+            (count("hits"), count("misses"), count("skipped")),
+            (2, 1, 2)
+        );
+        assert!(after["hit_rate"].as_f64().unwrap() > 0.0);
+    }
+}
@@ -1,37 +1,8 @@
-mod prompt;
-
-use crate::prompt::{create_prompt_handler, get_prompts_handler, get_providers_handler};
 use anyhow::{Context, Result};
-use axum::{
-    extract::State,
-    http::StatusCode,
-    response::Json,
-    routing::{get, post},
-    Router,
+use kubellm_api::{app, ApiConfig};
+use kubellm_core::{
+    cassette, connect_store, load_budgets, load_price_table, load_routes, retry_policy,
 };
-use kubellm_api::{get_models_handler, ApiConfig};
-use kubellm_core::{create_database_pool, init_database};
-use serde_json::json;
-use sqlx::MySqlPool;
-use std::sync::Arc;
-use tower_http::cors::CorsLayer;
-
-async fn health_check() -> &'static str {
-    "API is running!"
-}
-
-async fn readiness_check(
-    State(pool): State<Arc<MySqlPool>>,
-) -> Result<Json<serde_json::Value>, StatusCode> {
-    // Test database connection
-    match sqlx::query("SELECT 1").fetch_one(pool.as_ref()).await {
-        Ok(_) => Ok(Json(json!({
-            "status": "ready",
-            "database": "connected"
-        }))),
-        Err(_) => Err(StatusCode::SERVICE_UNAVAILABLE),
-    }
-}
 
 // Create a multi-threaded Tokio runtime for the api server
 #[tokio::main]
@@ -44,29 +15,31 @@ async fn main() -> Result<()> {
         "   Server: {}:{}",
         &api_config.api_server_host, &api_config.api_server_port
     );
+    println!("   Database: {}", core_config.database_backend);
     println!("   Max DB connections: {}", core_config.max_connections);
+    // load the price table and budgets now so a broken file stops the server from starting
+    println!("   Priced models: {}", load_price_table()?.models.len());
+    println!("   Budgets: {}", load_budgets()?.budgets.len());
+    println!("   Provider attempts: {}", retry_policy().max_attempts);
+    println!("   Routes: {}", load_routes()?);
+    // a missing cassette in replay mode stops the server here rather than at the first prompt
+    println!("   Provider cassette: {}", cassette().mode());
 
-    // create mysql pool using properties in config
-    let pool = create_database_pool(core_config).await?;
+    // connect to the store for the DATABASE_URL scheme using properties in config
+    let store = connect_store(core_config).await?;
 
-    // wait for the pool to initialize
-    init_database(&pool)
+    // bring the schema up to date, refuses to start against a schema newer than this build
+    let migrations = store
+        .migrate()
         .await
         .context("Failed to initialize database")?;
-
-    // Wrap db pool in a thread safe reference
-    let db_connection_pool = Arc::new(pool);
+    println!("   Schema version: {}", migrations.version);
+    for name in &migrations.applied {
+        println!("   Applied migration {}", name);
+    }
 
     // initialize app with routes
-    let app = Router::new()
-        .route("/health", get(health_check))
-        .route("/ready", get(readiness_check))
-        .route("/prompt", post(create_prompt_handler))
-        .route("/prompts", get(get_prompts_handler))
-        .route("/get-models", get(get_models_handler))
-        .route("/get-providers", get(get_providers_handler))
-        .layer(CorsLayer::permissive()) // this is not a good idea for production
-        .with_state(db_connection_pool); // set the DatabaseConnection state
+    let app = app(store);
 
     let bind_address = format!(
         "{}:{}",
@@ -78,8 +51,22 @@ async fn main() -> Result<()> {
 
     println!("🚀 Server running on http://{}", bind_address);
     println!("📝 POST to /prompt to create a prompt");
+    println!("📡 POST to /prompt/stream to stream a prompt's response");
+    println!("📬 POST to /prompt/async to queue a prompt for the worker");
+    println!("⏳ GET /tasks to view queued tasks, GET /tasks/:id for a single task");
     println!("📋 GET /prompts to view all prompts");
+    println!("🔎 GET /prompts/:id to view a single prompt");
+    println!("🔍 GET /prompts/search?q= to search prompts and responses");
+    println!("⚖️ POST /compare to send a prompt to several providers at once");
+    println!("💬 POST /conversations to start a conversation, GET to list them");
+    println!("🗨️ POST /conversations/:id/prompt to continue a conversation");
+    println!("📄 POST /templates to save a prompt template, GET to list them");
+    println!("▶️ POST /templates/:id/run to run a template with its variables");
+    println!("💰 GET /usage to view token usage and spend");
+    println!("🧾 GET /budgets to view spend against each budget");
+    println!("🗃️ GET /cache/stats to view response cache hits and misses");
     println!("⚛️ GET /models to view a provider's models");
+    println!("🔄 POST /models/refresh to refetch a provider's models");
     println!("❤️ GET /health for health check");
     println!("✅ GET /ready for readiness check");
 
@@ -1,99 +1,257 @@
-use axum::extract::{Query, State};
-use axum::http::StatusCode;
+use axum::extract::rejection::QueryRejection;
+use axum::extract::{Path, Query, State};
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::sse::{Event, KeepAlive, Sse};
 use axum::Json;
-use sqlx::MySqlPool;
+use futures::{Stream, StreamExt};
+use serde::Serialize;
+use serde_json::json;
+use std::convert::Infallible;
 use std::sync::Arc;
 
 use kubellm_core::{
-    get_all_prompts, get_models, prompt_model, CreatePromptRequest, ErrorResponse, GetModelsQuery,
-    Prompt, Provider,
+    budget_config, get_models, prompt_model, provider_names, stream_prompt_model, BudgetConfig,
+    CoreError, CreatePromptRequest, ErrorResponse, GetModelsQuery, Prompt, PromptPage, PromptQuery,
+    PromptStore, PromptStreamEvent, SearchHit, SearchQuery,
 };
 
-// Map Arc<MySqlPool> as the type DatabaseConnection
-// This allows the pool state to be extracted based on the state
+// Map Arc<dyn PromptStore> as the type DatabaseConnection
+// This allows the store to be extracted based on the state
 // passed in when the router is initialized
-// separates the db implementation from DatabaseConnection type in handlers
+// separates the db backend (mysql, postgres or sqlite) from the handlers
 
-type DatabaseConnection = Arc<MySqlPool>;
+type DatabaseConnection = Arc<dyn PromptStore>;
+
+// header naming the user or api key a request is made for, used for per user budgets.
+// the api has no authentication yet so the header is trusted as sent
+pub const USER_HEADER: &str = "x-kubellm-user";
+
+pub fn request_user(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get(USER_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(str::trim)
+        .filter(|user| !user.is_empty())
+        .map(str::to_string)
+}
+
+// the user a prompt is made for. while user budgets are set a prompt without the header is
+// refused, otherwise leaving it out would be a way around them
+pub fn prompt_user(
+    headers: &HeaderMap,
+    budgets: &BudgetConfig,
+) -> Result<Option<String>, (StatusCode, Json<ErrorResponse>)> {
+    let user = request_user(headers);
+    if user.is_none() && budgets.has_user_budgets() {
+        return Err((
+            StatusCode::UNAUTHORIZED,
+            Json(ErrorResponse::new(format!(
+                "The {} header is required, spend is limited per user",
+                USER_HEADER
+            ))),
+        ));
+    }
+    Ok(user)
+}
+
+// status code for an error from listing models or prompting a provider,
+// failures of the provider itself are reported as a bad gateway rather than our own 500
+pub fn error_status(error: &CoreError) -> StatusCode {
+    match error {
+        CoreError::UnknownProvider(_)
+        | CoreError::InvalidModel { .. }
+        | CoreError::UnsupportedParams { .. }
+        | CoreError::Template(_) => StatusCode::BAD_REQUEST,
+        CoreError::ConversationNotFound(_) | CoreError::TemplateNotFound(_) => {
+            StatusCode::NOT_FOUND
+        }
+        CoreError::BudgetExceeded(_) => StatusCode::PAYMENT_REQUIRED,
+        CoreError::RateLimited { .. } => StatusCode::TOO_MANY_REQUESTS,
+        CoreError::SafetyBlock(_) => StatusCode::UNPROCESSABLE_ENTITY,
+        CoreError::Timeout(_) => StatusCode::GATEWAY_TIMEOUT,
+        CoreError::MissingCredentials { .. } | CoreError::NotConfigured { .. } => {
+            StatusCode::SERVICE_UNAVAILABLE
+        }
+        CoreError::ProviderStatus { .. }
+        | CoreError::Provider { .. }
+        | CoreError::Connection(_)
+        | CoreError::Parse(_)
+        | CoreError::Cassette(_)
+        | CoreError::NoModels { .. } => StatusCode::BAD_GATEWAY,
+        CoreError::Database(_) | CoreError::Other(_) => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
 
 pub async fn create_prompt_handler(
-    State(pool): State<DatabaseConnection>, // extract db pool from api state (set in router declaration)
+    State(store): State<DatabaseConnection>, // extract the prompt store from api state (set in router declaration)
+    headers: HeaderMap,
     Json(payload): Json<CreatePromptRequest>, // extract prompt json from request
 ) -> anyhow::Result<Json<Prompt>, (StatusCode, Json<ErrorResponse>)> {
     if payload.prompt.trim().is_empty() {
         return Err((
             StatusCode::BAD_REQUEST,
-            Json(ErrorResponse {
-                error: "Prompt cannot be empty".to_string(),
-            }),
+            Json(ErrorResponse::new("Prompt cannot be empty")),
         ));
     }
+    if let Err(e) = payload.params.validate() {
+        return Err((StatusCode::BAD_REQUEST, Json(ErrorResponse::new(e))));
+    }
+    let user = prompt_user(&headers, budget_config())?;
 
-    match prompt_model(
-        &payload.prompt,
-        &payload.provider,
-        payload.model.as_deref(),
-        &pool,
-    )
-    .await
-    {
+    match prompt_model(&payload, user.as_deref(), &store).await {
         Ok(prompt) => Ok(Json(prompt)), // return prompt as json on success
         Err(e) => {
             eprintln!(
                 "Error prompting model for provider {}: {}",
                 &payload.provider, e
             );
-            Err((
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(ErrorResponse {
-                    error: e.to_string(),
-                }),
-            ))
+            Err((error_status(&e), Json(ErrorResponse::from(&e))))
         }
     }
 }
 
+// streams the response as server-sent events:
+// `token` events carry chunks of text, `done` carries the saved prompt and `error` ends a failed stream
+pub async fn stream_prompt_handler(
+    State(store): State<DatabaseConnection>, // extract the prompt store from api state (set in router declaration)
+    headers: HeaderMap,
+    Json(payload): Json<CreatePromptRequest>, // extract prompt json from request
+) -> anyhow::Result<
+    Sse<impl Stream<Item = Result<Event, Infallible>>>,
+    (StatusCode, Json<ErrorResponse>),
+> {
+    if payload.prompt.trim().is_empty() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(ErrorResponse::new("Prompt cannot be empty")),
+        ));
+    }
+    if let Err(e) = payload.params.validate() {
+        return Err((StatusCode::BAD_REQUEST, Json(ErrorResponse::new(e))));
+    }
+    let user = prompt_user(&headers, budget_config())?;
+
+    // errors before the stream starts are returned as a normal error response
+    let prompt_stream = match stream_prompt_model(&payload, user.as_deref(), &store).await {
+        Ok(prompt_stream) => prompt_stream,
+        Err(e) => {
+            eprintln!(
+                "Error streaming model for provider {}: {}",
+                &payload.provider, e
+            );
+            return Err((error_status(&e), Json(ErrorResponse::from(&e))));
+        }
+    };
+
+    let events = prompt_stream.map(move |event| {
+        Ok(match event {
+            Ok(PromptStreamEvent::Token(text)) => sse_event("token", &json!({ "text": text })),
+            Ok(PromptStreamEvent::Done(prompt)) => sse_event("done", &prompt),
+            Err(e) => {
+                eprintln!(
+                    "Error streaming model for provider {}: {}",
+                    &payload.provider, e
+                );
+                sse_event("error", &ErrorResponse::from(&e))
+            }
+        })
+    });
+
+    Ok(Sse::new(events).keep_alive(KeepAlive::default()))
+}
+
+fn sse_event<T: Serialize>(name: &str, data: &T) -> Event {
+    Event::default()
+        .event(name)
+        .json_data(data)
+        .unwrap_or_else(|e| Event::default().event("error").data(e.to_string()))
+}
+
 // Dunno why its marked dead code
 #[allow(dead_code)]
 pub async fn get_models_handler(
+    State(store): State<DatabaseConnection>,
     Query(params): Query<GetModelsQuery>,
 ) -> anyhow::Result<Json<Vec<String>>, (StatusCode, Json<ErrorResponse>)> {
-    match get_models(&params.provider).await {
+    match get_models(&params.provider, Some(store.as_ref())).await {
         Ok(models) => Ok(Json(models)),
         Err(e) => {
             eprintln!(
                 "Error retrieving models for provider {}: {}",
                 &params.provider, e
             );
-            Err((
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(ErrorResponse {
-                    error: "Failed to retrieve models".to_string(),
-                }),
-            ))
+            Err((error_status(&e), Json(ErrorResponse::from(&e))))
         }
     }
 }
 
 pub async fn get_providers_handler(
 ) -> anyhow::Result<Json<Vec<String>>, (StatusCode, Json<ErrorResponse>)> {
-    let providers = Provider::all();
-    let provider_strings: Vec<String> = providers.iter().map(|p| p.to_string()).collect();
-    Ok(Json(provider_strings))
+    Ok(Json(provider_names()))
 }
 
+// a page of prompts, newest first, e.g. /prompts?provider=OpenAI&from=2025-01-01&limit=20
+// the next page is fetched by passing the page's next_cursor as cursor
 pub async fn get_prompts_handler(
-    State(pool): State<DatabaseConnection>, // extract db pool from api state (router declaration)
-) -> anyhow::Result<Json<Vec<Prompt>>, (StatusCode, Json<ErrorResponse>)> {
-    match get_all_prompts(&pool).await {
-        Ok(prompts) => Ok(Json(prompts)), // return all prompts as json on success
+    State(store): State<DatabaseConnection>, // extract the prompt store from api state (router declaration)
+    query: Result<Query<PromptQuery>, QueryRejection>,
+) -> anyhow::Result<Json<PromptPage>, (StatusCode, Json<ErrorResponse>)> {
+    let bad_request = |error: String| (StatusCode::BAD_REQUEST, Json(ErrorResponse::new(error)));
+
+    let Query(query) = query.map_err(|e| bad_request(e.body_text()))?;
+    let filter = query.filter().map_err(bad_request)?;
+
+    match store.get_prompts(&filter).await {
+        Ok(page) => Ok(Json(page)), // return the page as json on success
+        Err(e) => {
+            eprintln!("Database error: {}", e);
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ErrorResponse::new("Failed to fetch prompts")),
+            ))
+        }
+    }
+}
+
+// prompts and responses matching a full text search, most relevant first,
+// e.g. /prompts/search?q=kafka+consumer+groups&limit=10
+pub async fn search_prompts_handler(
+    State(store): State<DatabaseConnection>,
+    query: Result<Query<SearchQuery>, QueryRejection>,
+) -> anyhow::Result<Json<Vec<SearchHit>>, (StatusCode, Json<ErrorResponse>)> {
+    let bad_request = |error: String| (StatusCode::BAD_REQUEST, Json(ErrorResponse::new(error)));
+
+    let Query(query) = query.map_err(|e| bad_request(e.body_text()))?;
+    let search = query.search().map_err(bad_request)?;
+
+    match store.search_prompts(&search).await {
+        Ok(hits) => Ok(Json(hits)),
         Err(e) => {
             eprintln!("Database error: {}", e);
             Err((
                 StatusCode::INTERNAL_SERVER_ERROR,
-                Json(ErrorResponse {
-                    error: "Failed to fetch prompts".to_string(),
-                }),
+                Json(ErrorResponse::new("Failed to search prompts")),
+            ))
+        }
+    }
+}
+
+// a single prompt, 404 if it doesn't exist
+pub async fn get_prompt_handler(
+    State(store): State<DatabaseConnection>,
+    Path(id): Path<i64>,
+) -> anyhow::Result<Json<Prompt>, (StatusCode, Json<ErrorResponse>)> {
+    match store.get_prompt_by_id(id).await {
+        Ok(Some(prompt)) => Ok(Json(prompt)),
+        Ok(None) => Err((
+            StatusCode::NOT_FOUND,
+            Json(ErrorResponse::new(format!("Prompt not found: {}", id))),
+        )),
+        Err(e) => {
+            eprintln!("Database error: {}", e);
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ErrorResponse::new("Failed to fetch prompt")),
             ))
         }
     }
@@ -103,7 +261,92 @@ pub async fn get_prompts_handler(
 mod tests {
     use super::*;
     use axum::http::StatusCode;
-    use kubellm_core::{CreatePromptRequest, ErrorResponse};
+    use kubellm_core::{
+        Budget, BudgetExceeded, BudgetScope, BudgetStatus, BudgetWindow, CacheMode,
+        CreatePromptRequest, ErrorResponse, GenerationParams, ModelMode, TemplateError,
+    };
+
+    #[test]
+    fn test_request_user_header() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(request_user(&headers), None);
+
+        headers.insert(USER_HEADER, " nightly-batch ".parse().unwrap());
+        assert_eq!(request_user(&headers), Some("nightly-batch".to_string()));
+
+        headers.insert(USER_HEADER, "".parse().unwrap());
+        assert_eq!(request_user(&headers), None);
+    }
+
+    #[test]
+    fn test_prompt_user_required_by_user_budgets() {
+        let global = BudgetConfig::from_json(
+            r#"{"budgets": [{"scope": "global", "window": "daily", "limit": 10.0}]}"#,
+        )
+        .unwrap();
+        let per_user = BudgetConfig::from_json(
+            r#"{"budgets": [{"scope": "user", "user": "nightly-batch", "window": "daily", "limit": 5.0}]}"#,
+        )
+        .unwrap();
+
+        let mut headers = HeaderMap::new();
+        assert_eq!(prompt_user(&headers, &global).unwrap(), None);
+        let (status, _) = prompt_user(&headers, &per_user).unwrap_err();
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+        headers.insert(USER_HEADER, "nightly-batch".parse().unwrap());
+        assert_eq!(
+            prompt_user(&headers, &per_user).unwrap(),
+            Some("nightly-batch".to_string())
+        );
+    }
+
+    #[test]
+    fn test_error_status() {
+        let now = chrono::Utc::now();
+        let error: CoreError = CoreError::BudgetExceeded(BudgetExceeded(BudgetStatus {
+            budget: Budget {
+                scope: BudgetScope::Global,
+                window: BudgetWindow::Daily,
+                limit: 10.0,
+            },
+            currency: "USD".to_string(),
+            spent: 10.5,
+            remaining: 0.0,
+            exceeded: true,
+            window_start: now,
+            resets_at: now,
+        }));
+        assert_eq!(error_status(&error), StatusCode::PAYMENT_REQUIRED);
+
+        let error = CoreError::UnknownProvider("invalid".to_string());
+        assert_eq!(error_status(&error), StatusCode::BAD_REQUEST);
+
+        let error = CoreError::RateLimited {
+            provider: "OpenAI".to_string(),
+            retry_after: None,
+            body: "Rate limit reached".to_string(),
+        };
+        assert_eq!(error_status(&error), StatusCode::TOO_MANY_REQUESTS);
+
+        let error = CoreError::ProviderStatus {
+            provider: "Anthropic".to_string(),
+            status: 401,
+            retry_after: None,
+            body: "invalid x-api-key".to_string(),
+        };
+        assert_eq!(error_status(&error), StatusCode::BAD_GATEWAY);
+
+        let error = CoreError::missing_credentials("OpenAI", "OPENAI_KEY");
+        assert_eq!(error_status(&error), StatusCode::SERVICE_UNAVAILABLE);
+
+        let error = CoreError::Template(TemplateError::MissingVariables(vec!["topic".to_string()]));
+        assert_eq!(error_status(&error), StatusCode::BAD_REQUEST);
+        assert_eq!(
+            error_status(&CoreError::TemplateNotFound(7)),
+            StatusCode::NOT_FOUND
+        );
+    }
 
     #[test]
     fn test_create_prompt_request_empty_prompt() {
@@ -111,6 +354,10 @@ mod tests {
             prompt: "".to_string(),
             provider: "Anthropic".to_string(),
             model: None,
+            model_mode: ModelMode::Strict,
+            params: GenerationParams::default(),
+            tags: Vec::new(),
+            cache: CacheMode::Use,
         };
 
         assert_eq!(payload.prompt, "");
@@ -124,6 +371,10 @@ mod tests {
             prompt: "Test prompt".to_string(),
             provider: "OpenAI".to_string(),
             model: Some("gpt-4".to_string()),
+            model_mode: ModelMode::Strict,
+            params: GenerationParams::default(),
+            tags: Vec::new(),
+            cache: CacheMode::Use,
         };
 
         assert_eq!(payload.prompt, "Test prompt");
@@ -131,15 +382,66 @@ mod tests {
         assert_eq!(payload.model, Some("gpt-4".to_string()));
     }
 
+    #[test]
+    fn test_search_query_params() {
+        let Query(query) = Query::<SearchQuery>::try_from_uri(
+            &"/prompts/search?q=kafka+lag&limit=5".parse().unwrap(),
+        )
+        .unwrap();
+        let search = query.search().unwrap();
+        assert_eq!(search.text, "kafka lag");
+        assert_eq!(search.limit, 5);
+
+        assert!(Query::<SearchQuery>::try_from_uri(&"/prompts/search".parse().unwrap()).is_err());
+        let Query(query) =
+            Query::<SearchQuery>::try_from_uri(&"/prompts/search?q=+".parse().unwrap()).unwrap();
+        assert!(query.search().is_err());
+    }
+
     #[test]
     fn test_error_response_creation() {
-        let error = ErrorResponse {
-            error: "Test error message".to_string(),
-        };
+        let error = ErrorResponse::new("Test error message");
 
         assert_eq!(error.error, "Test error message");
     }
 
+    #[test]
+    fn test_unknown_model_error_response() {
+        let error = CoreError::InvalidModel {
+            provider: "OpenAI".to_string(),
+            model: "gpt4o".to_string(),
+            suggestions: vec!["gpt-4o".to_string()],
+        };
+        assert_eq!(error_status(&error), StatusCode::BAD_REQUEST);
+
+        let body = serde_json::to_value(ErrorResponse::from(&error)).unwrap();
+        assert_eq!(
+            body,
+            json!({
+                "error": "Unknown model for OpenAI: gpt4o, did you mean gpt-4o?",
+                "code": "unknown_model",
+                "suggestions": ["gpt-4o"]
+            })
+        );
+
+        // other errors only carry their message
+        let body = serde_json::to_value(ErrorResponse::new("Prompt cannot be empty")).unwrap();
+        assert_eq!(body, json!({ "error": "Prompt cannot be empty" }));
+    }
+
+    #[test]
+    fn test_create_prompt_request_model_mode() {
+        let payload: CreatePromptRequest =
+            serde_json::from_str(r#"{"prompt": "hi", "provider": "OpenAI"}"#).unwrap();
+        assert_eq!(payload.model_mode, ModelMode::Strict);
+
+        let payload: CreatePromptRequest = serde_json::from_str(
+            r#"{"prompt": "hi", "provider": "OpenAI", "model_mode": "lenient"}"#,
+        )
+        .unwrap();
+        assert_eq!(payload.model_mode, ModelMode::Lenient);
+    }
+
     #[test]
     fn test_empty_prompt_validation() {
         assert!(
@@ -156,14 +458,34 @@ mod tests {
         );
     }
 
-    #[test]
-    fn test_provider_validation() {
-        let providers = Provider::all();
-        let provider_strings: Vec<String> = providers.iter().map(|p| p.to_string()).collect();
+    #[tokio::test]
+    async fn test_provider_validation() {
+        let Json(provider_strings) = get_providers_handler().await.ok().unwrap();
 
-        assert_eq!(provider_strings.len(), 2);
+        assert_eq!(provider_strings.len(), 5);
+        assert!(provider_strings.contains(&"Gemini".to_string()));
         assert!(provider_strings.contains(&"Anthropic".to_string()));
         assert!(provider_strings.contains(&"OpenAI".to_string()));
+        assert!(provider_strings.contains(&"Ollama".to_string()));
+        assert!(provider_strings.contains(&"OpenAI-Compatible".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_sse_event_json_data() {
+        use axum::response::IntoResponse;
+
+        let event = sse_event("token", &json!({ "text": "line one\nline two" }));
+        let response =
+            Sse::new(futures::stream::iter(vec![Ok::<_, Infallible>(event)])).into_response();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+
+        // newlines stay escaped inside the json so the event remains a single data line
+        assert_eq!(
+            String::from_utf8(body.to_vec()).unwrap(),
+            "event: token\ndata: {\"text\":\"line one\\nline two\"}\n\n"
+        );
     }
 
     #[test]
@@ -190,7 +512,7 @@ mod tests {
 
         assert_eq!(
             TypeId::of::<DatabaseConnection>(),
-            TypeId::of::<Arc<MySqlPool>>()
+            TypeId::of::<Arc<dyn PromptStore>>()
         );
     }
 }
@@ -0,0 +1,107 @@
+use axum::extract::rejection::QueryRejection;
+use axum::extract::{Path, Query, State};
+use axum::http::{HeaderMap, StatusCode};
+use axum::Json;
+use serde::Deserialize;
+use std::sync::Arc;
+
+use kubellm_core::{
+    budget_config, enqueue_prompt, CreatePromptRequest, ErrorResponse, PromptStore, Task,
+    TaskStatus,
+};
+
+use crate::prompt::prompt_user;
+
+type DatabaseConnection = Arc<dyn PromptStore>;
+
+// tasks returned by GET /tasks when no limit is given, and the most it returns
+const DEFAULT_TASK_LIMIT: u32 = 50;
+const MAX_TASK_LIMIT: u32 = 500;
+
+#[derive(Deserialize)]
+pub struct TasksQuery {
+    pub status: Option<TaskStatus>,
+    pub limit: Option<u32>,
+}
+
+// queue a prompt for the worker and answer straight away with the pending task,
+// GET /tasks/{id} has the id of the saved prompt once the task is completed
+pub async fn enqueue_prompt_handler(
+    State(store): State<DatabaseConnection>,
+    headers: HeaderMap,
+    Json(payload): Json<CreatePromptRequest>,
+) -> anyhow::Result<(StatusCode, Json<Task>), (StatusCode, Json<ErrorResponse>)> {
+    if payload.prompt.trim().is_empty() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(ErrorResponse::new("Prompt cannot be empty")),
+        ));
+    }
+    if let Err(e) = payload.params.validate() {
+        return Err((StatusCode::BAD_REQUEST, Json(ErrorResponse::new(e))));
+    }
+
+    let user = prompt_user(&headers, budget_config())?;
+
+    match enqueue_prompt(&payload, user.as_deref(), &store).await {
+        Ok(task) => Ok((StatusCode::ACCEPTED, Json(task))),
+        Err(e) => {
+            eprintln!(
+                "Error queueing prompt for provider {}: {}",
+                &payload.provider, e
+            );
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ErrorResponse::new("Failed to queue prompt")),
+            ))
+        }
+    }
+}
+
+pub async fn get_task_handler(
+    State(store): State<DatabaseConnection>,
+    Path(id): Path<i64>,
+) -> anyhow::Result<Json<Task>, (StatusCode, Json<ErrorResponse>)> {
+    match store.get_task(id).await {
+        Ok(Some(task)) => Ok(Json(task)),
+        Ok(None) => Err((
+            StatusCode::NOT_FOUND,
+            Json(ErrorResponse::new(format!("Task not found: {}", id))),
+        )),
+        Err(e) => {
+            eprintln!("Database error: {}", e);
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ErrorResponse::new("Failed to fetch task")),
+            ))
+        }
+    }
+}
+
+// the newest tasks, e.g. /tasks?status=dead for the ones that failed for good
+pub async fn get_tasks_handler(
+    State(store): State<DatabaseConnection>,
+    query: Result<Query<TasksQuery>, QueryRejection>,
+) -> anyhow::Result<Json<Vec<Task>>, (StatusCode, Json<ErrorResponse>)> {
+    let bad_request = |error: String| (StatusCode::BAD_REQUEST, Json(ErrorResponse::new(error)));
+
+    let Query(query) = query.map_err(|e| bad_request(e.body_text()))?;
+    let limit = query.limit.unwrap_or(DEFAULT_TASK_LIMIT);
+    if !(1..=MAX_TASK_LIMIT).contains(&limit) {
+        return Err(bad_request(format!(
+            "limit must be between 1 and {}",
+            MAX_TASK_LIMIT
+        )));
+    }
+
+    match store.get_tasks(query.status, limit).await {
+        Ok(tasks) => Ok(Json(tasks)),
+        Err(e) => {
+            eprintln!("Database error: {}", e);
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ErrorResponse::new("Failed to fetch tasks")),
+            ))
+        }
+    }
+}
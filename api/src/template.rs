@@ -0,0 +1,118 @@
+use axum::extract::{Path, State};
+use axum::http::{HeaderMap, StatusCode};
+use axum::Json;
+use std::sync::Arc;
+
+use crate::prompt::{error_status, prompt_user};
+
+use kubellm_core::{
+    budget_config, prompt_template, ErrorResponse, NewPromptTemplate, Prompt, PromptStore,
+    PromptTemplate, RunTemplateRequest,
+};
+
+type DatabaseConnection = Arc<dyn PromptStore>;
+
+// save a template, a name that is already used gets a new version
+pub async fn create_template_handler(
+    State(store): State<DatabaseConnection>,
+    Json(payload): Json<NewPromptTemplate>,
+) -> anyhow::Result<Json<PromptTemplate>, (StatusCode, Json<ErrorResponse>)> {
+    let new_template = match payload.validate() {
+        Ok(new_template) => new_template,
+        Err(e) => {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                Json(ErrorResponse::new(e.to_string())),
+            ))
+        }
+    };
+
+    match store.create_prompt_template(&new_template).await {
+        Ok(template) => Ok(Json(template)),
+        Err(e) => {
+            eprintln!("Database error: {}", e);
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ErrorResponse::new("Failed to create template")),
+            ))
+        }
+    }
+}
+
+// the latest version of each template
+pub async fn get_templates_handler(
+    State(store): State<DatabaseConnection>,
+) -> anyhow::Result<Json<Vec<PromptTemplate>>, (StatusCode, Json<ErrorResponse>)> {
+    match store.get_prompt_templates().await {
+        Ok(templates) => Ok(Json(templates)),
+        Err(e) => {
+            eprintln!("Database error: {}", e);
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ErrorResponse::new("Failed to fetch templates")),
+            ))
+        }
+    }
+}
+
+// a single template version, 404 if it doesn't exist
+pub async fn get_template_handler(
+    State(store): State<DatabaseConnection>,
+    Path(id): Path<i64>,
+) -> anyhow::Result<Json<PromptTemplate>, (StatusCode, Json<ErrorResponse>)> {
+    match store.get_prompt_template(id).await {
+        Ok(Some(template)) => Ok(Json(template)),
+        Ok(None) => Err((
+            StatusCode::NOT_FOUND,
+            Json(ErrorResponse::new(format!("Template not found: {}", id))),
+        )),
+        Err(e) => {
+            eprintln!("Database error: {}", e);
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ErrorResponse::new("Failed to fetch template")),
+            ))
+        }
+    }
+}
+
+// fill in the template's variables and prompt its provider, the prompt links back to the template
+pub async fn run_template_handler(
+    State(store): State<DatabaseConnection>,
+    Path(id): Path<i64>,
+    headers: HeaderMap,
+    Json(payload): Json<RunTemplateRequest>,
+) -> anyhow::Result<Json<Prompt>, (StatusCode, Json<ErrorResponse>)> {
+    if let Err(e) = payload.params.validate() {
+        return Err((StatusCode::BAD_REQUEST, Json(ErrorResponse::new(e))));
+    }
+    let user = prompt_user(&headers, budget_config())?;
+
+    match prompt_template(id, &payload, user.as_deref(), &store).await {
+        Ok(prompt) => Ok(Json(prompt)),
+        Err(e) => {
+            eprintln!("Error running template {}: {}", id, e);
+            Err((error_status(&e), Json(ErrorResponse::from(&e))))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_run_template_request_body() {
+        let payload: RunTemplateRequest = serde_json::from_str(
+            r#"{"variables":{"topic":"lifetimes"},"provider":"OpenAI","temperature":0.2}"#,
+        )
+        .unwrap();
+        assert_eq!(payload.variables["topic"], "lifetimes");
+        assert_eq!(payload.provider.as_deref(), Some("OpenAI"));
+        assert_eq!(payload.params.temperature, Some(0.2));
+
+        let payload: RunTemplateRequest = serde_json::from_str("{}").unwrap();
+        assert!(payload.variables.is_empty());
+        assert!(payload.provider.is_none());
+    }
+}
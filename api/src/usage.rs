@@ -0,0 +1,58 @@
+use axum::extract::rejection::QueryRejection;
+use axum::extract::{Query, State};
+use axum::http::StatusCode;
+use axum::Json;
+use std::sync::Arc;
+
+use kubellm_core::{price_table, ErrorResponse, PromptStore, UsageQuery, UsageReport};
+
+type DatabaseConnection = Arc<dyn PromptStore>;
+
+// spend report for charging llm usage back to projects, e.g. /usage?from=2025-01-01&to=2025-01-31&group_by=model
+pub async fn get_usage_handler(
+    State(store): State<DatabaseConnection>,
+    query: Result<Query<UsageQuery>, QueryRejection>,
+) -> anyhow::Result<Json<UsageReport>, (StatusCode, Json<ErrorResponse>)> {
+    let bad_request = |error: String| (StatusCode::BAD_REQUEST, Json(ErrorResponse::new(error)));
+
+    let Query(query) = query.map_err(|e| bad_request(e.body_text()))?;
+    let (from, to) = query.range().map_err(bad_request)?;
+
+    match store.get_usage(query.group_by, from, to).await {
+        Ok(rows) => Ok(Json(UsageReport::new(
+            query.group_by,
+            (from, to),
+            &price_table().currency,
+            rows,
+        ))),
+        Err(e) => {
+            eprintln!("Database error: {}", e);
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ErrorResponse::new("Failed to fetch usage")),
+            ))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use kubellm_core::UsageGroupBy;
+
+    #[test]
+    fn test_usage_query_from_query_string() {
+        let query: UsageQuery = serde_json::from_value(serde_json::json!({
+            "from": "2025-01-01",
+            "group_by": "day"
+        }))
+        .unwrap();
+        assert_eq!(query.group_by, UsageGroupBy::Day);
+        assert_eq!(query.from, Some("2025-01-01".to_string()));
+        assert!(query.to.is_none());
+
+        let query: Result<UsageQuery, _> =
+            serde_json::from_value(serde_json::json!({ "group_by": "week" }));
+        assert!(query.is_err());
+    }
+}
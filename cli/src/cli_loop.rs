@@ -1,16 +1,21 @@
 use crate::config::CliConfig;
 use crate::PromptFormatter;
-use clap::{Parser, Subcommand};
+use clap::{Args, Parser, Subcommand};
+use futures::StreamExt;
 use kubellm_core::{
-    create_database_pool, get_all_prompts, get_models, init_database, prompt_model, CoreConfig,
-    Provider,
+    compare_prompt, connect_store, get_budget_statuses, get_models, get_route, load_routes,
+    price_table, provider_names, refresh_models, stream_prompt_conversation, stream_prompt_model,
+    stream_prompt_template, BudgetStatus, CompareRequest, CompareTarget, Comparison, CoreConfig,
+    CreatePromptRequest, GenerationParams, ModelInfo, ModelMode, Prompt, PromptQuery, PromptStream,
+    PromptStreamEvent, PromptTemplate, RunTemplateRequest, SearchHit, SearchQuery, UsageGroupBy,
+    UsageQuery, UsageReport, UsageSummary,
 };
 use rustyline::error::ReadlineError;
 use rustyline::DefaultEditor;
 use std::fs::File;
 use std::io;
 use std::io::Write;
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, Mutex, Once};
 use std::time::{Duration, Instant};
 use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
 use tokio::task::JoinHandle;
@@ -47,6 +52,52 @@ impl Default for CtrlCState {
     }
 }
 
+/// The conversation that lines are sent to while in chat mode
+#[derive(Debug, Clone)]
+pub struct ChatSession {
+    conversation_id: i64,
+    provider: String,
+    model: Option<String>,
+    model_mode: ModelMode,
+    params: GenerationParams,
+}
+
+/// System prompt and sampling settings, the provider's defaults are used for anything left out
+#[derive(Args, Debug, Clone, Default, PartialEq)]
+struct GenerationArgs {
+    /// System prompt sent ahead of the conversation
+    #[arg(long)]
+    system: Option<String>,
+    /// Sampling temperature, 0 to 2
+    #[arg(long)]
+    temperature: Option<f32>,
+    /// Nucleus sampling probability, 0 to 1
+    #[arg(long)]
+    top_p: Option<f32>,
+    /// Most tokens to generate
+    #[arg(long)]
+    max_tokens: Option<u32>,
+    /// Sequence that ends the response, can be given more than once
+    #[arg(long)]
+    stop: Vec<String>,
+    /// Seed for providers that support repeatable sampling
+    #[arg(long)]
+    seed: Option<i64>,
+}
+
+impl From<GenerationArgs> for GenerationParams {
+    fn from(args: GenerationArgs) -> Self {
+        Self {
+            system: args.system,
+            temperature: args.temperature,
+            top_p: args.top_p,
+            max_tokens: args.max_tokens,
+            stop: (!args.stop.is_empty()).then_some(args.stop),
+            seed: args.seed,
+        }
+    }
+}
+
 #[derive(Parser)]
 // name of the program
 #[command(name = "prompt-cli")]
@@ -62,8 +113,44 @@ struct Cli {
 enum Commands {
     /// Initialize the database
     InitDb,
-    /// List all prompts
-    List,
+    /// List prompts, newest first
+    List {
+        /// Most prompts to show
+        #[arg(short, long, default_value_t = 20)]
+        limit: u32,
+        /// Only prompts answered by this provider
+        #[arg(short = 'r', long)]
+        provider: Option<String>,
+        /// Only prompts answered by this model
+        #[arg(short, long)]
+        model: Option<String>,
+        /// Only prompts made since this date (YYYY-MM-DD) or RFC 3339 timestamp
+        #[arg(short, long)]
+        since: Option<String>,
+        /// Only prompts in this conversation
+        #[arg(short, long)]
+        conversation: Option<i64>,
+        /// Only prompts with this tag
+        #[arg(long)]
+        tag: Option<String>,
+        /// Continue from the cursor printed after the previous page
+        #[arg(long)]
+        cursor: Option<String>,
+    },
+    /// Show a single prompt
+    Show {
+        /// The prompt's id
+        id: i64,
+    },
+    /// Search prompts and responses, most relevant first
+    Search {
+        /// Words to search for
+        #[arg(required = true)]
+        query: Vec<String>,
+        /// Most matches to show
+        #[arg(short, long, default_value_t = 10)]
+        limit: u32,
+    },
     /// Create a new prompt for a provider
     Prompt {
         /// The prompt content
@@ -72,9 +159,59 @@ enum Commands {
         /// The model to use
         #[arg(short, long)]
         model: Option<String>,
-        /// The model provider to use
+        /// The model provider, or a route from ROUTES_FILE, to use
+        #[arg(short = 'r', long)]
+        provider: String,
+        /// Use the provider's default model when the model is unknown (lenient) or refuse (strict)
+        #[arg(long, default_value = "lenient")]
+        model_mode: ModelMode,
+        /// Tag saved with the prompt, can be given more than once
+        #[arg(long = "tag")]
+        tags: Vec<String>,
+        #[command(flatten)]
+        generation: GenerationArgs,
+    },
+    /// Start or resume a conversation, every following line is sent as a prompt until /exit
+    Chat {
+        /// The model provider, or a route from ROUTES_FILE, to use
         #[arg(short = 'r', long)]
         provider: String,
+        /// The model to use
+        #[arg(short, long)]
+        model: Option<String>,
+        /// Resume an existing conversation instead of starting a new one
+        #[arg(short, long)]
+        conversation: Option<i64>,
+        /// Title for a new conversation
+        #[arg(short, long)]
+        title: Option<String>,
+        /// Use the provider's default model when the model is unknown (lenient) or refuse (strict)
+        #[arg(long, default_value = "lenient")]
+        model_mode: ModelMode,
+        #[command(flatten)]
+        generation: GenerationArgs,
+    },
+    /// Send a line to the current conversation (used by chat mode)
+    #[command(hide = true)]
+    Say { message: String },
+    /// Send the same prompt to several providers and models and show the answers side by side
+    Compare {
+        /// The prompt content
+        #[arg(short, long)]
+        prompt: String,
+        /// A provider, or provider:model, to compare, given once per target
+        #[arg(short = 'r', long = "target", required = true)]
+        targets: Vec<CompareTarget>,
+        /// Use the provider's default model when the model is unknown (lenient) or refuse (strict)
+        #[arg(long, default_value = "lenient")]
+        model_mode: ModelMode,
+        #[command(flatten)]
+        generation: GenerationArgs,
+    },
+    /// List, show and run prompt templates
+    Template {
+        #[command(subcommand)]
+        command: TemplateCommands,
     },
     /// Get a provider's list of models
     GetModels {
@@ -84,12 +221,75 @@ enum Commands {
     },
     /// Get a list of providers
     GetProviders,
+    /// Fetch providers' model lists again instead of waiting for the cached lists to expire
+    RefreshModels {
+        /// Only refresh this provider's models
+        #[arg(short = 'r', long)]
+        provider: Option<String>,
+    },
+    /// Show token usage and spend
+    Usage {
+        /// Start of the report, a date (YYYY-MM-DD) or RFC 3339 timestamp
+        #[arg(short, long)]
+        from: Option<String>,
+        /// End of the report, a date includes that whole day
+        #[arg(short, long)]
+        to: Option<String>,
+        /// Group the report by provider, model or day
+        #[arg(short, long, default_value = "provider")]
+        group_by: UsageGroupBy,
+    },
+    /// Show spend against each budget
+    Budgets,
     /// Show database connection status
     Status,
     /// Exit the application
     Exit,
 }
 
+#[derive(Subcommand)]
+enum TemplateCommands {
+    /// List the latest version of each template
+    List,
+    /// Show a template's text and variables
+    Show {
+        /// Template id, or a name for the latest version of that template
+        template: String,
+    },
+    /// Fill in a template's variables and prompt its provider
+    Run {
+        /// Template id, or a name for the latest version of that template
+        template: String,
+        /// A variable's value as key=value, can be given more than once
+        #[arg(long = "var", value_parser = parse_var)]
+        vars: Vec<(String, String)>,
+        /// The model provider to use instead of the template's
+        #[arg(short = 'r', long)]
+        provider: Option<String>,
+        /// The model to use instead of the template's
+        #[arg(short, long)]
+        model: Option<String>,
+        /// Use the provider's default model when the model is unknown (lenient) or refuse (strict)
+        #[arg(long, default_value = "lenient")]
+        model_mode: ModelMode,
+        #[command(flatten)]
+        generation: GenerationArgs,
+    },
+}
+
+/// Parses a `--var key=value` argument, the value may contain further `=`
+fn parse_var(arg: &str) -> Result<(String, String), String> {
+    match arg.split_once('=') {
+        Some((key, value)) if !key.trim().is_empty() => {
+            Ok((key.trim().to_string(), value.to_string()))
+        }
+        _ => Err(format!("expected key=value, got {}", arg)),
+    }
+}
+
+/// Width the columns of a comparison are fitted into
+const COMPARISON_WIDTH: usize = 120;
+
 /// macro to wrap a future and make it interruptible via Ctrl+C
 macro_rules! interruptible {
     ($future:expr, $ctrl_c_state:expr) => {{
@@ -179,6 +379,9 @@ pub async fn main_loop(
     ctrl_c_state: Arc<Mutex<CtrlCState>>,
     input_rx: &mut UnboundedReceiver<InputEvent>,
 ) {
+    // set while in chat mode
+    let chat_session: Arc<Mutex<Option<ChatSession>>> = Arc::new(Mutex::new(None));
+
     loop {
         tokio::select! {
             // Handle input from rustyline
@@ -203,22 +406,41 @@ pub async fn main_loop(
                             }
                         }
 
-                        // Parse and execute command
-                        let args = parse_quoted_args(&line);
-                        if args.is_empty() {
-                            continue;
-                        }
+                        // in chat mode every line is part of the conversation until /exit
+                        let in_chat = chat_session.lock().unwrap().is_some();
+                        let parsed = if in_chat {
+                            if line.trim() == "/exit" {
+                                *chat_session.lock().unwrap() = None;
+                                println!("\r\x1b[2KLeft chat mode");
+                                print!("\x1b[32mprompt-cli>\x1b[97m\x1b[?25h ");
+                                io::stdout().flush().unwrap();
+                                continue;
+                            }
+                            Ok(Cli {
+                                command: Commands::Say {
+                                    message: line.trim().to_string(),
+                                },
+                            })
+                        } else {
+                            // Parse and execute command
+                            let args = parse_quoted_args(&line);
+                            if args.is_empty() {
+                                continue;
+                            }
 
-                        let mut full_args = vec!["prompt-cli"];
-                        full_args.extend(args.iter().map(|s| s.as_str()));
+                            let mut full_args = vec!["prompt-cli"];
+                            full_args.extend(args.iter().map(|s| s.as_str()));
+                            Cli::try_parse_from(full_args)
+                        };
 
-                        match Cli::try_parse_from(full_args) {
+                        match parsed {
                             Ok(cli) => {
 
                                 // Spawn command execution in separate task so main loop stays responsive
                                 let ctrl_c_state_clone = ctrl_c_state.clone();
+                                let chat_session_clone = chat_session.clone();
                                 let mut command_handle = tokio::spawn(async move {
-                                    execute_command(cli.command, &ctrl_c_state_clone).await
+                                    execute_command(cli.command, &ctrl_c_state_clone, &chat_session_clone).await
                                 });
 
                                 // Wait for either command completion or keep processing other events
@@ -457,12 +679,21 @@ async fn command_in_progress_display(ctrl_c_state: Arc<Mutex<CtrlCState>>, messa
     }
 }
 
+static LOAD_ROUTES: Once = Once::new();
+
 /// Handles running a CLI command
 async fn execute_command(
     command: Commands,
     ctrl_c_state: &Arc<Mutex<CtrlCState>>,
+    chat_session: &Arc<Mutex<Option<ChatSession>>>,
 ) -> anyhow::Result<bool> {
     let config = CoreConfig::get();
+    // routes can be used in place of a provider once they're registered
+    LOAD_ROUTES.call_once(|| {
+        if let Err(e) = load_routes() {
+            eprintln!("Warning: Could not load routes: {:#}", e);
+        }
+    });
 
     {
         let mut state = ctrl_c_state.lock().unwrap();
@@ -479,96 +710,294 @@ async fn execute_command(
     match command {
         Commands::InitDb => {
             println!("\r\x1b[2KInitializing database...");
-            let pool = try_interruptible!(
-                create_database_pool(config),
+            let store = try_interruptible!(
+                connect_store(config),
                 &ctrl_c_state,
                 progress_task,
-                "Failed to create database pool"
+                "Failed to connect to the database"
             );
 
-            match interruptible!(init_database(&pool), &ctrl_c_state) {
-                Ok(_) => {
-                    println!("\r\x1b[2K✅ Database initialized successfully")
+            match interruptible!(store.migrate(), &ctrl_c_state) {
+                Ok(migrations) => {
+                    for name in &migrations.applied {
+                        println!("\r\x1b[2K   Applied migration {}", name);
+                    }
+                    if !migrations.adopted.is_empty() {
+                        println!(
+                            "\r\x1b[2K   Recorded {} migrations already made to the existing schema",
+                            migrations.adopted.len()
+                        );
+                    }
+                    println!(
+                        "\r\x1b[2K✅ Database initialized successfully, schema version {}",
+                        migrations.version
+                    )
                 }
                 Err(e) => {
                     eprintln!("\r\x1b[2K❌ Error initializing database: {}", e)
                 }
             }
         }
-        Commands::List => {
-            let pool = try_interruptible!(
-                create_database_pool(config),
+        Commands::List {
+            limit,
+            provider,
+            model,
+            since,
+            conversation,
+            tag,
+            cursor,
+        } => {
+            let query = PromptQuery {
+                provider,
+                model,
+                from: since,
+                conversation_id: conversation,
+                tag,
+                cursor,
+                limit: Some(limit),
+                ..Default::default()
+            };
+            let filter = match query.filter() {
+                Ok(filter) => filter,
+                Err(e) => {
+                    eprintln!("\r\x1b[2K❌ {}", e);
+                    reset_prompt(progress_task, ctrl_c_state).await;
+                    return Ok(true);
+                }
+            };
+
+            let store = try_interruptible!(
+                connect_store(config),
                 &ctrl_c_state,
                 progress_task,
-                "Failed to create database pool"
+                "Failed to connect to the database"
             );
 
-            match interruptible!(get_all_prompts(&pool), &ctrl_c_state) {
-                Ok(prompts) => {
-                    if prompts.is_empty() {
+            match interruptible!(store.get_prompts(&filter), &ctrl_c_state) {
+                Ok(page) => {
+                    if page.prompts.is_empty() {
                         println!("\r\x1b[2KNo prompts found");
                     } else {
                         let mut prompt_formatter = PromptFormatter::new();
-                        println!("\r\x1b[2KFound {} prompts:", prompts.len());
-                        for prompt in prompts {
-                            println!("  ╭─ [{}] ──────────────────────────────────────────────────────────────────", prompt.id);
-                            println!("  │ Prompt:");
-                            prompt_formatter
-                                .format_prompt(&prompt.prompt, 80)
-                                .iter()
-                                .for_each(|line| println!("  │     {}", line));
-                            println!("  │ Response: ");
-                            prompt_formatter
-                                .format_prompt(&prompt.response, 80)
-                                .iter()
-                                .for_each(|line| println!("  │     {}", line));
-                            println!("  │ Model: {}", prompt.model);
-                            println!("  │ Provider: {}", prompt.provider);
-                            println!("  │ Timestamp: {}", prompt.created_at.timestamp());
-                            println!("  ╰──────────────────────────────────────────────────────────────────────────");
+                        println!("\r\x1b[2KFound {} prompts:", page.prompts.len());
+                        for prompt in &page.prompts {
+                            print_prompt(prompt, &mut prompt_formatter);
                             println!();
                         }
                     }
+                    if let Some(next_cursor) = page.next_cursor {
+                        println!(
+                            "More prompts, run list again with the same options and --cursor {}",
+                            next_cursor
+                        );
+                    }
                 }
                 Err(e) => {
                     eprintln!("\r\x1b[2K❌ Error fetching prompts: {}", e)
                 }
             }
         }
+        Commands::Show { id } => {
+            let store = try_interruptible!(
+                connect_store(config),
+                &ctrl_c_state,
+                progress_task,
+                "Failed to connect to the database"
+            );
+
+            match interruptible!(store.get_prompt_by_id(id), ctrl_c_state) {
+                Ok(Some(prompt)) => {
+                    print!("\r\x1b[2K");
+                    print_prompt(&prompt, &mut PromptFormatter::new());
+                }
+                Ok(None) => eprintln!("\r\x1b[2K❌ Prompt {} not found", id),
+                Err(e) => eprintln!("\r\x1b[2K❌ Error fetching prompt: {}", e),
+            }
+        }
+        Commands::Search { query, limit } => {
+            let query = SearchQuery {
+                q: query.join(" "),
+                limit: Some(limit),
+            };
+            let search = match query.search() {
+                Ok(search) => search,
+                Err(e) => {
+                    eprintln!("\r\x1b[2K❌ {}", e);
+                    reset_prompt(progress_task, ctrl_c_state).await;
+                    return Ok(true);
+                }
+            };
+
+            let store = try_interruptible!(
+                connect_store(config),
+                &ctrl_c_state,
+                progress_task,
+                "Failed to connect to the database"
+            );
+
+            match interruptible!(store.search_prompts(&search), &ctrl_c_state) {
+                Ok(hits) if hits.is_empty() => {
+                    println!("\r\x1b[2KNo prompts match \"{}\"", search.text)
+                }
+                Ok(hits) => {
+                    let mut prompt_formatter = PromptFormatter::new();
+                    println!("\r\x1b[2KFound {} matches:", hits.len());
+                    for hit in &hits {
+                        print_search_hit(hit, &mut prompt_formatter);
+                        println!();
+                    }
+                }
+                Err(e) => eprintln!("\r\x1b[2K❌ Error searching prompts: {}", e),
+            }
+        }
         Commands::Prompt {
             prompt,
             model,
             provider,
+            model_mode,
+            tags,
+            generation,
         } => {
-            let pool = try_interruptible!(
-                create_database_pool(config),
+            let request = CreatePromptRequest::new(&prompt, &provider)
+                .with_model(model.as_deref())
+                .with_model_mode(model_mode)
+                .with_params(generation.into())
+                .with_tags(tags);
+            if let Err(e) = request.params.validate() {
+                eprintln!("\r\x1b[2K❌ {}", e);
+                reset_prompt(progress_task, ctrl_c_state).await;
+                return Ok(true);
+            }
+
+            let store = try_interruptible!(
+                connect_store(config),
                 &ctrl_c_state,
                 progress_task,
-                "Failed to create database pool"
+                "Failed to connect to the database"
             );
 
-            match interruptible!(
-                prompt_model(&prompt, &provider, model.as_deref(), &pool),
+            let mut prompt_stream = match interruptible!(
+                stream_prompt_model(&request, CliConfig::get().user.as_deref(), &store),
                 ctrl_c_state
             ) {
-                Ok(response) => {
-                    let mut prompt_formatter = PromptFormatter::new();
-                    println!("\r\x1b[2K✅ Response:");
-                    prompt_formatter
-                        .format_prompt(response.response.as_str(), 80)
-                        .iter()
-                        .for_each(|line| println!("  │     {}", line));
-                    println!("Prompt ID: {}", response.id);
+                Ok(prompt_stream) => prompt_stream,
+                Err(e) => {
+                    eprintln!("\r\x1b[2K❌ Error calling model: {}", e);
+                    reset_prompt(progress_task, ctrl_c_state).await;
+                    return Ok(true);
+                }
+            };
+
+            if let Err(e) =
+                print_prompt_stream(&mut prompt_stream, &progress_task, ctrl_c_state).await
+            {
+                eprintln!("\r\x1b[2K❌ Error calling model: {}", e);
+            }
+        }
+        Commands::Chat {
+            provider,
+            model,
+            conversation,
+            title,
+            model_mode,
+            generation,
+        } => {
+            let params = GenerationParams::from(generation);
+            if let Err(e) = params.validate() {
+                eprintln!("\r\x1b[2K❌ {}", e);
+                reset_prompt(progress_task, ctrl_c_state).await;
+                return Ok(true);
+            }
+
+            let store = try_interruptible!(
+                connect_store(config),
+                &ctrl_c_state,
+                progress_task,
+                "Failed to connect to the database"
+            );
+
+            let conversation = match conversation {
+                Some(id) => match interruptible!(store.get_conversation(id), ctrl_c_state) {
+                    Ok(Some(conversation)) => conversation,
+                    Ok(None) => {
+                        eprintln!("\r\x1b[2K❌ Conversation {} not found", id);
+                        reset_prompt(progress_task, ctrl_c_state).await;
+                        return Ok(true);
+                    }
+                    Err(e) => {
+                        eprintln!("\r\x1b[2K❌ Error fetching conversation: {}", e);
+                        reset_prompt(progress_task, ctrl_c_state).await;
+                        return Ok(true);
+                    }
+                },
+                None => {
+                    let title = title.unwrap_or_else(|| "New conversation".to_string());
+                    try_interruptible!(
+                        store.create_conversation(&title),
+                        &ctrl_c_state,
+                        progress_task,
+                        "Error creating conversation"
+                    )
                 }
+            };
+
+            *chat_session.lock().unwrap() = Some(ChatSession {
+                conversation_id: conversation.id,
+                provider: provider.clone(),
+                model,
+                model_mode,
+                params,
+            });
+            println!(
+                "\r\x1b[2K💬 Chatting with {} in conversation [{}] {}",
+                provider, conversation.id, conversation.title
+            );
+            println!("Type /exit to leave chat mode");
+        }
+        Commands::Say { message } => {
+            let Some(session) = chat_session.lock().unwrap().clone() else {
+                eprintln!("\r\x1b[2K❌ Not in chat mode, start one with chat -r <provider>");
+                reset_prompt(progress_task, ctrl_c_state).await;
+                return Ok(true);
+            };
+
+            let store = try_interruptible!(
+                connect_store(config),
+                &ctrl_c_state,
+                progress_task,
+                "Failed to connect to the database"
+            );
+
+            let request = CreatePromptRequest::new(&message, &session.provider)
+                .with_model(session.model.as_deref())
+                .with_model_mode(session.model_mode)
+                .with_params(session.params);
+
+            let mut prompt_stream = match interruptible!(
+                stream_prompt_conversation(
+                    session.conversation_id,
+                    &request,
+                    CliConfig::get().user.as_deref(),
+                    &store
+                ),
+                ctrl_c_state
+            ) {
+                Ok(prompt_stream) => prompt_stream,
                 Err(e) => {
                     eprintln!("\r\x1b[2K❌ Error calling model: {}", e);
                     reset_prompt(progress_task, ctrl_c_state).await;
                     return Ok(true);
                 }
+            };
+
+            if let Err(e) =
+                print_prompt_stream(&mut prompt_stream, &progress_task, ctrl_c_state).await
+            {
+                eprintln!("\r\x1b[2K❌ Error calling model: {}", e);
             }
         }
         Commands::GetModels { provider } => {
-            match interruptible!(get_models(&provider), ctrl_c_state) {
+            match interruptible!(get_models(&provider, None), ctrl_c_state) {
                 Ok(models) => {
                     if models.is_empty() {
                         println!("\r\x1b[2KNo models found for provider '{}'", provider);
@@ -585,21 +1014,275 @@ async fn execute_command(
             }
         }
         Commands::GetProviders => {
-            let providers = Provider::all();
+            let providers = provider_names();
             println!("\r\x1b[2KAvailable providers:");
             for provider in providers {
                 println!(" - {}", provider);
             }
         }
+        Commands::Usage { from, to, group_by } => {
+            let query = UsageQuery { from, to, group_by };
+            let range = match query.range() {
+                Ok(range) => range,
+                Err(e) => {
+                    eprintln!("\r\x1b[2K❌ {}", e);
+                    reset_prompt(progress_task, ctrl_c_state).await;
+                    return Ok(true);
+                }
+            };
+
+            let store = try_interruptible!(
+                connect_store(config),
+                &ctrl_c_state,
+                progress_task,
+                "Failed to connect to the database"
+            );
+
+            match interruptible!(store.get_usage(group_by, range.0, range.1), ctrl_c_state) {
+                Ok(rows) if rows.is_empty() => println!("\r\x1b[2KNo usage found"),
+                Ok(rows) => {
+                    let report = UsageReport::new(group_by, range, &price_table().currency, rows);
+                    print!("\r\x1b[2K");
+                    format_usage_table(&report)
+                        .iter()
+                        .for_each(|line| println!("{}", line));
+                    if report.total.unpriced_prompts > 0 {
+                        println!(
+                            "  {} prompts have no price and are not included in the cost",
+                            report.total.unpriced_prompts
+                        );
+                    }
+                }
+                Err(e) => {
+                    eprintln!("\r\x1b[2K❌ Error fetching usage: {}", e);
+                }
+            }
+        }
+        Commands::Compare {
+            prompt,
+            targets,
+            model_mode,
+            generation,
+        } => {
+            let request = CompareRequest {
+                prompt,
+                targets,
+                model_mode,
+                params: generation.into(),
+            };
+            if let Err(e) = request.validate() {
+                eprintln!("\r\x1b[2K❌ {}", e);
+                reset_prompt(progress_task, ctrl_c_state).await;
+                return Ok(true);
+            }
+
+            let store = try_interruptible!(
+                connect_store(config),
+                &ctrl_c_state,
+                progress_task,
+                "Failed to connect to the database"
+            );
+
+            let comparison = try_interruptible!(
+                async {
+                    anyhow::Ok(
+                        compare_prompt(&request, CliConfig::get().user.as_deref(), &store).await,
+                    )
+                },
+                &ctrl_c_state,
+                progress_task,
+                "Error comparing models"
+            );
+
+            println!("\r\x1b[2K✅ Comparison {}:", comparison.comparison_id);
+            format_comparison(&comparison, COMPARISON_WIDTH)
+                .iter()
+                .for_each(|line| println!("{}", line));
+        }
+        Commands::Template { command } => {
+            let store = try_interruptible!(
+                connect_store(config),
+                &ctrl_c_state,
+                progress_task,
+                "Failed to connect to the database"
+            );
+
+            match command {
+                TemplateCommands::List => {
+                    match interruptible!(store.get_prompt_templates(), ctrl_c_state) {
+                        Ok(templates) if templates.is_empty() => {
+                            println!("\r\x1b[2KNo templates found")
+                        }
+                        Ok(templates) => {
+                            println!("\r\x1b[2KFound {} templates:", templates.len());
+                            templates.iter().for_each(|template| {
+                                println!("{}", format_template_summary(template))
+                            });
+                        }
+                        Err(e) => {
+                            eprintln!("\r\x1b[2K❌ Error fetching templates: {}", e);
+                        }
+                    }
+                }
+                TemplateCommands::Show { template } => {
+                    match interruptible!(store.find_prompt_template(&template), ctrl_c_state) {
+                        Ok(Some(template)) => {
+                            println!("\r\x1b[2K{}", format_template_summary(&template));
+                            println!("  Template:");
+                            PromptFormatter::new()
+                                .format_prompt(&template.template, 80)
+                                .iter()
+                                .for_each(|line| println!("      {}", line));
+                            if !template.variables.is_empty() {
+                                println!("  Variables:");
+                            }
+                            for variable in &template.variables {
+                                let mut line = match &variable.default {
+                                    Some(default) => {
+                                        format!("   - {} = {:?}", variable.name, default)
+                                    }
+                                    None => format!("   - {} (required)", variable.name),
+                                };
+                                if let Some(description) = &variable.description {
+                                    line.push_str(&format!("  {}", description));
+                                }
+                                println!("{}", line);
+                            }
+                        }
+                        Ok(None) => eprintln!("\r\x1b[2K❌ Template {} not found", template),
+                        Err(e) => eprintln!("\r\x1b[2K❌ Error fetching template: {}", e),
+                    }
+                }
+                TemplateCommands::Run {
+                    template,
+                    vars,
+                    provider,
+                    model,
+                    model_mode,
+                    generation,
+                } => {
+                    let run = RunTemplateRequest {
+                        variables: vars.into_iter().collect(),
+                        provider,
+                        model,
+                        model_mode,
+                        params: generation.into(),
+                    };
+                    if let Err(e) = run.params.validate() {
+                        eprintln!("\r\x1b[2K❌ {}", e);
+                        reset_prompt(progress_task, ctrl_c_state).await;
+                        return Ok(true);
+                    }
+
+                    let template_id =
+                        match interruptible!(store.find_prompt_template(&template), ctrl_c_state) {
+                            Ok(Some(found)) => found.id,
+                            Ok(None) => {
+                                eprintln!("\r\x1b[2K❌ Template {} not found", template);
+                                reset_prompt(progress_task, ctrl_c_state).await;
+                                return Ok(true);
+                            }
+                            Err(e) => {
+                                eprintln!("\r\x1b[2K❌ Error fetching template: {}", e);
+                                reset_prompt(progress_task, ctrl_c_state).await;
+                                return Ok(true);
+                            }
+                        };
+
+                    let mut prompt_stream = match interruptible!(
+                        stream_prompt_template(
+                            template_id,
+                            &run,
+                            CliConfig::get().user.as_deref(),
+                            &store
+                        ),
+                        ctrl_c_state
+                    ) {
+                        Ok(prompt_stream) => prompt_stream,
+                        Err(e) => {
+                            eprintln!("\r\x1b[2K❌ Error running template: {}", e);
+                            reset_prompt(progress_task, ctrl_c_state).await;
+                            return Ok(true);
+                        }
+                    };
+
+                    if let Err(e) =
+                        print_prompt_stream(&mut prompt_stream, &progress_task, ctrl_c_state).await
+                    {
+                        eprintln!("\r\x1b[2K❌ Error calling model: {}", e);
+                    }
+                }
+            }
+        }
+        Commands::Budgets => {
+            let store = try_interruptible!(
+                connect_store(config),
+                &ctrl_c_state,
+                progress_task,
+                "Failed to connect to the database"
+            );
+
+            match interruptible!(get_budget_statuses(store.as_ref(), None), ctrl_c_state) {
+                Ok(statuses) if statuses.is_empty() => {
+                    println!("\r\x1b[2KNo budgets configured, set BUDGETS_FILE to add some")
+                }
+                Ok(statuses) => {
+                    println!("\r\x1b[2KBudgets:");
+                    statuses
+                        .iter()
+                        .for_each(|status| println!("{}", format_budget_status(status)));
+                }
+                Err(e) => {
+                    eprintln!("\r\x1b[2K❌ Error fetching budgets: {}", e);
+                }
+            }
+        }
+        Commands::RefreshModels { provider } => {
+            let store = try_interruptible!(
+                connect_store(config),
+                &ctrl_c_state,
+                progress_task,
+                "Failed to connect to the database"
+            );
+
+            // routes have no models of their own
+            let providers = match provider {
+                Some(provider) => vec![provider],
+                None => provider_names()
+                    .into_iter()
+                    .filter(|name| get_route(name).is_none())
+                    .collect(),
+            };
+
+            print!("\r\x1b[2K");
+            for provider in providers {
+                match interruptible!(
+                    refresh_models(&provider, Some(store.as_ref())),
+                    ctrl_c_state
+                ) {
+                    Ok(entry) => {
+                        println!("✅ {}: {} models", entry.provider, entry.models.len());
+                        entry
+                            .models
+                            .iter()
+                            .for_each(|model| println!("{}", format_model_info(model)));
+                    }
+                    Err(e) => eprintln!("❌ {}: {}", provider, e),
+                }
+            }
+        }
         Commands::Status => {
             println!("\r\x1b[2KChecking database connection...");
             let _ = try_interruptible!(
-                create_database_pool(config),
+                connect_store(config),
                 &ctrl_c_state,
                 progress_task,
-                "Failed to create database pool"
+                "Failed to connect to the database"
+            );
+            println!(
+                "\r\x1b[2K✅ Database connection successful ({})",
+                config.database_backend
             );
-            println!("\r\x1b[2K✅ Database connection successful");
             println!("Database URL: {}", config.database_url);
         }
         Commands::Exit => {
@@ -613,6 +1296,120 @@ async fn execute_command(
     Ok(true) // Continue the loop
 }
 
+/// Renders each line of a streamed response as soon as the stream completes it
+async fn print_prompt_stream(
+    prompt_stream: &mut PromptStream,
+    progress_task: &JoinHandle<()>,
+    ctrl_c_state: &Arc<Mutex<CtrlCState>>,
+) -> anyhow::Result<()> {
+    let mut prompt_formatter = PromptFormatter::new();
+    let mut response_started = false;
+    loop {
+        match interruptible!(
+            async { prompt_stream.next().await.transpose() },
+            ctrl_c_state
+        )? {
+            Some(PromptStreamEvent::Token(text)) => {
+                if !response_started {
+                    // stop the spinner so it doesn't draw over the response
+                    progress_task.abort();
+                    println!("\r\x1b[2K✅ Response:");
+                    response_started = true;
+                }
+                prompt_formatter
+                    .format_stream_chunk(&text, 80)
+                    .iter()
+                    .for_each(|line| println!("  │     {}", line));
+            }
+            Some(PromptStreamEvent::Done(response)) => {
+                prompt_formatter
+                    .finish_stream(80)
+                    .iter()
+                    .for_each(|line| println!("  │     {}", line));
+                if let Some(requested_model) = &response.requested_model {
+                    println!(
+                        "\r\x1b[2K⚠️  {} is not a {} model, answered by {} instead",
+                        requested_model, response.provider, response.model
+                    );
+                }
+                println!("\r\x1b[2KPrompt ID: {}", response.id);
+            }
+            None => return Ok(()),
+        }
+    }
+}
+
+/// Renders a saved prompt with its response and everything recorded about it
+fn print_prompt(prompt: &Prompt, prompt_formatter: &mut PromptFormatter) {
+    println!(
+        "  ╭─ [{}] ──────────────────────────────────────────────────────────────────",
+        prompt.id
+    );
+    if let Some(system) = &prompt.params.system {
+        println!("  │ System:");
+        prompt_formatter
+            .format_prompt(system, 80)
+            .iter()
+            .for_each(|line| println!("  │     {}", line));
+    }
+    println!("  │ Prompt:");
+    prompt_formatter
+        .format_prompt(&prompt.prompt, 80)
+        .iter()
+        .for_each(|line| println!("  │     {}", line));
+    println!("  │ Response: ");
+    prompt_formatter
+        .format_prompt(&prompt.response, 80)
+        .iter()
+        .for_each(|line| println!("  │     {}", line));
+    println!("  │ Model: {}", prompt.model);
+    if let Some(requested_model) = &prompt.requested_model {
+        println!(
+            "  │ Requested model: {} (unknown, default used)",
+            requested_model
+        );
+    }
+    println!("  │ Provider: {}", prompt.provider);
+    if let Some(settings) = format_generation_params(&prompt.params) {
+        println!("  │ Settings: {}", settings);
+    }
+    println!("  │ Tokens: {}", format_token_usage(prompt));
+    println!(
+        "  │ Latency: {}",
+        prompt
+            .latency_ms
+            .map(|ms| format!("{} ms", ms))
+            .unwrap_or_else(|| "-".to_string())
+    );
+    println!(
+        "  │ Finish reason: {}",
+        prompt.finish_reason.as_deref().unwrap_or("-")
+    );
+    println!("  │ Attempts: {}", prompt.attempts);
+    if let Some(template_id) = prompt.template_id {
+        println!("  │ Template: #{}", template_id);
+    }
+    if let Some(comparison_id) = &prompt.comparison_id {
+        println!("  │ Comparison: {}", comparison_id);
+    }
+    if !prompt.tags.is_empty() {
+        println!("  │ Tags: {}", prompt.tags.join(", "));
+    }
+    if let Some(route) = &prompt.route {
+        println!(
+            "  │ Route: {} (tried {})",
+            route,
+            prompt
+                .attempted_targets
+                .as_deref()
+                .unwrap_or_default()
+                .join(" → ")
+        );
+    }
+    println!("  │ Timestamp: {}", prompt.created_at.timestamp());
+    println!("  ╰──────────────────────────────────────────────────────────────────────────");
+}
+
 /// Resets the prompt back to normal after a command has finished or is interrupted
 async fn reset_prompt(progress_task: JoinHandle<()>, ctrl_c_state: &Arc<Mutex<CtrlCState>>) {
     progress_task.abort();
@@ -626,6 +1423,238 @@ async fn reset_prompt(progress_task: JoinHandle<()>, ctrl_c_state: &Arc<Mutex<Ct
     }
 }
 
+/// Columns of a comparison, one per target, each with its answer or error and then its usage
+fn format_comparison(comparison: &Comparison, width: usize) -> Vec<String> {
+    let count = comparison.results.len().max(1);
+    let column_width = (width.saturating_sub(3 * (count - 1)) / count).max(20);
+
+    let columns: Vec<Vec<String>> = comparison
+        .results
+        .iter()
+        .map(|result| {
+            let mut column = wrap_text(&result.target.to_string(), column_width);
+            column.push("─".repeat(column_width));
+            match (&result.prompt, &result.error) {
+                (Some(prompt), _) => {
+                    column.extend(wrap_text(&prompt.response, column_width));
+                    column.push(String::new());
+                    column.extend(wrap_text(&format!("Model: {}", prompt.model), column_width));
+                    column.push(format!("Tokens: {}", format_token_usage(prompt)));
+                    if let Some(latency_ms) = prompt.latency_ms {
+                        column.push(format!("Latency: {} ms", latency_ms));
+                    }
+                    if let Some(cost) = prompt.cost {
+                        column.push(format!("Cost: {:.4}", cost));
+                    }
+                    column.push(format!("Prompt ID: {}", prompt.id));
+                }
+                (None, Some(error)) => {
+                    column.extend(wrap_text(&format!("❌ {}", error.error), column_width))
+                }
+                (None, None) => {}
+            }
+            column
+        })
+        .collect();
+
+    let height = columns.iter().map(Vec::len).max().unwrap_or_default();
+    (0..height)
+        .map(|row| {
+            let cells: Vec<String> = columns
+                .iter()
+                .map(|column| {
+                    let cell = column.get(row).map(String::as_str).unwrap_or_default();
+                    let padding = column_width.saturating_sub(cell.chars().count());
+                    format!("{}{}", cell, " ".repeat(padding))
+                })
+                .collect();
+            format!("  {}", cells.join(" │ ")).trim_end().to_string()
+        })
+        .collect()
+}
+
+/// Wraps plain text at a width, words longer than the width are split
+fn wrap_text(text: &str, width: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    for paragraph in text.lines() {
+        let mut line = String::new();
+        for word in paragraph.split_whitespace() {
+            let mut word: Vec<char> = word.chars().collect();
+            while word.len() > width {
+                if !line.is_empty() {
+                    lines.push(std::mem::take(&mut line));
+                }
+                lines.push(word.drain(..width).collect());
+            }
+            let word: String = word.into_iter().collect();
+            if !line.is_empty() && line.chars().count() + 1 + word.chars().count() > width {
+                lines.push(std::mem::take(&mut line));
+            }
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            line.push_str(&word);
+        }
+        lines.push(line);
+    }
+    lines
+}
+
+/// Renders a search match as the matched parts of its prompt and response, matches in bold
+fn print_search_hit(hit: &SearchHit, prompt_formatter: &mut PromptFormatter) {
+    let prompt = &hit.prompt;
+    println!(
+        "  ╭─ [{}] {} · {} · {} · score {:.2}",
+        prompt.id,
+        prompt.provider,
+        prompt.model,
+        prompt.created_at.format("%Y-%m-%d %H:%M"),
+        hit.score
+    );
+    let snippets = [
+        ("Prompt", &hit.prompt_snippet),
+        ("Response", &hit.response_snippet),
+    ];
+    for (label, snippet) in snippets {
+        if let Some(snippet) = snippet {
+            println!("  │ {}:", label);
+            prompt_formatter
+                .format_prompt(snippet, 80)
+                .iter()
+                .for_each(|line| println!("  │     {}", line));
+        }
+    }
+    println!("  ╰─ show {} for the whole prompt", prompt.id);
+}
+
+/// One line summary of a template version and the provider and model it runs with
+fn format_template_summary(template: &PromptTemplate) -> String {
+    let mut line = format!(
+        " 📄 [{}] {} v{}",
+        template.id, template.name, template.version
+    );
+    if let Some(provider) = &template.provider {
+        line.push_str(&format!("  {}", provider));
+        if let Some(model) = &template.model {
+            line.push_str(&format!(" / {}", model));
+        }
+    }
+    let required = template.variables.iter().filter(|v| v.required()).count();
+    line.push_str(&format!(
+        "  ({} variables, {} required)",
+        template.variables.len(),
+        required
+    ));
+    line
+}
+
+/// Sampling settings a prompt was made with, None when it used the provider's defaults
+fn format_generation_params(params: &GenerationParams) -> Option<String> {
+    let mut settings = Vec::new();
+    if let Some(temperature) = params.temperature {
+        settings.push(format!("temperature {}", temperature));
+    }
+    if let Some(top_p) = params.top_p {
+        settings.push(format!("top_p {}", top_p));
+    }
+    if let Some(max_tokens) = params.max_tokens {
+        settings.push(format!("max tokens {}", max_tokens));
+    }
+    if let Some(stop) = &params.stop {
+        settings.push(format!("stop {:?}", stop));
+    }
+    if let Some(seed) = params.seed {
+        settings.push(format!("seed {}", seed));
+    }
+    (!settings.is_empty()).then(|| settings.join(", "))
+}
+
+/// Token counts of a prompt, "-" for prompts made before usage was recorded
+fn format_token_usage(prompt: &Prompt) -> String {
+    let count = |tokens: Option<i32>| tokens.map_or("-".to_string(), |t| t.to_string());
+    let mut usage = format!(
+        "in {} / out {}",
+        count(prompt.input_tokens),
+        count(prompt.output_tokens)
+    );
+
+    let extra: Vec<String> = [
+        ("reasoning", prompt.reasoning_tokens),
+        ("cached", prompt.cached_tokens),
+    ]
+    .iter()
+    .filter_map(|(label, tokens)| tokens.map(|t| format!("{} {}", label, t)))
+    .collect();
+    if !extra.is_empty() {
+        usage.push_str(&format!(" ({})", extra.join(", ")));
+    }
+
+    usage
+}
+
+/// Lines of a usage report table, one row per group followed by the total
+fn format_usage_table(report: &UsageReport) -> Vec<String> {
+    let label = match report.group_by {
+        UsageGroupBy::Provider => "Provider",
+        UsageGroupBy::Model => "Model",
+        UsageGroupBy::Day => "Day",
+    };
+    let width = report
+        .rows
+        .iter()
+        .map(|row| row.key.len())
+        .chain([label.len(), "Total".len()])
+        .max()
+        .unwrap_or_default();
+
+    let format_row = |key: &str, summary: &UsageSummary| {
+        format!(
+            "  {:<width$}  {:>8}  {:>13}  {:>13}  {:>12.4}",
+            key, summary.prompts, summary.input_tokens, summary.output_tokens, summary.cost
+        )
+    };
+
+    let mut lines = vec![format!(
+        "  {:<width$}  {:>8}  {:>13}  {:>13}  {:>12}",
+        label,
+        "Prompts",
+        "Input tokens",
+        "Output tokens",
+        format!("Cost ({})", report.currency)
+    )];
+    lines.extend(report.rows.iter().map(|row| format_row(&row.key, row)));
+    lines.push(format!("  {}", "─".repeat(width + 56)));
+    lines.push(format_row("Total", &report.total));
+
+    lines
+}
+
+/// One line summary of a budget's spend in its current window
+fn format_budget_status(status: &BudgetStatus) -> String {
+    format!(
+        " {} {} {}: {:.4} of {:.2} {} spent, resets {}",
+        if status.exceeded { "❌" } else { "✅" },
+        status.budget.window,
+        status.budget.scope,
+        status.spent,
+        status.budget.limit,
+        status.currency,
+        status.resets_at.format("%Y-%m-%d %H:%M UTC")
+    )
+}
+
+/// A model's id followed by its display name and creation date when the provider reports them
+fn format_model_info(model: &ModelInfo) -> String {
+    let mut line = format!(" - {}", model.id);
+    if let Some(display_name) = &model.display_name {
+        line.push_str(&format!("  {}", display_name));
+    }
+    if let Some(created_at) = model.created_at {
+        line.push_str(&format!("  ({})", created_at.format("%Y-%m-%d")));
+    }
+    line
+}
+
 /// Handles commands like `prompt -p "what is 2+2?"`
 fn parse_quoted_args(input: &str) -> Vec<String> {
     let mut args = Vec::new();
@@ -679,12 +1708,36 @@ fn parse_quoted_args(input: &str) -> Vec<String> {
 /// Prints help message
 fn show_help() {
     println!("  init-db                                         Initialize the database");
-    println!("  list                                            List all prompts");
-    println!("  get-providers                                   Get available model providers");
+    println!("  list [-l <limit>] [-r <provider>] [-m <model>] [-s <since>] [-c <conversation>]");
+    println!("       [--tag <tag>] [--cursor <cursor>]          List prompts, newest first");
+    println!("  show <id>                                       Show a single prompt");
+    println!("  search <words> [-l <limit>]                     Search prompts and responses");
+    println!("  get-providers                                   Get available model providers and routes");
     println!(
         "  get-models -r <provider>                        Get available models for a provider"
     );
-    println!("  prompt -p <prompt> -r <provider> [-m <model>]   Create a new prompt");
+    println!("  prompt -p <prompt> -r <provider> [-m <model>] [--model-mode strict|lenient] [--tag <tag>]");
+    println!("                                                  Create a new prompt");
+    println!(
+        "  chat -r <provider> [-m <model>] [-c <id>] [-t <title>] [--model-mode strict|lenient]"
+    );
+    println!("                                                  Start or resume a conversation, /exit to leave");
+    println!("    prompt and chat also take --system <text> --temperature <t> --top-p <p>");
+    println!("    --max-tokens <n> --stop <text> (repeatable) --seed <n>");
+    println!("  compare -p <prompt> -r <provider[:model]> -r <provider[:model]> ...");
+    println!(
+        "                                                  Compare providers' answers side by side"
+    );
+    println!("  template list                                   List prompt templates");
+    println!(
+        "  template show <id|name>                         Show a template's text and variables"
+    );
+    println!("  template run <id|name> [--var <key>=<value>] [-r <provider>] [-m <model>]");
+    println!("                                                  Run a template, also takes the prompt settings");
+    println!("  usage [-f <from>] [-t <to>] [-g provider|model|day]");
+    println!("                                                  Show token usage and spend");
+    println!("  budgets                                         Show spend against each budget");
+    println!("  refresh-models [-r <provider>]                  Fetch model lists again instead of using the cache");
     println!("  status                                          Show database connection status");
     println!("  help                                            Show this help message");
     println!("  exit                                            Exit the application");
@@ -693,6 +1746,20 @@ fn show_help() {
     println!("  prompt -p \"What is 2 + 2?\" -r anthropic");
     println!("  prompt -p \"What is 2 + 2?\" -r anthropic -m claude-sonnet-4-20250514");
     println!("  get-models -r anthropic");
+    println!("  get-models -r ollama");
+    println!("  prompt -p \"What is 2 + 2?\" -r openai-compatible -m qwen2.5-7b-instruct");
+    println!("  chat -r openai -t \"Trip planning\"");
+    println!("  chat -r anthropic -c 3");
+    println!("  prompt -p \"What is 2 + 2?\" -r openai -m gpt4o --model-mode strict");
+    println!("  prompt -p \"Name a colour\" -r openai --system \"Answer in one word\" --temperature 0.2 --seed 7");
+    println!("  prompt -p \"What is 2 + 2?\" -r primary          (a route from ROUTES_FILE)");
+    println!(
+        "  compare -p \"Explain lifetimes\" -r anthropic:claude-sonnet-4-20250514 -r openai:gpt-4o"
+    );
+    println!("  template run summarise --var topic=\"borrow checker\" --var words=50");
+    println!("  list -r anthropic -s 2025-01-01 -l 10");
+    println!("  search kafka consumer lag");
+    println!("  usage -f 2025-01-01 -t 2025-01-31 -g model");
 }
 
 #[cfg(test)]
@@ -762,6 +1829,387 @@ mod tests {
         assert_eq!(result, vec!["test", "quoted string", "normal"]);
     }
 
+    #[test]
+    fn test_parse_chat_command() {
+        let cli = Cli::try_parse_from(["prompt-cli", "chat", "-r", "openai", "-c", "3"]).unwrap();
+        match cli.command {
+            Commands::Chat {
+                provider,
+                model,
+                conversation,
+                title,
+                model_mode,
+                generation,
+            } => {
+                assert_eq!(provider, "openai");
+                assert_eq!(model, None);
+                assert_eq!(conversation, Some(3));
+                assert_eq!(title, None);
+                assert_eq!(model_mode, ModelMode::Lenient);
+                assert_eq!(generation, GenerationArgs::default());
+            }
+            _ => panic!("expected chat command"),
+        }
+    }
+
+    #[test]
+    fn test_parse_prompt_command_model_mode() {
+        let cli = Cli::try_parse_from([
+            "prompt-cli",
+            "prompt",
+            "-p",
+            "hi",
+            "-r",
+            "openai",
+            "-m",
+            "gpt4o",
+            "--model-mode",
+            "strict",
+        ])
+        .unwrap();
+        match cli.command {
+            Commands::Prompt { model_mode, .. } => assert_eq!(model_mode, ModelMode::Strict),
+            _ => panic!("expected prompt command"),
+        }
+    }
+
+    #[test]
+    fn test_parse_generation_args() {
+        let cli = Cli::try_parse_from([
+            "prompt-cli",
+            "prompt",
+            "-p",
+            "hi",
+            "-r",
+            "openai",
+            "--system",
+            "Be brief",
+            "--temperature",
+            "0.2",
+            "--max-tokens",
+            "200",
+            "--stop",
+            "END",
+            "--stop",
+            "STOP",
+        ])
+        .unwrap();
+        let Commands::Prompt { generation, .. } = cli.command else {
+            panic!("expected prompt command");
+        };
+
+        let params = GenerationParams::from(generation);
+        assert_eq!(params.system.as_deref(), Some("Be brief"));
+        assert_eq!(params.temperature, Some(0.2));
+        assert_eq!(params.max_tokens, Some(200));
+        assert_eq!(
+            params.stop,
+            Some(vec!["END".to_string(), "STOP".to_string()])
+        );
+        assert_eq!(params.top_p, None);
+        assert_eq!(
+            format_generation_params(&params).unwrap(),
+            "temperature 0.2, max tokens 200, stop [\"END\", \"STOP\"]"
+        );
+        assert_eq!(format_generation_params(&GenerationParams::default()), None);
+    }
+
+    #[test]
+    fn test_parse_var() {
+        assert_eq!(
+            parse_var("topic=borrow checker"),
+            Ok(("topic".to_string(), "borrow checker".to_string()))
+        );
+        assert_eq!(
+            parse_var("query=a=b"),
+            Ok(("query".to_string(), "a=b".to_string()))
+        );
+        assert_eq!(
+            parse_var("empty="),
+            Ok(("empty".to_string(), String::new()))
+        );
+        assert!(parse_var("topic").is_err());
+        assert!(parse_var("=value").is_err());
+    }
+
+    #[test]
+    fn test_parse_template_run_command() {
+        let args = parse_quoted_args(
+            "template run summarise --var topic=\"borrow checker\" --var words=50 -r openai",
+        );
+        let mut full_args = vec!["prompt-cli"];
+        full_args.extend(args.iter().map(|s| s.as_str()));
+        let cli = Cli::try_parse_from(full_args).unwrap();
+
+        let Commands::Template {
+            command:
+                TemplateCommands::Run {
+                    template,
+                    vars,
+                    provider,
+                    model,
+                    ..
+                },
+        } = cli.command
+        else {
+            panic!("expected template run command");
+        };
+        assert_eq!(template, "summarise");
+        assert_eq!(
+            vars,
+            vec![
+                ("topic".to_string(), "borrow checker".to_string()),
+                ("words".to_string(), "50".to_string())
+            ]
+        );
+        assert_eq!(provider.as_deref(), Some("openai"));
+        assert_eq!(model, None);
+    }
+
+    #[test]
+    fn test_parse_show_command() {
+        let cli = Cli::try_parse_from(["prompt-cli", "show", "12"]).unwrap();
+        assert!(matches!(cli.command, Commands::Show { id: 12 }));
+
+        assert!(Cli::try_parse_from(["prompt-cli", "show", "latest"]).is_err());
+        assert!(Cli::try_parse_from(["prompt-cli", "show"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_search_command() {
+        let cli =
+            Cli::try_parse_from(["prompt-cli", "search", "kafka", "consumer", "-l", "3"]).unwrap();
+        let Commands::Search { query, limit } = cli.command else {
+            panic!("expected search command");
+        };
+        assert_eq!(query, vec!["kafka", "consumer"]);
+        assert_eq!(limit, 3);
+
+        assert!(Cli::try_parse_from(["prompt-cli", "search"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_list_command() {
+        let cli = Cli::try_parse_from(["prompt-cli", "list"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Commands::List {
+                limit: 20,
+                provider: None,
+                since: None,
+                ..
+            }
+        ));
+
+        let cli = Cli::try_parse_from([
+            "prompt-cli",
+            "list",
+            "--limit",
+            "5",
+            "--provider",
+            "anthropic",
+            "--since",
+            "2025-01-01",
+            "--tag",
+            "eval",
+        ])
+        .unwrap();
+        let Commands::List {
+            limit,
+            provider,
+            since,
+            tag,
+            ..
+        } = cli.command
+        else {
+            panic!("expected list command");
+        };
+        assert_eq!(limit, 5);
+        assert_eq!(provider.as_deref(), Some("anthropic"));
+        assert_eq!(since.as_deref(), Some("2025-01-01"));
+        assert_eq!(tag.as_deref(), Some("eval"));
+    }
+
+    #[test]
+    fn test_parse_compare_command() {
+        let cli = Cli::try_parse_from([
+            "prompt-cli",
+            "compare",
+            "-p",
+            "What is 2 + 2?",
+            "-r",
+            "anthropic:claude-sonnet-4-20250514",
+            "-r",
+            "openai",
+        ])
+        .unwrap();
+        let Commands::Compare { targets, .. } = cli.command else {
+            panic!("expected compare command");
+        };
+        assert_eq!(
+            targets,
+            vec![
+                "anthropic:claude-sonnet-4-20250514".parse().unwrap(),
+                "openai".parse::<CompareTarget>().unwrap()
+            ]
+        );
+
+        assert!(Cli::try_parse_from(["prompt-cli", "compare", "-p", "hi"]).is_err());
+    }
+
+    #[test]
+    fn test_wrap_text() {
+        assert_eq!(
+            wrap_text("the quick brown fox", 10),
+            vec!["the quick", "brown fox"]
+        );
+        assert_eq!(wrap_text("abcdefghijkl", 5), vec!["abcde", "fghij", "kl"]);
+        assert_eq!(wrap_text("one\n\ntwo", 10), vec!["one", "", "two"]);
+    }
+
+    #[test]
+    fn test_format_comparison_columns() {
+        let failed = |target: &str, error: &str| kubellm_core::ComparisonResult {
+            target: target.parse().unwrap(),
+            prompt: None,
+            error: Some(kubellm_core::ErrorResponse::new(error)),
+        };
+        let comparison = Comparison {
+            comparison_id: "c0ffee".to_string(),
+            results: vec![
+                failed("anthropic", "rate limited"),
+                failed("openai:gpt-4o", "OPENAI_KEY is not set"),
+            ],
+        };
+
+        let lines = format_comparison(&comparison, 50);
+        assert_eq!(lines[0], format!("  {:<23} │ openai:gpt-4o", "anthropic"));
+        assert!(lines[2].starts_with("  ❌ rate limited"));
+        assert!(lines[2].ends_with("│ ❌ OPENAI_KEY is not set"));
+    }
+
+    #[test]
+    fn test_format_token_usage() {
+        let mut prompt = Prompt {
+            id: 1,
+            prompt: "hi".to_string(),
+            model: "gpt-4o".to_string(),
+            provider: "OpenAI".to_string(),
+            response: "hello".to_string(),
+            conversation_id: None,
+            sequence: None,
+            input_tokens: None,
+            output_tokens: None,
+            reasoning_tokens: None,
+            cached_tokens: None,
+            latency_ms: None,
+            finish_reason: None,
+            cost: None,
+            user_id: None,
+            attempts: 1,
+            cache_hit: false,
+            route: None,
+            attempted_targets: None,
+            requested_model: None,
+            params: GenerationParams::default(),
+            template_id: None,
+            comparison_id: None,
+            comparison_index: None,
+            tags: Vec::new(),
+            created_at: chrono::Utc::now(),
+        };
+        assert_eq!(format_token_usage(&prompt), "in - / out -");
+
+        prompt.input_tokens = Some(12);
+        prompt.output_tokens = Some(40);
+        prompt.reasoning_tokens = Some(32);
+        assert_eq!(format_token_usage(&prompt), "in 12 / out 40 (reasoning 32)");
+    }
+
+    #[test]
+    fn test_parse_usage_command() {
+        let cli =
+            Cli::try_parse_from(["prompt-cli", "usage", "-f", "2025-01-01", "-g", "day"]).unwrap();
+        match cli.command {
+            Commands::Usage { from, to, group_by } => {
+                assert_eq!(from, Some("2025-01-01".to_string()));
+                assert_eq!(to, None);
+                assert_eq!(group_by, UsageGroupBy::Day);
+            }
+            _ => panic!("expected usage command"),
+        }
+
+        assert!(Cli::try_parse_from(["prompt-cli", "usage", "-g", "week"]).is_err());
+    }
+
+    #[test]
+    fn test_format_model_info() {
+        let model = ModelInfo {
+            id: "claude-sonnet-4-20250514".to_string(),
+            display_name: Some("Claude Sonnet 4".to_string()),
+            created_at: chrono::DateTime::parse_from_rfc3339("2025-05-22T00:00:00Z")
+                .ok()
+                .map(|created_at| created_at.to_utc()),
+        };
+        assert_eq!(
+            format_model_info(&model),
+            " - claude-sonnet-4-20250514  Claude Sonnet 4  (2025-05-22)"
+        );
+        assert_eq!(
+            format_model_info(&ModelInfo::new("llama3.2")),
+            " - llama3.2"
+        );
+    }
+
+    #[test]
+    fn test_format_budget_status() {
+        let resets_at = chrono::DateTime::parse_from_rfc3339("2025-02-01T00:00:00Z")
+            .unwrap()
+            .with_timezone(&chrono::Utc);
+        let status = BudgetStatus {
+            budget: kubellm_core::Budget {
+                scope: kubellm_core::BudgetScope::Provider {
+                    provider: "OpenAI".to_string(),
+                },
+                window: kubellm_core::BudgetWindow::Monthly,
+                limit: 20.0,
+            },
+            currency: "USD".to_string(),
+            spent: 21.5,
+            remaining: 0.0,
+            exceeded: true,
+            window_start: resets_at,
+            resets_at,
+        };
+
+        assert_eq!(
+            format_budget_status(&status),
+            " ❌ monthly provider OpenAI: 21.5000 of 20.00 USD spent, resets 2025-02-01 00:00 UTC"
+        );
+    }
+
+    #[test]
+    fn test_format_usage_table() {
+        let row = UsageSummary {
+            key: "claude-sonnet-4-20250514".to_string(),
+            prompts: 4,
+            input_tokens: 1200,
+            output_tokens: 800,
+            cached_tokens: 0,
+            cost: 0.0156,
+            unpriced_prompts: 0,
+        };
+        let report = UsageReport::new(UsageGroupBy::Model, (None, None), "USD", vec![row]);
+
+        let lines = format_usage_table(&report);
+        assert_eq!(lines.len(), 4);
+        assert!(lines[0].trim_start().starts_with("Model"));
+        assert!(lines[0].ends_with("Cost (USD)"));
+        assert!(lines[1].contains("claude-sonnet-4-20250514"));
+        assert!(lines[3].trim_start().starts_with("Total"));
+        assert!(lines[3].ends_with("0.0156"));
+    }
+
     #[test]
     fn test_input_event_debug() {
         let event = InputEvent::Command("test".to_string());
@@ -6,6 +6,8 @@ use std::sync::OnceLock;
 #[derive(Debug)]
 pub struct CliConfig {
     pub history_file_path: PathBuf,
+    // user prompts are made as, counted against that user's budgets
+    pub user: Option<String>,
 }
 
 static CLI_CONFIG: OnceLock<CliConfig> = OnceLock::new();
@@ -18,7 +20,15 @@ impl CliConfig {
             .map(PathBuf::from)
             .unwrap_or_else(Self::get_history_file_path);
 
-        Ok(CliConfig { history_file_path })
+        let user = env::var("KUBELLM_USER")
+            .or_else(|_| env::var("USER"))
+            .ok()
+            .filter(|user| !user.is_empty());
+
+        Ok(CliConfig {
+            history_file_path,
+            user,
+        })
     }
 
     pub fn get() -> &'static CliConfig {
@@ -61,10 +71,19 @@ mod tests {
             .contains(".kubellm-cli-history"));
     }
 
+    #[test]
+    fn test_from_env_user() {
+        env::set_var("KUBELLM_USER", "nightly-batch");
+        let config = CliConfig::from_env().unwrap();
+        assert_eq!(config.user, Some("nightly-batch".to_string()));
+        env::remove_var("KUBELLM_USER");
+    }
+
     #[test]
     fn test_config_debug() {
         let config = CliConfig {
             history_file_path: PathBuf::from("/test/path"),
+            user: None,
         };
         let debug_str = format!("{:?}", config);
         assert!(debug_str.contains("CliConfig"));
@@ -2,6 +2,7 @@ use kubellm_cli::{
     crate_rustyline_background_loop, create_ctrlc_background_loop, main_loop, CtrlCState,
     InputEvent,
 };
+use kubellm_core::{load_budgets, load_price_table};
 use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
@@ -11,6 +12,13 @@ async fn main() {
     println!("Type 'help' for available commands or 'exit' to quit.");
     println!("Press Ctrl+C twice quickly to force exit.\n");
 
+    // prompts are costed and checked against the budgets, so a broken pricing or budgets
+    // file is reported now rather than part way through a prompt
+    if let Err(e) = load_price_table().and(load_budgets()) {
+        eprintln!("Error: {:#}", e);
+        std::process::exit(1);
+    }
+
     let ctrl_c_state = Arc::new(Mutex::new(CtrlCState::default()));
     let ctrl_c_timeout = Duration::from_secs(2);
 
@@ -24,6 +24,7 @@ pub struct PromptFormatter {
     code_block_single_quote_section: bool,
     width: usize,
     code_block_width: usize,
+    stream_buffer: String,
 }
 
 impl Default for PromptFormatter {
@@ -46,6 +47,7 @@ impl PromptFormatter {
             code_block_single_quote_section: false,
             width: DEFAULT_WIDTH,
             code_block_width: DEFAULT_WIDTH,
+            stream_buffer: String::new(),
         }
     }
 
@@ -59,126 +61,156 @@ impl PromptFormatter {
         self.determine_max_width(text);
 
         for paragraph in text.split('\n') {
-            // empty line
-            if paragraph.trim().is_empty() {
-                self.add_formatted_line(0, String::new());
-                continue;
-            }
+            self.format_paragraph(paragraph);
+        }
 
-            // get the level of indent to preserve for code blocks.
-            let indent_prefix = if paragraph.starts_with("-") { " " } else { "" };
-            let leading_whitespace = paragraph.len() - paragraph.trim_start().len();
-            let unformatted_indent = &paragraph[..leading_whitespace];
+        &self.formatted_prompt
+    }
 
-            let mut indent = String::with_capacity(
-                unformatted_indent.len() + START_CODE_BLOCK_SECTION_ESC.len(),
-            );
-            if self.code_block_section {
-                indent.push_str(START_CODE_BLOCK_SECTION_ESC);
-            }
-            indent.push_str(unformatted_indent);
+    /// Buffers streamed text and formats any lines it completes, returning only the new lines
+    pub fn format_stream_chunk(&mut self, chunk: &str, width: usize) -> Vec<String> {
+        self.width = width;
+        self.code_block_width = width;
+        self.formatted_prompt.clear();
+        self.stream_buffer.push_str(chunk);
 
-            let mut current_line = String::with_capacity(self.width);
-            current_line.push_str(&indent);
-            // need this so that escape characters don't count towards the length of the line
-            let mut unformatted_line = String::with_capacity(self.width);
-            unformatted_line.push_str(unformatted_indent);
+        while let Some(newline) = self.stream_buffer.find('\n') {
+            let paragraph: String = self.stream_buffer.drain(..=newline).collect();
+            self.format_paragraph(&paragraph[..paragraph.len() - 1]);
+        }
 
-            self.single_line_comment_section = false;
-            for word in paragraph.split_whitespace() {
-                // handle line wrap
-                let width_to_use = if self.code_block_section {
-                    self.code_block_width
-                } else {
-                    self.width
-                };
+        std::mem::take(&mut self.formatted_prompt)
+    }
 
-                // width of line + 2 for space between words and 1 for the end quote
-                let mut line_len = unformatted_line.len() + word.len() + 2;
-                if self.code_block_single_quote_section || self.code_block_double_quote_section {
-                    line_len += 1;
-                }
+    /// Formats whatever is left of a streamed response once the stream has ended
+    pub fn finish_stream(&mut self, width: usize) -> Vec<String> {
+        self.width = width;
+        self.formatted_prompt.clear();
 
-                // wrap the line if the next word will make the line longer than the width
-                if line_len > width_to_use && !unformatted_line.is_empty() {
-                    // add closing quotes to end of line
-                    if self.code_block_single_quote_section || self.code_block_double_quote_section
-                    {
-                        current_line.push('"');
-                        unformatted_line.push('"');
-                    }
-                    self.add_formatted_line(unformatted_line.len(), current_line);
+        if !self.stream_buffer.is_empty() {
+            let paragraph = std::mem::take(&mut self.stream_buffer);
+            self.format_paragraph(&paragraph);
+        }
 
-                    // start new lines with the same level of indent
-                    current_line = String::with_capacity(width_to_use);
-                    current_line.push_str(&indent);
-                    unformatted_line = String::with_capacity(width_to_use);
-                    unformatted_line.push_str(unformatted_indent);
+        std::mem::take(&mut self.formatted_prompt)
+    }
 
-                    self.handle_code_block_line_wrap(&mut current_line, &mut unformatted_line);
-                    current_line.push_str(indent_prefix);
+    /// Formats a single line of text, code block and emphasis state carries over between lines
+    fn format_paragraph(&mut self, paragraph: &str) {
+        // empty line
+        if paragraph.trim().is_empty() {
+            self.add_formatted_line(0, String::new());
+            return;
+        }
 
-                    if self.code_block_single_quote_section || self.code_block_double_quote_section
-                    {
-                        current_line.push_str(START_CODE_BLOCK_SECTION_ESC);
-                        current_line.push('+');
-                        current_line.push_str(QUOTED_CODE_BLOCK_TEXT_COLOR_ESC);
-                        current_line.push_str(" \"");
-                        unformatted_line.push_str("+ \"");
-                    }
-                }
+        // get the level of indent to preserve for code blocks.
+        let indent_prefix = if paragraph.starts_with("-") { " " } else { "" };
+        let leading_whitespace = paragraph.len() - paragraph.trim_start().len();
+        let unformatted_indent = &paragraph[..leading_whitespace];
 
-                // add space between words
-                if !current_line.is_empty() {
-                    current_line.push(' ');
-                    unformatted_line.push(' ');
-                }
+        let mut indent =
+            String::with_capacity(unformatted_indent.len() + START_CODE_BLOCK_SECTION_ESC.len());
+        if self.code_block_section {
+            indent.push_str(START_CODE_BLOCK_SECTION_ESC);
+        }
+        indent.push_str(unformatted_indent);
+
+        let mut current_line = String::with_capacity(self.width);
+        current_line.push_str(&indent);
+        // need this so that escape characters don't count towards the length of the line
+        let mut unformatted_line = String::with_capacity(self.width);
+        unformatted_line.push_str(unformatted_indent);
+
+        self.single_line_comment_section = false;
+        for word in paragraph.split_whitespace() {
+            // handle line wrap
+            let width_to_use = if self.code_block_section {
+                self.code_block_width
+            } else {
+                self.width
+            };
 
-                let mut processed_word = word.to_owned();
-                // handle bold
-                if processed_word.contains("**") && !self.code_block_section {
-                    self.handle_bold_formatting(&mut processed_word);
-                }
+            // width of line + 2 for space between words and 1 for the end quote
+            let mut line_len = unformatted_line.len() + word.len() + 2;
+            if self.code_block_single_quote_section || self.code_block_double_quote_section {
+                line_len += 1;
+            }
 
-                //  code block formatting
-                if processed_word.contains("```") {
-                    self.handle_code_block_formatting(&mut processed_word);
-                    self.bold_section = false;
-                    processed_word.insert_str(processed_word.len(), NON_BOLD_TEXT_ESC);
-                    if self.code_block_section {
-                        current_line.clear();
-                        unformatted_line.clear();
-                        //self.formatted_prompt.push("\x1b[1A".to_owned());
-                        break;
-                    }
+            // wrap the line if the next word will make the line longer than the width
+            if line_len > width_to_use && !unformatted_line.is_empty() {
+                // add closing quotes to end of line
+                if self.code_block_single_quote_section || self.code_block_double_quote_section {
+                    current_line.push('"');
+                    unformatted_line.push('"');
                 }
+                self.add_formatted_line(unformatted_line.len(), current_line);
 
-                unformatted_line.push_str(&processed_word);
+                // start new lines with the same level of indent
+                current_line = String::with_capacity(width_to_use);
+                current_line.push_str(&indent);
+                unformatted_line = String::with_capacity(width_to_use);
+                unformatted_line.push_str(unformatted_indent);
 
-                // handle comment flags
-                self.handle_comment_flags(&mut current_line, &mut processed_word);
+                self.handle_code_block_line_wrap(&mut current_line, &mut unformatted_line);
+                current_line.push_str(indent_prefix);
 
-                // syntax highlighting when in a code block but not in a comment
-                if self.code_block_section
-                    && !self.single_line_comment_section
-                    && !self.multi_line_comment_section
-                {
-                    self.handle_syntax_highlighting(&mut processed_word)
+                if self.code_block_single_quote_section || self.code_block_double_quote_section {
+                    current_line.push_str(START_CODE_BLOCK_SECTION_ESC);
+                    current_line.push('+');
+                    current_line.push_str(QUOTED_CODE_BLOCK_TEXT_COLOR_ESC);
+                    current_line.push_str(" \"");
+                    unformatted_line.push_str("+ \"");
                 }
+            }
 
-                current_line.push_str(&processed_word);
+            // add space between words
+            if !current_line.is_empty() {
+                current_line.push(' ');
+                unformatted_line.push(' ');
             }
 
-            // don't print a code block formatted line if its the line where we replaced
-            // ```lang with nothing but formatting
-            if self.first_code_block_line {
-                self.first_code_block_line = false;
-            } else {
-                self.add_formatted_line(unformatted_line.len(), current_line);
+            let mut processed_word = word.to_owned();
+            // handle bold
+            if processed_word.contains("**") && !self.code_block_section {
+                self.handle_bold_formatting(&mut processed_word);
             }
+
+            //  code block formatting
+            if processed_word.contains("```") {
+                self.handle_code_block_formatting(&mut processed_word);
+                self.bold_section = false;
+                processed_word.insert_str(processed_word.len(), NON_BOLD_TEXT_ESC);
+                if self.code_block_section {
+                    current_line.clear();
+                    unformatted_line.clear();
+                    //self.formatted_prompt.push("\x1b[1A".to_owned());
+                    break;
+                }
+            }
+
+            unformatted_line.push_str(&processed_word);
+
+            // handle comment flags
+            self.handle_comment_flags(&mut current_line, &mut processed_word);
+
+            // syntax highlighting when in a code block but not in a comment
+            if self.code_block_section
+                && !self.single_line_comment_section
+                && !self.multi_line_comment_section
+            {
+                self.handle_syntax_highlighting(&mut processed_word)
+            }
+
+            current_line.push_str(&processed_word);
         }
 
-        &self.formatted_prompt
+        // don't print a code block formatted line if its the line where we replaced
+        // ```lang with nothing but formatting
+        if self.first_code_block_line {
+            self.first_code_block_line = false;
+        } else {
+            self.add_formatted_line(unformatted_line.len(), current_line);
+        }
     }
 
     fn add_formatted_line(&mut self, unformatted_line_len: usize, mut current_line: String) {
@@ -433,6 +465,7 @@ mod tests {
         assert!(!formatter.code_block_single_quote_section);
         assert_eq!(formatter.width, DEFAULT_WIDTH);
         assert_eq!(formatter.code_block_width, DEFAULT_WIDTH);
+        assert!(formatter.stream_buffer.is_empty());
     }
 
     #[test]
@@ -526,6 +559,42 @@ mod tests {
         assert_eq!(result2[0], "Second text");
     }
 
+    #[test]
+    fn test_format_stream_chunk_waits_for_newline() {
+        let mut formatter = PromptFormatter::new();
+        assert!(formatter.format_stream_chunk("Hello ", 80).is_empty());
+
+        let lines = formatter.format_stream_chunk("world\nSecond", 80);
+        assert_eq!(lines, vec!["Hello world".to_string()]);
+
+        let lines = formatter.finish_stream(80);
+        assert_eq!(lines, vec!["Second".to_string()]);
+    }
+
+    #[test]
+    fn test_format_stream_matches_format_prompt() {
+        let text = "Intro with **bold** text\n\n```rust\nfn main() {}\n```\nDone";
+
+        let mut streamed = Vec::new();
+        let mut stream_formatter = PromptFormatter::new();
+        for chunk in text.as_bytes().chunks(3) {
+            let chunk = std::str::from_utf8(chunk).unwrap();
+            streamed.extend(stream_formatter.format_stream_chunk(chunk, 80));
+        }
+        streamed.extend(stream_formatter.finish_stream(80));
+
+        let mut formatter = PromptFormatter::new();
+        let expected = formatter.format_prompt(text, 80).clone();
+        assert_eq!(streamed, expected);
+    }
+
+    #[test]
+    fn test_finish_stream_empty_buffer() {
+        let mut formatter = PromptFormatter::new();
+        formatter.format_stream_chunk("Line\n", 80);
+        assert!(formatter.finish_stream(80).is_empty());
+    }
+
     #[test]
     fn test_debug_implementation() {
         let formatter = PromptFormatter::new();
@@ -0,0 +1,8 @@
+CREATE TABLE IF NOT EXISTS prompts (
+    id INTEGER PRIMARY KEY AUTO_INCREMENT,
+    prompt TEXT NOT NULL,
+    response MEDIUMTEXT NOT NULL,
+    model VARCHAR(255) NOT NULL,
+    provider VARCHAR(255) NOT NULL,
+    created_at DATETIME NOT NULL
+);
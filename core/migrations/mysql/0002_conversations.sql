@@ -0,0 +1,12 @@
+CREATE TABLE IF NOT EXISTS conversations (
+    id INTEGER PRIMARY KEY AUTO_INCREMENT,
+    title VARCHAR(255) NOT NULL,
+    created_at DATETIME NOT NULL,
+    updated_at DATETIME NOT NULL
+);
+
+ALTER TABLE prompts
+    ADD COLUMN conversation_id INTEGER NULL,
+    ADD COLUMN sequence INTEGER NULL,
+    ADD INDEX idx_prompts_conversation (conversation_id, sequence),
+    ADD FOREIGN KEY (conversation_id) REFERENCES conversations(id);
@@ -0,0 +1,7 @@
+ALTER TABLE prompts
+    ADD COLUMN input_tokens INTEGER NULL,
+    ADD COLUMN output_tokens INTEGER NULL,
+    ADD COLUMN reasoning_tokens INTEGER NULL,
+    ADD COLUMN cached_tokens INTEGER NULL,
+    ADD COLUMN latency_ms BIGINT NULL,
+    ADD COLUMN finish_reason VARCHAR(64) NULL;
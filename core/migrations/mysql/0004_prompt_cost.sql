@@ -0,0 +1 @@
+ALTER TABLE prompts ADD COLUMN cost DOUBLE NULL;
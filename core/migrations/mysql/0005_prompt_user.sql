@@ -0,0 +1,4 @@
+-- spend is summed per user and time window when checking budgets
+ALTER TABLE prompts
+    ADD COLUMN user_id VARCHAR(255) NULL,
+    ADD INDEX idx_prompts_created_at (created_at);
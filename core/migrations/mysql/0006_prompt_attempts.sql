@@ -0,0 +1 @@
+ALTER TABLE prompts ADD COLUMN attempts INTEGER NOT NULL DEFAULT 1;
@@ -0,0 +1,4 @@
+-- attempted_targets is a json array of the provider/model targets tried
+ALTER TABLE prompts
+    ADD COLUMN route VARCHAR(255) NULL,
+    ADD COLUMN attempted_targets TEXT NULL;
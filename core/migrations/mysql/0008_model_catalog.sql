@@ -0,0 +1,9 @@
+-- model lists cached by the model catalog, one row per model
+CREATE TABLE IF NOT EXISTS model_catalog (
+    provider VARCHAR(255) NOT NULL,
+    model_id VARCHAR(255) NOT NULL,
+    display_name VARCHAR(255) NULL,
+    created_at DATETIME NULL,
+    fetched_at DATETIME NOT NULL,
+    PRIMARY KEY (provider, model_id)
+);
@@ -0,0 +1 @@
+ALTER TABLE prompts ADD COLUMN requested_model VARCHAR(255) NULL;
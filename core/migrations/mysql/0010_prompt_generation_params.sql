@@ -0,0 +1,8 @@
+-- stop_sequences is a json array
+ALTER TABLE prompts
+    ADD COLUMN system_prompt TEXT NULL,
+    ADD COLUMN temperature FLOAT NULL,
+    ADD COLUMN top_p FLOAT NULL,
+    ADD COLUMN max_tokens INT UNSIGNED NULL,
+    ADD COLUMN stop_sequences TEXT NULL,
+    ADD COLUMN seed BIGINT NULL;
@@ -0,0 +1,16 @@
+-- every saved version of a prompt template, variables are stored as a json array
+CREATE TABLE IF NOT EXISTS prompt_templates (
+    id INTEGER PRIMARY KEY AUTO_INCREMENT,
+    name VARCHAR(255) NOT NULL,
+    version INTEGER NOT NULL,
+    template TEXT NOT NULL,
+    variables TEXT NOT NULL,
+    provider VARCHAR(255) NULL,
+    model VARCHAR(255) NULL,
+    created_at DATETIME NOT NULL,
+    UNIQUE KEY uq_prompt_templates_version (name, version)
+);
+
+ALTER TABLE prompts
+    ADD COLUMN template_id INTEGER NULL,
+    ADD FOREIGN KEY (template_id) REFERENCES prompt_templates(id);
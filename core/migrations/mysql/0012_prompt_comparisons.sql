@@ -0,0 +1,3 @@
+ALTER TABLE prompts
+    ADD COLUMN comparison_id CHAR(36) NULL,
+    ADD INDEX idx_prompts_comparison (comparison_id);
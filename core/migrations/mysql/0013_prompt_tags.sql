@@ -0,0 +1,2 @@
+-- tags are stored as a json array
+ALTER TABLE prompts ADD COLUMN tags TEXT NULL;
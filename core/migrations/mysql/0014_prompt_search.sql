@@ -0,0 +1,2 @@
+-- building the fulltext index reads every prompt, this can take a while on a large table
+ALTER TABLE prompts ADD FULLTEXT INDEX ft_prompts_text (prompt, response);
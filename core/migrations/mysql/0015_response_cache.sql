@@ -0,0 +1,16 @@
+-- responses cached under the hash of the request they answered
+CREATE TABLE IF NOT EXISTS response_cache (
+    cache_key CHAR(64) NOT NULL PRIMARY KEY,
+    provider VARCHAR(255) NOT NULL,
+    model VARCHAR(255) NOT NULL,
+    response MEDIUMTEXT NOT NULL,
+    input_tokens INTEGER NULL,
+    output_tokens INTEGER NULL,
+    reasoning_tokens INTEGER NULL,
+    cached_tokens INTEGER NULL,
+    finish_reason VARCHAR(255) NULL,
+    created_at DATETIME NOT NULL
+);
+
+-- prompts answered from the response cache instead of the provider
+ALTER TABLE prompts ADD COLUMN cache_hit BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,17 @@
+-- background work for the worker, payload and result are json
+CREATE TABLE IF NOT EXISTS tasks (
+    id INTEGER PRIMARY KEY AUTO_INCREMENT,
+    task_type VARCHAR(64) NOT NULL,
+    payload MEDIUMTEXT NOT NULL,
+    status VARCHAR(16) NOT NULL,
+    attempts INTEGER NOT NULL DEFAULT 0,
+    max_attempts INTEGER NOT NULL,
+    run_at DATETIME NOT NULL,
+    worker_id VARCHAR(255) NULL,
+    lease_expires_at DATETIME NULL,
+    result MEDIUMTEXT NULL,
+    error TEXT NULL,
+    created_at DATETIME NOT NULL,
+    updated_at DATETIME NOT NULL,
+    INDEX idx_tasks_claim (status, run_at)
+);
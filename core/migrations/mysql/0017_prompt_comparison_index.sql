@@ -0,0 +1,2 @@
+-- position of the target that answered in its comparison's list of targets
+ALTER TABLE prompts ADD COLUMN comparison_index INTEGER NULL;
@@ -0,0 +1,70 @@
+-- the schema the MySQL migrations 0001 to 0014 build, in one migration
+CREATE TABLE IF NOT EXISTS conversations (
+    id BIGSERIAL PRIMARY KEY,
+    title VARCHAR(255) NOT NULL,
+    created_at TIMESTAMP NOT NULL,
+    updated_at TIMESTAMP NOT NULL
+);
+
+-- every saved version of a prompt template, variables are stored as a json array
+CREATE TABLE IF NOT EXISTS prompt_templates (
+    id BIGSERIAL PRIMARY KEY,
+    name VARCHAR(255) NOT NULL,
+    version INTEGER NOT NULL,
+    template TEXT NOT NULL,
+    variables TEXT NOT NULL,
+    provider VARCHAR(255) NULL,
+    model VARCHAR(255) NULL,
+    created_at TIMESTAMP NOT NULL,
+    CONSTRAINT uq_prompt_templates_version UNIQUE (name, version)
+);
+
+-- attempted_targets, stop_sequences and tags are json arrays
+CREATE TABLE IF NOT EXISTS prompts (
+    id BIGSERIAL PRIMARY KEY,
+    prompt TEXT NOT NULL,
+    response TEXT NOT NULL,
+    model VARCHAR(255) NOT NULL,
+    provider VARCHAR(255) NOT NULL,
+    conversation_id BIGINT NULL REFERENCES conversations(id),
+    sequence INTEGER NULL,
+    input_tokens INTEGER NULL,
+    output_tokens INTEGER NULL,
+    reasoning_tokens INTEGER NULL,
+    cached_tokens INTEGER NULL,
+    latency_ms BIGINT NULL,
+    finish_reason VARCHAR(64) NULL,
+    cost DOUBLE PRECISION NULL,
+    user_id VARCHAR(255) NULL,
+    attempts INTEGER NOT NULL DEFAULT 1,
+    route VARCHAR(255) NULL,
+    attempted_targets TEXT NULL,
+    requested_model VARCHAR(255) NULL,
+    system_prompt TEXT NULL,
+    temperature REAL NULL,
+    top_p REAL NULL,
+    max_tokens BIGINT NULL,
+    stop_sequences TEXT NULL,
+    seed BIGINT NULL,
+    template_id BIGINT NULL REFERENCES prompt_templates(id),
+    comparison_id VARCHAR(36) NULL,
+    tags TEXT NULL,
+    created_at TIMESTAMP NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_prompts_conversation ON prompts (conversation_id, sequence);
+CREATE INDEX IF NOT EXISTS idx_prompts_created_at ON prompts (created_at);
+CREATE INDEX IF NOT EXISTS idx_prompts_comparison ON prompts (comparison_id);
+-- searches must use the same expression for the index to be used
+CREATE INDEX IF NOT EXISTS ft_prompts_text ON prompts
+    USING GIN (to_tsvector('english', prompt || ' ' || response));
+
+-- model lists cached by the model catalog, one row per model
+CREATE TABLE IF NOT EXISTS model_catalog (
+    provider VARCHAR(255) NOT NULL,
+    model_id VARCHAR(255) NOT NULL,
+    display_name VARCHAR(255) NULL,
+    created_at TIMESTAMP NULL,
+    fetched_at TIMESTAMP NOT NULL,
+    PRIMARY KEY (provider, model_id)
+);
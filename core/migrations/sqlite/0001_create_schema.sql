@@ -0,0 +1,91 @@
+-- the schema the MySQL migrations 0001 to 0014 build, in one migration
+CREATE TABLE IF NOT EXISTS conversations (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    title TEXT NOT NULL,
+    created_at DATETIME NOT NULL,
+    updated_at DATETIME NOT NULL
+);
+
+-- every saved version of a prompt template, variables are stored as a json array
+CREATE TABLE IF NOT EXISTS prompt_templates (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL,
+    version INTEGER NOT NULL,
+    template TEXT NOT NULL,
+    variables TEXT NOT NULL,
+    provider TEXT NULL,
+    model TEXT NULL,
+    created_at DATETIME NOT NULL,
+    UNIQUE (name, version)
+);
+
+-- attempted_targets, stop_sequences and tags are json arrays
+CREATE TABLE IF NOT EXISTS prompts (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    prompt TEXT NOT NULL,
+    response TEXT NOT NULL,
+    model TEXT NOT NULL,
+    provider TEXT NOT NULL,
+    conversation_id INTEGER NULL REFERENCES conversations(id),
+    sequence INTEGER NULL,
+    input_tokens INTEGER NULL,
+    output_tokens INTEGER NULL,
+    reasoning_tokens INTEGER NULL,
+    cached_tokens INTEGER NULL,
+    latency_ms INTEGER NULL,
+    finish_reason TEXT NULL,
+    cost REAL NULL,
+    user_id TEXT NULL,
+    attempts INTEGER NOT NULL DEFAULT 1,
+    route TEXT NULL,
+    attempted_targets TEXT NULL,
+    requested_model TEXT NULL,
+    system_prompt TEXT NULL,
+    temperature REAL NULL,
+    top_p REAL NULL,
+    max_tokens INTEGER NULL,
+    stop_sequences TEXT NULL,
+    seed INTEGER NULL,
+    template_id INTEGER NULL REFERENCES prompt_templates(id),
+    comparison_id TEXT NULL,
+    tags TEXT NULL,
+    created_at DATETIME NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_prompts_conversation ON prompts (conversation_id, sequence);
+CREATE INDEX IF NOT EXISTS idx_prompts_created_at ON prompts (created_at);
+CREATE INDEX IF NOT EXISTS idx_prompts_comparison ON prompts (comparison_id);
+
+-- the fulltext index, kept in step with the prompts table by the triggers below
+CREATE VIRTUAL TABLE IF NOT EXISTS prompts_fts USING fts5(
+    prompt,
+    response,
+    content = 'prompts',
+    content_rowid = 'id',
+    tokenize = 'porter unicode61'
+);
+
+CREATE TRIGGER IF NOT EXISTS prompts_fts_insert AFTER INSERT ON prompts BEGIN
+    INSERT INTO prompts_fts (rowid, prompt, response) VALUES (new.id, new.prompt, new.response);
+END;
+
+CREATE TRIGGER IF NOT EXISTS prompts_fts_delete AFTER DELETE ON prompts BEGIN
+    INSERT INTO prompts_fts (prompts_fts, rowid, prompt, response)
+        VALUES ('delete', old.id, old.prompt, old.response);
+END;
+
+CREATE TRIGGER IF NOT EXISTS prompts_fts_update AFTER UPDATE OF prompt, response ON prompts BEGIN
+    INSERT INTO prompts_fts (prompts_fts, rowid, prompt, response)
+        VALUES ('delete', old.id, old.prompt, old.response);
+    INSERT INTO prompts_fts (rowid, prompt, response) VALUES (new.id, new.prompt, new.response);
+END;
+
+-- model lists cached by the model catalog, one row per model
+CREATE TABLE IF NOT EXISTS model_catalog (
+    provider TEXT NOT NULL,
+    model_id TEXT NOT NULL,
+    display_name TEXT NULL,
+    created_at DATETIME NULL,
+    fetched_at DATETIME NOT NULL,
+    PRIMARY KEY (provider, model_id)
+);
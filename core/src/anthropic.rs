@@ -1,14 +1,25 @@
-use crate::{create_prompt_record, Provider};
-use crate::{CoreConfig, Prompt};
-use reqwest::Client;
+use crate::cassette::{cassette, Cassette};
+use crate::error::CoreError;
+use crate::models::{GenerationParams, ModelInfo, TokenUsage};
+use crate::provider::{
+    ChatMessage, Completion, CompletionRequest, LlmProvider, StreamChunk, TokenStream,
+};
+use crate::sse::{sse_chunks, SseEvent};
+use crate::CoreConfig;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use reqwest::{Client, RequestBuilder};
 use serde::{Deserialize, Serialize};
-use sqlx::MySqlPool;
+use std::sync::Arc;
+
+const ANTHROPIC_VERSION: &str = "2023-06-01";
 
 #[derive(Debug, Deserialize)]
 pub struct AnthropicResponse {
     pub content: Vec<ContentBlock>,
     pub model: String,
     pub role: String,
+    pub stop_reason: Option<String>,
     pub usage: Usage,
 }
 
@@ -23,6 +34,22 @@ pub struct ContentBlock {
 pub struct Usage {
     pub input_tokens: u32,
     pub output_tokens: u32,
+    #[serde(default)]
+    pub cache_read_input_tokens: Option<u32>,
+}
+
+impl From<&Usage> for TokenUsage {
+    fn from(usage: &Usage) -> Self {
+        // anthropic leaves cache reads out of input_tokens, other providers include them
+        let cached_tokens = usage.cache_read_input_tokens.unwrap_or(0);
+        Self {
+            input_tokens: Some(usage.input_tokens + cached_tokens),
+            output_tokens: Some(usage.output_tokens),
+            // anthropic doesn't report thinking tokens separately from output tokens
+            reasoning_tokens: None,
+            cached_tokens: usage.cache_read_input_tokens,
+        }
+    }
 }
 
 // Request structures
@@ -30,8 +57,17 @@ pub struct Usage {
 pub struct AnthropicRequest {
     pub model: String,
     pub messages: Vec<AnthropicMessage>,
+    // anthropic takes the system prompt separately from the messages
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub system: Option<String>,
     pub max_tokens: u32,
     pub temperature: f32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stop_sequences: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stream: Option<bool>,
 }
 
 impl AnthropicRequest {
@@ -39,8 +75,12 @@ impl AnthropicRequest {
         Self {
             model,
             messages,
+            system: None,
             temperature: 0.5, // default to moderate randomness
             max_tokens: 1024,
+            top_p: None,
+            stop_sequences: None,
+            stream: None,
         }
     }
 
@@ -53,13 +93,23 @@ impl AnthropicRequest {
         self.temperature = temperature;
         self
     }
+
+    // ask for the response as server-sent events
+    pub fn with_stream(mut self, stream: bool) -> Self {
+        self.stream = stream.then_some(true);
+        self
+    }
 }
 
 pub struct AnthropicRequestBuilder {
     pub model: String,
     pub messages: Vec<AnthropicMessage>,
+    pub system: Option<String>,
     pub max_tokens: u32,
     pub temperature: f32,
+    pub top_p: Option<f32>,
+    pub stop_sequences: Option<Vec<String>>,
+    pub stream: bool,
 }
 
 impl AnthropicRequestBuilder {
@@ -67,8 +117,12 @@ impl AnthropicRequestBuilder {
         Self {
             model,
             messages: Vec::new(),
+            system: None,
             temperature: 0.5,
             max_tokens: 1024,
+            top_p: None,
+            stop_sequences: None,
+            stream: false,
         }
     }
 
@@ -95,10 +149,36 @@ impl AnthropicRequestBuilder {
         self
     }
 
+    pub fn stream(mut self, stream: bool) -> Self {
+        self.stream = stream;
+        self
+    }
+
+    // override the defaults with whatever the caller set, anthropic has no seed so it's ignored
+    pub fn params(mut self, params: &GenerationParams) -> Self {
+        if let Some(system) = &params.system {
+            self.system = Some(system.clone());
+        }
+        if let Some(temperature) = params.temperature {
+            self.temperature = temperature;
+        }
+        if let Some(max_tokens) = params.max_tokens {
+            self.max_tokens = max_tokens;
+        }
+        self.top_p = params.top_p.or(self.top_p);
+        self.stop_sequences = params.stop.clone().or(self.stop_sequences);
+        self
+    }
+
     pub fn build(self) -> AnthropicRequest {
-        AnthropicRequest::new(self.model, self.messages)
+        let mut request = AnthropicRequest::new(self.model, self.messages)
             .with_temperature(self.temperature)
             .with_max_tokens(self.max_tokens)
+            .with_stream(self.stream);
+        request.system = self.system;
+        request.top_p = self.top_p;
+        request.stop_sequences = self.stop_sequences;
+        request
     }
 }
 
@@ -108,6 +188,114 @@ pub struct AnthropicMessage {
     pub content: String,
 }
 
+impl From<&ChatMessage> for AnthropicMessage {
+    fn from(message: &ChatMessage) -> Self {
+        Self {
+            role: message.role.clone(),
+            content: message.content.clone(),
+        }
+    }
+}
+
+// Streaming structures, only the events that carry text, usage or errors are parsed
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AnthropicStreamEvent {
+    MessageStart {
+        message: AnthropicStreamMessage,
+    },
+    ContentBlockDelta {
+        index: u32,
+        delta: AnthropicDelta,
+    },
+    MessageDelta {
+        delta: AnthropicMessageDelta,
+        usage: AnthropicStreamUsage,
+    },
+    Error {
+        error: serde_json::Value,
+    },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AnthropicStreamMessage {
+    pub usage: AnthropicStreamUsage,
+}
+
+// message_start carries the input counts and message_delta the output count
+#[derive(Debug, Deserialize)]
+pub struct AnthropicStreamUsage {
+    pub input_tokens: Option<u32>,
+    pub output_tokens: Option<u32>,
+    pub cache_read_input_tokens: Option<u32>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AnthropicDelta {
+    #[serde(rename = "type")]
+    pub delta_type: String,
+    pub text: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AnthropicMessageDelta {
+    pub stop_reason: Option<String>,
+}
+
+impl From<AnthropicStreamUsage> for TokenUsage {
+    fn from(usage: AnthropicStreamUsage) -> Self {
+        Self {
+            input_tokens: usage
+                .input_tokens
+                .map(|input| input + usage.cache_read_input_tokens.unwrap_or(0)),
+            output_tokens: usage.output_tokens,
+            reasoning_tokens: None,
+            cached_tokens: usage.cache_read_input_tokens,
+        }
+    }
+}
+
+// chunks carried by a streamed event, events that aren't needed produce none
+fn anthropic_stream_chunks(event: &SseEvent) -> Result<Vec<StreamChunk>, CoreError> {
+    match serde_json::from_str::<AnthropicStreamEvent>(&event.data)? {
+        AnthropicStreamEvent::MessageStart { message } => {
+            Ok(vec![StreamChunk::Usage(message.usage.into())])
+        }
+        AnthropicStreamEvent::ContentBlockDelta { delta, .. } => {
+            Ok(delta.text.into_iter().map(StreamChunk::Text).collect())
+        }
+        AnthropicStreamEvent::MessageDelta { delta, usage } => {
+            let mut chunks = vec![StreamChunk::Usage(usage.into())];
+            chunks.extend(delta.stop_reason.map(StreamChunk::Finish));
+            Ok(chunks)
+        }
+        AnthropicStreamEvent::Error { error } => Err(anthropic_stream_error(error)),
+        AnthropicStreamEvent::Other => Ok(vec![]),
+    }
+}
+
+// errors sent part way through a stream arrive without an http status, map the
+// rate limit and overload types to the errors a failed request would have given
+fn anthropic_stream_error(error: serde_json::Value) -> CoreError {
+    let body = error.to_string();
+    match error["type"].as_str() {
+        Some("rate_limit_error") => CoreError::RateLimited {
+            provider: "Anthropic".to_string(),
+            retry_after: None,
+            body,
+        },
+        Some("overloaded_error") => CoreError::ProviderStatus {
+            provider: "Anthropic".to_string(),
+            status: 529,
+            retry_after: None,
+            body,
+        },
+        _ => CoreError::provider("Anthropic", body),
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct AnthropicModel {
     pub id: String,
@@ -117,6 +305,18 @@ pub struct AnthropicModel {
     pub created_at: String,
 }
 
+impl From<AnthropicModel> for ModelInfo {
+    fn from(model: AnthropicModel) -> Self {
+        Self {
+            created_at: DateTime::parse_from_rfc3339(&model.created_at)
+                .ok()
+                .map(|created_at| created_at.with_timezone(&Utc)),
+            display_name: Some(model.display_name),
+            id: model.id,
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 struct AnthropicModelsResponse {
     data: Vec<AnthropicModel>,
@@ -125,90 +325,352 @@ struct AnthropicModelsResponse {
     last_id: Option<String>,
 }
 
-pub async fn call_anthropic(
-    prompt: &str,
-    model: Option<&str>,
-    pool: &MySqlPool,
-) -> Result<Prompt, Box<dyn std::error::Error>> {
-    let config = CoreConfig::get();
-    let client = Client::new();
+pub struct AnthropicProvider {
+    client: Client,
+    base_url: Option<String>,
+    api_key: Option<String>,
+    default_model: Option<String>,
+    cassette: Option<Arc<Cassette>>,
+}
 
-    if config.anthropic_key.is_none() {
-        return Err("ANTHROPIC_KEY is not set".into());
+impl Default for AnthropicProvider {
+    fn default() -> Self {
+        Self::new()
     }
+}
 
-    let mut model = model.unwrap_or(&config.default_anthropic_model);
-    let models = get_anthropic_models().await?;
-    // loop over models and make sure the passed in models is valid otherwise use default
-    if !models.iter().any(|m| m.id == model) {
-        println!(
-            "\r\x1b[2kInvalid model, {}, falling back to default model, {}",
-            model, &config.default_anthropic_model
-        );
-        model = &config.default_anthropic_model;
-    }
-
-    let request = AnthropicRequestBuilder::new(model.to_string())
-        .add_message("user", prompt)
-        .max_tokens(1024)
-        .build();
-
-    let response = client
-        .post(format!("{}/messages", &config.anthropic_url))
-        .header("x-api-key", &config.anthropic_key.clone().unwrap())
-        .header("anthropic-version", "2023-06-01")
-        .header("content-type", "application/json")
-        .json(&request)
-        .send()
-        .await?;
-
-    if response.status().is_success() {
-        // Parse the response
-        let anthropic_response: AnthropicResponse = response.json().await?;
-
-        // Extract the text from the first content block
-        let response_text = anthropic_response
-            .content
-            .first()
-            .map(|block| block.text.clone())
-            .unwrap_or_else(|| "No response content".to_string());
-
-        Ok(create_prompt_record(
-            pool,
-            prompt.to_string(),
-            &response_text,
-            model,
-            Provider::Anthropic.to_string().as_str(),
-        )
-        .await?)
-    } else {
-        let error_text = response.text().await?;
-        Err(format!("Anthropic API request failed: {}", error_text).into())
+impl AnthropicProvider {
+    pub fn new() -> Self {
+        Self {
+            client: Client::new(),
+            base_url: None,
+            api_key: None,
+            default_model: None,
+            cassette: None,
+        }
+    }
+
+    pub fn with_base_url(mut self, base_url: &str) -> Self {
+        self.base_url = Some(base_url.to_string());
+        self
+    }
+
+    pub fn with_api_key(mut self, api_key: &str) -> Self {
+        self.api_key = Some(api_key.to_string());
+        self
+    }
+
+    pub fn with_default_model(mut self, model: &str) -> Self {
+        self.default_model = Some(model.to_string());
+        self
+    }
+
+    pub fn with_cassette(mut self, cassette: Arc<Cassette>) -> Self {
+        self.cassette = Some(cassette);
+        self
+    }
+
+    fn base_url(&self) -> String {
+        self.base_url
+            .clone()
+            .unwrap_or_else(|| CoreConfig::get().anthropic_url.clone())
+    }
+
+    fn api_key(&self) -> Result<String, CoreError> {
+        self.api_key
+            .clone()
+            .or_else(|| CoreConfig::get().anthropic_key.clone())
+            .ok_or_else(|| CoreError::missing_credentials(self.name(), "ANTHROPIC_KEY"))
+    }
+
+    // every request goes through the cassette so it can be recorded or replayed
+    async fn send(&self, request: RequestBuilder) -> Result<reqwest::Response, CoreError> {
+        match &self.cassette {
+            Some(cassette) => cassette.send(request).await,
+            None => cassette().send(request).await,
+        }
+    }
+
+    async fn send_messages_request(
+        &self,
+        request: &CompletionRequest,
+        stream: bool,
+    ) -> Result<reqwest::Response, CoreError> {
+        let api_key = self.api_key()?;
+
+        let anthropic_request = AnthropicRequestBuilder::new(request.model.clone())
+            .messages(
+                request
+                    .messages
+                    .iter()
+                    .map(AnthropicMessage::from)
+                    .collect(),
+            )
+            .max_tokens(1024)
+            .params(&request.params)
+            .stream(stream)
+            .build();
+
+        let request = self
+            .client
+            .post(format!("{}/messages", self.base_url()))
+            .header("x-api-key", api_key)
+            .header("anthropic-version", ANTHROPIC_VERSION)
+            .header("content-type", "application/json")
+            .json(&anthropic_request);
+
+        self.send(request).await
+    }
+
+    /// Get the full model details from the models endpoint
+    pub async fn get_models(&self) -> Result<Vec<AnthropicModel>, CoreError> {
+        let api_key = self.api_key()?;
+
+        let response = self
+            .send(
+                self.client
+                    .get(format!("{}/models", self.base_url()))
+                    .header("x-api-key", api_key)
+                    .header("anthropic-version", ANTHROPIC_VERSION),
+            )
+            .await?;
+
+        if response.status().is_success() {
+            let models_response: AnthropicModelsResponse = response.json().await?;
+            let models: Vec<AnthropicModel> = models_response.data;
+
+            Ok(models)
+        } else {
+            Err(CoreError::from_response(self.name(), response).await)
+        }
     }
 }
 
-pub async fn get_anthropic_models() -> Result<Vec<AnthropicModel>, Box<dyn std::error::Error>> {
-    let config = CoreConfig::get();
+#[async_trait]
+impl LlmProvider for AnthropicProvider {
+    fn name(&self) -> &str {
+        "Anthropic"
+    }
+
+    fn default_model(&self) -> String {
+        self.default_model
+            .clone()
+            .unwrap_or_else(|| CoreConfig::get().default_anthropic_model.clone())
+    }
+
+    async fn list_models(&self) -> Result<Vec<String>, CoreError> {
+        let models = self.get_models().await?;
+        Ok(models.into_iter().map(|m| m.id).collect())
+    }
 
-    if config.anthropic_key.is_none() {
-        return Err("ANTHROPIC_KEY is not set".into());
+    async fn list_model_info(&self) -> Result<Vec<ModelInfo>, CoreError> {
+        let models = self.get_models().await?;
+        Ok(models.into_iter().map(ModelInfo::from).collect())
     }
 
-    let client = Client::new();
-    let response = client
-        .get(format!("{}/models", &config.anthropic_url))
-        .header("x-api-key", &config.anthropic_key.clone().unwrap())
-        .header("anthropic-version", "2023-06-01")
-        .send()
-        .await?;
+    async fn complete(&self, request: &CompletionRequest) -> Result<Completion, CoreError> {
+        let response = self.send_messages_request(request, false).await?;
+
+        if response.status().is_success() {
+            // Parse the response
+            let anthropic_response: AnthropicResponse = response.json().await?;
 
-    if response.status().is_success() {
-        let models_response: AnthropicModelsResponse = response.json().await?;
-        let models: Vec<AnthropicModel> = models_response.data;
+            // Extract the text from the first content block
+            let response_text = anthropic_response
+                .content
+                .first()
+                .map(|block| block.text.clone())
+                .unwrap_or_else(|| "No response content".to_string());
 
-        Ok(models)
-    } else {
-        let error_text = response.text().await?;
-        Err(format!("Anthropic API request failed: {}", error_text).into())
+            Ok(Completion {
+                text: response_text,
+                usage: TokenUsage::from(&anthropic_response.usage),
+                finish_reason: anthropic_response.stop_reason,
+                model: anthropic_response.model,
+            })
+        } else {
+            Err(CoreError::from_response(self.name(), response).await)
+        }
+    }
+    async fn stream(&self, request: &CompletionRequest) -> Result<TokenStream, CoreError> {
+        let response = self.send_messages_request(request, true).await?;
+
+        if response.status().is_success() {
+            Ok(sse_chunks(response, anthropic_stream_chunks))
+        } else {
+            Err(CoreError::from_response(self.name(), response).await)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(data: &str) -> SseEvent {
+        SseEvent {
+            event: None,
+            data: data.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_request_builder_params() {
+        let params = GenerationParams {
+            system: Some("Answer in French".to_string()),
+            temperature: Some(0.1),
+            top_p: Some(0.9),
+            max_tokens: Some(256),
+            stop: Some(vec!["END".to_string()]),
+            seed: Some(42),
+        };
+        let request = AnthropicRequestBuilder::new("claude".to_string())
+            .add_message("user", "hi")
+            .params(&params)
+            .build();
+        let json = serde_json::to_value(&request).unwrap();
+
+        assert_eq!(json["system"], "Answer in French");
+        assert_eq!(json["max_tokens"], 256);
+        assert_eq!(json["top_p"].as_f64().unwrap() as f32, 0.9);
+        assert_eq!(json["stop_sequences"], serde_json::json!(["END"]));
+        assert!(json.get("seed").is_none());
+
+        // unset params keep the defaults
+        let request = AnthropicRequestBuilder::new("claude".to_string())
+            .params(&GenerationParams::default())
+            .build();
+        let json = serde_json::to_value(&request).unwrap();
+        assert_eq!(json["max_tokens"], 1024);
+        assert_eq!(json["temperature"], 0.5);
+        assert!(json.get("system").is_none());
+        assert!(json.get("top_p").is_none());
+    }
+
+    #[test]
+    fn test_request_builder_stream_flag() {
+        let request = AnthropicRequestBuilder::new("claude".to_string())
+            .add_message("user", "hi")
+            .build();
+        let json = serde_json::to_value(&request).unwrap();
+        assert!(json.get("stream").is_none());
+
+        let request = AnthropicRequestBuilder::new("claude".to_string())
+            .add_message("user", "hi")
+            .stream(true)
+            .build();
+        let json = serde_json::to_value(&request).unwrap();
+        assert_eq!(json["stream"], true);
+    }
+
+    #[test]
+    fn test_messages_from_history() {
+        let request = CompletionRequest::new("claude", "second").with_history(vec![
+            ChatMessage::user("first"),
+            ChatMessage::assistant("answer"),
+        ]);
+        let messages: Vec<AnthropicMessage> = request
+            .messages
+            .iter()
+            .map(AnthropicMessage::from)
+            .collect();
+
+        assert_eq!(messages.len(), 3);
+        assert_eq!(messages[1].role, "assistant");
+        assert_eq!(messages[1].content, "answer");
+        assert_eq!(messages[2].content, "second");
+    }
+
+    #[test]
+    fn test_stream_chunks_from_delta() {
+        let chunks = anthropic_stream_chunks(&event(
+            r#"{"type":"content_block_delta","index":0,"delta":{"type":"text_delta","text":"Hello"}}"#,
+        ))
+        .unwrap();
+        assert_eq!(chunks, vec![StreamChunk::Text("Hello".to_string())]);
+    }
+
+    #[test]
+    fn test_stream_chunks_usage_and_stop_reason() {
+        let chunks = anthropic_stream_chunks(&event(
+            r#"{"type":"message_start","message":{"id":"msg_1","type":"message","role":"assistant","content":[],"model":"claude-sonnet-4-20250514","usage":{"input_tokens":25,"output_tokens":1,"cache_read_input_tokens":10}}}"#,
+        ))
+        .unwrap();
+        assert_eq!(
+            chunks,
+            vec![StreamChunk::Usage(TokenUsage {
+                input_tokens: Some(35),
+                output_tokens: Some(1),
+                reasoning_tokens: None,
+                cached_tokens: Some(10),
+            })]
+        );
+
+        let chunks = anthropic_stream_chunks(&event(
+            r#"{"type":"message_delta","delta":{"stop_reason":"end_turn","stop_sequence":null},"usage":{"output_tokens":15}}"#,
+        ))
+        .unwrap();
+        assert_eq!(
+            chunks,
+            vec![
+                StreamChunk::Usage(TokenUsage {
+                    output_tokens: Some(15),
+                    ..Default::default()
+                }),
+                StreamChunk::Finish("end_turn".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_stream_chunks_ignores_other_events() {
+        let chunks = anthropic_stream_chunks(&event(r#"{"type":"message_stop"}"#)).unwrap();
+        assert!(chunks.is_empty());
+
+        let chunks = anthropic_stream_chunks(&event(r#"{"type":"ping"}"#)).unwrap();
+        assert!(chunks.is_empty());
+    }
+
+    #[test]
+    fn test_stream_chunks_error_event() {
+        let result = anthropic_stream_chunks(&event(
+            r#"{"type":"error","error":{"type":"overloaded_error","message":"Overloaded"}}"#,
+        ));
+        assert!(result.unwrap_err().to_string().contains("overloaded_error"));
+    }
+
+    #[test]
+    fn test_response_usage() {
+        let response: AnthropicResponse = serde_json::from_str(
+            r#"{"id":"msg_1","type":"message","role":"assistant","model":"claude-sonnet-4-20250514","content":[{"type":"text","text":"Hi"}],"stop_reason":"max_tokens","usage":{"input_tokens":12,"output_tokens":1024}}"#,
+        )
+        .unwrap();
+
+        assert_eq!(response.stop_reason, Some("max_tokens".to_string()));
+        assert_eq!(
+            TokenUsage::from(&response.usage),
+            TokenUsage {
+                input_tokens: Some(12),
+                output_tokens: Some(1024),
+                reasoning_tokens: None,
+                cached_tokens: None,
+            }
+        );
+    }
+
+    #[test]
+    fn test_model_info_from_model() {
+        let model: AnthropicModel = serde_json::from_str(
+            r#"{"id":"claude-sonnet-4-20250514","type":"model","display_name":"Claude Sonnet 4","created_at":"2025-05-22T00:00:00Z"}"#,
+        )
+        .unwrap();
+
+        let info = ModelInfo::from(model);
+        assert_eq!(info.id, "claude-sonnet-4-20250514");
+        assert_eq!(info.display_name, Some("Claude Sonnet 4".to_string()));
+        assert_eq!(
+            info.created_at.unwrap().to_rfc3339(),
+            "2025-05-22T00:00:00+00:00"
+        );
     }
 }
@@ -0,0 +1,329 @@
+use crate::config::CoreConfig;
+use crate::error::CoreError;
+use crate::pricing::price_table;
+use crate::store::PromptStore;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Datelike, Duration, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::sync::OnceLock;
+
+/// What a budget's spend is counted across
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "scope", rename_all = "lowercase")]
+pub enum BudgetScope {
+    // every prompt
+    Global,
+    // prompts sent to one provider
+    Provider { provider: String },
+    // prompts made by one user or api key, see the user passed to prompt_model
+    User { user: String },
+}
+
+impl BudgetScope {
+    fn applies_to(&self, provider: &str, user: Option<&str>) -> bool {
+        match self {
+            BudgetScope::Global => true,
+            BudgetScope::Provider { provider: scoped } => scoped.eq_ignore_ascii_case(provider),
+            BudgetScope::User { user: scoped } => user == Some(scoped.as_str()),
+        }
+    }
+}
+
+impl fmt::Display for BudgetScope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BudgetScope::Global => write!(f, "global"),
+            BudgetScope::Provider { provider } => write!(f, "provider {}", provider),
+            BudgetScope::User { user } => write!(f, "user {}", user),
+        }
+    }
+}
+
+/// Period a budget's spend is counted over, windows start at midnight UTC
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BudgetWindow {
+    Daily,
+    Monthly,
+}
+
+impl BudgetWindow {
+    /// Start and end of the window that contains `now`
+    pub fn bounds(&self, now: DateTime<Utc>) -> (DateTime<Utc>, DateTime<Utc>) {
+        let today = now.date_naive();
+        let (start, end) = match self {
+            BudgetWindow::Daily => (today, today + Duration::days(1)),
+            BudgetWindow::Monthly => {
+                let start = NaiveDate::from_ymd_opt(today.year(), today.month(), 1).unwrap();
+                let end = match today.month() {
+                    12 => NaiveDate::from_ymd_opt(today.year() + 1, 1, 1),
+                    month => NaiveDate::from_ymd_opt(today.year(), month + 1, 1),
+                }
+                .unwrap();
+                (start, end)
+            }
+        };
+
+        (
+            start.and_hms_opt(0, 0, 0).unwrap().and_utc(),
+            end.and_hms_opt(0, 0, 0).unwrap().and_utc(),
+        )
+    }
+}
+
+impl fmt::Display for BudgetWindow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BudgetWindow::Daily => write!(f, "daily"),
+            BudgetWindow::Monthly => write!(f, "monthly"),
+        }
+    }
+}
+
+/// A spend limit, in the currency of the price table
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Budget {
+    #[serde(flatten)]
+    pub scope: BudgetScope,
+    pub window: BudgetWindow,
+    pub limit: f64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct BudgetConfig {
+    pub budgets: Vec<Budget>,
+}
+
+impl BudgetConfig {
+    pub fn from_json(json: &str) -> Result<Self> {
+        serde_json::from_str(json).context("Invalid budgets file")
+    }
+
+    pub fn from_file(path: &str) -> Result<Self> {
+        let json = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read budgets file {}", path))?;
+        Self::from_json(&json)
+    }
+
+    /// Whether any budget limits a single user, prompts then have to say who they are for
+    pub fn has_user_budgets(&self) -> bool {
+        self.budgets
+            .iter()
+            .any(|budget| matches!(budget.scope, BudgetScope::User { .. }))
+    }
+
+    /// Budgets that a prompt to the provider by the user counts towards
+    pub fn applicable<'a>(
+        &'a self,
+        provider: &'a str,
+        user: Option<&'a str>,
+    ) -> impl Iterator<Item = &'a Budget> + 'a {
+        self.budgets
+            .iter()
+            .filter(move |budget| budget.scope.applies_to(provider, user))
+    }
+}
+
+static BUDGETS: OnceLock<BudgetConfig> = OnceLock::new();
+
+/// Load the budgets from BUDGETS_FILE, none when it isn't set.
+/// Binaries call this on start so a broken file is reported rather than panicking later
+pub fn load_budgets() -> Result<&'static BudgetConfig> {
+    if let Some(config) = BUDGETS.get() {
+        return Ok(config);
+    }
+
+    let config = match &CoreConfig::get().budgets_file {
+        Some(path) => BudgetConfig::from_file(path)?,
+        None => BudgetConfig::default(),
+    };
+    Ok(BUDGETS.get_or_init(|| config))
+}
+
+/// Budgets loaded from BUDGETS_FILE, nothing is limited when it isn't set
+pub fn budget_config() -> &'static BudgetConfig {
+    load_budgets().expect("Failed to load budgets file")
+}
+
+/// How much of a budget has been spent in its current window
+#[derive(Debug, Clone, Serialize)]
+pub struct BudgetStatus {
+    #[serde(flatten)]
+    pub budget: Budget,
+    pub currency: String,
+    pub spent: f64,
+    pub remaining: f64,
+    pub exceeded: bool,
+    pub window_start: DateTime<Utc>,
+    pub resets_at: DateTime<Utc>,
+}
+
+impl BudgetStatus {
+    pub fn new(budget: &Budget, spent: f64, now: DateTime<Utc>) -> Self {
+        let (window_start, resets_at) = budget.window.bounds(now);
+        Self {
+            budget: budget.clone(),
+            currency: price_table().currency.clone(),
+            spent,
+            remaining: (budget.limit - spent).max(0.0),
+            exceeded: spent >= budget.limit,
+            window_start,
+            resets_at,
+        }
+    }
+}
+
+/// Returned instead of calling the provider when a budget has been used up.
+/// Callers get it back as `CoreError::BudgetExceeded`.
+#[derive(Debug, Clone)]
+pub struct BudgetExceeded(pub BudgetStatus);
+
+impl fmt::Display for BudgetExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let status = &self.0;
+        write!(
+            f,
+            "{} {} budget exceeded: spent {:.4} of {:.2} {}, resets at {}",
+            status.budget.window,
+            status.budget.scope,
+            status.spent,
+            status.budget.limit,
+            status.currency,
+            status.resets_at.to_rfc3339()
+        )
+    }
+}
+
+impl std::error::Error for BudgetExceeded {}
+
+/// Spend so far against every configured budget, or only those the user counts towards
+pub async fn get_budget_statuses(
+    store: &dyn PromptStore,
+    user: Option<&str>,
+) -> Result<Vec<BudgetStatus>, sqlx::Error> {
+    let now = Utc::now();
+    let mut statuses = Vec::new();
+
+    for budget in &budget_config().budgets {
+        if let (Some(user), BudgetScope::User { user: scoped }) = (user, &budget.scope) {
+            if scoped != user {
+                continue;
+            }
+        }
+        let spent = budget_spend(store, budget, now).await?;
+        statuses.push(BudgetStatus::new(budget, spent, now));
+    }
+
+    Ok(statuses)
+}
+
+/// Fails with BudgetExceeded if any budget the prompt would count towards is used up.
+///
+/// Spend is only known once a prompt is saved, so nothing is reserved here. Prompts that are
+/// checked while others are still running, such as the targets of a comparison, can all pass
+/// and take a budget over its limit by up to the cost of one round of concurrent prompts.
+pub async fn check_budgets(
+    store: &dyn PromptStore,
+    provider: &str,
+    user: Option<&str>,
+) -> Result<(), CoreError> {
+    let now = Utc::now();
+
+    for budget in budget_config().applicable(provider, user) {
+        let spent = budget_spend(store, budget, now).await?;
+        if spent >= budget.limit {
+            return Err(BudgetExceeded(BudgetStatus::new(budget, spent, now)).into());
+        }
+    }
+
+    Ok(())
+}
+
+async fn budget_spend(
+    store: &dyn PromptStore,
+    budget: &Budget,
+    now: DateTime<Utc>,
+) -> Result<f64, sqlx::Error> {
+    let (window_start, _) = budget.window.bounds(now);
+    match &budget.scope {
+        BudgetScope::Global => store.get_spend(window_start, None, None).await,
+        BudgetScope::Provider { provider } => {
+            store.get_spend(window_start, Some(provider), None).await
+        }
+        BudgetScope::User { user } => store.get_spend(window_start, None, Some(user)).await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> BudgetConfig {
+        BudgetConfig::from_json(
+            r#"{"budgets": [
+                {"scope": "global", "window": "monthly", "limit": 500.0},
+                {"scope": "provider", "provider": "OpenAI", "window": "daily", "limit": 20.0},
+                {"scope": "user", "user": "nightly-batch", "window": "daily", "limit": 5.0}
+            ]}"#,
+        )
+        .unwrap()
+    }
+
+    fn time(value: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(value)
+            .unwrap()
+            .with_timezone(&Utc)
+    }
+
+    #[test]
+    fn test_parse_budgets() {
+        let config = config();
+        assert_eq!(config.budgets.len(), 3);
+        assert!(config.has_user_budgets());
+        assert!(!BudgetConfig::default().has_user_budgets());
+        assert_eq!(config.budgets[0].scope, BudgetScope::Global);
+        assert_eq!(
+            config.budgets[1].scope,
+            BudgetScope::Provider {
+                provider: "OpenAI".to_string()
+            }
+        );
+        assert_eq!(config.budgets[2].window, BudgetWindow::Daily);
+
+        assert!(BudgetConfig::from_json(r#"{"budgets": [{"scope": "team"}]}"#).is_err());
+    }
+
+    #[test]
+    fn test_applicable_budgets() {
+        let config = config();
+
+        let scopes: Vec<String> = config
+            .applicable("openai", Some("nightly-batch"))
+            .map(|b| b.scope.to_string())
+            .collect();
+        assert_eq!(
+            scopes,
+            vec!["global", "provider OpenAI", "user nightly-batch"]
+        );
+
+        let scopes: Vec<String> = config
+            .applicable("Anthropic", None)
+            .map(|b| b.scope.to_string())
+            .collect();
+        assert_eq!(scopes, vec!["global"]);
+    }
+
+    #[test]
+    fn test_window_bounds() {
+        let now = time("2025-12-31T18:30:00Z");
+
+        let (start, end) = BudgetWindow::Daily.bounds(now);
+        assert_eq!(start, time("2025-12-31T00:00:00Z"));
+        assert_eq!(end, time("2026-01-01T00:00:00Z"));
+
+        let (start, end) = BudgetWindow::Monthly.bounds(now);
+        assert_eq!(start, time("2025-12-01T00:00:00Z"));
+        assert_eq!(end, time("2026-01-01T00:00:00Z"));
+    }
+}
@@ -0,0 +1,356 @@
+use crate::config::CoreConfig;
+use crate::error::CoreError;
+use crate::models::TokenUsage;
+use crate::provider::{Completion, CompletionRequest};
+use crate::store::PromptStore;
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, OnceLock};
+
+/// A completion saved in the response cache under the hash of the request it answered
+#[derive(Debug, Clone, PartialEq)]
+pub struct CachedResponse {
+    pub key: String,
+    pub provider: String,
+    pub model: String,
+    pub response: String,
+    pub usage: TokenUsage,
+    pub finish_reason: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl CachedResponse {
+    pub fn new(key: &str, provider: &str, completion: &Completion) -> Self {
+        Self {
+            key: key.to_string(),
+            provider: provider.to_string(),
+            model: completion.model.clone(),
+            response: completion.text.clone(),
+            usage: completion.usage.clone(),
+            finish_reason: completion.finish_reason.clone(),
+            created_at: Utc::now(),
+        }
+    }
+
+    pub fn completion(&self) -> Completion {
+        Completion {
+            text: self.response.clone(),
+            model: self.model.clone(),
+            usage: self.usage.clone(),
+            finish_reason: self.finish_reason.clone(),
+        }
+    }
+}
+
+/// How often prompts were answered from the cache since the process started
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CacheStats {
+    pub enabled: bool,
+    pub ttl_secs: u64,
+    pub capacity: usize,
+    // responses held in memory, the database can hold more
+    pub entries: usize,
+    pub lookups: u64,
+    pub hits: u64,
+    // hits answered from the database after the response had left memory or came from another process
+    pub store_hits: u64,
+    pub misses: u64,
+    // prompts sent with cache bypass or refresh, they aren't lookups
+    pub skipped: u64,
+    // hits divided by lookups, 0 before the first lookup
+    pub hit_rate: f64,
+}
+
+#[derive(Default)]
+struct Entries {
+    responses: HashMap<String, (CachedResponse, u64)>,
+    // bumped on every use, the entry with the lowest tick is the least recently used
+    tick: u64,
+}
+
+/// Exact match cache of provider responses, keyed on a hash of the provider, model,
+/// settings and messages of a request. Responses are kept for a TTL in a least recently
+/// used map and, when a prompt store is passed in, in the response_cache table so they
+/// survive restarts and are shared by the api and cli. A TTL of 0 turns the cache off.
+pub struct ResponseCache {
+    ttl: Duration,
+    capacity: usize,
+    entries: Mutex<Entries>,
+    hits: AtomicU64,
+    store_hits: AtomicU64,
+    misses: AtomicU64,
+    skipped: AtomicU64,
+}
+
+impl ResponseCache {
+    pub fn new(ttl: std::time::Duration, capacity: usize) -> Self {
+        Self {
+            ttl: Duration::from_std(ttl).unwrap_or(Duration::MAX),
+            capacity,
+            entries: Mutex::new(Entries::default()),
+            hits: AtomicU64::new(0),
+            store_hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            skipped: AtomicU64::new(0),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.ttl > Duration::zero()
+    }
+
+    /// Hash of everything that decides a request's response, as hex
+    pub fn key(provider: &str, request: &CompletionRequest) -> String {
+        let messages: Vec<[&str; 2]> = request
+            .messages
+            .iter()
+            .map(|m| [m.role.as_str(), m.content.as_str()])
+            .collect();
+        let params = &request.params;
+        let keyed = json!({
+            "provider": provider.to_lowercase(),
+            "model": request.model,
+            "system": params.system,
+            "temperature": params.temperature,
+            "top_p": params.top_p,
+            "max_tokens": params.max_tokens,
+            "stop": params.stop,
+            "seed": params.seed,
+            "messages": messages,
+        });
+
+        hex::encode(Sha256::digest(keyed.to_string().as_bytes()))
+    }
+
+    /// The fresh response cached under the key, from memory or else the store
+    pub async fn get(
+        &self,
+        key: &str,
+        store: Option<&dyn PromptStore>,
+    ) -> Result<Option<CachedResponse>, CoreError> {
+        if let Some(response) = self.cached(key) {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            return Ok(Some(response));
+        }
+
+        if let Some(store) = store {
+            if let Some(response) = store.get_cached_response(key).await? {
+                if self.is_fresh(&response) {
+                    self.hits.fetch_add(1, Ordering::Relaxed);
+                    self.store_hits.fetch_add(1, Ordering::Relaxed);
+                    self.insert(response.clone());
+                    return Ok(Some(response));
+                }
+            }
+        }
+
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        Ok(None)
+    }
+
+    /// Cache a response, replacing any response under the same key
+    pub async fn put(
+        &self,
+        response: CachedResponse,
+        store: Option<&dyn PromptStore>,
+    ) -> Result<(), CoreError> {
+        if let Some(store) = store {
+            store.save_cached_response(&response).await?;
+        }
+        self.insert(response);
+        Ok(())
+    }
+
+    /// Count a prompt that was sent without looking in the cache
+    pub fn skip(&self) {
+        self.skipped.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn insert(&self, response: CachedResponse) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        let mut entries = self.entries.lock().unwrap();
+        entries.tick += 1;
+        let tick = entries.tick;
+        entries
+            .responses
+            .insert(response.key.clone(), (response, tick));
+
+        if entries.responses.len() > self.capacity {
+            let oldest = entries
+                .responses
+                .iter()
+                .min_by_key(|(_, (_, used))| *used)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                entries.responses.remove(&oldest);
+            }
+        }
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        let hits = self.hits.load(Ordering::Relaxed);
+        let misses = self.misses.load(Ordering::Relaxed);
+        let lookups = hits + misses;
+
+        CacheStats {
+            enabled: self.is_enabled(),
+            ttl_secs: self.ttl.num_seconds().max(0) as u64,
+            capacity: self.capacity,
+            entries: self.entries.lock().unwrap().responses.len(),
+            lookups,
+            hits,
+            store_hits: self.store_hits.load(Ordering::Relaxed),
+            misses,
+            skipped: self.skipped.load(Ordering::Relaxed),
+            hit_rate: if lookups == 0 {
+                0.0
+            } else {
+                hits as f64 / lookups as f64
+            },
+        }
+    }
+
+    // the response from memory if it hasn't expired, marking it as just used
+    fn cached(&self, key: &str) -> Option<CachedResponse> {
+        let mut entries = self.entries.lock().unwrap();
+        entries.tick += 1;
+        let tick = entries.tick;
+
+        let (response, used) = entries.responses.get_mut(key)?;
+        if !self.is_fresh(response) {
+            entries.responses.remove(key);
+            return None;
+        }
+        *used = tick;
+        Some(response.clone())
+    }
+
+    fn is_fresh(&self, response: &CachedResponse) -> bool {
+        Utc::now() - response.created_at < self.ttl
+    }
+}
+
+static RESPONSE_CACHE: OnceLock<ResponseCache> = OnceLock::new();
+
+/// Cache shared by every prompt, from RESPONSE_CACHE_TTL_SECS and RESPONSE_CACHE_SIZE
+pub fn response_cache() -> &'static ResponseCache {
+    RESPONSE_CACHE.get_or_init(|| {
+        let config = CoreConfig::get();
+        ResponseCache::new(
+            std::time::Duration::from_secs(config.response_cache_ttl_secs),
+            config.response_cache_size,
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::GenerationParams;
+    use crate::provider::ChatMessage;
+    use crate::test_support::MemoryStore;
+
+    fn response(key: &str, text: &str) -> CachedResponse {
+        CachedResponse {
+            key: key.to_string(),
+            provider: "Anthropic".to_string(),
+            model: "claude-mock-1".to_string(),
+            response: text.to_string(),
+            usage: TokenUsage::default(),
+            finish_reason: Some("end_turn".to_string()),
+            created_at: Utc::now(),
+        }
+    }
+
+    fn cache(capacity: usize) -> ResponseCache {
+        ResponseCache::new(std::time::Duration::from_secs(60), capacity)
+    }
+
+    #[test]
+    fn test_key() {
+        let request = CompletionRequest::new("claude-mock-1", "What is Kafka?");
+        let key = ResponseCache::key("Anthropic", &request);
+        assert_eq!(key.len(), 64);
+        assert_eq!(key, ResponseCache::key("anthropic", &request.clone()));
+
+        // anything that changes the response changes the key
+        let other_model = CompletionRequest::new("claude-mock-2", "What is Kafka?");
+        let with_system = request.clone().with_params(GenerationParams {
+            system: Some("Be brief".to_string()),
+            ..Default::default()
+        });
+        let with_history = request.clone().with_history(vec![
+            ChatMessage::user("hi"),
+            ChatMessage::assistant("hello"),
+        ]);
+        for other in [other_model, with_system, with_history] {
+            assert_ne!(key, ResponseCache::key("Anthropic", &other));
+        }
+        assert_ne!(key, ResponseCache::key("OpenAI", &request));
+    }
+
+    #[tokio::test]
+    async fn test_get_and_put() {
+        let cache = cache(10);
+        assert!(cache.get("a", None).await.unwrap().is_none());
+
+        cache.put(response("a", "first"), None).await.unwrap();
+        let hit = cache.get("a", None).await.unwrap().unwrap();
+        assert_eq!(hit.response, "first");
+
+        // expired responses aren't returned
+        let mut stale = response("b", "old");
+        stale.created_at = Utc::now() - Duration::seconds(61);
+        cache.insert(stale);
+        assert!(cache.get("b", None).await.unwrap().is_none());
+
+        cache.skip();
+        let stats = cache.stats();
+        assert_eq!((stats.lookups, stats.hits, stats.misses), (3, 1, 2));
+        assert_eq!(stats.skipped, 1);
+        assert!((stats.hit_rate - 1.0 / 3.0).abs() < 1e-9);
+        assert_eq!(stats.entries, 1);
+    }
+
+    #[tokio::test]
+    async fn test_least_recently_used_is_evicted() {
+        let cache = cache(2);
+        cache.insert(response("a", "a"));
+        cache.insert(response("b", "b"));
+        // using a makes b the least recently used
+        assert!(cache.get("a", None).await.unwrap().is_some());
+        cache.insert(response("c", "c"));
+
+        assert!(cache.get("a", None).await.unwrap().is_some());
+        assert!(cache.get("b", None).await.unwrap().is_none());
+        assert!(cache.get("c", None).await.unwrap().is_some());
+        assert_eq!(cache.stats().entries, 2);
+    }
+
+    #[tokio::test]
+    async fn test_store_is_shared() {
+        let store = MemoryStore::new();
+        cache(10)
+            .put(response("a", "saved"), Some(&store))
+            .await
+            .unwrap();
+
+        // a cache that never saw the response, like another process, finds it in the store
+        let other = cache(10);
+        let hit = other.get("a", Some(&store)).await.unwrap().unwrap();
+        assert_eq!(hit.response, "saved");
+        assert_eq!(other.stats().store_hits, 1);
+        assert!(other.get("a", None).await.unwrap().is_some());
+
+        let disabled = ResponseCache::new(std::time::Duration::ZERO, 10);
+        assert!(!disabled.is_enabled());
+        assert!(disabled.get("a", Some(&store)).await.unwrap().is_none());
+    }
+}
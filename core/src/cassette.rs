@@ -0,0 +1,405 @@
+use crate::config::{CassetteMode, CoreConfig};
+use crate::error::CoreError;
+use anyhow::{Context, Result};
+use reqwest::header::HeaderMap;
+use reqwest::{Request, RequestBuilder, Response};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::{Mutex, OnceLock};
+
+// headers holding credentials, their values are never written to a cassette
+const SECRET_HEADERS: [&str; 7] = [
+    "authorization",
+    "x-api-key",
+    "x-goog-api-key",
+    "api-key",
+    "openai-organization",
+    "cookie",
+    "set-cookie",
+];
+
+const SCRUBBED: &str = "[scrubbed]";
+
+/// A provider request and the response it got, as saved in a cassette file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Interaction {
+    pub request: RecordedRequest,
+    pub response: RecordedResponse,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordedRequest {
+    pub method: String,
+    pub url: String,
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    // json bodies are kept as json so cassettes can be read and edited by hand
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub body: Option<Value>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordedResponse {
+    pub status: u16,
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    // streamed responses are saved whole, server-sent events and all
+    pub body: String,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct CassetteFile {
+    interactions: Vec<Interaction>,
+}
+
+#[derive(Debug, Default)]
+struct Tape {
+    interactions: Vec<Interaction>,
+    // which interactions have been replayed, each is answered once before any repeats
+    played: Vec<bool>,
+}
+
+/// Provider http traffic recorded to a json file, or answered from one without the network.
+/// Requests are matched on method, url and body, so the same prompt to the same model
+/// gets the recorded reply. Credentials are scrubbed from recorded headers.
+#[derive(Debug)]
+pub struct Cassette {
+    mode: CassetteMode,
+    path: String,
+    tape: Mutex<Tape>,
+}
+
+impl Cassette {
+    /// Requests go straight to the provider
+    pub fn off() -> Self {
+        Self {
+            mode: CassetteMode::Off,
+            path: String::new(),
+            tape: Mutex::new(Tape::default()),
+        }
+    }
+
+    /// Start a new recording, the file is replaced once the first request has been made
+    pub fn record(path: &str) -> Self {
+        Self {
+            mode: CassetteMode::Record,
+            path: path.to_string(),
+            tape: Mutex::new(Tape::default()),
+        }
+    }
+
+    /// Answer requests from a cassette recorded earlier
+    pub fn replay(path: &str) -> Result<Self> {
+        let json = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read cassette {}", path))?;
+        let file: CassetteFile = serde_json::from_str(&json).context("Invalid cassette file")?;
+
+        Ok(Self {
+            mode: CassetteMode::Replay,
+            path: path.to_string(),
+            tape: Mutex::new(Tape {
+                played: vec![false; file.interactions.len()],
+                interactions: file.interactions,
+            }),
+        })
+    }
+
+    pub fn from_config(config: &CoreConfig) -> Result<Self> {
+        match config.cassette_mode {
+            CassetteMode::Off => Ok(Self::off()),
+            CassetteMode::Record => Ok(Self::record(&config.cassette_file)),
+            CassetteMode::Replay => Self::replay(&config.cassette_file),
+        }
+    }
+
+    pub fn mode(&self) -> CassetteMode {
+        self.mode
+    }
+
+    pub fn interactions(&self) -> Vec<Interaction> {
+        self.tape.lock().unwrap().interactions.clone()
+    }
+
+    /// Send a provider request, recording or replaying it depending on the mode
+    pub async fn send(&self, request: RequestBuilder) -> Result<Response, CoreError> {
+        if self.mode == CassetteMode::Off {
+            return Ok(request.send().await?);
+        }
+
+        let (client, request) = request.build_split();
+        let request = request?;
+        let recorded = RecordedRequest::from_request(&request);
+
+        match self.mode {
+            CassetteMode::Replay => {
+                let response = self.find(&recorded).ok_or_else(|| {
+                    CoreError::Cassette(format!(
+                        "no recorded response for {} {} in {}",
+                        recorded.method, recorded.url, self.path
+                    ))
+                })?;
+                Ok(response.to_response())
+            }
+            _ => {
+                // the whole body is read before it is handed on, so streams arrive in one go
+                let response = client.execute(request).await?;
+                let status = response.status().as_u16();
+                let headers = scrub_headers(response.headers());
+                let body = response.text().await?;
+
+                let response = RecordedResponse {
+                    status,
+                    headers,
+                    body,
+                };
+                self.save(Interaction {
+                    request: recorded,
+                    response: response.clone(),
+                })?;
+                Ok(response.to_response())
+            }
+        }
+    }
+
+    // the first matching interaction that hasn't been replayed yet, or the last match again
+    fn find(&self, request: &RecordedRequest) -> Option<RecordedResponse> {
+        let mut tape = self.tape.lock().unwrap();
+        let matches: Vec<usize> = (0..tape.interactions.len())
+            .filter(|&i| tape.interactions[i].request.matches(request))
+            .collect();
+
+        let index = matches
+            .iter()
+            .find(|&&i| !tape.played[i])
+            .or(matches.last())
+            .copied()?;
+        tape.played[index] = true;
+        Some(tape.interactions[index].response.clone())
+    }
+
+    // the file is rewritten after every request so a recording survives the process being killed
+    fn save(&self, interaction: Interaction) -> Result<(), CoreError> {
+        let mut tape = self.tape.lock().unwrap();
+        tape.interactions.push(interaction);
+        tape.played.push(false);
+
+        let file = CassetteFile {
+            interactions: tape.interactions.clone(),
+        };
+        let json = serde_json::to_string_pretty(&file)?;
+        write_file(&self.path, &json).map_err(|e| {
+            CoreError::Cassette(format!("failed to write cassette {}: {}", self.path, e))
+        })
+    }
+}
+
+impl RecordedRequest {
+    fn from_request(request: &Request) -> Self {
+        let body = request
+            .body()
+            .and_then(|body| body.as_bytes())
+            .map(|bytes| {
+                serde_json::from_slice(bytes)
+                    .unwrap_or_else(|_| Value::String(String::from_utf8_lossy(bytes).to_string()))
+            });
+
+        Self {
+            method: request.method().to_string(),
+            url: request.url().to_string(),
+            headers: scrub_headers(request.headers()),
+            body,
+        }
+    }
+
+    // headers are left out, the scrubbed credentials would never match
+    fn matches(&self, other: &RecordedRequest) -> bool {
+        self.method.eq_ignore_ascii_case(&other.method)
+            && self.url == other.url
+            && self.body == other.body
+    }
+}
+
+impl RecordedResponse {
+    fn to_response(&self) -> Response {
+        let mut builder = http::Response::builder().status(self.status);
+        for (name, value) in &self.headers {
+            builder = builder.header(name, value);
+        }
+        let response = builder
+            .body(self.body.clone())
+            .unwrap_or_else(|_| http::Response::new(self.body.clone()));
+        Response::from(response)
+    }
+}
+
+fn scrub_headers(headers: &HeaderMap) -> BTreeMap<String, String> {
+    headers
+        .iter()
+        .map(|(name, value)| {
+            let value = if SECRET_HEADERS.contains(&name.as_str()) {
+                SCRUBBED.to_string()
+            } else {
+                String::from_utf8_lossy(value.as_bytes()).to_string()
+            };
+            (name.to_string(), value)
+        })
+        .collect()
+}
+
+fn write_file(path: &str, contents: &str) -> std::io::Result<()> {
+    if let Some(parent) = Path::new(path).parent() {
+        if !parent.as_os_str().is_empty() {
+            std::fs::create_dir_all(parent)?;
+        }
+    }
+    std::fs::write(path, contents)
+}
+
+static CASSETTE: OnceLock<Cassette> = OnceLock::new();
+
+/// Cassette every built in provider sends its requests through, from CASSETTE_MODE and CASSETTE_FILE
+pub fn cassette() -> &'static Cassette {
+    CASSETTE
+        .get_or_init(|| Cassette::from_config(CoreConfig::get()).expect("Failed to load cassette"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::mock_server;
+    use axum::http::HeaderMap as AxumHeaderMap;
+    use axum::routing::{get, post};
+    use axum::{Json, Router};
+    use reqwest::Client;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::Arc;
+
+    async fn mock_provider(calls: Arc<AtomicU32>) -> String {
+        let app = Router::new()
+            .route(
+                "/v1/messages",
+                post(move |headers: AxumHeaderMap, Json(body): Json<Value>| {
+                    let call = calls.fetch_add(1, Ordering::SeqCst) + 1;
+                    // the real key still reaches the provider while recording
+                    assert_eq!(headers["x-api-key"], "secret-key");
+                    async move { format!("reply {} to {}", call, body["prompt"]) }
+                }),
+            )
+            .route(
+                "/v1/models",
+                get(|| async { ([("set-cookie", "session=abc")], "models") }),
+            );
+        format!("{}/v1", mock_server(app).await)
+    }
+
+    fn cassette_path(name: &str) -> String {
+        std::env::temp_dir()
+            .join(format!("kubellm-{}-{}.json", name, std::process::id()))
+            .to_string_lossy()
+            .to_string()
+    }
+
+    fn message(client: &Client, base_url: &str, prompt: &str) -> RequestBuilder {
+        client
+            .post(format!("{}/messages", base_url))
+            .header("x-api-key", "secret-key")
+            .json(&serde_json::json!({ "prompt": prompt }))
+    }
+
+    #[tokio::test]
+    async fn test_record_then_replay() {
+        let calls = Arc::new(AtomicU32::new(0));
+        let base_url = mock_provider(calls.clone()).await;
+        let path = cassette_path("record-replay");
+        let client = Client::new();
+
+        let recorder = Cassette::record(&path);
+        for prompt in ["hi", "hi", "bye"] {
+            let response = recorder
+                .send(message(&client, &base_url, prompt))
+                .await
+                .unwrap();
+            assert!(response.status().is_success());
+        }
+        let models = recorder
+            .send(client.get(format!("{}/models", base_url)))
+            .await
+            .unwrap();
+        assert_eq!(models.text().await.unwrap(), "models");
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+
+        // secrets are kept out of the file
+        let saved = std::fs::read_to_string(&path).unwrap();
+        assert!(!saved.contains("secret-key"));
+        let interactions = recorder.interactions();
+        assert_eq!(interactions.len(), 4);
+        assert_eq!(interactions[0].request.headers["x-api-key"], SCRUBBED);
+        assert_eq!(
+            interactions[0].request.body,
+            Some(serde_json::json!({"prompt": "hi"}))
+        );
+        assert_eq!(interactions[3].response.headers["set-cookie"], SCRUBBED);
+
+        // replayed in recorded order, without reaching the server
+        let player = Cassette::replay(&path).unwrap();
+        let mut replies = Vec::new();
+        for prompt in ["hi", "bye", "hi", "hi"] {
+            let response = player
+                .send(message(&client, &base_url, prompt))
+                .await
+                .unwrap();
+            replies.push(response.text().await.unwrap());
+        }
+        assert_eq!(
+            replies,
+            vec![
+                "reply 1 to \"hi\"",
+                "reply 3 to \"bye\"",
+                "reply 2 to \"hi\"",
+                "reply 2 to \"hi\"",
+            ]
+        );
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+
+        let missing = player
+            .send(message(&client, &base_url, "unrecorded"))
+            .await
+            .unwrap_err();
+        assert!(matches!(missing, CoreError::Cassette(_)));
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_replay_status_and_headers() {
+        let path = cassette_path("status");
+        let file = serde_json::json!({
+            "interactions": [{
+                "request": { "method": "GET", "url": "http://provider.test/v1/models" },
+                "response": {
+                    "status": 429,
+                    "headers": { "retry-after": "7" },
+                    "body": "{\"error\":{\"type\":\"rate_limit_error\"}}"
+                }
+            }]
+        });
+        std::fs::write(&path, file.to_string()).unwrap();
+
+        let player = Cassette::replay(&path).unwrap();
+        let response = player
+            .send(Client::new().get("http://provider.test/v1/models"))
+            .await
+            .unwrap();
+        match CoreError::from_response("Anthropic", response).await {
+            CoreError::RateLimited { retry_after, .. } => {
+                assert_eq!(retry_after, Some(std::time::Duration::from_secs(7)))
+            }
+            other => panic!("expected a rate limit error, got {:?}", other),
+        }
+
+        std::fs::remove_file(&path).unwrap();
+        assert!(Cassette::replay(&path).is_err());
+    }
+}
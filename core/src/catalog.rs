@@ -0,0 +1,194 @@
+use crate::config::CoreConfig;
+use crate::error::CoreError;
+use crate::models::ModelCatalogEntry;
+use crate::provider::LlmProvider;
+use crate::retry::RetryPolicy;
+use crate::store::PromptStore;
+use chrono::{Duration, Utc};
+use std::collections::HashMap;
+use std::sync::{OnceLock, RwLock};
+
+/// Providers' model lists, kept for a TTL so prompts don't list the provider's models every time.
+/// Entries are held in memory and, when a prompt store is passed in, in the model_catalog
+/// table so they survive restarts and are shared by the api and cli.
+pub struct ModelCatalog {
+    ttl: Duration,
+    entries: RwLock<HashMap<String, ModelCatalogEntry>>,
+}
+
+impl ModelCatalog {
+    pub fn new(ttl: std::time::Duration) -> Self {
+        Self {
+            ttl: Duration::from_std(ttl).unwrap_or(Duration::MAX),
+            entries: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// The provider's entry from memory if it hasn't expired
+    pub fn cached(&self, provider: &str) -> Option<ModelCatalogEntry> {
+        self.entries
+            .read()
+            .unwrap()
+            .get(provider)
+            .filter(|entry| self.is_fresh(entry))
+            .cloned()
+    }
+
+    /// The provider's models, fetched from the provider only when no cached entry is fresh
+    pub async fn models(
+        &self,
+        provider: &dyn LlmProvider,
+        retry: &RetryPolicy,
+        store: Option<&dyn PromptStore>,
+    ) -> Result<ModelCatalogEntry, CoreError> {
+        if let Some(entry) = self.cached(provider.name()) {
+            return Ok(entry);
+        }
+
+        if let Some(store) = store {
+            if let Some(entry) = store.get_model_catalog(provider.name()).await? {
+                if self.is_fresh(&entry) {
+                    self.insert(entry.clone());
+                    return Ok(entry);
+                }
+            }
+        }
+
+        self.refresh(provider, retry, store).await
+    }
+
+    /// Fetch the provider's models now, replacing any cached entry
+    pub async fn refresh(
+        &self,
+        provider: &dyn LlmProvider,
+        retry: &RetryPolicy,
+        store: Option<&dyn PromptStore>,
+    ) -> Result<ModelCatalogEntry, CoreError> {
+        let (models, _) = retry.run(|| provider.list_model_info()).await?;
+        let entry = ModelCatalogEntry {
+            provider: provider.name().to_string(),
+            models,
+            fetched_at: Utc::now(),
+        };
+
+        if let Some(store) = store {
+            store.save_model_catalog(&entry).await?;
+        }
+        self.insert(entry.clone());
+
+        Ok(entry)
+    }
+
+    pub fn insert(&self, entry: ModelCatalogEntry) {
+        self.entries
+            .write()
+            .unwrap()
+            .insert(entry.provider.clone(), entry);
+    }
+
+    fn is_fresh(&self, entry: &ModelCatalogEntry) -> bool {
+        Utc::now() - entry.fetched_at < self.ttl
+    }
+}
+
+static CATALOG: OnceLock<ModelCatalog> = OnceLock::new();
+
+/// Catalog shared by every prompt, entries expire after MODEL_CACHE_TTL_SECS
+pub fn model_catalog() -> &'static ModelCatalog {
+    CATALOG.get_or_init(|| {
+        ModelCatalog::new(std::time::Duration::from_secs(
+            CoreConfig::get().model_cache_ttl_secs,
+        ))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::ModelInfo;
+    use crate::provider::{Completion, CompletionRequest};
+    use async_trait::async_trait;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    // counts how often its models are listed
+    struct CountingProvider {
+        calls: AtomicU32,
+    }
+
+    #[async_trait]
+    impl LlmProvider for CountingProvider {
+        fn name(&self) -> &str {
+            "Counting"
+        }
+
+        fn default_model(&self) -> String {
+            "count-1".to_string()
+        }
+
+        async fn list_models(&self) -> Result<Vec<String>, CoreError> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            Ok(vec!["count-1".to_string()])
+        }
+
+        async fn complete(&self, _request: &CompletionRequest) -> Result<Completion, CoreError> {
+            Err(CoreError::Other("Counting only lists models".to_string()))
+        }
+    }
+
+    fn no_retries() -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: 1,
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_models_are_cached() {
+        let catalog = ModelCatalog::new(std::time::Duration::from_secs(60));
+        let provider = CountingProvider {
+            calls: AtomicU32::new(0),
+        };
+
+        let entry = catalog
+            .models(&provider, &no_retries(), None)
+            .await
+            .unwrap();
+        assert_eq!(entry.provider, "Counting");
+        assert_eq!(entry.models, vec![ModelInfo::new("count-1")]);
+
+        catalog
+            .models(&provider, &no_retries(), None)
+            .await
+            .unwrap();
+        assert_eq!(provider.calls.load(Ordering::SeqCst), 1);
+
+        // a refresh always goes to the provider
+        catalog
+            .refresh(&provider, &no_retries(), None)
+            .await
+            .unwrap();
+        assert_eq!(provider.calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_expired_models_are_fetched_again() {
+        let catalog = ModelCatalog::new(std::time::Duration::from_secs(60));
+        let provider = CountingProvider {
+            calls: AtomicU32::new(0),
+        };
+
+        catalog.insert(ModelCatalogEntry {
+            provider: "Counting".to_string(),
+            models: vec![ModelInfo::new("count-0")],
+            fetched_at: Utc::now() - Duration::minutes(5),
+        });
+        assert!(catalog.cached("Counting").is_none());
+
+        let entry = catalog
+            .models(&provider, &no_retries(), None)
+            .await
+            .unwrap();
+        assert_eq!(entry.models[0].id, "count-1");
+        assert_eq!(provider.calls.load(Ordering::SeqCst), 1);
+    }
+}
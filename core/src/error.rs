@@ -0,0 +1,401 @@
+use crate::budget::BudgetExceeded;
+use crate::gemini::GeminiSafetyBlock;
+use crate::template::TemplateError;
+use reqwest::header::RETRY_AFTER;
+use reqwest::StatusCode;
+use std::fmt;
+use std::time::Duration;
+
+/// Everything that can go wrong while listing models or prompting a provider.
+/// Errors need to cross task boundaries for streamed responses so every variant is Send + Sync.
+#[derive(Debug)]
+pub enum CoreError {
+    /// The api key the provider needs isn't set
+    MissingCredentials {
+        provider: String,
+        variable: String,
+    },
+    /// Some other setting the provider needs, such as its base url, isn't set
+    NotConfigured {
+        provider: String,
+        variable: String,
+    },
+    UnknownProvider(String),
+    /// The model isn't one the provider lists, suggestions are listed models with similar names
+    InvalidModel {
+        provider: String,
+        model: String,
+        suggestions: Vec<String>,
+    },
+    NoModels {
+        provider: String,
+    },
+    /// The model doesn't take some of the generation params the caller set
+    UnsupportedParams {
+        provider: String,
+        model: String,
+        params: Vec<String>,
+    },
+    ConversationNotFound(i64),
+    TemplateNotFound(i64),
+    /// A template couldn't be saved or its variables didn't fit it
+    Template(TemplateError),
+    /// The provider answered with an error status, body is the provider's error response
+    ProviderStatus {
+        provider: String,
+        status: u16,
+        retry_after: Option<Duration>,
+        body: String,
+    },
+    /// The provider refused the request because a rate limit was reached
+    RateLimited {
+        provider: String,
+        retry_after: Option<Duration>,
+        body: String,
+    },
+    /// The provider reported an error without an http status, e.g. part way through a stream
+    Provider {
+        provider: String,
+        message: String,
+    },
+    Timeout(String),
+    /// The provider couldn't be reached or the connection dropped
+    Connection(String),
+    /// A provider response couldn't be parsed
+    Parse(String),
+    /// A replayed request isn't in the cassette, or a recording couldn't be saved
+    Cassette(String),
+    Database(sqlx::Error),
+    SafetyBlock(GeminiSafetyBlock),
+    BudgetExceeded(BudgetExceeded),
+    /// Errors from providers registered outside kubellm-core
+    Other(String),
+}
+
+impl CoreError {
+    pub fn missing_credentials(provider: &str, variable: &str) -> Self {
+        CoreError::MissingCredentials {
+            provider: provider.to_string(),
+            variable: variable.to_string(),
+        }
+    }
+
+    pub fn provider(provider: &str, message: impl Into<String>) -> Self {
+        CoreError::Provider {
+            provider: provider.to_string(),
+            message: message.into(),
+        }
+    }
+
+    /// Error for an unsuccessful provider response, 429s become RateLimited
+    pub async fn from_response(provider: &str, response: reqwest::Response) -> Self {
+        let status = response.status();
+        // only the delay-seconds form of Retry-After is used by the providers
+        let retry_after = response
+            .headers()
+            .get(RETRY_AFTER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.trim().parse::<u64>().ok())
+            .map(Duration::from_secs);
+
+        let body = match response.text().await {
+            Ok(body) => body,
+            Err(e) => return e.into(),
+        };
+
+        if status == StatusCode::TOO_MANY_REQUESTS {
+            CoreError::RateLimited {
+                provider: provider.to_string(),
+                retry_after,
+                body,
+            }
+        } else {
+            CoreError::ProviderStatus {
+                provider: provider.to_string(),
+                status: status.as_u16(),
+                retry_after,
+                body,
+            }
+        }
+    }
+
+    /// Whether trying the same request again could succeed: rate limits, overloaded or
+    /// failing provider servers and dropped connections, but not bad requests or credentials
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            CoreError::RateLimited { .. } | CoreError::Timeout(_) | CoreError::Connection(_) => {
+                true
+            }
+            // 529 is Anthropic's overloaded_error
+            CoreError::ProviderStatus { status, body, .. } => {
+                matches!(status, 408 | 409 | 500..=599) || body.contains("overloaded_error")
+            }
+            _ => false,
+        }
+    }
+
+    /// How long the provider asked to be left alone for with its Retry-After header
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            CoreError::RateLimited { retry_after, .. }
+            | CoreError::ProviderStatus { retry_after, .. } => *retry_after,
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for CoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CoreError::MissingCredentials { provider, variable } => {
+                write!(
+                    f,
+                    "{} is not set, it is needed to use {}",
+                    variable, provider
+                )
+            }
+            CoreError::NotConfigured { provider, variable } => {
+                write!(
+                    f,
+                    "{} is not set, it is needed to use {}",
+                    variable, provider
+                )
+            }
+            CoreError::UnknownProvider(name) => write!(f, "Unknown provider: {}", name),
+            CoreError::InvalidModel {
+                provider,
+                model,
+                suggestions,
+            } => {
+                write!(f, "Unknown model for {}: {}", provider, model)?;
+                if !suggestions.is_empty() {
+                    write!(f, ", did you mean {}?", suggestions.join(", "))?;
+                }
+                Ok(())
+            }
+            CoreError::NoModels { provider } => write!(f, "No models available for {}", provider),
+            CoreError::UnsupportedParams {
+                provider,
+                model,
+                params,
+            } => write!(
+                f,
+                "{} model {} doesn't support {}, leave them out of the request",
+                provider,
+                model,
+                params.join(", ")
+            ),
+            CoreError::ConversationNotFound(id) => write!(f, "Conversation not found: {}", id),
+            CoreError::TemplateNotFound(id) => write!(f, "Template not found: {}", id),
+            CoreError::Template(e) => e.fmt(f),
+            CoreError::ProviderStatus {
+                provider,
+                status,
+                body,
+                ..
+            } => write!(
+                f,
+                "{} API request failed with status {}: {}",
+                provider, status, body
+            ),
+            CoreError::RateLimited {
+                provider,
+                retry_after,
+                body,
+            } => {
+                write!(f, "{} rate limit reached", provider)?;
+                if let Some(retry_after) = retry_after {
+                    write!(f, ", retry after {}s", retry_after.as_secs())?;
+                }
+                write!(f, ": {}", body)
+            }
+            CoreError::Provider { provider, message } => {
+                write!(f, "{} API error: {}", provider, message)
+            }
+            CoreError::Timeout(message) => write!(f, "Request timed out: {}", message),
+            CoreError::Connection(message) => write!(f, "Request failed: {}", message),
+            CoreError::Parse(message) => write!(f, "Failed to parse response: {}", message),
+            CoreError::Cassette(message) => write!(f, "Cassette error: {}", message),
+            CoreError::Database(e) => write!(f, "Database error: {}", e),
+            CoreError::SafetyBlock(block) => block.fmt(f),
+            CoreError::BudgetExceeded(exceeded) => exceeded.fmt(f),
+            CoreError::Other(message) => f.write_str(message),
+        }
+    }
+}
+
+impl std::error::Error for CoreError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            CoreError::Database(e) => Some(e),
+            CoreError::SafetyBlock(block) => Some(block),
+            CoreError::BudgetExceeded(exceeded) => Some(exceeded),
+            CoreError::Template(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for CoreError {
+    fn from(error: reqwest::Error) -> Self {
+        if error.is_timeout() {
+            CoreError::Timeout(error.to_string())
+        } else if error.is_decode() {
+            CoreError::Parse(error.to_string())
+        } else {
+            CoreError::Connection(error.to_string())
+        }
+    }
+}
+
+impl From<serde_json::Error> for CoreError {
+    fn from(error: serde_json::Error) -> Self {
+        CoreError::Parse(error.to_string())
+    }
+}
+
+impl From<sqlx::Error> for CoreError {
+    fn from(error: sqlx::Error) -> Self {
+        CoreError::Database(error)
+    }
+}
+
+impl From<GeminiSafetyBlock> for CoreError {
+    fn from(block: GeminiSafetyBlock) -> Self {
+        CoreError::SafetyBlock(block)
+    }
+}
+
+impl From<TemplateError> for CoreError {
+    fn from(error: TemplateError) -> Self {
+        CoreError::Template(error)
+    }
+}
+
+impl From<BudgetExceeded> for CoreError {
+    fn from(exceeded: BudgetExceeded) -> Self {
+        CoreError::BudgetExceeded(exceeded)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::mock_server;
+    use axum::http::HeaderMap;
+    use axum::routing::get;
+    use axum::Router;
+
+    async fn error_response(app: Router) -> CoreError {
+        let response = reqwest::get(format!("{}/", mock_server(app).await))
+            .await
+            .unwrap();
+        CoreError::from_response("OpenAI", response).await
+    }
+
+    #[tokio::test]
+    async fn test_from_response_status() {
+        let app = Router::new().route(
+            "/",
+            get(|| async {
+                (
+                    axum::http::StatusCode::UNAUTHORIZED,
+                    r#"{"error":{"message":"Incorrect API key provided"}}"#,
+                )
+            }),
+        );
+
+        match error_response(app).await {
+            CoreError::ProviderStatus {
+                provider,
+                status,
+                body,
+                ..
+            } => {
+                assert_eq!(provider, "OpenAI");
+                assert_eq!(status, 401);
+                assert!(body.contains("Incorrect API key"));
+            }
+            other => panic!("expected a provider status error, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_from_response_rate_limited() {
+        let app = Router::new().route(
+            "/",
+            get(|| async {
+                let mut headers = HeaderMap::new();
+                headers.insert("retry-after", "20".parse().unwrap());
+                (
+                    axum::http::StatusCode::TOO_MANY_REQUESTS,
+                    headers,
+                    "Rate limit reached for requests",
+                )
+            }),
+        );
+
+        let error = error_response(app).await;
+        assert!(matches!(
+            error,
+            CoreError::RateLimited { retry_after: Some(delay), .. } if delay == Duration::from_secs(20)
+        ));
+        assert_eq!(
+            error.to_string(),
+            "OpenAI rate limit reached, retry after 20s: Rate limit reached for requests"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_from_response_overloaded_retry_after() {
+        let app = Router::new().route(
+            "/",
+            get(|| async {
+                let mut headers = HeaderMap::new();
+                headers.insert("retry-after", "3".parse().unwrap());
+                (
+                    axum::http::StatusCode::from_u16(529).unwrap(),
+                    headers,
+                    r#"{"type":"error","error":{"type":"overloaded_error","message":"Overloaded"}}"#,
+                )
+            }),
+        );
+
+        let error = error_response(app).await;
+        assert!(error.is_retryable());
+        assert_eq!(error.retry_after(), Some(Duration::from_secs(3)));
+    }
+
+    #[test]
+    fn test_is_retryable() {
+        let status = |status: u16| CoreError::ProviderStatus {
+            provider: "OpenAI".to_string(),
+            status,
+            retry_after: None,
+            body: String::new(),
+        };
+        assert!(status(500).is_retryable());
+        assert!(status(503).is_retryable());
+        assert!(!status(400).is_retryable());
+        assert!(!status(401).is_retryable());
+        assert!(CoreError::Timeout("deadline".to_string()).is_retryable());
+        assert!(!CoreError::missing_credentials("OpenAI", "OPENAI_KEY").is_retryable());
+    }
+
+    #[test]
+    fn test_parse_error_from_json() {
+        let error: CoreError = serde_json::from_str::<serde_json::Value>("{not json")
+            .unwrap_err()
+            .into();
+        assert!(matches!(error, CoreError::Parse(_)));
+    }
+
+    #[test]
+    fn test_missing_credentials_message() {
+        let error = CoreError::missing_credentials("OpenAI", "OPENAI_KEY");
+        assert_eq!(
+            error.to_string(),
+            "OPENAI_KEY is not set, it is needed to use OpenAI"
+        );
+    }
+}
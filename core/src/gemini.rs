@@ -0,0 +1,691 @@
+use crate::error::CoreError;
+use crate::models::{ModelInfo, TokenUsage};
+use crate::provider::{
+    ChatMessage, Completion, CompletionRequest, LlmProvider, StreamChunk, TokenStream,
+};
+use crate::sse::{sse_chunks, SseEvent};
+use crate::CoreConfig;
+use async_trait::async_trait;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+// Request structures
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GeminiRequest {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub system_instruction: Option<GeminiContent>,
+    pub contents: Vec<GeminiContent>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub generation_config: Option<GeminiGenerationConfig>,
+}
+
+impl From<&CompletionRequest> for GeminiRequest {
+    fn from(request: &CompletionRequest) -> Self {
+        let params = &request.params;
+        Self {
+            // the system prompt is sent as an instruction without a role
+            system_instruction: params.system.as_ref().map(|system| GeminiContent {
+                role: None,
+                parts: vec![GeminiPart {
+                    text: Some(system.clone()),
+                }],
+            }),
+            contents: request.messages.iter().map(GeminiContent::from).collect(),
+            generation_config: Some(GeminiGenerationConfig {
+                temperature: Some(params.temperature.unwrap_or(0.5)),
+                max_output_tokens: Some(params.max_tokens.unwrap_or(1024)),
+                top_p: params.top_p,
+                stop_sequences: params.stop.clone(),
+                seed: params.seed,
+            }),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GeminiGenerationConfig {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_output_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stop_sequences: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seed: Option<i64>,
+}
+
+// a turn in the conversation, gemini calls the assistant role "model"
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GeminiContent {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub role: Option<String>,
+    #[serde(default)]
+    pub parts: Vec<GeminiPart>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GeminiPart {
+    #[serde(default)]
+    pub text: Option<String>,
+}
+
+impl From<&ChatMessage> for GeminiContent {
+    fn from(message: &ChatMessage) -> Self {
+        let role = match message.role.as_str() {
+            "assistant" => "model",
+            role => role,
+        };
+        Self {
+            role: Some(role.to_string()),
+            parts: vec![GeminiPart {
+                text: Some(message.content.clone()),
+            }],
+        }
+    }
+}
+
+// Response structures, streamed responses send one of these per event
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GeminiResponse {
+    #[serde(default)]
+    pub candidates: Vec<GeminiCandidate>,
+    pub prompt_feedback: Option<GeminiPromptFeedback>,
+    pub usage_metadata: Option<GeminiUsageMetadata>,
+    pub model_version: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GeminiUsageMetadata {
+    pub prompt_token_count: Option<u32>,
+    pub candidates_token_count: Option<u32>,
+    pub thoughts_token_count: Option<u32>,
+    pub cached_content_token_count: Option<u32>,
+}
+
+impl From<&GeminiUsageMetadata> for TokenUsage {
+    fn from(usage: &GeminiUsageMetadata) -> Self {
+        Self {
+            input_tokens: usage.prompt_token_count,
+            // thinking tokens are billed as output but not counted in candidates_token_count
+            output_tokens: usage
+                .candidates_token_count
+                .map(|output| output + usage.thoughts_token_count.unwrap_or(0)),
+            reasoning_tokens: usage.thoughts_token_count,
+            cached_tokens: usage.cached_content_token_count,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GeminiCandidate {
+    pub content: Option<GeminiContent>,
+    pub finish_reason: Option<String>,
+    #[serde(default)]
+    pub safety_ratings: Vec<GeminiSafetyRating>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GeminiPromptFeedback {
+    pub block_reason: Option<String>,
+    #[serde(default)]
+    pub safety_ratings: Vec<GeminiSafetyRating>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct GeminiSafetyRating {
+    pub category: String,
+    pub probability: String,
+    #[serde(default)]
+    pub blocked: bool,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GeminiModelsResponse {
+    #[serde(default)]
+    pub models: Vec<GeminiModel>,
+    pub next_page_token: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GeminiModel {
+    // always prefixed with "models/"
+    pub name: String,
+    pub display_name: Option<String>,
+    #[serde(default)]
+    pub supported_generation_methods: Vec<String>,
+}
+
+impl GeminiModel {
+    // the id used in generateContent urls
+    pub fn id(&self) -> &str {
+        self.name.strip_prefix("models/").unwrap_or(&self.name)
+    }
+}
+
+// finish reasons that mean the response was withheld rather than completed
+const BLOCKED_FINISH_REASONS: [&str; 5] = [
+    "SAFETY",
+    "RECITATION",
+    "BLOCKLIST",
+    "PROHIBITED_CONTENT",
+    "SPII",
+];
+
+/// Returned instead of an empty completion when Gemini blocks the prompt or its response.
+/// Callers get it back as `CoreError::SafetyBlock`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GeminiSafetyBlock {
+    pub reason: String,
+    // categories of the ratings that caused the block
+    pub categories: Vec<String>,
+}
+
+impl fmt::Display for GeminiSafetyBlock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Gemini blocked the response ({})", self.reason)?;
+        if !self.categories.is_empty() {
+            write!(f, ": {}", self.categories.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for GeminiSafetyBlock {}
+
+fn safety_block(reason: &str, ratings: &[GeminiSafetyRating]) -> GeminiSafetyBlock {
+    GeminiSafetyBlock {
+        reason: reason.to_string(),
+        categories: ratings
+            .iter()
+            .filter(|r| r.blocked)
+            .map(|r| r.category.clone())
+            .collect(),
+    }
+}
+
+impl GeminiResponse {
+    pub fn usage(&self) -> TokenUsage {
+        self.usage_metadata
+            .as_ref()
+            .map(TokenUsage::from)
+            .unwrap_or_default()
+    }
+
+    pub fn finish_reason(&self) -> Option<String> {
+        self.candidates
+            .first()
+            .and_then(|candidate| candidate.finish_reason.clone())
+    }
+
+    /// Text of the first candidate, or a GeminiSafetyBlock error if the prompt or candidate was blocked
+    pub fn text(&self) -> Result<String, CoreError> {
+        if let Some(feedback) = &self.prompt_feedback {
+            if let Some(reason) = &feedback.block_reason {
+                return Err(safety_block(reason, &feedback.safety_ratings).into());
+            }
+        }
+
+        let Some(candidate) = self.candidates.first() else {
+            return Ok(String::new());
+        };
+
+        if let Some(reason) = &candidate.finish_reason {
+            if BLOCKED_FINISH_REASONS.contains(&reason.as_str()) {
+                return Err(safety_block(reason, &candidate.safety_ratings).into());
+            }
+        }
+
+        Ok(candidate
+            .content
+            .iter()
+            .flat_map(|content| content.parts.iter())
+            .filter_map(|part| part.text.as_deref())
+            .collect())
+    }
+}
+
+// chunks carried by a streamed event, every event repeats the usage so far
+fn gemini_stream_chunks(event: &SseEvent) -> Result<Vec<StreamChunk>, CoreError> {
+    let response: GeminiResponse = serde_json::from_str(&event.data)?;
+    let text = response.text()?;
+
+    let mut chunks = Vec::new();
+    if !text.is_empty() {
+        chunks.push(StreamChunk::Text(text));
+    }
+    if response.usage_metadata.is_some() {
+        chunks.push(StreamChunk::Usage(response.usage()));
+    }
+    chunks.extend(response.finish_reason().map(StreamChunk::Finish));
+    Ok(chunks)
+}
+
+pub struct GeminiProvider {
+    client: Client,
+    base_url: Option<String>,
+    api_key: Option<String>,
+    default_model: Option<String>,
+}
+
+impl Default for GeminiProvider {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl GeminiProvider {
+    pub fn new() -> Self {
+        Self {
+            client: Client::new(),
+            base_url: None,
+            api_key: None,
+            default_model: None,
+        }
+    }
+
+    pub fn with_base_url(mut self, base_url: &str) -> Self {
+        self.base_url = Some(base_url.to_string());
+        self
+    }
+
+    pub fn with_api_key(mut self, api_key: &str) -> Self {
+        self.api_key = Some(api_key.to_string());
+        self
+    }
+
+    pub fn with_default_model(mut self, model: &str) -> Self {
+        self.default_model = Some(model.to_string());
+        self
+    }
+
+    fn base_url(&self) -> String {
+        self.base_url
+            .clone()
+            .unwrap_or_else(|| CoreConfig::get().gemini_url.clone())
+    }
+
+    fn api_key(&self) -> Result<String, CoreError> {
+        self.api_key
+            .clone()
+            .or_else(|| CoreConfig::get().gemini_key.clone())
+            .ok_or_else(|| CoreError::missing_credentials(self.name(), "GEMINI_KEY"))
+    }
+
+    async fn send_generate_request(
+        &self,
+        request: &CompletionRequest,
+        stream: bool,
+    ) -> Result<reqwest::Response, CoreError> {
+        let api_key = self.api_key()?;
+
+        let gemini_request = GeminiRequest::from(request);
+
+        // the streaming endpoint only sends server-sent events when asked to with alt=sse
+        let url = if stream {
+            format!(
+                "{}/models/{}:streamGenerateContent?alt=sse",
+                self.base_url(),
+                request.model
+            )
+        } else {
+            format!(
+                "{}/models/{}:generateContent",
+                self.base_url(),
+                request.model
+            )
+        };
+
+        let response = self
+            .client
+            .post(url)
+            .header("x-goog-api-key", api_key)
+            .header("content-type", "application/json")
+            .json(&gemini_request)
+            .send()
+            .await?;
+
+        Ok(response)
+    }
+
+    /// Get the models that support generateContent, following every page of the listing
+    pub async fn get_models(&self) -> Result<Vec<GeminiModel>, CoreError> {
+        let api_key = self.api_key()?;
+        let mut models: Vec<GeminiModel> = Vec::new();
+        let mut page_token: Option<String> = None;
+
+        loop {
+            let mut query = vec![("pageSize", "1000".to_string())];
+            if let Some(token) = &page_token {
+                query.push(("pageToken", token.clone()));
+            }
+
+            let response = self
+                .client
+                .get(format!("{}/models", self.base_url()))
+                .header("x-goog-api-key", &api_key)
+                .query(&query)
+                .send()
+                .await?;
+
+            if !response.status().is_success() {
+                return Err(CoreError::from_response(self.name(), response).await);
+            }
+
+            let models_response: GeminiModelsResponse = response.json().await?;
+            models.extend(models_response.models.into_iter().filter(|m| {
+                m.supported_generation_methods
+                    .iter()
+                    .any(|method| method == "generateContent")
+            }));
+
+            match models_response.next_page_token {
+                Some(token) if !token.is_empty() => page_token = Some(token),
+                _ => break,
+            }
+        }
+
+        models.sort_by(|a, b| a.id().cmp(b.id()));
+        Ok(models)
+    }
+}
+
+#[async_trait]
+impl LlmProvider for GeminiProvider {
+    fn name(&self) -> &str {
+        "Gemini"
+    }
+
+    fn default_model(&self) -> String {
+        self.default_model
+            .clone()
+            .unwrap_or_else(|| CoreConfig::get().default_gemini_model.clone())
+    }
+
+    async fn list_models(&self) -> Result<Vec<String>, CoreError> {
+        let models = self.get_models().await?;
+        Ok(models.iter().map(|m| m.id().to_string()).collect())
+    }
+
+    async fn list_model_info(&self) -> Result<Vec<ModelInfo>, CoreError> {
+        let models = self.get_models().await?;
+        Ok(models
+            .into_iter()
+            .map(|m| ModelInfo {
+                id: m.id().to_string(),
+                display_name: m.display_name,
+                created_at: None,
+            })
+            .collect())
+    }
+
+    async fn complete(&self, request: &CompletionRequest) -> Result<Completion, CoreError> {
+        let response = self.send_generate_request(request, false).await?;
+
+        if response.status().is_success() {
+            let gemini_response: GeminiResponse = response.json().await?;
+            let text = gemini_response.text()?;
+            if gemini_response.candidates.is_empty() {
+                return Err(CoreError::provider(self.name(), "no candidates returned"));
+            }
+
+            Ok(Completion {
+                text,
+                usage: gemini_response.usage(),
+                finish_reason: gemini_response.finish_reason(),
+                model: gemini_response
+                    .model_version
+                    .unwrap_or_else(|| request.model.clone()),
+            })
+        } else {
+            Err(CoreError::from_response(self.name(), response).await)
+        }
+    }
+
+    async fn stream(&self, request: &CompletionRequest) -> Result<TokenStream, CoreError> {
+        let response = self.send_generate_request(request, true).await?;
+
+        if response.status().is_success() {
+            Ok(sse_chunks(response, gemini_stream_chunks))
+        } else {
+            Err(CoreError::from_response(self.name(), response).await)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::GenerationParams;
+    use crate::test_support::mock_server;
+    use axum::extract::{Path, Query};
+    use axum::routing::{get, post};
+    use axum::{Json, Router};
+    use futures::StreamExt;
+    use serde_json::{json, Value};
+    use std::collections::HashMap;
+
+    // serve the router on a random local port and return its base url
+    fn response(data: Value) -> GeminiResponse {
+        serde_json::from_value(data).unwrap()
+    }
+
+    #[test]
+    fn test_assistant_role_maps_to_model() {
+        let request = CompletionRequest::new("gemini-2.5-flash", "second").with_history(vec![
+            ChatMessage::user("first"),
+            ChatMessage::assistant("answer"),
+        ]);
+        let gemini_request = GeminiRequest {
+            system_instruction: None,
+            contents: request.messages.iter().map(GeminiContent::from).collect(),
+            generation_config: None,
+        };
+        let json = serde_json::to_value(&gemini_request).unwrap();
+
+        assert_eq!(json["contents"][0]["role"], "user");
+        assert_eq!(json["contents"][1]["role"], "model");
+        assert_eq!(json["contents"][2]["parts"][0]["text"], "second");
+        assert!(json.get("generationConfig").is_none());
+    }
+
+    #[test]
+    fn test_request_params() {
+        let request =
+            CompletionRequest::new("gemini-2.5-flash", "hi").with_params(GenerationParams {
+                system: Some("Answer in French".to_string()),
+                top_p: Some(0.9),
+                max_tokens: Some(256),
+                stop: Some(vec!["END".to_string()]),
+                seed: Some(42),
+                ..Default::default()
+            });
+        let json = serde_json::to_value(GeminiRequest::from(&request)).unwrap();
+
+        assert_eq!(
+            json["systemInstruction"],
+            json!({ "parts": [{ "text": "Answer in French" }] })
+        );
+        let config = &json["generationConfig"];
+        assert_eq!(config["temperature"], 0.5);
+        assert_eq!(config["maxOutputTokens"], 256);
+        assert_eq!(config["stopSequences"], json!(["END"]));
+        assert_eq!(config["seed"], 42);
+    }
+
+    #[test]
+    fn test_text_joins_parts() {
+        let response = response(json!({
+            "candidates": [{
+                "content": { "role": "model", "parts": [{ "text": "Hello" }, { "text": " there" }] },
+                "finishReason": "STOP"
+            }]
+        }));
+        assert_eq!(response.text().unwrap(), "Hello there");
+    }
+
+    #[test]
+    fn test_blocked_prompt_is_safety_error() {
+        let response = response(json!({
+            "promptFeedback": {
+                "blockReason": "SAFETY",
+                "safetyRatings": [
+                    { "category": "HARM_CATEGORY_DANGEROUS_CONTENT", "probability": "HIGH", "blocked": true },
+                    { "category": "HARM_CATEGORY_HARASSMENT", "probability": "NEGLIGIBLE" }
+                ]
+            }
+        }));
+
+        let error = response.text().unwrap_err();
+        let CoreError::SafetyBlock(block) = error else {
+            panic!("expected a safety block, got {:?}", error);
+        };
+        assert_eq!(block.reason, "SAFETY");
+        assert_eq!(block.categories, vec!["HARM_CATEGORY_DANGEROUS_CONTENT"]);
+    }
+
+    #[test]
+    fn test_blocked_candidate_is_safety_error() {
+        let response = response(json!({
+            "candidates": [{
+                "finishReason": "SAFETY",
+                "safetyRatings": [
+                    { "category": "HARM_CATEGORY_HATE_SPEECH", "probability": "MEDIUM", "blocked": true }
+                ]
+            }]
+        }));
+
+        let error = response.text().unwrap_err();
+        assert!(matches!(error, CoreError::SafetyBlock(_)));
+        assert_eq!(
+            error.to_string(),
+            "Gemini blocked the response (SAFETY): HARM_CATEGORY_HATE_SPEECH"
+        );
+    }
+
+    #[test]
+    fn test_usage_and_finish_reason() {
+        let response = response(json!({
+            "candidates": [{
+                "content": { "role": "model", "parts": [{ "text": "Done" }] },
+                "finishReason": "MAX_TOKENS"
+            }],
+            "usageMetadata": {
+                "promptTokenCount": 9,
+                "candidatesTokenCount": 1024,
+                "thoughtsTokenCount": 300,
+                "totalTokenCount": 1333
+            }
+        }));
+
+        assert_eq!(response.finish_reason(), Some("MAX_TOKENS".to_string()));
+        assert_eq!(
+            response.usage(),
+            TokenUsage {
+                input_tokens: Some(9),
+                output_tokens: Some(1324),
+                reasoning_tokens: Some(300),
+                cached_tokens: None,
+            }
+        );
+    }
+
+    #[test]
+    fn test_model_id_strips_prefix() {
+        let model: GeminiModel = serde_json::from_value(json!({
+            "name": "models/gemini-2.5-flash",
+            "displayName": "Gemini 2.5 Flash",
+            "supportedGenerationMethods": ["generateContent", "countTokens"]
+        }))
+        .unwrap();
+        assert_eq!(model.id(), "gemini-2.5-flash");
+    }
+
+    #[tokio::test]
+    async fn test_list_models_follows_pages() {
+        let app = Router::new().route(
+            "/v1beta/models",
+            get(|Query(query): Query<HashMap<String, String>>| async move {
+                match query.get("pageToken").map(String::as_str) {
+                    None => Json(json!({
+                        "models": [
+                            { "name": "models/gemini-2.5-pro", "supportedGenerationMethods": ["generateContent"] },
+                            { "name": "models/text-embedding-004", "supportedGenerationMethods": ["embedContent"] }
+                        ],
+                        "nextPageToken": "page-2"
+                    })),
+                    Some(_) => Json(json!({
+                        "models": [
+                            { "name": "models/gemini-2.5-flash", "supportedGenerationMethods": ["generateContent"] }
+                        ]
+                    })),
+                }
+            }),
+        );
+        let base_url = format!("{}/v1beta", mock_server(app).await);
+        let provider = GeminiProvider::new()
+            .with_base_url(&base_url)
+            .with_api_key("test-key");
+
+        let models = provider.list_models().await.unwrap();
+        assert_eq!(models, vec!["gemini-2.5-flash", "gemini-2.5-pro"]);
+    }
+
+    #[tokio::test]
+    async fn test_complete_and_stream() {
+        let app = Router::new().route(
+            "/v1beta/models/:method",
+            post(
+                |Path(method): Path<String>, Json(body): Json<Value>| async move {
+                    let contents = body["contents"].as_array().unwrap().len();
+                    let chunk = |text: &str| {
+                        json!({
+                            "candidates": [{
+                                "content": { "role": "model", "parts": [{ "text": text }] },
+                                "finishReason": "STOP"
+                            }],
+                            "modelVersion": "gemini-2.5-flash-001"
+                        })
+                    };
+                    // both endpoints are served here, the streaming one gets server-sent events
+                    if method.ends_with(":streamGenerateContent") {
+                        format!("data: {}\n\ndata: {}\n\n", chunk("Hi"), chunk(" again"))
+                    } else {
+                        chunk(&format!("{} turns", contents)).to_string()
+                    }
+                },
+            ),
+        );
+        let base_url = format!("{}/v1beta", mock_server(app).await);
+        let provider = GeminiProvider::new()
+            .with_base_url(&base_url)
+            .with_api_key("test-key");
+        let request = CompletionRequest::new("gemini-2.5-flash", "and now?").with_history(vec![
+            ChatMessage::user("hi"),
+            ChatMessage::assistant("hello"),
+        ]);
+
+        let completion = provider.complete(&request).await.unwrap();
+        assert_eq!(completion.text, "3 turns");
+        assert_eq!(completion.model, "gemini-2.5-flash-001");
+
+        let chunks: Vec<String> = provider
+            .stream(&request)
+            .await
+            .unwrap()
+            .filter_map(|chunk| async move { chunk.unwrap().into_text() })
+            .collect()
+            .await;
+        assert_eq!(chunks, vec!["Hi", " again"]);
+    }
+}
@@ -1,16 +1,62 @@
 // allow these files to publicly accessed by things importing the core library
 pub mod anthropic;
+pub mod budget;
+pub mod cache;
+pub mod cassette;
+pub mod catalog;
 pub mod config;
-pub mod database;
+pub mod error;
+pub mod gemini;
+pub mod migrations;
 pub mod models;
+pub mod ollama;
 pub mod openai;
+pub mod openai_compatible;
+pub mod pricing;
 pub mod prompt;
+pub mod provider;
+pub mod retry;
+pub mod route;
+pub mod search;
+pub mod sse;
+pub mod store;
+pub mod task;
+pub mod template;
+#[cfg(any(test, feature = "test-support"))]
+pub mod test_support;
 
 // allows use of these structs and functions outside the core library without
 // needing to specify the full path
-pub use anthropic::{call_anthropic, get_anthropic_models, AnthropicModel};
-pub use config::CoreConfig;
-pub use database::{create_database_pool, create_prompt_record, get_all_prompts, init_database};
+pub use anthropic::{AnthropicModel, AnthropicProvider};
+pub use budget::{
+    budget_config, check_budgets, get_budget_statuses, load_budgets, Budget, BudgetConfig,
+    BudgetExceeded, BudgetScope, BudgetStatus, BudgetWindow,
+};
+pub use cache::{response_cache, CacheStats, CachedResponse, ResponseCache};
+pub use cassette::{cassette, Cassette, Interaction, RecordedRequest, RecordedResponse};
+pub use catalog::{model_catalog, ModelCatalog};
+pub use config::{CassetteMode, CoreConfig, DatabaseBackend};
+pub use error::CoreError;
+pub use gemini::{GeminiModel, GeminiProvider, GeminiSafetyBlock};
+pub use migrations::{schema_version, MigrationError, MigrationReport};
 pub use models::*;
-pub use openai::{call_openai, get_openai_models, OpenAIModel};
+pub use ollama::{OllamaModel, OllamaProvider};
+pub use openai::{OpenAIModel, OpenAIProvider};
+pub use openai_compatible::OpenAICompatibleProvider;
+pub use pricing::{load_price_table, price_table, ModelPrice, PriceTable};
 pub use prompt::*;
+pub use provider::{
+    get_provider, get_route, provider_names, register_provider, register_route, ChatMessage,
+    Completion, CompletionRequest, LlmProvider, ProviderRegistry, StreamChunk, TokenStream,
+};
+pub use retry::{retry_policy, RetryPolicy};
+pub use route::{load_routes, Route, RouteConfig, RouteTarget};
+pub use search::{PromptSearch, SearchHit, SearchQuery};
+pub use store::{connect_store, MySqlStore, PostgresStore, PromptStore, SqliteStore};
+pub use task::{
+    enqueue_prompt, run_task, task_policy, NewTask, PromptTask, Task, TaskPolicy, TaskStatus,
+    PROMPT_TASK,
+};
+pub use template::{
+    NewPromptTemplate, PromptTemplate, RunTemplateRequest, TemplateError, TemplateVariable,
+};
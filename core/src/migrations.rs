@@ -0,0 +1,354 @@
+use crate::config::DatabaseBackend;
+use std::fmt;
+
+// held while migrating so an api and webapp starting together don't both apply a migration
+pub(crate) const MIGRATION_LOCK: &str = "kubellm_schema_migrations";
+pub(crate) const MIGRATION_LOCK_TIMEOUT_SECS: i64 = 60;
+
+/// A schema change, applied once and in order of version
+#[derive(Debug)]
+pub struct Migration {
+    pub version: i64,
+    pub name: &'static str,
+    pub(crate) sql: &'static str,
+    // what the migration adds, MySQL databases set up before migrations existed were already
+    // altered by init_database so migrations whose marker exists are recorded without running
+    pub(crate) marker: Option<Marker>,
+}
+
+#[derive(Debug)]
+pub(crate) enum Marker {
+    Table(&'static str),
+    Column(&'static str, &'static str),
+    Index(&'static str, &'static str),
+}
+
+macro_rules! migration {
+    ($backend:literal, $version:expr, $name:literal) => {
+        Migration {
+            version: $version,
+            name: $name,
+            sql: include_str!(concat!("../migrations/", $backend, "/", $name, ".sql")),
+            marker: None,
+        }
+    };
+    ($backend:literal, $version:expr, $name:literal, $marker:expr) => {
+        Migration {
+            version: $version,
+            name: $name,
+            sql: include_str!(concat!("../migrations/", $backend, "/", $name, ".sql")),
+            marker: Some($marker),
+        }
+    };
+}
+
+// new migrations are added at the end, applied migrations must never be edited.
+// MySQL can't roll schema changes back, a migration that fails part way is run again from its
+// first statement, so every statement but the last has to be a CREATE TABLE IF NOT EXISTS
+pub static MYSQL_MIGRATIONS: &[Migration] = &[
+    migration!("mysql", 1, "0001_create_prompts", Marker::Table("prompts")),
+    migration!(
+        "mysql",
+        2,
+        "0002_conversations",
+        Marker::Column("prompts", "conversation_id")
+    ),
+    migration!(
+        "mysql",
+        3,
+        "0003_prompt_usage",
+        Marker::Column("prompts", "input_tokens")
+    ),
+    migration!(
+        "mysql",
+        4,
+        "0004_prompt_cost",
+        Marker::Column("prompts", "cost")
+    ),
+    migration!(
+        "mysql",
+        5,
+        "0005_prompt_user",
+        Marker::Column("prompts", "user_id")
+    ),
+    migration!(
+        "mysql",
+        6,
+        "0006_prompt_attempts",
+        Marker::Column("prompts", "attempts")
+    ),
+    migration!(
+        "mysql",
+        7,
+        "0007_prompt_routes",
+        Marker::Column("prompts", "route")
+    ),
+    migration!(
+        "mysql",
+        8,
+        "0008_model_catalog",
+        Marker::Table("model_catalog")
+    ),
+    migration!(
+        "mysql",
+        9,
+        "0009_prompt_requested_model",
+        Marker::Column("prompts", "requested_model")
+    ),
+    migration!(
+        "mysql",
+        10,
+        "0010_prompt_generation_params",
+        Marker::Column("prompts", "system_prompt")
+    ),
+    migration!(
+        "mysql",
+        11,
+        "0011_prompt_templates",
+        Marker::Column("prompts", "template_id")
+    ),
+    migration!(
+        "mysql",
+        12,
+        "0012_prompt_comparisons",
+        Marker::Column("prompts", "comparison_id")
+    ),
+    migration!(
+        "mysql",
+        13,
+        "0013_prompt_tags",
+        Marker::Column("prompts", "tags")
+    ),
+    migration!(
+        "mysql",
+        14,
+        "0014_prompt_search",
+        Marker::Index("prompts", "ft_prompts_text")
+    ),
+    migration!(
+        "mysql",
+        15,
+        "0015_response_cache",
+        Marker::Column("prompts", "cache_hit")
+    ),
+    migration!("mysql", 16, "0016_tasks", Marker::Table("tasks")),
+    migration!(
+        "mysql",
+        17,
+        "0017_prompt_comparison_index",
+        Marker::Column("prompts", "comparison_index")
+    ),
+];
+
+// PostgreSQL and SQLite started out with the whole MySQL schema, they get their own versions
+// of any migration added after it
+pub static POSTGRES_MIGRATIONS: &[Migration] = &[
+    migration!("postgres", 1, "0001_create_schema"),
+    migration!("postgres", 2, "0002_response_cache"),
+    migration!("postgres", 3, "0003_tasks"),
+    migration!("postgres", 4, "0004_prompt_comparison_index"),
+];
+
+pub static SQLITE_MIGRATIONS: &[Migration] = &[
+    migration!("sqlite", 1, "0001_create_schema"),
+    migration!("sqlite", 2, "0002_response_cache"),
+    migration!("sqlite", 3, "0003_tasks"),
+    migration!("sqlite", 4, "0004_prompt_comparison_index"),
+];
+
+/// The migrations of a backend in the order they are applied
+pub fn migrations(backend: DatabaseBackend) -> &'static [Migration] {
+    match backend {
+        DatabaseBackend::MySql => MYSQL_MIGRATIONS,
+        DatabaseBackend::Postgres => POSTGRES_MIGRATIONS,
+        DatabaseBackend::Sqlite => SQLITE_MIGRATIONS,
+    }
+}
+
+/// The schema version this build expects of a backend, the version of its last migration
+pub fn schema_version(backend: DatabaseBackend) -> i64 {
+    migrations(backend)
+        .last()
+        .map_or(0, |migration| migration.version)
+}
+
+/// What running the migrations did
+#[derive(Debug, Clone, PartialEq)]
+pub struct MigrationReport {
+    // the database's schema version after migrating
+    pub version: i64,
+    // names of the migrations that were run
+    pub applied: Vec<&'static str>,
+    // names of the migrations recorded without running, for a database set up before migrations
+    pub adopted: Vec<&'static str>,
+}
+
+/// Why the database couldn't be migrated
+#[derive(Debug)]
+pub enum MigrationError {
+    Database(sqlx::Error),
+    /// The database was migrated by a newer version, running against it could lose data
+    NewerSchema {
+        database: i64,
+        supported: i64,
+    },
+    /// Another process held the migration lock for too long
+    Locked,
+    Failed {
+        name: &'static str,
+        error: sqlx::Error,
+    },
+}
+
+impl fmt::Display for MigrationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MigrationError::Database(e) => write!(f, "Database error: {}", e),
+            MigrationError::NewerSchema {
+                database,
+                supported,
+            } => write!(
+                f,
+                "Database schema version {} is newer than the latest version this build supports ({}), upgrade kubellm",
+                database, supported
+            ),
+            MigrationError::Locked => write!(
+                f,
+                "Timed out after {}s waiting for another process to finish migrating the database",
+                MIGRATION_LOCK_TIMEOUT_SECS
+            ),
+            MigrationError::Failed { name, error } => {
+                write!(f, "Migration {} failed: {}", name, error)
+            }
+        }
+    }
+}
+
+impl std::error::Error for MigrationError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            MigrationError::Database(e) | MigrationError::Failed { error: e, .. } => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<sqlx::Error> for MigrationError {
+    fn from(error: sqlx::Error) -> Self {
+        MigrationError::Database(error)
+    }
+}
+
+/// The migrations a database that has had the applied versions still needs, in order,
+/// and a report of the database's version before they run.
+/// Refuses a database migrated by a newer version.
+pub(crate) fn pending_migrations(
+    migrations: &'static [Migration],
+    applied_versions: &[i64],
+) -> Result<(MigrationReport, Vec<&'static Migration>), MigrationError> {
+    let supported = migrations.last().map_or(0, |migration| migration.version);
+    let database_version = applied_versions.iter().copied().max().unwrap_or(0);
+    if database_version > supported {
+        return Err(MigrationError::NewerSchema {
+            database: database_version,
+            supported,
+        });
+    }
+
+    let report = MigrationReport {
+        version: database_version,
+        applied: Vec::new(),
+        adopted: Vec::new(),
+    };
+    let pending = migrations
+        .iter()
+        .filter(|migration| !applied_versions.contains(&migration.version))
+        .collect();
+    Ok((report, pending))
+}
+
+// the statements of a migration file, without comment lines
+pub(crate) fn statements(sql: &str) -> Vec<String> {
+    sql.split(';')
+        .map(|statement| {
+            statement
+                .lines()
+                .filter(|line| !line.trim_start().starts_with("--"))
+                .collect::<Vec<_>>()
+                .join("\n")
+                .trim()
+                .to_string()
+        })
+        .filter(|statement| !statement.is_empty())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_migrations_are_ordered() {
+        for backend in [
+            DatabaseBackend::MySql,
+            DatabaseBackend::Postgres,
+            DatabaseBackend::Sqlite,
+        ] {
+            for (i, migration) in migrations(backend).iter().enumerate() {
+                assert_eq!(migration.version, i as i64 + 1);
+                assert!(migration
+                    .name
+                    .starts_with(&format!("{:04}_", migration.version)));
+                assert!(!statements(migration.sql).is_empty(), "{}", migration.name);
+            }
+            assert_eq!(schema_version(backend), migrations(backend).len() as i64);
+        }
+        // only MySQL databases can predate the migrations
+        assert!(MYSQL_MIGRATIONS.iter().all(|m| m.marker.is_some()));
+    }
+
+    #[test]
+    fn test_mysql_migrations_can_be_run_again() {
+        for migration in MYSQL_MIGRATIONS {
+            let statements = statements(migration.sql);
+            let (_, repeated) = statements.split_last().unwrap();
+            for statement in repeated {
+                assert!(
+                    statement.starts_with("CREATE TABLE IF NOT EXISTS"),
+                    "{} can't be run again after its last statement fails",
+                    migration.name
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_pending_migrations() {
+        let (report, pending) = pending_migrations(MYSQL_MIGRATIONS, &[1, 2, 3]).unwrap();
+        assert_eq!(report.version, 3);
+        assert_eq!(pending.len(), MYSQL_MIGRATIONS.len() - 3);
+        assert_eq!(pending[0].version, 4);
+
+        let (report, pending) = pending_migrations(SQLITE_MIGRATIONS, &[]).unwrap();
+        assert_eq!(report.version, 0);
+        assert_eq!(pending.len(), SQLITE_MIGRATIONS.len());
+
+        let newer = schema_version(DatabaseBackend::Postgres) + 1;
+        assert!(matches!(
+            pending_migrations(POSTGRES_MIGRATIONS, &[1, newer]),
+            Err(MigrationError::NewerSchema { database, .. }) if database == newer
+        ));
+    }
+
+    #[test]
+    fn test_statements() {
+        let sql = "-- the first table\nCREATE TABLE a (id INTEGER);\n\n-- the second\nCREATE TABLE b (\n    id INTEGER\n);\n";
+        assert_eq!(
+            statements(sql),
+            vec![
+                "CREATE TABLE a (id INTEGER)",
+                "CREATE TABLE b (\n    id INTEGER\n)"
+            ]
+        );
+    }
+}
@@ -1,32 +1,401 @@
-use chrono::{DateTime, Utc};
+use crate::error::CoreError;
+use chrono::{DateTime, Duration, NaiveDate, Utc};
 use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
 use std::str::FromStr;
-use strum::{Display, EnumIter, IntoEnumIterator};
+use strum::Display;
 
 // maps the json containing the prompt into this struct
-#[derive(Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct CreatePromptRequest {
     pub prompt: String,
     pub provider: String,
     pub model: Option<String>,
+    // what happens when the model isn't one the provider lists, strict unless set
+    #[serde(default)]
+    pub model_mode: ModelMode,
+    // system prompt and sampling settings sent alongside the prompt
+    #[serde(flatten)]
+    pub params: GenerationParams,
+    // labels saved with the prompt so it can be found again, e.g. an experiment or ticket name
+    #[serde(default)]
+    pub tags: Vec<String>,
+    // whether an identical earlier prompt's cached response may answer this one
+    #[serde(default)]
+    pub cache: CacheMode,
+}
+
+impl CreatePromptRequest {
+    pub fn new(prompt: &str, provider: &str) -> Self {
+        Self {
+            prompt: prompt.to_string(),
+            provider: provider.to_string(),
+            ..Default::default()
+        }
+    }
+
+    pub fn with_model(mut self, model: Option<&str>) -> Self {
+        self.model = model.map(str::to_string);
+        self
+    }
+
+    pub fn with_model_mode(mut self, model_mode: ModelMode) -> Self {
+        self.model_mode = model_mode;
+        self
+    }
+
+    pub fn with_params(mut self, params: GenerationParams) -> Self {
+        self.params = params;
+        self
+    }
+
+    pub fn with_tags(mut self, tags: Vec<String>) -> Self {
+        self.tags = tags;
+        self
+    }
+
+    pub fn with_cache(mut self, cache: CacheMode) -> Self {
+        self.cache = cache;
+        self
+    }
+}
+
+// tags are trimmed and saved once each, in the order given
+pub fn normalize_tags(tags: &[String]) -> Vec<String> {
+    let mut normalized: Vec<String> = Vec::new();
+    for tag in tags
+        .iter()
+        .map(|tag| tag.trim())
+        .filter(|tag| !tag.is_empty())
+    {
+        if !normalized.iter().any(|existing| existing == tag) {
+            normalized.push(tag.to_string());
+        }
+    }
+    normalized
+}
+
+// how the response is generated, anything unset is left to the provider's defaults.
+// providers that don't support a setting ignore it, e.g. Anthropic has no seed
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct GenerationParams {
+    pub system: Option<String>,
+    pub temperature: Option<f32>,
+    pub top_p: Option<f32>,
+    pub max_tokens: Option<u32>,
+    // sequences that end the response when generated
+    pub stop: Option<Vec<String>>,
+    pub seed: Option<i64>,
+}
+
+impl GenerationParams {
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(temperature) = self.temperature {
+            if !(0.0..=2.0).contains(&temperature) {
+                return Err("temperature must be between 0 and 2".to_string());
+            }
+        }
+        if let Some(top_p) = self.top_p {
+            if !(0.0..=1.0).contains(&top_p) {
+                return Err("top_p must be between 0 and 1".to_string());
+            }
+        }
+        if self.max_tokens == Some(0) {
+            return Err("max_tokens must be greater than 0".to_string());
+        }
+        if let Some(stop) = &self.stop {
+            if stop.iter().any(String::is_empty) {
+                return Err("stop sequences cannot be empty".to_string());
+            }
+        }
+        Ok(())
+    }
+}
+
+// how a prompt for a model the provider doesn't list is handled
+#[derive(Display, Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub enum ModelMode {
+    // refuse the prompt with an unknown model error suggesting similar models
+    #[default]
+    Strict,
+    // prompt the provider's default model instead and record the model that was asked for
+    Lenient,
+}
+
+impl FromStr for ModelMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "strict" => Ok(ModelMode::Strict),
+            "lenient" => Ok(ModelMode::Lenient),
+            _ => Err(format!(
+                "Unknown model mode: {} (expected strict or lenient)",
+                s
+            )),
+        }
+    }
+}
+
+// how a prompt uses the response cache, only has an effect when the cache is turned on
+#[derive(Display, Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub enum CacheMode {
+    // answer from the cache when there is a fresh response, otherwise prompt and cache the answer
+    #[default]
+    Use,
+    // prompt the provider and leave the cache alone
+    Bypass,
+    // prompt the provider and replace the cached response
+    Refresh,
+}
+
+impl FromStr for CacheMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "use" => Ok(CacheMode::Use),
+            "bypass" => Ok(CacheMode::Bypass),
+            "refresh" => Ok(CacheMode::Refresh),
+            _ => Err(format!(
+                "Unknown cache mode: {} (expected use, bypass or refresh)",
+                s
+            )),
+        }
+    }
 }
 
 // Serialize: used to convert this struct into JSON for responses
 // FromRow: maps the database row into this struct
-#[derive(Serialize, FromRow)]
+#[derive(Debug, Clone, Serialize, FromRow)]
 pub struct Prompt {
     pub id: i64,
     pub prompt: String,
     pub model: String,
     pub provider: String,
     pub response: String,
+    // set when the prompt is a turn in a conversation
+    pub conversation_id: Option<i64>,
+    pub sequence: Option<i32>,
+    // usage reported by the provider, None for older prompts or when it wasn't reported
+    pub input_tokens: Option<i32>,
+    pub output_tokens: Option<i32>,
+    pub reasoning_tokens: Option<i32>,
+    pub cached_tokens: Option<i32>,
+    // time from sending the request until the full response was received
+    pub latency_ms: Option<i64>,
+    pub finish_reason: Option<String>,
+    // cost from the price table when the prompt was made, None when the model has no price
+    pub cost: Option<f64>,
+    // user or api key the prompt was made by, counted against that user's budgets
+    pub user_id: Option<String>,
+    // requests made to the provider for this prompt, more than 1 when failed requests were retried,
+    // 0 when the response came from the response cache
+    pub attempts: i32,
+    pub cache_hit: bool,
+    // route the prompt was sent to, provider and model are then the target that answered
+    pub route: Option<String>,
+    // targets of the route in the order they were tried, the last one answered
+    pub attempted_targets: Option<Vec<String>>,
+    // model that was asked for when it was unknown and the default model answered instead
+    pub requested_model: Option<String>,
+    // system prompt and sampling settings the prompt was made with
+    #[serde(flatten)]
+    pub params: GenerationParams,
+    // version of the prompt template the prompt was rendered from
+    pub template_id: Option<i64>,
+    // shared by the prompts of one comparison, each answered by a different target
+    pub comparison_id: Option<String>,
+    // position of the target that answered in the comparison's targets
+    pub comparison_index: Option<i32>,
+    pub tags: Vec<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+// everything create_prompt_record needs to save a prompt
+#[derive(Debug, Default)]
+pub struct NewPrompt {
+    pub prompt: String,
+    pub response: String,
+    pub model: String,
+    pub provider: String,
+    pub conversation_id: Option<i64>,
+    pub usage: TokenUsage,
+    pub latency_ms: Option<i64>,
+    pub finish_reason: Option<String>,
+    pub cost: Option<f64>,
+    pub user_id: Option<String>,
+    pub attempts: u32,
+    pub cache_hit: bool,
+    pub route: Option<String>,
+    pub attempted_targets: Option<Vec<String>>,
+    pub requested_model: Option<String>,
+    pub params: GenerationParams,
+    pub template_id: Option<i64>,
+    pub comparison_id: Option<String>,
+    pub comparison_index: Option<i32>,
+    pub tags: Vec<String>,
+}
+
+// token counts reported by a provider, None when the provider doesn't report that count
+// input tokens include cached tokens and output tokens include reasoning tokens
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TokenUsage {
+    pub input_tokens: Option<u32>,
+    pub output_tokens: Option<u32>,
+    pub reasoning_tokens: Option<u32>,
+    pub cached_tokens: Option<u32>,
+}
+
+impl TokenUsage {
+    // streamed responses report counts across several events, later counts win
+    pub fn merge(&mut self, other: TokenUsage) {
+        self.input_tokens = other.input_tokens.or(self.input_tokens);
+        self.output_tokens = other.output_tokens.or(self.output_tokens);
+        self.reasoning_tokens = other.reasoning_tokens.or(self.reasoning_tokens);
+        self.cached_tokens = other.cached_tokens.or(self.cached_tokens);
+    }
+}
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct Conversation {
+    pub id: i64,
+    pub title: String,
     pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
 }
 
+// a conversation along with its prompts in the order they were asked
 #[derive(Serialize)]
+pub struct ConversationDetail {
+    #[serde(flatten)]
+    pub conversation: Conversation,
+    pub prompts: Vec<Prompt>,
+}
+
+// most targets a single comparison can prompt
+pub const MAX_COMPARE_TARGETS: usize = 8;
+
+// a provider, or route, and optional model that a comparison prompts, written provider:model
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CompareTarget {
+    pub provider: String,
+    pub model: Option<String>,
+}
+
+impl FromStr for CompareTarget {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (provider, model) = match s.split_once(':') {
+            Some((provider, model)) => (provider.trim(), Some(model.trim())),
+            None => (s.trim(), None),
+        };
+        if provider.is_empty() {
+            return Err(format!("Invalid target: {} (expected provider:model)", s));
+        }
+        Ok(CompareTarget {
+            provider: provider.to_string(),
+            model: model.filter(|m| !m.is_empty()).map(str::to_string),
+        })
+    }
+}
+
+impl std::fmt::Display for CompareTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.model {
+            Some(model) => write!(f, "{}:{}", self.provider, model),
+            None => f.write_str(&self.provider),
+        }
+    }
+}
+
+// body of POST /compare, the same prompt and settings are sent to every target
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CompareRequest {
+    pub prompt: String,
+    pub targets: Vec<CompareTarget>,
+    #[serde(default)]
+    pub model_mode: ModelMode,
+    #[serde(flatten)]
+    pub params: GenerationParams,
+}
+
+impl CompareRequest {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.prompt.trim().is_empty() {
+            return Err("Prompt cannot be empty".to_string());
+        }
+        if self.targets.len() < 2 {
+            return Err("A comparison needs at least two targets".to_string());
+        }
+        if self.targets.len() > MAX_COMPARE_TARGETS {
+            return Err(format!(
+                "A comparison can have at most {} targets",
+                MAX_COMPARE_TARGETS
+            ));
+        }
+        self.params.validate()
+    }
+}
+
+// one target's answer, or why it couldn't answer
+#[derive(Debug, Serialize)]
+pub struct ComparisonResult {
+    pub target: CompareTarget,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prompt: Option<Prompt>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<ErrorResponse>,
+}
+
+// results are in the order the targets were given
+#[derive(Debug, Serialize)]
+pub struct Comparison {
+    pub comparison_id: String,
+    pub results: Vec<ComparisonResult>,
+}
+
+#[derive(Deserialize)]
+pub struct CreateConversationRequest {
+    pub title: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize)]
 pub struct ErrorResponse {
     pub error: String,
+    // set for errors callers can act on, e.g. unknown_model
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub code: Option<String>,
+    // models named like the unknown model that was asked for
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub suggestions: Vec<String>,
+}
+
+impl ErrorResponse {
+    pub fn new(error: impl Into<String>) -> Self {
+        Self {
+            error: error.into(),
+            ..Default::default()
+        }
+    }
+}
+
+impl From<&CoreError> for ErrorResponse {
+    fn from(error: &CoreError) -> Self {
+        match error {
+            CoreError::InvalidModel { suggestions, .. } => Self {
+                error: error.to_string(),
+                code: Some("unknown_model".to_string()),
+                suggestions: suggestions.clone(),
+            },
+            _ => Self::new(error.to_string()),
+        }
+    }
 }
 
 #[derive(Deserialize)]
@@ -34,33 +403,289 @@ pub struct GetModelsQuery {
     pub provider: String,
 }
 
-#[derive(Display, EnumIter, Debug, PartialEq)]
-pub enum Provider {
-    #[strum(to_string = "Anthropic")]
-    Anthropic,
-    #[strum(to_string = "OpenAI")]
-    OpenAI,
+// a model in a provider's catalog, display name and creation date are set when the provider reports them
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ModelInfo {
+    pub id: String,
+    pub display_name: Option<String>,
+    pub created_at: Option<DateTime<Utc>>,
 }
 
-impl FromStr for Provider {
+impl ModelInfo {
+    pub fn new(id: &str) -> Self {
+        Self {
+            id: id.to_string(),
+            display_name: None,
+            created_at: None,
+        }
+    }
+}
+
+// a provider's models as they were when last fetched from the provider
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ModelCatalogEntry {
+    pub provider: String,
+    pub models: Vec<ModelInfo>,
+    pub fetched_at: DateTime<Utc>,
+}
+
+// column a usage report is grouped by
+#[derive(Display, Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub enum UsageGroupBy {
+    #[default]
+    Provider,
+    Model,
+    Day,
+}
+
+impl FromStr for UsageGroupBy {
     type Err = String;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         match s.to_lowercase().as_str() {
-            "anthropic" => Ok(Provider::Anthropic),
-            "openai" => Ok(Provider::OpenAI),
-            _ => Err(format!("Unknown provider: {}", s)),
+            "provider" => Ok(UsageGroupBy::Provider),
+            "model" => Ok(UsageGroupBy::Model),
+            "day" => Ok(UsageGroupBy::Day),
+            _ => Err(format!(
+                "Unknown usage grouping: {} (expected provider, model or day)",
+                s
+            )),
+        }
+    }
+}
+
+// from and to are RFC 3339 timestamps or YYYY-MM-DD dates, a date in to includes that whole day
+#[derive(Debug, Default, Deserialize)]
+pub struct UsageQuery {
+    pub from: Option<String>,
+    pub to: Option<String>,
+    #[serde(default)]
+    pub group_by: UsageGroupBy,
+}
+
+// start (inclusive) and end (exclusive) of a usage report, None leaves that side open
+pub type UsageRange = (Option<DateTime<Utc>>, Option<DateTime<Utc>>);
+
+impl UsageQuery {
+    pub fn range(&self) -> Result<UsageRange, String> {
+        time_range(self.from.as_deref(), self.to.as_deref())
+    }
+}
+
+fn time_range(from: Option<&str>, to: Option<&str>) -> Result<UsageRange, String> {
+    let from = from
+        .map(|from| parse_report_time(from, false))
+        .transpose()?;
+    let to = to.map(|to| parse_report_time(to, true)).transpose()?;
+
+    if let (Some(from), Some(to)) = (from, to) {
+        if from >= to {
+            return Err("from must be before to".to_string());
+        }
+    }
+
+    Ok((from, to))
+}
+
+fn parse_report_time(value: &str, end_of_range: bool) -> Result<DateTime<Utc>, String> {
+    if let Ok(time) = DateTime::parse_from_rfc3339(value) {
+        return Ok(time.with_timezone(&Utc));
+    }
+
+    let date = NaiveDate::parse_from_str(value, "%Y-%m-%d").map_err(|_| {
+        format!(
+            "Invalid date: {} (expected YYYY-MM-DD or an RFC 3339 timestamp)",
+            value
+        )
+    })?;
+    let start = date.and_hms_opt(0, 0, 0).unwrap().and_utc();
+    Ok(if end_of_range {
+        start + Duration::days(1)
+    } else {
+        start
+    })
+}
+
+// prompts returned by GET /prompts when no limit is given, and the most a page can have
+pub const DEFAULT_PROMPT_PAGE_SIZE: u32 = 50;
+pub const MAX_PROMPT_PAGE_SIZE: u32 = 200;
+
+// query params of GET /prompts, every filter is optional and from and to work as in UsageQuery.
+// cursor is the next_cursor of the previous page
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PromptQuery {
+    pub provider: Option<String>,
+    pub model: Option<String>,
+    pub from: Option<String>,
+    pub to: Option<String>,
+    pub conversation_id: Option<i64>,
+    pub tag: Option<String>,
+    pub cursor: Option<String>,
+    pub limit: Option<u32>,
+}
+
+impl PromptQuery {
+    pub fn filter(&self) -> Result<PromptFilter, String> {
+        let (from, to) = time_range(self.from.as_deref(), self.to.as_deref())?;
+        let limit = self.limit.unwrap_or(DEFAULT_PROMPT_PAGE_SIZE);
+        if !(1..=MAX_PROMPT_PAGE_SIZE).contains(&limit) {
+            return Err(format!(
+                "limit must be between 1 and {}",
+                MAX_PROMPT_PAGE_SIZE
+            ));
         }
+        let non_empty = |value: &Option<String>| {
+            value
+                .as_deref()
+                .map(str::trim)
+                .filter(|value| !value.is_empty())
+                .map(str::to_string)
+        };
+
+        Ok(PromptFilter {
+            provider: non_empty(&self.provider),
+            model: non_empty(&self.model),
+            from,
+            to,
+            conversation_id: self.conversation_id,
+            tag: non_empty(&self.tag),
+            after: self.cursor.as_deref().map(str::parse).transpose()?,
+            limit,
+        })
     }
 }
 
-impl Provider {
-    pub fn all() -> Vec<Provider> {
-        Provider::iter().collect()
+// which prompts get_prompts returns, newest first
+#[derive(Debug, Clone, PartialEq)]
+pub struct PromptFilter {
+    pub provider: Option<String>,
+    pub model: Option<String>,
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    pub conversation_id: Option<i64>,
+    pub tag: Option<String>,
+    // only prompts older than the last prompt of the previous page
+    pub after: Option<PromptCursor>,
+    pub limit: u32,
+}
+
+impl Default for PromptFilter {
+    fn default() -> Self {
+        Self {
+            provider: None,
+            model: None,
+            from: None,
+            to: None,
+            conversation_id: None,
+            tag: None,
+            after: None,
+            limit: DEFAULT_PROMPT_PAGE_SIZE,
+        }
     }
+}
 
-    pub fn all_names() -> Vec<String> {
-        Provider::iter().map(|p| p.to_string()).collect()
+// position of the last prompt on a page, prompts are ordered by created_at then id
+// so prompts saved in the same second aren't skipped or repeated
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PromptCursor {
+    pub created_at: DateTime<Utc>,
+    pub id: i64,
+}
+
+impl From<&Prompt> for PromptCursor {
+    fn from(prompt: &Prompt) -> Self {
+        Self {
+            created_at: prompt.created_at,
+            id: prompt.id,
+        }
+    }
+}
+
+impl std::fmt::Display for PromptCursor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}", self.created_at.timestamp(), self.id)
+    }
+}
+
+impl FromStr for PromptCursor {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid cursor: {}", s);
+        let (seconds, id) = s.split_once('.').ok_or_else(invalid)?;
+        let created_at = seconds
+            .parse()
+            .ok()
+            .and_then(|seconds| DateTime::from_timestamp(seconds, 0))
+            .ok_or_else(invalid)?;
+        let id = id.parse().map_err(|_| invalid())?;
+        Ok(Self { created_at, id })
+    }
+}
+
+// a page of prompts, next_cursor is set when there are older prompts
+#[derive(Debug, Serialize)]
+pub struct PromptPage {
+    pub prompts: Vec<Prompt>,
+    pub next_cursor: Option<String>,
+}
+
+// prompt count, tokens and cost for one group of a usage report
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct UsageSummary {
+    pub key: String,
+    pub prompts: i64,
+    pub input_tokens: i64,
+    pub output_tokens: i64,
+    pub cached_tokens: i64,
+    pub cost: f64,
+    // prompts without a cost, their tokens are counted but not their cost
+    pub unpriced_prompts: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct UsageReport {
+    pub group_by: UsageGroupBy,
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    pub currency: String,
+    pub rows: Vec<UsageSummary>,
+    pub total: UsageSummary,
+}
+
+impl UsageReport {
+    pub fn new(
+        group_by: UsageGroupBy,
+        (from, to): UsageRange,
+        currency: &str,
+        rows: Vec<UsageSummary>,
+    ) -> Self {
+        let total = rows.iter().fold(
+            UsageSummary {
+                key: "total".to_string(),
+                ..Default::default()
+            },
+            |mut total, row| {
+                total.prompts += row.prompts;
+                total.input_tokens += row.input_tokens;
+                total.output_tokens += row.output_tokens;
+                total.cached_tokens += row.cached_tokens;
+                total.cost += row.cost;
+                total.unpriced_prompts += row.unpriced_prompts;
+                total
+            },
+        );
+
+        Self {
+            group_by,
+            from,
+            to,
+            currency: currency.to_string(),
+            rows,
+            total,
+        }
     }
 }
 
@@ -69,54 +694,259 @@ mod tests {
     use super::*;
 
     #[test]
-    fn test_provider_from_str() {
-        assert_eq!(
-            Provider::from_str("anthropic").unwrap(),
-            Provider::Anthropic
-        );
-        assert_eq!(
-            Provider::from_str("Anthropic").unwrap(),
-            Provider::Anthropic
-        );
+    fn test_token_usage_merge_keeps_earlier_counts() {
+        let mut usage = TokenUsage {
+            input_tokens: Some(12),
+            output_tokens: Some(1),
+            ..Default::default()
+        };
+        usage.merge(TokenUsage {
+            output_tokens: Some(40),
+            ..Default::default()
+        });
+
+        assert_eq!(usage.input_tokens, Some(12));
+        assert_eq!(usage.output_tokens, Some(40));
+        assert_eq!(usage.reasoning_tokens, None);
+    }
+
+    #[test]
+    fn test_usage_group_by() {
         assert_eq!(
-            Provider::from_str("ANTHROPIC").unwrap(),
-            Provider::Anthropic
+            UsageGroupBy::from_str("Model").unwrap(),
+            UsageGroupBy::Model
         );
+        assert_eq!(UsageGroupBy::Day.to_string(), "day");
+        assert!(UsageGroupBy::from_str("week").is_err());
+
+        let query: UsageQuery = serde_json::from_str("{}").unwrap();
+        assert_eq!(query.group_by, UsageGroupBy::Provider);
+    }
+
+    #[test]
+    fn test_compare_target_from_str() {
+        let target: CompareTarget = "anthropic:claude-sonnet-4-20250514".parse().unwrap();
+        assert_eq!(target.provider, "anthropic");
+        assert_eq!(target.model.as_deref(), Some("claude-sonnet-4-20250514"));
+        assert_eq!(target.to_string(), "anthropic:claude-sonnet-4-20250514");
+
+        // ollama model tags contain a colon of their own
+        let target: CompareTarget = "ollama:llama3.2:3b".parse().unwrap();
+        assert_eq!(target.model.as_deref(), Some("llama3.2:3b"));
 
-        assert_eq!(Provider::from_str("openai").unwrap(), Provider::OpenAI);
-        assert_eq!(Provider::from_str("OpenAI").unwrap(), Provider::OpenAI);
-        assert_eq!(Provider::from_str("OPENAI").unwrap(), Provider::OpenAI);
+        let target: CompareTarget = "openai".parse().unwrap();
+        assert_eq!(target.model, None);
+        assert_eq!(target.to_string(), "openai");
 
-        assert!(Provider::from_str("invalid").is_err());
-        assert!(Provider::from_str("").is_err());
+        assert!(":gpt-4o".parse::<CompareTarget>().is_err());
     }
 
     #[test]
-    fn test_provider_display() {
-        assert_eq!(Provider::Anthropic.to_string(), "Anthropic");
-        assert_eq!(Provider::OpenAI.to_string(), "OpenAI");
+    fn test_compare_request_validate() {
+        let target = |s: &str| s.parse::<CompareTarget>().unwrap();
+        let mut request = CompareRequest {
+            prompt: "What is 2 + 2?".to_string(),
+            targets: vec![target("anthropic"), target("openai:gpt-4o")],
+            ..Default::default()
+        };
+        assert!(request.validate().is_ok());
+
+        request.targets.truncate(1);
+        assert!(request.validate().is_err());
+
+        request.targets = vec![target("openai"); MAX_COMPARE_TARGETS + 1];
+        assert!(request.validate().is_err());
+
+        request.targets.truncate(2);
+        request.params.temperature = Some(3.0);
+        assert!(request.validate().is_err());
+
+        let request: CompareRequest = serde_json::from_str(
+            r#"{"prompt":"hi","targets":[{"provider":"anthropic"},{"provider":"openai","model":"gpt-4o"}],"seed":7}"#,
+        )
+        .unwrap();
+        assert_eq!(request.targets[1], target("openai:gpt-4o"));
+        assert_eq!(request.params.seed, Some(7));
     }
 
     #[test]
-    fn test_provider_all() {
-        let providers = Provider::all();
-        assert_eq!(providers.len(), 2);
-        assert!(providers.contains(&Provider::Anthropic));
-        assert!(providers.contains(&Provider::OpenAI));
+    fn test_model_mode() {
+        assert_eq!(ModelMode::from_str("Lenient").unwrap(), ModelMode::Lenient);
+        assert_eq!(ModelMode::Strict.to_string(), "strict");
+        assert!(ModelMode::from_str("loose").is_err());
+        assert_eq!(ModelMode::default(), ModelMode::Strict);
     }
 
     #[test]
-    fn test_provider_all_names() {
-        let names = Provider::all_names();
-        assert_eq!(names.len(), 2);
-        assert!(names.contains(&"Anthropic".to_string()));
-        assert!(names.contains(&"OpenAI".to_string()));
+    fn test_cache_mode() {
+        assert_eq!(CacheMode::from_str("Bypass").unwrap(), CacheMode::Bypass);
+        assert_eq!(CacheMode::Refresh.to_string(), "refresh");
+        assert!(CacheMode::from_str("skip").is_err());
+
+        let request: CreatePromptRequest =
+            serde_json::from_str(r#"{"prompt":"hi","provider":"openai","cache":"refresh"}"#)
+                .unwrap();
+        assert_eq!(request.cache, CacheMode::Refresh);
+        let request: CreatePromptRequest =
+            serde_json::from_str(r#"{"prompt":"hi","provider":"openai"}"#).unwrap();
+        assert_eq!(request.cache, CacheMode::Use);
     }
 
     #[test]
-    fn test_provider_partial_eq() {
-        assert_eq!(Provider::Anthropic, Provider::Anthropic);
-        assert_eq!(Provider::OpenAI, Provider::OpenAI);
-        assert_ne!(Provider::Anthropic, Provider::OpenAI);
+    fn test_generation_params_validate() {
+        assert!(GenerationParams::default().validate().is_ok());
+
+        let params = GenerationParams {
+            temperature: Some(0.2),
+            top_p: Some(0.9),
+            max_tokens: Some(200),
+            stop: Some(vec!["END".to_string()]),
+            ..Default::default()
+        };
+        assert!(params.validate().is_ok());
+
+        let invalid = |params: GenerationParams| params.validate().is_err();
+        assert!(invalid(GenerationParams {
+            temperature: Some(2.5),
+            ..Default::default()
+        }));
+        assert!(invalid(GenerationParams {
+            top_p: Some(-0.1),
+            ..Default::default()
+        }));
+        assert!(invalid(GenerationParams {
+            max_tokens: Some(0),
+            ..Default::default()
+        }));
+        assert!(invalid(GenerationParams {
+            stop: Some(vec![String::new()]),
+            ..Default::default()
+        }));
+    }
+
+    #[test]
+    fn test_create_prompt_request_params() {
+        let request: CreatePromptRequest = serde_json::from_str(
+            r#"{"prompt": "hi", "provider": "OpenAI", "system": "Be brief", "temperature": 0.2, "stop": ["END"], "seed": 7}"#,
+        )
+        .unwrap();
+        assert_eq!(request.params.system.as_deref(), Some("Be brief"));
+        assert_eq!(request.params.temperature, Some(0.2));
+        assert_eq!(request.params.stop, Some(vec!["END".to_string()]));
+        assert_eq!(request.params.seed, Some(7));
+        assert_eq!(request.params.max_tokens, None);
+    }
+
+    #[test]
+    fn test_usage_query_range() {
+        let query = UsageQuery {
+            from: Some("2025-01-01".to_string()),
+            to: Some("2025-01-31".to_string()),
+            ..Default::default()
+        };
+        let (from, to) = query.range().unwrap();
+        assert_eq!(from.unwrap().to_rfc3339(), "2025-01-01T00:00:00+00:00");
+        // the whole of the last day is included
+        assert_eq!(to.unwrap().to_rfc3339(), "2025-02-01T00:00:00+00:00");
+
+        let query = UsageQuery {
+            from: Some("2025-01-01T12:00:00+02:00".to_string()),
+            ..Default::default()
+        };
+        let (from, to) = query.range().unwrap();
+        assert_eq!(from.unwrap().to_rfc3339(), "2025-01-01T10:00:00+00:00");
+        assert!(to.is_none());
+    }
+
+    #[test]
+    fn test_usage_query_invalid_range() {
+        let query = UsageQuery {
+            from: Some("yesterday".to_string()),
+            ..Default::default()
+        };
+        assert!(query.range().unwrap_err().contains("Invalid date"));
+
+        let query = UsageQuery {
+            from: Some("2025-02-01".to_string()),
+            to: Some("2025-01-01".to_string()),
+            ..Default::default()
+        };
+        assert!(query.range().is_err());
+    }
+
+    #[test]
+    fn test_prompt_query_filter() {
+        let filter = PromptQuery::default().filter().unwrap();
+        assert_eq!(filter, PromptFilter::default());
+
+        let query = PromptQuery {
+            provider: Some(" OpenAI ".to_string()),
+            tag: Some("".to_string()),
+            from: Some("2025-01-01".to_string()),
+            cursor: Some("1735689600.42".to_string()),
+            limit: Some(10),
+            ..Default::default()
+        };
+        let filter = query.filter().unwrap();
+        assert_eq!(filter.provider.as_deref(), Some("OpenAI"));
+        assert_eq!(filter.tag, None);
+        assert_eq!(filter.limit, 10);
+        let after = filter.after.unwrap();
+        assert_eq!(after.id, 42);
+        assert_eq!(after.created_at, filter.from.unwrap());
+        assert_eq!(after.to_string(), "1735689600.42");
+
+        for query in [
+            PromptQuery {
+                limit: Some(0),
+                ..Default::default()
+            },
+            PromptQuery {
+                limit: Some(MAX_PROMPT_PAGE_SIZE + 1),
+                ..Default::default()
+            },
+            PromptQuery {
+                cursor: Some("42".to_string()),
+                ..Default::default()
+            },
+        ] {
+            assert!(query.filter().is_err());
+        }
+    }
+
+    #[test]
+    fn test_normalize_tags() {
+        let tags = vec![
+            " eval ".to_string(),
+            "".to_string(),
+            "nightly".to_string(),
+            "eval".to_string(),
+        ];
+        assert_eq!(normalize_tags(&tags), vec!["eval", "nightly"]);
+    }
+
+    #[test]
+    fn test_usage_report_total() {
+        let row = |key: &str, prompts, cost, unpriced_prompts| UsageSummary {
+            key: key.to_string(),
+            prompts,
+            input_tokens: prompts * 100,
+            output_tokens: prompts * 10,
+            cached_tokens: 0,
+            cost,
+            unpriced_prompts,
+        };
+        let report = UsageReport::new(
+            UsageGroupBy::Provider,
+            (None, None),
+            "USD",
+            vec![row("Anthropic", 2, 0.5, 0), row("Ollama", 3, 0.0, 1)],
+        );
+
+        assert_eq!(report.total.key, "total");
+        assert_eq!(report.total.prompts, 5);
+        assert_eq!(report.total.input_tokens, 500);
+        assert_eq!(report.total.unpriced_prompts, 1);
+        assert!((report.total.cost - 0.5).abs() < 1e-9);
     }
 }
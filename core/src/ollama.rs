@@ -0,0 +1,429 @@
+use crate::error::CoreError;
+use crate::models::TokenUsage;
+use crate::provider::{
+    ChatMessage, Completion, CompletionRequest, LlmProvider, StreamChunk, TokenStream,
+};
+use crate::sse::line_chunks;
+use crate::CoreConfig;
+use async_trait::async_trait;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OllamaTagsResponse {
+    pub models: Vec<OllamaModel>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OllamaModel {
+    pub name: String,
+    #[serde(default)]
+    pub modified_at: Option<String>,
+    #[serde(default)]
+    pub size: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct OllamaChatRequest {
+    pub model: String,
+    pub messages: Vec<OllamaMessage>,
+    // ollama streams unless told otherwise
+    pub stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub options: Option<OllamaOptions>,
+}
+
+impl OllamaChatRequest {
+    pub fn new(request: &CompletionRequest, stream: bool) -> Self {
+        let params = &request.params;
+        // the system prompt goes in front of the conversation
+        let system = params.system.as_ref().map(|system| OllamaMessage {
+            role: "system".to_string(),
+            content: system.clone(),
+        });
+        let messages = system
+            .into_iter()
+            .chain(request.messages.iter().map(OllamaMessage::from))
+            .collect();
+
+        let options = OllamaOptions {
+            temperature: params.temperature,
+            top_p: params.top_p,
+            num_predict: params.max_tokens,
+            stop: params.stop.clone(),
+            seed: params.seed,
+        };
+
+        Self {
+            model: request.model.clone(),
+            messages,
+            stream,
+            // the model's own settings are used when nothing is set
+            options: (options != OllamaOptions::default()).then_some(options),
+        }
+    }
+}
+
+// sampling settings, ollama calls max tokens num_predict
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub struct OllamaOptions {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub num_predict: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stop: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seed: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OllamaMessage {
+    pub role: String,
+    pub content: String,
+}
+
+impl From<&ChatMessage> for OllamaMessage {
+    fn from(message: &ChatMessage) -> Self {
+        Self {
+            role: message.role.clone(),
+            content: message.content.clone(),
+        }
+    }
+}
+
+// returned by /api/chat, streamed responses send one of these per line
+#[derive(Debug, Deserialize)]
+pub struct OllamaChatResponse {
+    #[serde(default)]
+    pub model: String,
+    pub message: Option<OllamaMessage>,
+    #[serde(default)]
+    pub done: bool,
+    pub done_reason: Option<String>,
+    // token counts are only sent once generation is done
+    pub prompt_eval_count: Option<u32>,
+    pub eval_count: Option<u32>,
+    // set instead of a message when generation fails part way through
+    pub error: Option<String>,
+}
+
+impl OllamaChatResponse {
+    pub fn usage(&self) -> TokenUsage {
+        TokenUsage {
+            input_tokens: self.prompt_eval_count,
+            output_tokens: self.eval_count,
+            ..Default::default()
+        }
+    }
+}
+
+// chunks carried by a single line of a streamed response
+fn ollama_stream_chunks(line: &str) -> Result<Vec<StreamChunk>, CoreError> {
+    if line.trim().is_empty() {
+        return Ok(vec![]);
+    }
+
+    let chunk: OllamaChatResponse = serde_json::from_str(line)?;
+    if let Some(error) = chunk.error {
+        return Err(CoreError::provider("Ollama", error));
+    }
+
+    let mut chunks: Vec<StreamChunk> = chunk
+        .message
+        .as_ref()
+        .map(|message| message.content.clone())
+        .filter(|text| !text.is_empty())
+        .map(StreamChunk::Text)
+        .into_iter()
+        .collect();
+
+    if chunk.done {
+        chunks.push(StreamChunk::Usage(chunk.usage()));
+        chunks.extend(chunk.done_reason.map(StreamChunk::Finish));
+    }
+
+    Ok(chunks)
+}
+
+pub struct OllamaProvider {
+    client: Client,
+    base_url: Option<String>,
+    default_model: Option<String>,
+}
+
+impl Default for OllamaProvider {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl OllamaProvider {
+    pub fn new() -> Self {
+        Self {
+            client: Client::new(),
+            base_url: None,
+            default_model: None,
+        }
+    }
+
+    pub fn with_base_url(mut self, base_url: &str) -> Self {
+        self.base_url = Some(base_url.to_string());
+        self
+    }
+
+    pub fn with_default_model(mut self, model: &str) -> Self {
+        self.default_model = Some(model.to_string());
+        self
+    }
+
+    fn base_url(&self) -> String {
+        self.base_url
+            .clone()
+            .unwrap_or_else(|| CoreConfig::get().ollama_url.clone())
+    }
+
+    async fn send_chat_request(
+        &self,
+        request: &CompletionRequest,
+        stream: bool,
+    ) -> Result<reqwest::Response, CoreError> {
+        let chat_request = OllamaChatRequest::new(request, stream);
+
+        let response = self
+            .client
+            .post(format!("{}/api/chat", self.base_url()))
+            .header("content-type", "application/json")
+            .json(&chat_request)
+            .send()
+            .await?;
+
+        Ok(response)
+    }
+
+    /// Get the models that have been pulled onto the Ollama server, sorted by name
+    pub async fn get_models(&self) -> Result<Vec<OllamaModel>, CoreError> {
+        let response = self
+            .client
+            .get(format!("{}/api/tags", self.base_url()))
+            .send()
+            .await?;
+
+        if response.status().is_success() {
+            let tags_response: OllamaTagsResponse = response.json().await?;
+            let mut models = tags_response.models;
+            models.sort_by(|a, b| a.name.cmp(&b.name));
+
+            Ok(models)
+        } else {
+            Err(CoreError::from_response(self.name(), response).await)
+        }
+    }
+}
+
+#[async_trait]
+impl LlmProvider for OllamaProvider {
+    fn name(&self) -> &str {
+        "Ollama"
+    }
+
+    fn default_model(&self) -> String {
+        self.default_model
+            .clone()
+            .unwrap_or_else(|| CoreConfig::get().default_ollama_model.clone())
+    }
+
+    async fn list_models(&self) -> Result<Vec<String>, CoreError> {
+        let models = self.get_models().await?;
+        Ok(models.into_iter().map(|m| m.name).collect())
+    }
+
+    async fn complete(&self, request: &CompletionRequest) -> Result<Completion, CoreError> {
+        let response = self.send_chat_request(request, false).await?;
+
+        if response.status().is_success() {
+            let chat_response: OllamaChatResponse = response.json().await?;
+            if let Some(error) = chat_response.error {
+                return Err(CoreError::provider(self.name(), error));
+            }
+
+            let usage = chat_response.usage();
+            match chat_response.message {
+                Some(message) => Ok(Completion {
+                    text: message.content,
+                    model: chat_response.model,
+                    usage,
+                    finish_reason: chat_response.done_reason,
+                }),
+                None => Err(CoreError::provider(self.name(), "no message returned")),
+            }
+        } else {
+            Err(CoreError::from_response(self.name(), response).await)
+        }
+    }
+
+    async fn stream(&self, request: &CompletionRequest) -> Result<TokenStream, CoreError> {
+        let response = self.send_chat_request(request, true).await?;
+
+        if response.status().is_success() {
+            // ollama streams newline delimited json rather than server-sent events
+            Ok(line_chunks(response, ollama_stream_chunks))
+        } else {
+            Err(CoreError::from_response(self.name(), response).await)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::GenerationParams;
+    use crate::test_support::mock_server;
+    use axum::routing::{get, post};
+    use axum::{Json, Router};
+    use futures::StreamExt;
+    use serde_json::{json, Value};
+
+    // serve the router on a random local port and return its base url
+    fn mock_ollama() -> Router {
+        Router::new()
+            .route(
+                "/api/tags",
+                get(|| async {
+                    Json(json!({
+                        "models": [
+                            { "name": "mistral:latest", "modified_at": "2024-05-01T10:00:00Z", "size": 4109865159u64 },
+                            { "name": "llama3.2:latest", "modified_at": "2024-09-25T10:00:00Z", "size": 2019393189u64 }
+                        ]
+                    }))
+                }),
+            )
+            .route(
+                "/api/chat",
+                post(|Json(body): Json<Value>| async move {
+                    let last = body["messages"].as_array().unwrap().last().unwrap().clone();
+                    let reply = format!("{} messages, last: {}", body["messages"].as_array().unwrap().len(), last["content"].as_str().unwrap());
+
+                    if body["stream"] == true {
+                        let lines = [
+                            json!({ "model": body["model"], "message": { "role": "assistant", "content": "Hello" }, "done": false }),
+                            json!({ "model": body["model"], "message": { "role": "assistant", "content": " world" }, "done": false }),
+                            json!({ "model": body["model"], "message": { "role": "assistant", "content": "" }, "done": true, "done_reason": "stop", "prompt_eval_count": 26, "eval_count": 2 }),
+                        ];
+                        lines.iter().map(|l| format!("{}\n", l)).collect::<String>()
+                    } else {
+                        json!({
+                            "model": body["model"],
+                            "message": { "role": "assistant", "content": reply },
+                            "done": true,
+                            "done_reason": "stop",
+                            "prompt_eval_count": 40,
+                            "eval_count": 7
+                        })
+                        .to_string()
+                    }
+                }),
+            )
+    }
+
+    #[tokio::test]
+    async fn test_list_models_from_tags() {
+        let base_url = mock_server(mock_ollama()).await;
+        let provider = OllamaProvider::new().with_base_url(&base_url);
+
+        let models = provider.list_models().await.unwrap();
+        assert_eq!(models, vec!["llama3.2:latest", "mistral:latest"]);
+    }
+
+    #[tokio::test]
+    async fn test_complete_sends_history() {
+        let base_url = mock_server(mock_ollama()).await;
+        let provider = OllamaProvider::new().with_base_url(&base_url);
+        let request = CompletionRequest::new("llama3.2:latest", "and now?").with_history(vec![
+            ChatMessage::user("hi"),
+            ChatMessage::assistant("hello"),
+        ]);
+
+        let completion = provider.complete(&request).await.unwrap();
+        assert_eq!(completion.text, "3 messages, last: and now?");
+        assert_eq!(completion.model, "llama3.2:latest");
+        assert_eq!(completion.usage.input_tokens, Some(40));
+        assert_eq!(completion.usage.output_tokens, Some(7));
+        assert_eq!(completion.finish_reason, Some("stop".to_string()));
+    }
+
+    #[test]
+    fn test_chat_request_params() {
+        let request = CompletionRequest::new("llama3.2:latest", "hi");
+        let json = serde_json::to_value(OllamaChatRequest::new(&request, false)).unwrap();
+        assert!(json.get("options").is_none());
+
+        let request = request.with_params(GenerationParams {
+            system: Some("Answer in French".to_string()),
+            temperature: Some(0.1),
+            max_tokens: Some(256),
+            seed: Some(42),
+            ..Default::default()
+        });
+        let json = serde_json::to_value(OllamaChatRequest::new(&request, false)).unwrap();
+        assert_eq!(json["messages"][0]["role"], "system");
+        assert_eq!(json["messages"][1]["content"], "hi");
+        assert_eq!(json["options"]["num_predict"], 256);
+        assert_eq!(json["options"]["seed"], 42);
+        assert!(json["options"].get("top_p").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_stream_reads_json_lines() {
+        let base_url = mock_server(mock_ollama()).await;
+        let provider = OllamaProvider::new().with_base_url(&base_url);
+        let request = CompletionRequest::new("llama3.2:latest", "hi");
+
+        let chunks: Vec<StreamChunk> = provider
+            .stream(&request)
+            .await
+            .unwrap()
+            .map(|chunk| chunk.unwrap())
+            .collect()
+            .await;
+        assert_eq!(
+            chunks,
+            vec![
+                StreamChunk::Text("Hello".to_string()),
+                StreamChunk::Text(" world".to_string()),
+                StreamChunk::Usage(TokenUsage {
+                    input_tokens: Some(26),
+                    output_tokens: Some(2),
+                    ..Default::default()
+                }),
+                StreamChunk::Finish("stop".to_string()),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_server_error_is_returned() {
+        let app = Router::new().route(
+            "/api/tags",
+            get(|| async {
+                (
+                    axum::http::StatusCode::INTERNAL_SERVER_ERROR,
+                    "ollama is not running",
+                )
+            }),
+        );
+        let base_url = mock_server(app).await;
+        let provider = OllamaProvider::new().with_base_url(&base_url);
+
+        let error = provider.list_models().await.unwrap_err();
+        assert!(error.to_string().contains("ollama is not running"));
+    }
+
+    #[test]
+    fn test_stream_text_error_line() {
+        let result = ollama_stream_chunks(r#"{"error":"model not found"}"#);
+        assert!(result.unwrap_err().to_string().contains("model not found"));
+    }
+}
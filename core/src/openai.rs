@@ -1,9 +1,17 @@
-use crate::{create_prompt_record, Provider};
-use crate::{CoreConfig, Prompt};
-use reqwest::Client;
+use crate::cassette::{cassette, Cassette};
+use crate::error::CoreError;
+use crate::models::{GenerationParams, ModelInfo, TokenUsage};
+use crate::provider::{
+    ChatMessage, Completion, CompletionRequest, LlmProvider, StreamChunk, TokenStream,
+};
+use crate::sse::{sse_chunks, SseEvent};
+use crate::CoreConfig;
+use async_trait::async_trait;
+use chrono::DateTime;
+use reqwest::{Client, RequestBuilder};
 use serde::{Deserialize, Serialize};
-use sqlx::MySqlPool;
 use std::str::FromStr;
+use std::sync::Arc;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct OpenAIModelsResponse {
@@ -15,10 +23,26 @@ pub struct OpenAIModelsResponse {
 pub struct OpenAIModel {
     pub id: String,
     pub object: String,
+    // not every OpenAI-compatible server fills these in
+    #[serde(default)]
     pub created: u64,
+    #[serde(default)]
     pub owned_by: String,
 }
 
+// OpenAI has no display names, created is a unix timestamp that's 0 when a compatible server leaves it out
+impl From<OpenAIModel> for ModelInfo {
+    fn from(model: OpenAIModel) -> Self {
+        Self {
+            created_at: (model.created > 0)
+                .then(|| DateTime::from_timestamp(model.created as i64, 0))
+                .flatten(),
+            display_name: None,
+            id: model.id,
+        }
+    }
+}
+
 impl OpenAIModelsResponse {
     /// Filter models that start with any valid TextModelType prefix
     pub fn filter_by_valid_prefixes(&self) -> Vec<OpenAIModel> {
@@ -49,6 +73,22 @@ pub struct OpenAIChatRequest {
     pub max_completion_tokens: Option<u32>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stop: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seed: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stream: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stream_options: Option<OpenAIStreamOptions>,
+}
+
+// usage is only sent at the end of a stream when it's asked for
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OpenAIStreamOptions {
+    pub include_usage: bool,
 }
 
 impl OpenAIChatRequest {
@@ -59,10 +99,24 @@ impl OpenAIChatRequest {
             temperature: None,
             max_tokens: None,
             max_completion_tokens: None,
+            top_p: None,
+            stop: None,
+            seed: None,
+            stream: None,
+            stream_options: None,
             //additional: Map::new(),
         }
     }
 
+    // ask for the response as server-sent events
+    pub fn with_stream(mut self, stream: bool) -> Self {
+        self.stream = stream.then_some(true);
+        self.stream_options = stream.then_some(OpenAIStreamOptions {
+            include_usage: true,
+        });
+        self
+    }
+
     // newer models use max_completion_tokens, not max_tokens
     pub fn with_max_tokens(mut self, max_tokens: u32) -> Self {
         if self.is_newer_model() {
@@ -84,23 +138,80 @@ impl OpenAIChatRequest {
         self
     }
 
+    pub fn with_top_p(mut self, top_p: Option<f32>) -> Self {
+        self.top_p = top_p;
+        self
+    }
+
+    pub fn with_stop(mut self, stop: Option<Vec<String>>) -> Self {
+        self.stop = stop;
+        self
+    }
+
+    pub fn with_seed(mut self, seed: Option<i64>) -> Self {
+        self.seed = seed;
+        self
+    }
+
     fn is_newer_model(&self) -> bool {
-        let newer_model_prefixes = [
-            "gpt-5", "o1",
-            // Add more as they're released
-        ];
+        is_newer_model(&self.model)
+    }
+}
 
-        newer_model_prefixes
-            .iter()
-            .any(|prefix| self.model.starts_with(prefix))
+fn is_newer_model(model: &str) -> bool {
+    let newer_model_prefixes = [
+        "gpt-5", "o1",
+        // Add more as they're released
+    ];
+
+    newer_model_prefixes
+        .iter()
+        .any(|prefix| model.starts_with(prefix))
+}
+
+// newer models only sample at their defaults, they refuse a temperature other than 1, top_p
+// and stop. the caller is told rather than having them dropped, so a saved prompt's params
+// are always the ones that were sent
+fn check_params(provider: &str, model: &str, params: &GenerationParams) -> Result<(), CoreError> {
+    if !is_newer_model(model) {
+        return Ok(());
+    }
+
+    let mut unsupported = Vec::new();
+    if params
+        .temperature
+        .is_some_and(|temperature| temperature != 1.0)
+    {
+        unsupported.push("temperature".to_string());
+    }
+    if params.top_p.is_some() {
+        unsupported.push("top_p".to_string());
+    }
+    if params.stop.is_some() {
+        unsupported.push("stop".to_string());
+    }
+
+    if unsupported.is_empty() {
+        Ok(())
+    } else {
+        Err(CoreError::UnsupportedParams {
+            provider: provider.to_string(),
+            model: model.to_string(),
+            params: unsupported,
+        })
     }
 }
 
 pub struct OpenAIChatRequestBuilder {
     model: String,
     messages: Vec<OpenAIMessage>,
+    system: Option<String>,
     temperature: Option<f32>,
     max_tokens_value: Option<u32>,
+    top_p: Option<f32>,
+    stop: Option<Vec<String>>,
+    seed: Option<i64>,
+    stream: bool,
     //additional_params: Map<String, Value>,
 }
 
@@ -109,8 +220,13 @@ impl OpenAIChatRequestBuilder {
         Self {
             model,
             messages: Vec::new(),
+            system: None,
             temperature: None,
             max_tokens_value: None,
+            top_p: None,
+            stop: None,
+            seed: None,
+            stream: false,
             //additional_params: Map::new(),
         }
     }
@@ -138,6 +254,22 @@ impl OpenAIChatRequestBuilder {
         self
     }
 
+    pub fn stream(mut self, stream: bool) -> Self {
+        self.stream = stream;
+        self
+    }
+
+    // override the defaults with whatever the caller set
+    pub fn params(mut self, params: &GenerationParams) -> Self {
+        self.system = params.system.clone().or(self.system);
+        self.temperature = params.temperature.or(self.temperature);
+        self.max_tokens_value = params.max_tokens.or(self.max_tokens_value);
+        self.top_p = params.top_p.or(self.top_p);
+        self.stop = params.stop.clone().or(self.stop);
+        self.seed = params.seed.or(self.seed);
+        self
+    }
+
     /*    pub fn additional_param<T: serde::Serialize>(mut self, key: &str, value: T) -> Self {
         if let Ok(json_value) = serde_json::to_value(value) {
             self.additional_params.insert(key.to_string(), json_value);
@@ -145,8 +277,22 @@ impl OpenAIChatRequestBuilder {
         self
     }*/
 
-    pub fn build(self) -> OpenAIChatRequest {
-        let mut request = OpenAIChatRequest::new(self.model, self.messages);
+    pub fn build(mut self) -> OpenAIChatRequest {
+        // the system prompt goes in front of the conversation
+        if let Some(system) = self.system {
+            self.messages.insert(
+                0,
+                OpenAIMessage {
+                    role: "system".to_string(),
+                    content: system,
+                },
+            );
+        }
+
+        let mut request = OpenAIChatRequest::new(self.model, self.messages)
+            .with_top_p(self.top_p)
+            .with_stop(self.stop)
+            .with_seed(self.seed);
 
         if let Some(temp) = self.temperature {
             request = request.with_temperature(Some(temp));
@@ -157,7 +303,7 @@ impl OpenAIChatRequestBuilder {
         }
 
         //request.additional = self.additional_params;
-        request
+        request.with_stream(self.stream)
     }
 }
 
@@ -168,8 +314,9 @@ pub struct OpenAIChatResponse {
     pub created: u64,
     pub model: String,
     pub choices: Vec<OpenAIChoice>,
-    pub usage: OpenAIUsage,
-    pub service_tier: String,
+    // OpenAI always sends these, compatible servers may not
+    pub usage: Option<OpenAIUsage>,
+    pub service_tier: Option<String>,
     pub system_fingerprint: Option<String>,
 }
 
@@ -188,26 +335,117 @@ pub struct OpenAIMessage {
     pub annotations: Vec<serde_json::Value>,*/
 }
 
+impl From<&ChatMessage> for OpenAIMessage {
+    fn from(message: &ChatMessage) -> Self {
+        Self {
+            role: message.role.clone(),
+            content: message.content.clone(),
+        }
+    }
+}
+
+// Streaming structures
+#[derive(Debug, Deserialize)]
+pub struct OpenAIStreamChunk {
+    // empty for the final usage chunk
+    #[serde(default)]
+    pub choices: Vec<OpenAIStreamChoice>,
+    pub usage: Option<OpenAIUsage>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct OpenAIStreamChoice {
+    pub index: u32,
+    pub delta: OpenAIDelta,
+    pub finish_reason: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct OpenAIDelta {
+    pub role: Option<String>,
+    pub content: Option<String>,
+}
+
+// chunks carried by a streamed event, role only deltas produce none
+pub(crate) fn openai_stream_chunks(event: &SseEvent) -> Result<Vec<StreamChunk>, CoreError> {
+    // the stream is terminated by a [DONE] message rather than json
+    if event.data == "[DONE]" {
+        return Ok(vec![]);
+    }
+
+    let chunk: OpenAIStreamChunk = serde_json::from_str(&event.data)?;
+    let mut chunks = Vec::new();
+    if let Some(choice) = chunk.choices.into_iter().next() {
+        chunks.extend(choice.delta.content.map(StreamChunk::Text));
+        chunks.extend(choice.finish_reason.map(StreamChunk::Finish));
+    }
+    chunks.extend(chunk.usage.map(|usage| StreamChunk::Usage((&usage).into())));
+    Ok(chunks)
+}
+
+// builds the completion from a chat response, shared with the OpenAI-compatible provider
+pub(crate) fn completion_from_response(
+    provider: &str,
+    chat_response: OpenAIChatResponse,
+) -> Result<Completion, CoreError> {
+    let usage = chat_response
+        .usage
+        .as_ref()
+        .map(TokenUsage::from)
+        .unwrap_or_default();
+
+    match chat_response.choices.into_iter().next() {
+        Some(choice) => Ok(Completion {
+            text: choice.message.content,
+            model: chat_response.model,
+            usage,
+            finish_reason: Some(choice.finish_reason),
+        }),
+        None => Err(CoreError::provider(provider, "no choices returned")),
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct OpenAIUsage {
     pub prompt_tokens: u32,
     pub completion_tokens: u32,
     pub total_tokens: u32,
-    pub prompt_tokens_details: OpenAIPromptTokensDetails,
-    pub completion_tokens_details: OpenAICompletionTokensDetails,
+    pub prompt_tokens_details: Option<OpenAIPromptTokensDetails>,
+    pub completion_tokens_details: Option<OpenAICompletionTokensDetails>,
+}
+
+impl From<&OpenAIUsage> for TokenUsage {
+    fn from(usage: &OpenAIUsage) -> Self {
+        Self {
+            input_tokens: Some(usage.prompt_tokens),
+            output_tokens: Some(usage.completion_tokens),
+            reasoning_tokens: usage
+                .completion_tokens_details
+                .as_ref()
+                .map(|details| details.reasoning_tokens),
+            cached_tokens: usage
+                .prompt_tokens_details
+                .as_ref()
+                .map(|details| details.cached_tokens),
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct OpenAIPromptTokensDetails {
     pub cached_tokens: u32,
+    #[serde(default)]
     pub audio_tokens: u32,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct OpenAICompletionTokensDetails {
     pub reasoning_tokens: u32,
+    #[serde(default)]
     pub audio_tokens: u32,
+    #[serde(default)]
     pub accepted_prediction_tokens: u32,
+    #[serde(default)]
     pub rejected_prediction_tokens: u32,
 }
 
@@ -257,97 +495,329 @@ impl TextModelType {
     }
 }
 
-pub async fn call_openai(
-    prompt: &str,
-    model: Option<&str>,
-    pool: &MySqlPool,
-) -> Result<Prompt, Box<dyn std::error::Error>> {
-    let config = CoreConfig::get();
-    let client = Client::new();
+pub struct OpenAIProvider {
+    client: Client,
+    base_url: Option<String>,
+    api_key: Option<String>,
+    default_model: Option<String>,
+    cassette: Option<Arc<Cassette>>,
+}
 
-    if config.openai_key.is_none() {
-        return Err("ANTHROPIC_KEY is not set".into());
+impl Default for OpenAIProvider {
+    fn default() -> Self {
+        Self::new()
     }
+}
 
-    let mut model = model.unwrap_or(&config.default_openai_model);
-    let models = get_openai_models().await?;
-    if !models.iter().any(|m| m.id == model) {
-        println!(
-            "\r\x1b[2kInvalid model, {}, falling back to default model, {}",
-            model, &config.default_openai_model
-        );
-        model = &config.default_openai_model;
-    }
-
-    let request = OpenAIChatRequestBuilder::new(model.to_string())
-        //.add_message("system", "You are a helpful assistant")
-        .add_message("user", prompt)
-        .temperature(0.5)
-        .max_tokens(500)
-        //.additional_param("top_p", 0.9)
-        //.additional_param("frequency_penalty", 0.1)
-        .build();
-
-    let response = client
-        .post(format!("{}/chat/completions", &config.openai_url))
-        .header(
-            "authorization",
-            format!("Bearer {}", &config.openai_key.as_ref().unwrap()),
-        )
-        .header("content-type", "application/json")
-        .json(&request)
-        .send()
-        .await?;
-
-    if response.status().is_success() {
-        let chat_response: OpenAIChatResponse = response.json().await?;
-        if let Some(choice) = chat_response.choices.first() {
-            let repose_text = choice.message.content.as_str();
-
-            Ok(create_prompt_record(
-                pool,
-                prompt.to_string(),
-                repose_text,
-                model,
-                Provider::OpenAI.to_string().as_str(),
+impl OpenAIProvider {
+    pub fn new() -> Self {
+        Self {
+            client: Client::new(),
+            base_url: None,
+            api_key: None,
+            default_model: None,
+            cassette: None,
+        }
+    }
+
+    pub fn with_base_url(mut self, base_url: &str) -> Self {
+        self.base_url = Some(base_url.to_string());
+        self
+    }
+
+    pub fn with_api_key(mut self, api_key: &str) -> Self {
+        self.api_key = Some(api_key.to_string());
+        self
+    }
+
+    pub fn with_default_model(mut self, model: &str) -> Self {
+        self.default_model = Some(model.to_string());
+        self
+    }
+
+    pub fn with_cassette(mut self, cassette: Arc<Cassette>) -> Self {
+        self.cassette = Some(cassette);
+        self
+    }
+
+    fn base_url(&self) -> String {
+        self.base_url
+            .clone()
+            .unwrap_or_else(|| CoreConfig::get().openai_url.clone())
+    }
+
+    fn api_key(&self) -> Result<String, CoreError> {
+        self.api_key
+            .clone()
+            .or_else(|| CoreConfig::get().openai_key.clone())
+            .ok_or_else(|| CoreError::missing_credentials(self.name(), "OPENAI_KEY"))
+    }
+
+    // every request goes through the cassette so it can be recorded or replayed
+    async fn send(&self, request: RequestBuilder) -> Result<reqwest::Response, CoreError> {
+        match &self.cassette {
+            Some(cassette) => cassette.send(request).await,
+            None => cassette().send(request).await,
+        }
+    }
+
+    async fn send_chat_request(
+        &self,
+        request: &CompletionRequest,
+        stream: bool,
+    ) -> Result<reqwest::Response, CoreError> {
+        let api_key = self.api_key()?;
+        check_params(self.name(), &request.model, &request.params)?;
+
+        let chat_request = OpenAIChatRequestBuilder::new(request.model.clone())
+            //.add_message("system", "You are a helpful assistant")
+            .messages(request.messages.iter().map(OpenAIMessage::from).collect())
+            .temperature(0.5)
+            .max_tokens(500)
+            .params(&request.params)
+            //.additional_param("frequency_penalty", 0.1)
+            .stream(stream)
+            .build();
+
+        let request = self
+            .client
+            .post(format!("{}/chat/completions", self.base_url()))
+            .header("authorization", format!("Bearer {}", api_key))
+            .header("content-type", "application/json")
+            .json(&chat_request);
+
+        self.send(request).await
+    }
+
+    /// Get the text generation models from the models endpoint, sorted by id
+    pub async fn get_models(&self) -> Result<Vec<OpenAIModel>, CoreError> {
+        let api_key = self.api_key()?;
+
+        let response = self
+            .send(
+                self.client
+                    .get(format!("{}/models", self.base_url()))
+                    .header("Authorization", format!("Bearer {}", api_key)),
             )
-            .await?)
+            .await?;
+
+        if response.status().is_success() {
+            let models_response: OpenAIModelsResponse = response.json().await?;
+            // only keep models whose id starts with one of the TextModelType prefixes
+            let mut models: Vec<OpenAIModel> = models_response.filter_by_valid_prefixes();
+            models.sort_by(|a, b| a.id.cmp(&b.id));
+
+            Ok(models)
         } else {
-            Err("No choices returned from OpenAI API".into())
+            Err(CoreError::from_response(self.name(), response).await)
+        }
+    }
+}
+
+#[async_trait]
+impl LlmProvider for OpenAIProvider {
+    fn name(&self) -> &str {
+        "OpenAI"
+    }
+
+    fn default_model(&self) -> String {
+        self.default_model
+            .clone()
+            .unwrap_or_else(|| CoreConfig::get().default_openai_model.clone())
+    }
+
+    async fn list_models(&self) -> Result<Vec<String>, CoreError> {
+        let models = self.get_models().await?;
+        Ok(models.into_iter().map(|m| m.id).collect())
+    }
+
+    async fn list_model_info(&self) -> Result<Vec<ModelInfo>, CoreError> {
+        let models = self.get_models().await?;
+        Ok(models.into_iter().map(ModelInfo::from).collect())
+    }
+
+    async fn complete(&self, request: &CompletionRequest) -> Result<Completion, CoreError> {
+        let response = self.send_chat_request(request, false).await?;
+
+        if response.status().is_success() {
+            let chat_response: OpenAIChatResponse = response.json().await?;
+            completion_from_response(self.name(), chat_response)
+        } else {
+            Err(CoreError::from_response(self.name(), response).await)
+        }
+    }
+    async fn stream(&self, request: &CompletionRequest) -> Result<TokenStream, CoreError> {
+        let response = self.send_chat_request(request, true).await?;
+
+        if response.status().is_success() {
+            Ok(sse_chunks(response, openai_stream_chunks))
+        } else {
+            Err(CoreError::from_response(self.name(), response).await)
         }
-    } else {
-        let error_text = response.text().await?;
-        Err(format!("OpenAI API request failed: {}", error_text).into())
     }
 }
 
-pub async fn get_openai_models() -> Result<Vec<OpenAIModel>, Box<dyn std::error::Error>> {
-    let config = CoreConfig::get();
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    if config.openai_key.is_none() {
-        return Err("OPENAI_KEY is not set".into());
+    fn event(data: &str) -> SseEvent {
+        SseEvent {
+            event: None,
+            data: data.to_string(),
+        }
     }
 
-    let client = Client::new();
-    let response = client
-        .get(format!("{}/models", &config.openai_url))
-        .header(
-            "Authorization",
-            format!("Bearer {}", &config.openai_key.clone().unwrap()),
-        )
-        .send()
-        .await?;
+    #[test]
+    fn test_request_builder_stream_flag() {
+        let request = OpenAIChatRequestBuilder::new("gpt-4o".to_string())
+            .add_message("user", "hi")
+            .build();
+        let json = serde_json::to_value(&request).unwrap();
+        assert!(json.get("stream").is_none());
+
+        let request = OpenAIChatRequestBuilder::new("gpt-4o".to_string())
+            .add_message("user", "hi")
+            .stream(true)
+            .build();
+        let json = serde_json::to_value(&request).unwrap();
+        assert_eq!(json["stream"], true);
+        assert_eq!(json["stream_options"]["include_usage"], true);
+    }
 
-    if response.status().is_success() {
-        let models_response: OpenAIModelsResponse = response.json().await?;
-        let mut models: Vec<OpenAIModel> = models_response.filter_by_valid_prefixes();
-        models.sort_by(|a, b| a.id.cmp(&b.id));
+    #[test]
+    fn test_request_builder_params() {
+        let params = GenerationParams {
+            system: Some("Answer in French".to_string()),
+            temperature: Some(0.1),
+            top_p: Some(0.9),
+            max_tokens: Some(256),
+            stop: Some(vec!["END".to_string()]),
+            seed: Some(42),
+        };
+        let build = |model: &str| {
+            let request = OpenAIChatRequestBuilder::new(model.to_string())
+                .add_message("user", "hi")
+                .temperature(0.5)
+                .max_tokens(500)
+                .params(&params)
+                .build();
+            serde_json::to_value(&request).unwrap()
+        };
+
+        let json = build("gpt-4o");
+        assert_eq!(json["messages"][0]["role"], "system");
+        assert_eq!(json["messages"][0]["content"], "Answer in French");
+        assert_eq!(json["messages"][1]["role"], "user");
+        assert_eq!(json["temperature"].as_f64().unwrap() as f32, 0.1);
+        assert_eq!(json["max_tokens"], 256);
+        assert_eq!(json["top_p"].as_f64().unwrap() as f32, 0.9);
+        assert_eq!(json["stop"], serde_json::json!(["END"]));
+        assert_eq!(json["seed"], 42);
+
+        // newer models only take max_completion_tokens and their default sampling
+        let json = build("gpt-5-mini");
+        assert_eq!(json["max_completion_tokens"], 256);
+        assert!(json.get("max_tokens").is_none());
+        assert_eq!(json["seed"], 42);
+    }
 
-        // see if model id starts with one of the TextModelType fromstrs
+    #[test]
+    fn test_newer_models_refuse_sampling_params() {
+        let params = GenerationParams {
+            temperature: Some(0.1),
+            top_p: Some(0.9),
+            stop: Some(vec!["END".to_string()]),
+            seed: Some(42),
+            ..Default::default()
+        };
+        assert!(check_params("OpenAI", "gpt-4o", &params).is_ok());
+        match check_params("OpenAI", "gpt-5-mini", &params) {
+            Err(CoreError::UnsupportedParams { params, .. }) => {
+                assert_eq!(params, vec!["temperature", "top_p", "stop"])
+            }
+            other => panic!("expected unsupported params, got {:?}", other),
+        }
 
-        Ok(models)
-    } else {
-        let error_text = response.text().await?;
-        Err(format!("OpenAI API request failed: {}", error_text).into())
+        // their default temperature can be asked for, and is what's sent without one
+        let defaults = GenerationParams {
+            temperature: Some(1.0),
+            max_tokens: Some(256),
+            ..Default::default()
+        };
+        assert!(check_params("OpenAI", "o1-mini", &defaults).is_ok());
+        let request = OpenAIChatRequestBuilder::new("o1-mini".to_string())
+            .add_message("user", "hi")
+            .temperature(0.5)
+            .build();
+        let json = serde_json::to_value(&request).unwrap();
+        assert_eq!(json["temperature"], 1.0);
+    }
+
+    #[test]
+    fn test_messages_from_history() {
+        let request = CompletionRequest::new("gpt-4o", "second").with_history(vec![
+            ChatMessage::user("first"),
+            ChatMessage::assistant("answer"),
+        ]);
+        let chat_request = OpenAIChatRequestBuilder::new(request.model.clone())
+            .messages(request.messages.iter().map(OpenAIMessage::from).collect())
+            .build();
+        let json = serde_json::to_value(&chat_request).unwrap();
+
+        assert_eq!(json["messages"][0]["role"], "user");
+        assert_eq!(json["messages"][1]["role"], "assistant");
+        assert_eq!(json["messages"][2]["content"], "second");
+    }
+
+    #[test]
+    fn test_stream_chunks_from_chunk() {
+        let chunks = openai_stream_chunks(&event(
+            r#"{"id":"1","object":"chat.completion.chunk","choices":[{"index":0,"delta":{"content":"Hi"},"finish_reason":null}]}"#,
+        ))
+        .unwrap();
+        assert_eq!(chunks, vec![StreamChunk::Text("Hi".to_string())]);
+    }
+
+    #[test]
+    fn test_stream_chunks_role_only_chunk() {
+        let chunks = openai_stream_chunks(&event(
+            r#"{"choices":[{"index":0,"delta":{"role":"assistant"},"finish_reason":null}]}"#,
+        ))
+        .unwrap();
+        assert!(chunks.is_empty());
+    }
+
+    #[test]
+    fn test_stream_chunks_finish_and_usage() {
+        let chunks = openai_stream_chunks(&event(
+            r#"{"choices":[{"index":0,"delta":{},"finish_reason":"length"}]}"#,
+        ))
+        .unwrap();
+        assert_eq!(chunks, vec![StreamChunk::Finish("length".to_string())]);
+
+        let chunks = openai_stream_chunks(&event(
+            r#"{"choices":[],"usage":{"prompt_tokens":20,"completion_tokens":300,"total_tokens":320,"prompt_tokens_details":{"cached_tokens":8},"completion_tokens_details":{"reasoning_tokens":256}}}"#,
+        ))
+        .unwrap();
+        assert_eq!(
+            chunks,
+            vec![StreamChunk::Usage(TokenUsage {
+                input_tokens: Some(20),
+                output_tokens: Some(300),
+                reasoning_tokens: Some(256),
+                cached_tokens: Some(8),
+            })]
+        );
+    }
+
+    #[test]
+    fn test_stream_chunks_done() {
+        assert!(openai_stream_chunks(&event("[DONE]")).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_stream_chunks_invalid_json() {
+        assert!(openai_stream_chunks(&event("not json")).is_err());
     }
 }
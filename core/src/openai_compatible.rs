@@ -0,0 +1,338 @@
+use crate::cassette::{cassette, Cassette};
+use crate::error::CoreError;
+use crate::models::ModelInfo;
+use crate::openai::{
+    completion_from_response, openai_stream_chunks, OpenAIChatRequestBuilder, OpenAIChatResponse,
+    OpenAIMessage, OpenAIModel, OpenAIModelsResponse,
+};
+use crate::provider::{Completion, CompletionRequest, LlmProvider, TokenStream};
+use crate::sse::sse_chunks;
+use crate::CoreConfig;
+use async_trait::async_trait;
+use reqwest::{Client, RequestBuilder};
+use std::sync::Arc;
+
+/// Provider for self hosted servers that speak the OpenAI chat completions api,
+/// such as vLLM or the llama.cpp server. The api key is optional.
+pub struct OpenAICompatibleProvider {
+    client: Client,
+    base_url: Option<String>,
+    api_key: Option<String>,
+    default_model: Option<String>,
+    cassette: Option<Arc<Cassette>>,
+}
+
+impl Default for OpenAICompatibleProvider {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl OpenAICompatibleProvider {
+    pub fn new() -> Self {
+        Self {
+            client: Client::new(),
+            base_url: None,
+            api_key: None,
+            default_model: None,
+            cassette: None,
+        }
+    }
+
+    pub fn with_base_url(mut self, base_url: &str) -> Self {
+        self.base_url = Some(base_url.to_string());
+        self
+    }
+
+    pub fn with_api_key(mut self, api_key: &str) -> Self {
+        self.api_key = Some(api_key.to_string());
+        self
+    }
+
+    pub fn with_default_model(mut self, model: &str) -> Self {
+        self.default_model = Some(model.to_string());
+        self
+    }
+
+    pub fn with_cassette(mut self, cassette: Arc<Cassette>) -> Self {
+        self.cassette = Some(cassette);
+        self
+    }
+
+    fn base_url(&self) -> Result<String, CoreError> {
+        self.base_url
+            .clone()
+            .or_else(|| CoreConfig::get().openai_compatible_url.clone())
+            .ok_or_else(|| CoreError::NotConfigured {
+                provider: self.name().to_string(),
+                variable: "OPENAI_COMPATIBLE_BASE_URL".to_string(),
+            })
+    }
+
+    fn api_key(&self) -> Option<String> {
+        self.api_key
+            .clone()
+            .or_else(|| CoreConfig::get().openai_compatible_key.clone())
+    }
+
+    // local servers usually run without auth so the header is only sent when a key is set
+    fn authorize(&self, request: RequestBuilder) -> RequestBuilder {
+        match self.api_key() {
+            Some(api_key) => request.header("authorization", format!("Bearer {}", api_key)),
+            None => request,
+        }
+    }
+
+    // every request goes through the cassette so it can be recorded or replayed
+    async fn send(&self, request: RequestBuilder) -> Result<reqwest::Response, CoreError> {
+        match &self.cassette {
+            Some(cassette) => cassette.send(request).await,
+            None => cassette().send(request).await,
+        }
+    }
+
+    async fn send_chat_request(
+        &self,
+        request: &CompletionRequest,
+        stream: bool,
+    ) -> Result<reqwest::Response, CoreError> {
+        let chat_request = OpenAIChatRequestBuilder::new(request.model.clone())
+            .messages(request.messages.iter().map(OpenAIMessage::from).collect())
+            .temperature(0.5)
+            .max_tokens(500)
+            .params(&request.params)
+            .stream(stream)
+            .build();
+
+        let request = self
+            .authorize(
+                self.client
+                    .post(format!("{}/chat/completions", self.base_url()?)),
+            )
+            .header("content-type", "application/json")
+            .json(&chat_request);
+
+        self.send(request).await
+    }
+
+    /// Get every model the server exposes, sorted by id
+    pub async fn get_models(&self) -> Result<Vec<OpenAIModel>, CoreError> {
+        let response = self
+            .send(self.authorize(self.client.get(format!("{}/models", self.base_url()?))))
+            .await?;
+
+        if response.status().is_success() {
+            // unlike OpenAI there's no way to tell chat models apart so nothing is filtered out
+            let models_response: OpenAIModelsResponse = response.json().await?;
+            let mut models = models_response.data;
+            models.sort_by(|a, b| a.id.cmp(&b.id));
+
+            Ok(models)
+        } else {
+            Err(CoreError::from_response(self.name(), response).await)
+        }
+    }
+}
+
+#[async_trait]
+impl LlmProvider for OpenAICompatibleProvider {
+    fn name(&self) -> &str {
+        "OpenAI-Compatible"
+    }
+
+    // empty when no default is configured, the first listed model is used instead
+    fn default_model(&self) -> String {
+        self.default_model
+            .clone()
+            .or_else(|| CoreConfig::get().default_openai_compatible_model.clone())
+            .unwrap_or_default()
+    }
+
+    async fn list_models(&self) -> Result<Vec<String>, CoreError> {
+        let models = self.get_models().await?;
+        Ok(models.into_iter().map(|m| m.id).collect())
+    }
+
+    async fn list_model_info(&self) -> Result<Vec<ModelInfo>, CoreError> {
+        let models = self.get_models().await?;
+        Ok(models.into_iter().map(ModelInfo::from).collect())
+    }
+
+    async fn complete(&self, request: &CompletionRequest) -> Result<Completion, CoreError> {
+        let response = self.send_chat_request(request, false).await?;
+
+        if response.status().is_success() {
+            let chat_response: OpenAIChatResponse = response.json().await?;
+            completion_from_response(self.name(), chat_response)
+        } else {
+            Err(CoreError::from_response(self.name(), response).await)
+        }
+    }
+
+    async fn stream(&self, request: &CompletionRequest) -> Result<TokenStream, CoreError> {
+        let response = self.send_chat_request(request, true).await?;
+
+        if response.status().is_success() {
+            Ok(sse_chunks(response, openai_stream_chunks))
+        } else {
+            Err(CoreError::from_response(self.name(), response).await)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::TokenUsage;
+    use crate::provider::StreamChunk;
+    use crate::test_support::mock_server;
+    use axum::http::{HeaderMap, StatusCode};
+    use axum::routing::{get, post};
+    use axum::{Json, Router};
+    use futures::StreamExt;
+    use serde_json::{json, Value};
+
+    // serve the router on a random local port and return its base url
+    // a llama.cpp style server, models don't have owned_by and responses have no usage details
+    fn mock_compatible_server() -> Router {
+        Router::new()
+            .route(
+                "/v1/models",
+                get(|headers: HeaderMap| async move {
+                    let authorized = headers
+                        .get("authorization")
+                        .map(|v| v.to_str().unwrap().to_string());
+                    Json(json!({
+                        "object": "list",
+                        "data": [
+                            { "id": "qwen2.5-7b-instruct", "object": "model" },
+                            { "id": "llama-3.1-8b-instruct", "object": "model" },
+                            { "id": format!("auth:{}", authorized.unwrap_or_else(|| "none".to_string())), "object": "model" }
+                        ]
+                    }))
+                }),
+            )
+            .route(
+                "/v1/chat/completions",
+                post(|Json(body): Json<Value>| async move {
+                    if body["stream"] == true {
+                        let chunks = [
+                            json!({ "choices": [{ "index": 0, "delta": { "role": "assistant" }, "finish_reason": null }] }),
+                            json!({ "choices": [{ "index": 0, "delta": { "content": "Local" }, "finish_reason": null }] }),
+                            json!({ "choices": [{ "index": 0, "delta": { "content": " reply" }, "finish_reason": "stop" }] }),
+                        ];
+                        let mut body: String =
+                            chunks.iter().map(|c| format!("data: {}\n\n", c)).collect();
+                        body.push_str("data: [DONE]\n\n");
+                        (StatusCode::OK, body)
+                    } else {
+                        let response = json!({
+                            "id": "chatcmpl-1",
+                            "object": "chat.completion",
+                            "created": 1727000000u64,
+                            "model": body["model"],
+                            "choices": [{
+                                "index": 0,
+                                "message": { "role": "assistant", "content": "Local reply" },
+                                "finish_reason": "stop"
+                            }]
+                        });
+                        (StatusCode::OK, response.to_string())
+                    }
+                }),
+            )
+    }
+
+    #[tokio::test]
+    async fn test_list_models_sends_key_when_set() {
+        let base_url = format!("{}/v1", mock_server(mock_compatible_server()).await);
+        let provider = OpenAICompatibleProvider::new()
+            .with_base_url(&base_url)
+            .with_api_key("local-key")
+            .with_cassette(Arc::new(Cassette::off()));
+
+        let models = provider.list_models().await.unwrap();
+        assert_eq!(
+            models,
+            vec![
+                "auth:Bearer local-key",
+                "llama-3.1-8b-instruct",
+                "qwen2.5-7b-instruct"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_complete_without_usage() {
+        let base_url = format!("{}/v1", mock_server(mock_compatible_server()).await);
+        let provider = OpenAICompatibleProvider::new()
+            .with_base_url(&base_url)
+            .with_api_key("local-key")
+            .with_cassette(Arc::new(Cassette::off()));
+
+        let completion = provider
+            .complete(&CompletionRequest::new("llama-3.1-8b-instruct", "hi"))
+            .await
+            .unwrap();
+        assert_eq!(completion.text, "Local reply");
+        assert_eq!(completion.model, "llama-3.1-8b-instruct");
+        assert_eq!(completion.usage, TokenUsage::default());
+        assert_eq!(completion.finish_reason, Some("stop".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_stream() {
+        let base_url = format!("{}/v1", mock_server(mock_compatible_server()).await);
+        let provider = OpenAICompatibleProvider::new()
+            .with_base_url(&base_url)
+            .with_api_key("local-key")
+            .with_cassette(Arc::new(Cassette::off()));
+
+        let chunks: Vec<StreamChunk> = provider
+            .stream(&CompletionRequest::new("llama-3.1-8b-instruct", "hi"))
+            .await
+            .unwrap()
+            .map(|chunk| chunk.unwrap())
+            .collect()
+            .await;
+        assert_eq!(
+            chunks,
+            vec![
+                StreamChunk::Text("Local".to_string()),
+                StreamChunk::Text(" reply".to_string()),
+                StreamChunk::Finish("stop".to_string()),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_stream_through_cassette() {
+        let base_url = format!("{}/v1", mock_server(mock_compatible_server()).await);
+        let path = std::env::temp_dir()
+            .join(format!("kubellm-compatible-{}.json", std::process::id()))
+            .to_string_lossy()
+            .to_string();
+        let request = CompletionRequest::new("llama-3.1-8b-instruct", "hi");
+        let chunks = |cassette: Cassette| {
+            let provider = OpenAICompatibleProvider::new()
+                .with_base_url(&base_url)
+                .with_api_key("local-key")
+                .with_cassette(Arc::new(cassette));
+            let request = request.clone();
+            async move {
+                let stream = provider.stream(&request).await.unwrap();
+                stream
+                    .map(|chunk| chunk.unwrap())
+                    .collect::<Vec<StreamChunk>>()
+                    .await
+            }
+        };
+
+        let recorded = chunks(Cassette::record(&path)).await;
+        let replayed = chunks(Cassette::replay(&path).unwrap()).await;
+        assert_eq!(recorded.len(), 3);
+        assert_eq!(replayed, recorded);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,174 @@
+use crate::config::CoreConfig;
+use crate::models::TokenUsage;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::sync::OnceLock;
+
+// prices shipped with kubellm, used when PRICING_FILE isn't set
+const BUILTIN_PRICING: &str = include_str!("../pricing.json");
+
+/// Price of a model in currency units per million tokens
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ModelPrice {
+    pub provider: String,
+    // matched against the start of the model id so dated versions share a price,
+    // an empty model prices every model of the provider
+    pub model: String,
+    pub input: f64,
+    pub output: f64,
+    // price of input tokens read from the provider's prompt cache, defaults to the input price
+    #[serde(default)]
+    pub cached_input: Option<f64>,
+}
+
+/// Per-model prices loaded from a json pricing file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PriceTable {
+    #[serde(default = "default_currency")]
+    pub currency: String,
+    pub models: Vec<ModelPrice>,
+}
+
+fn default_currency() -> String {
+    "USD".to_string()
+}
+
+impl PriceTable {
+    pub fn from_json(json: &str) -> Result<Self> {
+        serde_json::from_str(json).context("Invalid pricing file")
+    }
+
+    pub fn from_file(path: &str) -> Result<Self> {
+        let json = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read pricing file {}", path))?;
+        Self::from_json(&json)
+    }
+
+    pub fn builtin() -> Self {
+        Self::from_json(BUILTIN_PRICING).expect("built in pricing file is valid")
+    }
+
+    /// Price for a model, the entry with the longest matching model prefix wins
+    pub fn find(&self, provider: &str, model: &str) -> Option<&ModelPrice> {
+        self.models
+            .iter()
+            .filter(|price| price.provider.eq_ignore_ascii_case(provider))
+            .filter(|price| model.starts_with(&price.model))
+            .max_by_key(|price| price.model.len())
+    }
+
+    /// Cost of a prompt, None if the model has no price or the provider reported no usage.
+    /// Cached tokens are counted as part of the input tokens.
+    pub fn cost(&self, provider: &str, model: &str, usage: &TokenUsage) -> Option<f64> {
+        if usage.input_tokens.is_none() && usage.output_tokens.is_none() {
+            return None;
+        }
+        let price = self.find(provider, model)?;
+
+        let input = usage.input_tokens.unwrap_or(0) as f64;
+        let output = usage.output_tokens.unwrap_or(0) as f64;
+        let cached = (usage.cached_tokens.unwrap_or(0) as f64).min(input);
+
+        let cost = (input - cached) * price.input
+            + cached * price.cached_input.unwrap_or(price.input)
+            + output * price.output;
+        Some(cost / 1_000_000.0)
+    }
+}
+
+static PRICE_TABLE: OnceLock<PriceTable> = OnceLock::new();
+
+/// Load the prices from PRICING_FILE, or the built in table when it isn't set.
+/// Binaries call this on start so a broken file is reported rather than panicking later
+pub fn load_price_table() -> Result<&'static PriceTable> {
+    if let Some(table) = PRICE_TABLE.get() {
+        return Ok(table);
+    }
+
+    let table = match &CoreConfig::get().pricing_file {
+        Some(path) => PriceTable::from_file(path)?,
+        None => PriceTable::builtin(),
+    };
+    Ok(PRICE_TABLE.get_or_init(|| table))
+}
+
+/// Prices used to cost prompts, loaded from PRICING_FILE or the built in table
+pub fn price_table() -> &'static PriceTable {
+    load_price_table().expect("Failed to load pricing file")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn usage(input: u32, output: u32, cached: Option<u32>) -> TokenUsage {
+        TokenUsage {
+            input_tokens: Some(input),
+            output_tokens: Some(output),
+            reasoning_tokens: None,
+            cached_tokens: cached,
+        }
+    }
+
+    #[test]
+    fn test_builtin_pricing_parses() {
+        let table = PriceTable::builtin();
+        assert_eq!(table.currency, "USD");
+        assert!(!table.models.is_empty());
+    }
+
+    #[test]
+    fn test_find_longest_prefix() {
+        let table = PriceTable::builtin();
+        assert_eq!(
+            table
+                .find("openai", "gpt-4o-mini-2024-07-18")
+                .unwrap()
+                .model,
+            "gpt-4o-mini"
+        );
+        assert_eq!(
+            table.find("OpenAI", "gpt-4o-2024-08-06").unwrap().model,
+            "gpt-4o"
+        );
+        assert_eq!(
+            table
+                .find("Anthropic", "claude-opus-4-1-20250805")
+                .unwrap()
+                .model,
+            "claude-opus-4"
+        );
+        assert_eq!(table.find("Ollama", "llama3.2:latest").unwrap().input, 0.0);
+        assert!(table.find("Anthropic", "gpt-4o").is_none());
+    }
+
+    #[test]
+    fn test_cost() {
+        let table = PriceTable::from_json(
+            r#"{"models":[{"provider":"OpenAI","model":"gpt-test","input":2.0,"output":8.0,"cached_input":0.5}]}"#,
+        )
+        .unwrap();
+
+        let cost = table
+            .cost("OpenAI", "gpt-test", &usage(1_000_000, 500_000, None))
+            .unwrap();
+        assert!((cost - 6.0).abs() < 1e-9);
+
+        // half the input came from the cache
+        let cost = table
+            .cost("OpenAI", "gpt-test", &usage(1_000_000, 0, Some(500_000)))
+            .unwrap();
+        assert!((cost - 1.25).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_cost_unknown() {
+        let table = PriceTable::builtin();
+        assert!(table
+            .cost("OpenAI", "unpriced-model", &usage(10, 10, None))
+            .is_none());
+        assert!(table
+            .cost("OpenAI", "gpt-4o", &TokenUsage::default())
+            .is_none());
+    }
+}
@@ -1,102 +1,768 @@
-use crate::anthropic;
-use crate::models::{Prompt, Provider};
-use crate::openai;
-use sqlx::MySqlPool;
-use std::str::FromStr;
+use crate::budget::check_budgets;
+use crate::cache::{response_cache, CachedResponse, ResponseCache};
+use crate::catalog::model_catalog;
+use crate::error::CoreError;
+use crate::models::{
+    normalize_tags, CacheMode, CompareRequest, Comparison, ComparisonResult, CreatePromptRequest,
+    ErrorResponse, ModelCatalogEntry, ModelMode, NewPrompt, Prompt, TokenUsage,
+};
+use crate::pricing::price_table;
+use crate::provider::{
+    get_provider, get_route, ChatMessage, Completion, CompletionRequest, LlmProvider, StreamChunk,
+    TokenStream,
+};
+use crate::retry::retry_policy;
+use crate::route::RouteTarget;
+use crate::store::PromptStore;
+use crate::template::{PromptTemplate, RunTemplateRequest};
+use futures::{future, stream, Stream, StreamExt};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::sync::mpsc;
+use uuid::Uuid;
 
-// prompt the provider - model optional
+// events produced while a prompt's response is streamed
+#[derive(Debug)]
+pub enum PromptStreamEvent {
+    // a chunk of the response text
+    Token(String),
+    // the stream has finished and the full response was saved
+    Done(Box<Prompt>),
+}
+
+pub type PromptStream = Pin<Box<dyn Stream<Item = Result<PromptStreamEvent, CoreError>> + Send>>;
+
+// prompt the request's provider - model optional, model_mode decides what happens when it is unknown
+// user is the caller's user or api key name, the prompt counts towards that user's budgets
 pub async fn prompt_model(
-    prompt: &str,
-    provider: &str,
-    model: Option<&str>,
-    pool: &MySqlPool,
-) -> Result<Prompt, Box<dyn std::error::Error>> {
-    match Provider::from_str(provider) {
-        Ok(provider) => match provider {
-            Provider::Anthropic => match anthropic::call_anthropic(prompt, model, pool).await {
-                Ok(create_prompt_response) => Ok(create_prompt_response),
-                Err(e) => Err(e),
-            },
-            Provider::OpenAI => match openai::call_openai(prompt, model, pool).await {
-                Ok(create_prompt_response) => Ok(create_prompt_response),
-                Err(e) => Err(e),
-            },
+    request: &CreatePromptRequest,
+    user: Option<&str>,
+    store: &Arc<dyn PromptStore>,
+) -> Result<Prompt, CoreError> {
+    complete_prompt(request, PromptLinks::default(), user, store.as_ref()).await
+}
+
+// prompt the provider and stream the response, the full response is saved once the provider
+// finishes even if the stream is dropped before then
+pub async fn stream_prompt_model(
+    request: &CreatePromptRequest,
+    user: Option<&str>,
+    store: &Arc<dyn PromptStore>,
+) -> Result<PromptStream, CoreError> {
+    stream_prompt(request, PromptLinks::default(), user, store).await
+}
+
+// continue a conversation, earlier turns are sent to the provider along with the prompt
+pub async fn prompt_conversation(
+    conversation_id: i64,
+    request: &CreatePromptRequest,
+    user: Option<&str>,
+    store: &Arc<dyn PromptStore>,
+) -> Result<Prompt, CoreError> {
+    let links = PromptLinks {
+        conversation_id: Some(conversation_id),
+        ..Default::default()
+    };
+    complete_prompt(request, links, user, store.as_ref()).await
+}
+
+// continue a conversation and stream the response
+pub async fn stream_prompt_conversation(
+    conversation_id: i64,
+    request: &CreatePromptRequest,
+    user: Option<&str>,
+    store: &Arc<dyn PromptStore>,
+) -> Result<PromptStream, CoreError> {
+    let links = PromptLinks {
+        conversation_id: Some(conversation_id),
+        ..Default::default()
+    };
+    stream_prompt(request, links, user, store).await
+}
+
+// run a version of a prompt template, the saved prompt links back to that version
+pub async fn prompt_template(
+    template_id: i64,
+    run: &RunTemplateRequest,
+    user: Option<&str>,
+    store: &Arc<dyn PromptStore>,
+) -> Result<Prompt, CoreError> {
+    let (template, request) = template_request(template_id, run, store.as_ref()).await?;
+    let links = PromptLinks {
+        template_id: Some(template.id),
+        ..Default::default()
+    };
+    complete_prompt(&request, links, user, store.as_ref()).await
+}
+
+// run a version of a prompt template and stream the response
+pub async fn stream_prompt_template(
+    template_id: i64,
+    run: &RunTemplateRequest,
+    user: Option<&str>,
+    store: &Arc<dyn PromptStore>,
+) -> Result<PromptStream, CoreError> {
+    let (template, request) = template_request(template_id, run, store.as_ref()).await?;
+    let links = PromptLinks {
+        template_id: Some(template.id),
+        ..Default::default()
+    };
+    stream_prompt(&request, links, user, store).await
+}
+
+async fn template_request(
+    template_id: i64,
+    run: &RunTemplateRequest,
+    store: &dyn PromptStore,
+) -> Result<(PromptTemplate, CreatePromptRequest), CoreError> {
+    let template = store
+        .get_prompt_template(template_id)
+        .await?
+        .ok_or(CoreError::TemplateNotFound(template_id))?;
+    let request = template.prompt_request(run)?;
+    Ok((template, request))
+}
+
+// send one prompt to every target at once, the answers are saved under a shared comparison id.
+// a target that fails doesn't stop the others, its error is returned in its result instead
+pub async fn compare_prompt(
+    request: &CompareRequest,
+    user: Option<&str>,
+    store: &Arc<dyn PromptStore>,
+) -> Comparison {
+    let comparison_id = Uuid::new_v4().to_string();
+    let id = &comparison_id;
+
+    // answers are saved as they come in, the target's index keeps them in the order given
+    let results = future::join_all(request.targets.iter().enumerate().map(
+        |(index, target)| async move {
+            let prompt_request = CreatePromptRequest::new(&request.prompt, &target.provider)
+                .with_model(target.model.as_deref())
+                .with_model_mode(request.model_mode)
+                .with_params(request.params.clone());
+            let links = PromptLinks {
+                comparison_id: Some(id.clone()),
+                comparison_index: Some(index as i32),
+                ..Default::default()
+            };
+
+            match complete_prompt(&prompt_request, links, user, store.as_ref()).await {
+                Ok(prompt) => ComparisonResult {
+                    target: target.clone(),
+                    prompt: Some(prompt),
+                    error: None,
+                },
+                Err(e) => ComparisonResult {
+                    target: target.clone(),
+                    prompt: None,
+                    error: Some(ErrorResponse::from(&e)),
+                },
+            }
         },
-        Err(e) => Err(Box::from(e)),
+    ))
+    .await;
+
+    Comparison {
+        comparison_id,
+        results,
     }
 }
 
-// get models for a given provider
-pub async fn get_models(provider: &str) -> Result<Vec<String>, Box<dyn std::error::Error>> {
-    match Provider::from_str(provider) {
-        Ok(provider) => match provider {
-            Provider::Anthropic => match anthropic::get_anthropic_models().await {
-                Ok(models) => {
-                    let model_names = models.into_iter().map(|m| m.id).collect();
-                    Ok(model_names)
-                }
-                Err(e) => Err(e),
-            },
-            Provider::OpenAI => match openai::get_openai_models().await {
-                Ok(models) => {
-                    let model_names = models.into_iter().map(|m| m.id).collect();
-                    Ok(model_names)
-                }
-                Err(e) => Err(e),
-            },
+// replay saved prompts as alternating user and assistant turns
+pub fn conversation_history(prompts: &[Prompt]) -> Vec<ChatMessage> {
+    prompts
+        .iter()
+        .flat_map(|p| {
+            [
+                ChatMessage::user(&p.prompt),
+                ChatMessage::assistant(&p.response),
+            ]
+        })
+        .collect()
+}
+
+// what a saved prompt is part of besides its own request
+#[derive(Debug, Default)]
+struct PromptLinks {
+    conversation_id: Option<i64>,
+    template_id: Option<i64>,
+    comparison_id: Option<String>,
+    comparison_index: Option<i32>,
+}
+
+async fn complete_prompt(
+    request: &CreatePromptRequest,
+    links: PromptLinks,
+    user: Option<&str>,
+    store: &dyn PromptStore,
+) -> Result<Prompt, CoreError> {
+    let (route, targets) = prompt_targets(&request.provider, request.model.as_deref());
+    let conversation_id = links.conversation_id;
+
+    let ((target, completion, attempts, cache_hit, latency_ms), attempted) =
+        first_answer(&targets, |target| async move {
+            let target = prepare_target(target, request, conversation_id, user, store).await?;
+            let started = Instant::now();
+            let (completion, attempts, cache_hit) =
+                complete_cached(&target, request.cache, store).await?;
+            let latency_ms = started.elapsed().as_millis() as i64;
+            Ok((target, completion, attempts, cache_hit, latency_ms))
+        })
+        .await?;
+
+    let provider = target.provider;
+    let model = target.model;
+    let new_prompt = NewPrompt {
+        prompt: request.prompt.clone(),
+        response: completion.text,
+        // a cached response costs nothing, its usage is what the original prompt used
+        cost: if cache_hit {
+            Some(0.0)
+        } else {
+            price_table().cost(provider.name(), &model, &completion.usage)
         },
-        Err(e) => Err(Box::from(e)),
+        model,
+        provider: provider.name().to_string(),
+        conversation_id,
+        usage: completion.usage,
+        latency_ms: Some(latency_ms),
+        finish_reason: completion.finish_reason,
+        user_id: user.map(str::to_string),
+        attempts,
+        cache_hit,
+        attempted_targets: route.as_ref().map(|_| attempted),
+        route,
+        requested_model: target.requested_model,
+        params: request.params.clone(),
+        template_id: links.template_id,
+        comparison_id: links.comparison_id,
+        comparison_index: links.comparison_index,
+        tags: normalize_tags(&request.tags),
+    };
+
+    Ok(store.create_prompt_record(&new_prompt).await?)
+}
+
+async fn stream_prompt(
+    request: &CreatePromptRequest,
+    links: PromptLinks,
+    user: Option<&str>,
+    store: &Arc<dyn PromptStore>,
+) -> Result<PromptStream, CoreError> {
+    let (route, targets) = prompt_targets(&request.provider, request.model.as_deref());
+    let conversation_id = links.conversation_id;
+
+    // once a target starts streaming it is the one that answers, later failures aren't retried
+    let ((target, mut chunks, attempts, started), attempted) =
+        first_answer(&targets, |target| async move {
+            let target =
+                prepare_target(target, request, conversation_id, user, store.as_ref()).await?;
+            let started = Instant::now();
+            let (chunks, attempts) = retry_policy()
+                .run(|| open_stream(target.provider.as_ref(), &target.request))
+                .await?;
+            Ok((target, chunks, attempts, started))
+        })
+        .await?;
+
+    // the task outlives this call so it needs its own copies
+    let store = store.clone();
+    let prompt = request.prompt.clone();
+    let params = request.params.clone();
+    let tags = normalize_tags(&request.tags);
+    let user_id = user.map(str::to_string);
+    let provider = target.provider;
+    let model = target.model;
+    let requested_model = target.requested_model;
+    let attempted_targets = route.as_ref().map(|_| attempted);
+
+    // the provider is read to the end in its own task, so a client that stops reading doesn't
+    // lose the prompt's record along with its usage and cost
+    let (events, received) = mpsc::channel(16);
+    tokio::spawn(async move {
+        let saved = async {
+            let mut response_text = String::new();
+            let mut usage = TokenUsage::default();
+            let mut finish_reason: Option<String> = None;
+
+            while let Some(chunk) = chunks.next().await {
+                match chunk? {
+                    StreamChunk::Text(text) => {
+                        response_text.push_str(&text);
+                        // a client that went away just stops getting tokens
+                        let _ = events.send(Ok(PromptStreamEvent::Token(text))).await;
+                    }
+                    StreamChunk::Usage(chunk_usage) => usage.merge(chunk_usage),
+                    StreamChunk::Finish(reason) => finish_reason = Some(reason),
+                }
+            }
+
+            let new_prompt = NewPrompt {
+                prompt,
+                response: response_text,
+                cost: price_table().cost(provider.name(), &model, &usage),
+                model,
+                provider: provider.name().to_string(),
+                conversation_id,
+                usage,
+                latency_ms: Some(started.elapsed().as_millis() as i64),
+                finish_reason,
+                user_id,
+                attempts,
+                cache_hit: false,
+                route,
+                attempted_targets,
+                requested_model,
+                params,
+                template_id: links.template_id,
+                comparison_id: links.comparison_id,
+                comparison_index: links.comparison_index,
+                tags,
+            };
+            Ok::<_, CoreError>(store.create_prompt_record(&new_prompt).await?)
+        }
+        .await;
+        let _ = events
+            .send(saved.map(|record| PromptStreamEvent::Done(Box::new(record))))
+            .await;
+    });
+
+    Ok(Box::pin(stream::unfold(
+        received,
+        |mut received| async move { received.recv().await.map(|event| (event, received)) },
+    )))
+}
+
+// where a prompt is sent: the targets of the route with that name in order,
+// otherwise just the provider and model that were asked for
+fn prompt_targets(provider: &str, model: Option<&str>) -> (Option<String>, Vec<RouteTarget>) {
+    match get_route(provider) {
+        Some(route) => (Some(route.name), route.targets),
+        None => (
+            None,
+            vec![RouteTarget {
+                provider: provider.to_string(),
+                model: model.map(str::to_string),
+            }],
+        ),
+    }
+}
+
+// the answer of the first target that succeeds along with every target that was tried.
+// a target that fails with a retryable error, after its own retries, moves on to the next one,
+// any other error is returned straight away
+async fn first_answer<'a, T, F, Fut>(
+    targets: &'a [RouteTarget],
+    mut answer: F,
+) -> Result<(T, Vec<String>), CoreError>
+where
+    F: FnMut(&'a RouteTarget) -> Fut,
+    Fut: Future<Output = Result<T, CoreError>>,
+{
+    let mut attempted = Vec::new();
+    for (index, target) in targets.iter().enumerate() {
+        attempted.push(target.to_string());
+        match answer(target).await {
+            Ok(value) => return Ok((value, attempted)),
+            Err(e) if e.is_retryable() && index + 1 < targets.len() => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    Err(CoreError::Other("No targets to prompt".to_string()))
+}
+
+// a target that has passed its budget checks, with its model resolved and request built
+struct PreparedTarget {
+    provider: Arc<dyn LlmProvider>,
+    model: String,
+    // set when the target's model was unknown and the default model is used instead
+    requested_model: Option<String>,
+    request: CompletionRequest,
+}
+
+async fn prepare_target(
+    target: &RouteTarget,
+    prompt_request: &CreatePromptRequest,
+    conversation_id: Option<i64>,
+    user: Option<&str>,
+    store: &dyn PromptStore,
+) -> Result<PreparedTarget, CoreError> {
+    let provider = get_provider(&target.provider)?;
+    // refuse before anything is sent so a used up budget can't be overspent
+    check_budgets(store, provider.name(), user).await?;
+    let (model, requested_model) = resolve_model(
+        provider.as_ref(),
+        target.model.as_deref(),
+        prompt_request.model_mode,
+        store,
+    )
+    .await?;
+    let request = build_request(&model, prompt_request, conversation_id, store).await?;
+    Ok(PreparedTarget {
+        provider,
+        model,
+        requested_model,
+        request,
+    })
+}
+
+// answer from the response cache when it holds this exact request, otherwise prompt the provider
+// and cache the answer. returns the requests made to the provider and whether the cache answered
+async fn complete_cached(
+    target: &PreparedTarget,
+    mode: CacheMode,
+    store: &dyn PromptStore,
+) -> Result<(Completion, u32, bool), CoreError> {
+    let cache = response_cache();
+    let complete = || retry_policy().run(|| target.provider.complete(&target.request));
+    if !cache.is_enabled() {
+        let (completion, attempts) = complete().await?;
+        return Ok((completion, attempts, false));
+    }
+
+    let key = ResponseCache::key(target.provider.name(), &target.request);
+    match mode {
+        CacheMode::Use => {
+            if let Some(cached) = cache.get(&key, Some(store)).await? {
+                return Ok((cached.completion(), 0, true));
+            }
+        }
+        CacheMode::Bypass | CacheMode::Refresh => cache.skip(),
     }
+
+    let (completion, attempts) = complete().await?;
+    if mode != CacheMode::Bypass {
+        let cached = CachedResponse::new(&key, target.provider.name(), &completion);
+        cache.put(cached, Some(store)).await?;
+    }
+    Ok((completion, attempts, false))
+}
+
+// open the provider's stream and wait for its first chunk, so errors sent before any text,
+// like Anthropic's overloaded_error event, fail the attempt and can be retried
+async fn open_stream(
+    provider: &dyn LlmProvider,
+    request: &CompletionRequest,
+) -> Result<TokenStream, CoreError> {
+    let mut chunks = provider.stream(request).await?;
+    match chunks.next().await {
+        Some(Err(e)) => Err(e),
+        Some(Ok(first)) => Ok(Box::pin(stream::once(async { Ok(first) }).chain(chunks))),
+        None => Ok(chunks),
+    }
+}
+
+// build the completion request, loading the conversation's earlier turns if there is one
+async fn build_request(
+    model: &str,
+    prompt_request: &CreatePromptRequest,
+    conversation_id: Option<i64>,
+    store: &dyn PromptStore,
+) -> Result<CompletionRequest, CoreError> {
+    let request = CompletionRequest::new(model, &prompt_request.prompt)
+        .with_params(prompt_request.params.clone());
+
+    let Some(conversation_id) = conversation_id else {
+        return Ok(request);
+    };
+
+    if store.get_conversation(conversation_id).await?.is_none() {
+        return Err(CoreError::ConversationNotFound(conversation_id));
+    }
+
+    let prompts = store.get_conversation_prompts(conversation_id).await?;
+    Ok(request.with_history(conversation_history(&prompts)))
+}
+
+// get models for a given provider
+// routes have none of their own, each target picks its model.
+// models come from the model catalog, the store lets cached lists be shared through the database
+pub async fn get_models(
+    provider: &str,
+    store: Option<&dyn PromptStore>,
+) -> Result<Vec<String>, CoreError> {
+    if get_route(provider).is_some() {
+        return Ok(vec![]);
+    }
+    list_models(get_provider(provider)?.as_ref(), store).await
+}
+
+// a provider's models with their display names and creation dates
+pub async fn get_model_info(
+    provider: &str,
+    store: Option<&dyn PromptStore>,
+) -> Result<ModelCatalogEntry, CoreError> {
+    let provider = get_provider(provider)?;
+    model_catalog()
+        .models(provider.as_ref(), retry_policy(), store)
+        .await
+}
+
+// fetch a provider's models now instead of waiting for the cached list to expire
+pub async fn refresh_models(
+    provider: &str,
+    store: Option<&dyn PromptStore>,
+) -> Result<ModelCatalogEntry, CoreError> {
+    let provider = get_provider(provider)?;
+    model_catalog()
+        .refresh(provider.as_ref(), retry_policy(), store)
+        .await
+}
+
+async fn list_models(
+    provider: &dyn LlmProvider,
+    store: Option<&dyn PromptStore>,
+) -> Result<Vec<String>, CoreError> {
+    let entry = model_catalog()
+        .models(provider, retry_policy(), store)
+        .await?;
+    Ok(entry.models.into_iter().map(|model| model.id).collect())
+}
+
+// the model to prompt, checked against the models the provider lists.
+// an unknown model is refused in strict mode, in lenient mode the provider's default is used
+// instead and the model that was asked for is returned alongside it
+async fn resolve_model(
+    provider: &dyn LlmProvider,
+    model: Option<&str>,
+    model_mode: ModelMode,
+    store: &dyn PromptStore,
+) -> Result<(String, Option<String>), CoreError> {
+    let models = list_models(provider, Some(store)).await?;
+
+    // providers without a configured default fall back to the first model they list
+    let mut default_model = provider.default_model();
+    if default_model.is_empty() {
+        default_model = models.first().cloned().ok_or_else(|| CoreError::NoModels {
+            provider: provider.name().to_string(),
+        })?;
+    }
+
+    let Some(model) = model else {
+        return Ok((default_model, None));
+    };
+    if models.iter().any(|m| m == model) {
+        return Ok((model.to_string(), None));
+    }
+
+    match model_mode {
+        ModelMode::Strict => Err(CoreError::InvalidModel {
+            provider: provider.name().to_string(),
+            model: model.to_string(),
+            suggestions: suggest_models(model, &models),
+        }),
+        ModelMode::Lenient => Ok((default_model, Some(model.to_string()))),
+    }
+}
+
+// up to three listed models named most like an unknown model, closest first.
+// names are compared on their first characters too so a short name still matches the dated
+// model ids it is a prefix of, e.g. claude-sonet-4 suggests claude-sonnet-4-20250514
+fn suggest_models(model: &str, models: &[String]) -> Vec<String> {
+    let wanted: Vec<char> = model.to_lowercase().chars().collect();
+    let max_distance = wanted.len().div_ceil(3);
+
+    let mut close: Vec<(usize, usize, &String)> = models
+        .iter()
+        .filter_map(|candidate| {
+            let name: Vec<char> = candidate.to_lowercase().chars().collect();
+            let prefix = &name[..name.len().min(wanted.len())];
+            let prefix_distance = edit_distance(&wanted, prefix);
+            (prefix_distance <= max_distance)
+                .then(|| (prefix_distance, edit_distance(&wanted, &name), candidate))
+        })
+        .collect();
+    close.sort();
+
+    close
+        .into_iter()
+        .take(3)
+        .map(|(_, _, candidate)| candidate.clone())
+        .collect()
+}
+
+// levenshtein distance, the single character edits needed to turn a into b
+fn edit_distance(a: &[char], b: &[char]) -> usize {
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, a_char) in a.iter().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a_char != b_char);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        previous = current;
+    }
+    previous[b.len()]
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::models::Provider;
 
     #[test]
     fn test_prompt_model_invalid_provider() {
         // Since we can't create a real database pool in unit tests,
-        // we test the provider parsing logic separately
-        let result = Provider::from_str("invalid_provider");
+        // we test the provider lookup separately
+        let result = get_provider("invalid_provider");
         assert!(result.is_err());
-        assert!(result.unwrap_err().contains("Unknown provider"));
+        assert!(result
+            .err()
+            .unwrap()
+            .to_string()
+            .contains("Unknown provider"));
     }
 
     #[tokio::test]
     async fn test_get_models_invalid_provider() {
-        let result = get_models("invalid_provider").await;
+        let result = get_models("invalid_provider", None).await;
         assert!(result.is_err());
         assert!(result.unwrap_err().to_string().contains("Unknown provider"));
     }
 
     #[test]
     fn test_provider_string_parsing() {
-        assert!(Provider::from_str("anthropic").is_ok());
-        assert!(Provider::from_str("openai").is_ok());
-        assert!(Provider::from_str("Anthropic").is_ok());
-        assert!(Provider::from_str("OpenAI").is_ok());
-        assert!(Provider::from_str("invalid").is_err());
+        assert!(get_provider("anthropic").is_ok());
+        assert!(get_provider("openai").is_ok());
+        assert!(get_provider("Anthropic").is_ok());
+        assert!(get_provider("OpenAI").is_ok());
+        assert!(get_provider("invalid").is_err());
     }
 
     #[test]
     fn test_error_propagation() {
         // Test that errors are properly wrapped and returned
-        let provider_error = Provider::from_str("invalid");
-        assert!(provider_error.is_err());
+        let provider_error = get_provider("invalid");
+        assert!(matches!(
+            provider_error,
+            Err(CoreError::UnknownProvider(ref name)) if name == "invalid"
+        ));
+    }
 
-        let error_message = provider_error.unwrap_err();
-        assert!(error_message.contains("Unknown provider"));
+    #[test]
+    fn test_conversation_history_alternates_roles() {
+        let prompt = |id: i64, prompt: &str, response: &str| Prompt {
+            id,
+            prompt: prompt.to_string(),
+            response: response.to_string(),
+            model: "gpt-4o".to_string(),
+            provider: "OpenAI".to_string(),
+            conversation_id: Some(1),
+            sequence: Some(id as i32),
+            input_tokens: None,
+            output_tokens: None,
+            reasoning_tokens: None,
+            cached_tokens: None,
+            latency_ms: None,
+            finish_reason: None,
+            cost: None,
+            user_id: None,
+            attempts: 1,
+            cache_hit: false,
+            route: None,
+            attempted_targets: None,
+            requested_model: None,
+            params: Default::default(),
+            template_id: None,
+            comparison_id: None,
+            comparison_index: None,
+            tags: Vec::new(),
+            created_at: chrono::Utc::now(),
+        };
+
+        let history = conversation_history(&[prompt(1, "hi", "hello"), prompt(2, "how?", "fine")]);
+        assert_eq!(
+            history,
+            vec![
+                ChatMessage::user("hi"),
+                ChatMessage::assistant("hello"),
+                ChatMessage::user("how?"),
+                ChatMessage::assistant("fine"),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_first_answer_falls_back() {
+        let target = |provider: &str| RouteTarget {
+            provider: provider.to_string(),
+            model: None,
+        };
+        let targets = vec![target("Anthropic"), target("OpenAI"), target("Gemini")];
+
+        // overloaded providers are skipped
+        let (answer, attempted) = first_answer(&targets, |target| async move {
+            match target.provider.as_str() {
+                "Anthropic" => Err(CoreError::ProviderStatus {
+                    provider: "Anthropic".to_string(),
+                    status: 529,
+                    retry_after: None,
+                    body: "overloaded_error".to_string(),
+                }),
+                provider => Ok(provider.to_string()),
+            }
+        })
+        .await
+        .unwrap();
+        assert_eq!(answer, "OpenAI");
+        assert_eq!(attempted, vec!["Anthropic", "OpenAI"]);
+
+        // but errors another provider wouldn't fix are returned
+        let result = first_answer(&targets, |target| async move {
+            Err::<(), _>(CoreError::missing_credentials(&target.provider, "KEY"))
+        })
+        .await;
+        assert!(
+            matches!(result, Err(CoreError::MissingCredentials { provider, .. }) if provider == "Anthropic")
+        );
+    }
+
+    #[test]
+    fn test_suggest_models() {
+        let models: Vec<String> = [
+            "gpt-4o",
+            "gpt-4o-mini",
+            "o1",
+            "claude-sonnet-4-20250514",
+            "claude-3-5-haiku-20241022",
+        ]
+        .iter()
+        .map(ToString::to_string)
+        .collect();
+
+        assert_eq!(
+            suggest_models("gpt4o", &models),
+            vec!["gpt-4o", "gpt-4o-mini"]
+        );
+        assert_eq!(
+            suggest_models("claude-sonet-4", &models)[0],
+            "claude-sonnet-4-20250514"
+        );
+        assert!(suggest_models("llama3.2", &models).is_empty());
+    }
+
+    #[test]
+    fn test_invalid_model_message() {
+        let error = CoreError::InvalidModel {
+            provider: "OpenAI".to_string(),
+            model: "gpt4o".to_string(),
+            suggestions: vec!["gpt-4o".to_string(), "gpt-4o-mini".to_string()],
+        };
+        assert_eq!(
+            error.to_string(),
+            "Unknown model for OpenAI: gpt4o, did you mean gpt-4o, gpt-4o-mini?"
+        );
     }
 
     #[test]
     fn test_valid_providers() {
-        let anthropic_result = Provider::from_str("anthropic");
+        let anthropic_result = get_provider("anthropic");
         assert!(anthropic_result.is_ok());
-        assert_eq!(anthropic_result.unwrap(), Provider::Anthropic);
+        assert_eq!(anthropic_result.ok().unwrap().name(), "Anthropic");
 
-        let openai_result = Provider::from_str("openai");
+        let openai_result = get_provider("openai");
         assert!(openai_result.is_ok());
-        assert_eq!(openai_result.unwrap(), Provider::OpenAI);
+        assert_eq!(openai_result.ok().unwrap().name(), "OpenAI");
     }
 }
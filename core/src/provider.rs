@@ -0,0 +1,407 @@
+use crate::anthropic::AnthropicProvider;
+use crate::error::CoreError;
+use crate::gemini::GeminiProvider;
+use crate::models::{GenerationParams, ModelInfo, TokenUsage};
+use crate::ollama::OllamaProvider;
+use crate::openai::OpenAIProvider;
+use crate::openai_compatible::OpenAICompatibleProvider;
+use crate::route::Route;
+use async_trait::async_trait;
+use futures::stream::{self, Stream};
+use std::pin::Pin;
+use std::sync::{Arc, OnceLock, RwLock};
+
+// a piece of a streamed completion
+#[derive(Debug, Clone, PartialEq)]
+pub enum StreamChunk {
+    // text as it is generated by the model
+    Text(String),
+    // token counts, may be sent more than once and should be merged
+    Usage(TokenUsage),
+    // why the model stopped generating
+    Finish(String),
+}
+
+impl StreamChunk {
+    pub fn into_text(self) -> Option<String> {
+        match self {
+            StreamChunk::Text(text) => Some(text),
+            _ => None,
+        }
+    }
+}
+
+// stream of chunks as they are generated by the model
+pub type TokenStream = Pin<Box<dyn Stream<Item = Result<StreamChunk, CoreError>> + Send>>;
+
+// a single turn in a conversation, role is "user" or "assistant"
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChatMessage {
+    pub role: String,
+    pub content: String,
+}
+
+impl ChatMessage {
+    pub fn user(content: &str) -> Self {
+        Self {
+            role: "user".to_string(),
+            content: content.to_string(),
+        }
+    }
+
+    pub fn assistant(content: &str) -> Self {
+        Self {
+            role: "assistant".to_string(),
+            content: content.to_string(),
+        }
+    }
+}
+
+// provider agnostic request passed to an LlmProvider
+// messages are in conversation order and end with the prompt being asked
+#[derive(Debug, Clone)]
+pub struct CompletionRequest {
+    pub model: String,
+    pub messages: Vec<ChatMessage>,
+    // system prompt and sampling settings, each provider maps them to its own request
+    pub params: GenerationParams,
+}
+
+impl CompletionRequest {
+    pub fn new(model: &str, prompt: &str) -> Self {
+        Self {
+            model: model.to_string(),
+            messages: vec![ChatMessage::user(prompt)],
+            params: GenerationParams::default(),
+        }
+    }
+
+    pub fn with_params(mut self, params: GenerationParams) -> Self {
+        self.params = params;
+        self
+    }
+
+    // put earlier turns of the conversation in front of the prompt
+    pub fn with_history(mut self, mut history: Vec<ChatMessage>) -> Self {
+        history.append(&mut self.messages);
+        self.messages = history;
+        self
+    }
+
+    // text of the latest user message
+    pub fn prompt(&self) -> &str {
+        self.messages
+            .iter()
+            .rev()
+            .find(|m| m.role == "user")
+            .map(|m| m.content.as_str())
+            .unwrap_or("")
+    }
+}
+
+// provider agnostic completion returned by an LlmProvider
+#[derive(Debug, Clone)]
+pub struct Completion {
+    pub text: String,
+    pub model: String,
+    pub usage: TokenUsage,
+    pub finish_reason: Option<String>,
+}
+
+/// An LLM backend that can list its models and generate completions.
+/// Implementations only talk to the provider, model validation and persistence
+/// are handled by `prompt_model`. Settings a provider isn't given with its `with_*`
+/// functions, such as its api key and base url, are read from CoreConfig when used.
+#[async_trait]
+pub trait LlmProvider: Send + Sync {
+    /// Display name of the provider, also used to look it up in the registry
+    fn name(&self) -> &str;
+
+    /// Model used when the caller doesn't ask for one
+    fn default_model(&self) -> String;
+
+    /// Ids of the models that can be used for completions
+    async fn list_models(&self) -> Result<Vec<String>, CoreError>;
+
+    /// The models along with their display names and creation dates, used for the model catalog.
+    /// Defaults to the ids from `list_models` with nothing else filled in.
+    async fn list_model_info(&self) -> Result<Vec<ModelInfo>, CoreError> {
+        let models = self.list_models().await?;
+        Ok(models.iter().map(|id| ModelInfo::new(id)).collect())
+    }
+
+    /// Generate a full completion for the request
+    async fn complete(&self, request: &CompletionRequest) -> Result<Completion, CoreError>;
+
+    /// Generate a completion as a stream of chunks.
+    /// Defaults to yielding the full completion as a single text chunk.
+    async fn stream(&self, request: &CompletionRequest) -> Result<TokenStream, CoreError> {
+        let completion = self.complete(request).await?;
+        let mut chunks = vec![
+            Ok(StreamChunk::Text(completion.text)),
+            Ok(StreamChunk::Usage(completion.usage)),
+        ];
+        if let Some(finish_reason) = completion.finish_reason {
+            chunks.push(Ok(StreamChunk::Finish(finish_reason)));
+        }
+        Ok(Box::pin(stream::iter(chunks)))
+    }
+}
+
+/// Holds the providers and routes that can be prompted, in registration order
+#[derive(Default)]
+pub struct ProviderRegistry {
+    providers: Vec<Arc<dyn LlmProvider>>,
+    routes: Vec<Route>,
+}
+
+impl ProviderRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registry populated with the providers built into kubellm-core
+    pub fn with_builtin_providers() -> Self {
+        let mut registry = Self::new();
+        registry.register(Arc::new(AnthropicProvider::new()));
+        registry.register(Arc::new(OpenAIProvider::new()));
+        registry.register(Arc::new(GeminiProvider::new()));
+        registry.register(Arc::new(OllamaProvider::new()));
+        registry.register(Arc::new(OpenAICompatibleProvider::new()));
+        registry
+    }
+
+    /// Add a provider, replacing any existing provider with the same name
+    pub fn register(&mut self, provider: Arc<dyn LlmProvider>) {
+        match self.position(provider.name()) {
+            Some(index) => self.providers[index] = provider,
+            None => self.providers.push(provider),
+        }
+    }
+
+    /// Look up a provider by name, ignoring case
+    pub fn get(&self, name: &str) -> Option<Arc<dyn LlmProvider>> {
+        self.position(name)
+            .map(|index| self.providers[index].clone())
+    }
+
+    /// Add a route, replacing any existing route with the same name
+    pub fn register_route(&mut self, route: Route) {
+        match self
+            .routes
+            .iter()
+            .position(|r| r.name.eq_ignore_ascii_case(&route.name))
+        {
+            Some(index) => self.routes[index] = route,
+            None => self.routes.push(route),
+        }
+    }
+
+    /// Look up a route by name, ignoring case
+    pub fn route(&self, name: &str) -> Option<Route> {
+        self.routes
+            .iter()
+            .find(|r| r.name.eq_ignore_ascii_case(name))
+            .cloned()
+    }
+
+    /// Names of the providers followed by the routes, both can be prompted
+    pub fn names(&self) -> Vec<String> {
+        self.providers
+            .iter()
+            .map(|p| p.name().to_string())
+            .chain(self.routes.iter().map(|r| r.name.clone()))
+            .collect()
+    }
+
+    fn position(&self, name: &str) -> Option<usize> {
+        self.providers
+            .iter()
+            .position(|p| p.name().eq_ignore_ascii_case(name))
+    }
+}
+
+static REGISTRY: OnceLock<RwLock<ProviderRegistry>> = OnceLock::new();
+
+// process wide registry, starts out with the built in providers
+fn registry() -> &'static RwLock<ProviderRegistry> {
+    REGISTRY.get_or_init(|| RwLock::new(ProviderRegistry::with_builtin_providers()))
+}
+
+/// Make a provider available to `prompt_model`, `get_models` and the provider listings
+pub fn register_provider(provider: Arc<dyn LlmProvider>) {
+    registry().write().unwrap().register(provider);
+}
+
+/// Find a registered provider by name
+pub fn get_provider(name: &str) -> Result<Arc<dyn LlmProvider>, CoreError> {
+    registry()
+        .read()
+        .unwrap()
+        .get(name)
+        .ok_or_else(|| CoreError::UnknownProvider(name.to_string()))
+}
+
+/// Make a route available to `prompt_model` under its name, see `load_routes`
+pub fn register_route(route: Route) {
+    registry().write().unwrap().register_route(route);
+}
+
+/// Find a registered route by name
+pub fn get_route(name: &str) -> Option<Route> {
+    registry().read().unwrap().route(name)
+}
+
+/// Names of all registered providers and routes
+pub fn provider_names() -> Vec<String> {
+    registry().read().unwrap().names()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::route::RouteTarget;
+    use futures::StreamExt;
+
+    struct EchoProvider {
+        name: &'static str,
+    }
+
+    #[async_trait]
+    impl LlmProvider for EchoProvider {
+        fn name(&self) -> &str {
+            self.name
+        }
+
+        fn default_model(&self) -> String {
+            "echo-1".to_string()
+        }
+
+        async fn list_models(&self) -> Result<Vec<String>, CoreError> {
+            Ok(vec!["echo-1".to_string()])
+        }
+
+        async fn complete(&self, request: &CompletionRequest) -> Result<Completion, CoreError> {
+            Ok(Completion {
+                text: request.prompt().to_string(),
+                model: request.model.clone(),
+                usage: TokenUsage {
+                    input_tokens: Some(3),
+                    output_tokens: Some(2),
+                    ..Default::default()
+                },
+                finish_reason: Some("stop".to_string()),
+            })
+        }
+    }
+
+    #[test]
+    fn test_completion_request_with_history() {
+        let request = CompletionRequest::new("echo-1", "and then?").with_history(vec![
+            ChatMessage::user("first question"),
+            ChatMessage::assistant("first answer"),
+        ]);
+
+        let roles: Vec<&str> = request.messages.iter().map(|m| m.role.as_str()).collect();
+        assert_eq!(roles, vec!["user", "assistant", "user"]);
+        assert_eq!(request.prompt(), "and then?");
+    }
+
+    #[test]
+    fn test_builtin_providers_registered() {
+        let registry = ProviderRegistry::with_builtin_providers();
+        assert_eq!(
+            registry.names(),
+            vec![
+                "Anthropic".to_string(),
+                "OpenAI".to_string(),
+                "Gemini".to_string(),
+                "Ollama".to_string(),
+                "OpenAI-Compatible".to_string()
+            ]
+        );
+    }
+
+    #[test]
+    fn test_get_ignores_case() {
+        let registry = ProviderRegistry::with_builtin_providers();
+        assert_eq!(registry.get("anthropic").unwrap().name(), "Anthropic");
+        assert_eq!(registry.get("OPENAI").unwrap().name(), "OpenAI");
+        assert_eq!(registry.get("gemini").unwrap().name(), "Gemini");
+        assert_eq!(registry.get("ollama").unwrap().name(), "Ollama");
+        assert_eq!(
+            registry.get("openai-compatible").unwrap().name(),
+            "OpenAI-Compatible"
+        );
+        assert!(registry.get("invalid").is_none());
+    }
+
+    #[test]
+    fn test_register_replaces_same_name() {
+        let mut registry = ProviderRegistry::new();
+        registry.register(Arc::new(EchoProvider { name: "Echo" }));
+        registry.register(Arc::new(EchoProvider { name: "echo" }));
+        assert_eq!(registry.names(), vec!["echo".to_string()]);
+    }
+
+    #[test]
+    fn test_register_route() {
+        let mut registry = ProviderRegistry::new();
+        registry.register(Arc::new(EchoProvider { name: "Echo" }));
+        registry.register_route(Route {
+            name: "primary".to_string(),
+            targets: vec![RouteTarget {
+                provider: "Echo".to_string(),
+                model: None,
+            }],
+        });
+
+        assert_eq!(registry.names(), vec!["Echo", "primary"]);
+        assert_eq!(registry.route("PRIMARY").unwrap().targets.len(), 1);
+        // routes aren't providers
+        assert!(registry.get("primary").is_none());
+    }
+
+    #[test]
+    fn test_register_provider_globally() {
+        register_provider(Arc::new(EchoProvider { name: "GlobalEcho" }));
+        assert!(provider_names().contains(&"GlobalEcho".to_string()));
+        assert_eq!(get_provider("globalecho").unwrap().name(), "GlobalEcho");
+    }
+
+    #[test]
+    fn test_get_provider_unknown() {
+        let result = get_provider("invalid_provider");
+        assert!(result.is_err());
+        assert!(result
+            .err()
+            .unwrap()
+            .to_string()
+            .contains("Unknown provider"));
+    }
+
+    #[tokio::test]
+    async fn test_default_stream_yields_completion() {
+        let provider = EchoProvider { name: "Echo" };
+        let request = CompletionRequest::new("echo-1", "hello there");
+        let chunks: Vec<StreamChunk> = provider
+            .stream(&request)
+            .await
+            .unwrap()
+            .map(|chunk| chunk.unwrap())
+            .collect()
+            .await;
+        assert_eq!(
+            chunks,
+            vec![
+                StreamChunk::Text("hello there".to_string()),
+                StreamChunk::Usage(TokenUsage {
+                    input_tokens: Some(3),
+                    output_tokens: Some(2),
+                    ..Default::default()
+                }),
+                StreamChunk::Finish("stop".to_string()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,214 @@
+use crate::config::CoreConfig;
+use crate::error::CoreError;
+use rand::Rng;
+use std::future::Future;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+/// How provider calls that fail with a retryable error are tried again.
+/// Waits double after every failed attempt, with jitter so clients that failed
+/// together don't retry together, unless the provider sent a Retry-After.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetryPolicy {
+    // attempts in total, 1 never retries
+    pub max_attempts: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+    pub jitter: bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(30),
+            jitter: true,
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn from_config(config: &CoreConfig) -> Self {
+        Self {
+            max_attempts: config.retry_max_attempts.max(1),
+            base_delay: Duration::from_millis(config.retry_base_delay_ms),
+            max_delay: Duration::from_millis(config.retry_max_delay_ms),
+            jitter: true,
+        }
+    }
+
+    /// Wait before the next attempt after `attempt` attempts have failed with `error`,
+    /// None when the error shouldn't be retried or the attempts are used up
+    pub fn delay(&self, attempt: u32, error: &CoreError) -> Option<Duration> {
+        if !error.is_retryable() {
+            return None;
+        }
+        self.backoff(attempt, error)
+    }
+
+    /// Wait before the next attempt after `attempt` attempts have failed, for callers that
+    /// decide themselves which errors are retried, None when the attempts are used up
+    pub fn backoff(&self, attempt: u32, error: &CoreError) -> Option<Duration> {
+        if attempt >= self.max_attempts {
+            return None;
+        }
+
+        // retrying sooner than the provider asked would only be refused again
+        if let Some(retry_after) = error.retry_after() {
+            return (retry_after <= self.max_delay).then_some(retry_after);
+        }
+
+        let backoff = self
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(attempt - 1))
+            .min(self.max_delay);
+        if !self.jitter {
+            return Some(backoff);
+        }
+
+        // somewhere between half and all of the backoff
+        let half = backoff / 2;
+        Some(half + rand::thread_rng().gen_range(Duration::ZERO..=half))
+    }
+
+    /// Run the operation until it succeeds or fails for good,
+    /// returns its result along with the number of attempts it took
+    pub async fn run<T, F, Fut>(&self, mut operation: F) -> Result<(T, u32), CoreError>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, CoreError>>,
+    {
+        let mut attempt = 1;
+        loop {
+            match operation().await {
+                Ok(value) => return Ok((value, attempt)),
+                Err(e) => match self.delay(attempt, &e) {
+                    Some(delay) => {
+                        tokio::time::sleep(delay).await;
+                        attempt += 1;
+                    }
+                    None => return Err(e),
+                },
+            }
+        }
+    }
+}
+
+static RETRY_POLICY: OnceLock<RetryPolicy> = OnceLock::new();
+
+/// Retry policy used for every provider call, from the RETRY_* settings
+pub fn retry_policy() -> &'static RetryPolicy {
+    RETRY_POLICY.get_or_init(|| RetryPolicy::from_config(CoreConfig::get()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    fn policy() -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(1),
+            max_delay: Duration::from_millis(10),
+            jitter: false,
+        }
+    }
+
+    fn overloaded() -> CoreError {
+        CoreError::ProviderStatus {
+            provider: "Anthropic".to_string(),
+            status: 529,
+            retry_after: None,
+            body: r#"{"type":"overloaded_error"}"#.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_delay_backoff() {
+        let policy = RetryPolicy {
+            max_attempts: 10,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(3),
+            jitter: false,
+        };
+        let error = overloaded();
+
+        assert_eq!(policy.delay(1, &error), Some(Duration::from_millis(500)));
+        assert_eq!(policy.delay(2, &error), Some(Duration::from_millis(1000)));
+        assert_eq!(policy.delay(3, &error), Some(Duration::from_millis(2000)));
+        assert_eq!(policy.delay(4, &error), Some(Duration::from_secs(3)));
+        assert_eq!(policy.delay(10, &error), None);
+
+        let jittered = RetryPolicy {
+            jitter: true,
+            ..policy
+        }
+        .delay(2, &error)
+        .unwrap();
+        assert!(jittered >= Duration::from_millis(500) && jittered <= Duration::from_millis(1000));
+    }
+
+    #[test]
+    fn test_delay_retry_after() {
+        let policy = RetryPolicy::default();
+        let rate_limited = |seconds: u64| CoreError::RateLimited {
+            provider: "OpenAI".to_string(),
+            retry_after: Some(Duration::from_secs(seconds)),
+            body: String::new(),
+        };
+
+        assert_eq!(
+            policy.delay(1, &rate_limited(7)),
+            Some(Duration::from_secs(7))
+        );
+        // asked to wait longer than we're willing to
+        assert_eq!(policy.delay(1, &rate_limited(120)), None);
+        assert_eq!(
+            policy.delay(1, &CoreError::UnknownProvider("x".to_string())),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn test_run_retries_until_success() {
+        let calls = AtomicU32::new(0);
+        let (value, attempts) = policy()
+            .run(|| async {
+                match calls.fetch_add(1, Ordering::SeqCst) {
+                    0 => Err(overloaded()),
+                    _ => Ok("done"),
+                }
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(value, "done");
+        assert_eq!(attempts, 2);
+    }
+
+    #[tokio::test]
+    async fn test_run_gives_up() {
+        let calls = AtomicU32::new(0);
+        let result: Result<((), u32), CoreError> = policy()
+            .run(|| async {
+                calls.fetch_add(1, Ordering::SeqCst);
+                Err(overloaded())
+            })
+            .await;
+        assert!(result.is_err());
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+
+        // errors that can't succeed on a retry are returned straight away
+        let calls = AtomicU32::new(0);
+        let result: Result<((), u32), CoreError> = policy()
+            .run(|| async {
+                calls.fetch_add(1, Ordering::SeqCst);
+                Err(CoreError::missing_credentials("OpenAI", "OPENAI_KEY"))
+            })
+            .await;
+        assert!(result.is_err());
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+}
@@ -0,0 +1,132 @@
+use crate::config::CoreConfig;
+use crate::provider::{get_provider, register_route};
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// A provider and model a route can send a prompt to
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RouteTarget {
+    pub provider: String,
+    // the provider's default model is used when unset
+    #[serde(default)]
+    pub model: Option<String>,
+}
+
+impl fmt::Display for RouteTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.model {
+            Some(model) => write!(f, "{}/{}", self.provider, model),
+            None => write!(f, "{}", self.provider),
+        }
+    }
+}
+
+/// A named chain of targets that is prompted like a provider.
+/// Targets are tried in order, moving on to the next when one fails with a retryable error.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Route {
+    pub name: String,
+    pub targets: Vec<RouteTarget>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RouteConfig {
+    pub routes: Vec<Route>,
+}
+
+impl RouteConfig {
+    pub fn from_json(json: &str) -> Result<Self> {
+        serde_json::from_str(json).context("Invalid routes file")
+    }
+
+    pub fn from_file(path: &str) -> Result<Self> {
+        let json = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read routes file {}", path))?;
+        Self::from_json(&json)
+    }
+
+    // a route needs at least one target, every target must be a registered provider
+    // and route names can't hide a provider
+    fn validate(&self) -> Result<()> {
+        for route in &self.routes {
+            if get_provider(&route.name).is_ok() {
+                bail!("Route {} has the same name as a provider", route.name);
+            }
+            if route.targets.is_empty() {
+                bail!("Route {} has no targets", route.name);
+            }
+            for target in &route.targets {
+                if get_provider(&target.provider).is_err() {
+                    bail!(
+                        "Route {} uses an unknown provider: {}",
+                        route.name,
+                        target.provider
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Register the routes in ROUTES_FILE so they can be prompted by name, returns how many there are
+pub fn load_routes() -> Result<usize> {
+    let Some(path) = &CoreConfig::get().routes_file else {
+        return Ok(0);
+    };
+
+    let config = RouteConfig::from_file(path)?;
+    config.validate()?;
+
+    let count = config.routes.len();
+    config.routes.into_iter().for_each(register_route);
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_routes() {
+        let config = RouteConfig::from_json(
+            r#"{"routes": [
+                {"name": "primary", "targets": [
+                    {"provider": "Anthropic", "model": "claude-sonnet-4-20250514"},
+                    {"provider": "OpenAI", "model": "gpt-4o"},
+                    {"provider": "Ollama"}
+                ]}
+            ]}"#,
+        )
+        .unwrap();
+
+        let targets: Vec<String> = config.routes[0]
+            .targets
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            targets,
+            vec![
+                "Anthropic/claude-sonnet-4-20250514",
+                "OpenAI/gpt-4o",
+                "Ollama"
+            ]
+        );
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_invalid_routes() {
+        let invalid = |json: &str| RouteConfig::from_json(json).unwrap().validate().is_err();
+
+        assert!(invalid(r#"{"routes": [{"name": "empty", "targets": []}]}"#));
+        assert!(invalid(
+            r#"{"routes": [{"name": "openai", "targets": [{"provider": "Anthropic"}]}]}"#
+        ));
+        assert!(invalid(
+            r#"{"routes": [{"name": "primary", "targets": [{"provider": "Mistral"}]}]}"#
+        ));
+    }
+}
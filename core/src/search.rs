@@ -0,0 +1,214 @@
+use crate::models::Prompt;
+use serde::{Deserialize, Serialize};
+
+// results returned by GET /prompts/search when no limit is given, and the most it returns
+pub const DEFAULT_SEARCH_LIMIT: u32 = 20;
+pub const MAX_SEARCH_LIMIT: u32 = 100;
+
+// characters of prompt or response text shown around the first match
+const SNIPPET_LENGTH: usize = 160;
+
+// query params of GET /prompts/search
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SearchQuery {
+    pub q: String,
+    pub limit: Option<u32>,
+}
+
+impl SearchQuery {
+    pub fn search(&self) -> Result<PromptSearch, String> {
+        let terms = search_terms(&self.q);
+        if terms.is_empty() {
+            return Err("Search query cannot be empty".to_string());
+        }
+        let limit = self.limit.unwrap_or(DEFAULT_SEARCH_LIMIT);
+        if !(1..=MAX_SEARCH_LIMIT).contains(&limit) {
+            return Err(format!("limit must be between 1 and {}", MAX_SEARCH_LIMIT));
+        }
+
+        Ok(PromptSearch {
+            text: self.q.trim().to_string(),
+            terms,
+            limit,
+        })
+    }
+}
+
+/// A full text search of prompts and responses
+#[derive(Debug, Clone, PartialEq)]
+pub struct PromptSearch {
+    // matched against the fulltext index, MySQL ranks the results
+    pub text: String,
+    // the lowercased words of the text, highlighted in snippets
+    pub terms: Vec<String>,
+    pub limit: u32,
+}
+
+/// A prompt matching a search with its relevance and the matched text.
+/// Snippets mark every match with `**` and are None when the text has no match.
+#[derive(Debug, Serialize)]
+pub struct SearchHit {
+    pub prompt: Prompt,
+    pub score: f64,
+    pub prompt_snippet: Option<String>,
+    pub response_snippet: Option<String>,
+}
+
+impl SearchHit {
+    pub fn new(prompt: Prompt, score: f64, terms: &[String]) -> Self {
+        Self {
+            prompt_snippet: snippet(&prompt.prompt, terms),
+            response_snippet: snippet(&prompt.response, terms),
+            prompt,
+            score,
+        }
+    }
+}
+
+/// The lowercased words of a search, without surrounding punctuation or repeats
+pub fn search_terms(text: &str) -> Vec<String> {
+    let mut terms: Vec<String> = Vec::new();
+    for word in text.split_whitespace() {
+        let term = word
+            .trim_matches(|c: char| !c.is_alphanumeric())
+            .to_lowercase();
+        if !term.is_empty() && !terms.contains(&term) {
+            terms.push(term);
+        }
+    }
+    terms
+}
+
+/// The text around the first word starting with one of the terms, with every such word
+/// start wrapped in `**`. Whitespace is collapsed and markdown emphasis and code fences are
+/// dropped so the highlights are the only markup left.
+pub fn snippet(text: &str, terms: &[String]) -> Option<String> {
+    let cleaned = text.replace("**", "").replace("```", "");
+    let chars: Vec<char> = cleaned
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .chars()
+        .collect();
+    // lowercased a char at a time so positions line up with chars
+    let lower: Vec<char> = chars.iter().map(|c| lowercase(*c)).collect();
+    let terms: Vec<Vec<char>> = terms
+        .iter()
+        .map(|term| term.chars().map(lowercase).collect())
+        .filter(|term: &Vec<char>| !term.is_empty())
+        .collect();
+
+    let mut matches: Vec<(usize, usize)> = Vec::new();
+    let mut i = 0;
+    while i < lower.len() {
+        let word_start = i == 0 || !lower[i - 1].is_alphanumeric();
+        let term = terms
+            .iter()
+            .filter(|term| word_start && lower[i..].starts_with(term))
+            .max_by_key(|term| term.len());
+        match term {
+            Some(term) => {
+                matches.push((i, i + term.len()));
+                i += term.len();
+            }
+            None => i += 1,
+        }
+    }
+    let (first_start, _) = *matches.first()?;
+
+    // start a third of the snippet before the first match, at the beginning of a word
+    let mut start = first_start.saturating_sub(SNIPPET_LENGTH / 3);
+    while start > 0 && start < first_start && chars[start - 1] != ' ' {
+        start += 1;
+    }
+    let end = (start + SNIPPET_LENGTH).min(chars.len());
+
+    let mut snippet = String::with_capacity(SNIPPET_LENGTH + 16);
+    if start > 0 {
+        snippet.push('…');
+    }
+    let mut position = start;
+    for &(match_start, match_end) in matches
+        .iter()
+        .filter(|(match_start, _)| *match_start >= start && *match_start < end)
+    {
+        let match_end = match_end.min(end);
+        snippet.extend(&chars[position..match_start]);
+        snippet.push_str("**");
+        snippet.extend(&chars[match_start..match_end]);
+        snippet.push_str("**");
+        position = match_end;
+    }
+    snippet.extend(&chars[position..end]);
+    if end < chars.len() {
+        snippet.push('…');
+    }
+    Some(snippet)
+}
+
+fn lowercase(c: char) -> char {
+    c.to_lowercase().next().unwrap_or(c)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snippet_of(text: &str, query: &str) -> Option<String> {
+        snippet(text, &search_terms(query))
+    }
+
+    #[test]
+    fn test_search_query() {
+        let search = SearchQuery {
+            q: "  Kafka, consumer groups kafka ".to_string(),
+            limit: None,
+        }
+        .search()
+        .unwrap();
+        assert_eq!(search.text, "Kafka, consumer groups kafka");
+        assert_eq!(search.terms, vec!["kafka", "consumer", "groups"]);
+        assert_eq!(search.limit, DEFAULT_SEARCH_LIMIT);
+
+        let search = |q: &str, limit| {
+            SearchQuery {
+                q: q.to_string(),
+                limit,
+            }
+            .search()
+        };
+        assert!(search(" ?! ", None).is_err());
+        assert!(search("kafka", Some(0)).is_err());
+        assert!(search("kafka", Some(MAX_SEARCH_LIMIT + 1)).is_err());
+    }
+
+    #[test]
+    fn test_snippet_highlights_word_starts() {
+        let snippet = snippet_of(
+            "Use **Kafka** consumer   groups.\nEach kafka partition goes to one consumer.",
+            "kafka consumer",
+        );
+        assert_eq!(
+            snippet.as_deref(),
+            Some("Use **Kafka** **consumer** groups. Each **kafka** partition goes to one **consumer**.")
+        );
+
+        // only the start of a word matches
+        assert_eq!(snippet_of("Confluent", "fluent"), None);
+        assert_eq!(
+            snippet_of("Stream processing", "process").as_deref(),
+            Some("Stream **process**ing")
+        );
+    }
+
+    #[test]
+    fn test_snippet_window() {
+        let text = format!("{} kafka {}", "before ".repeat(40), "after ".repeat(40));
+        let snippet = snippet_of(&text, "kafka").unwrap();
+
+        assert!(snippet.starts_with("…before"));
+        assert!(snippet.ends_with('…'));
+        assert!(snippet.contains("**kafka**"));
+        assert!(snippet.chars().count() <= SNIPPET_LENGTH + 6);
+    }
+}
@@ -0,0 +1,208 @@
+use crate::error::CoreError;
+use crate::provider::{StreamChunk, TokenStream};
+use async_stream::try_stream;
+use futures::{Stream, StreamExt};
+
+// a single server-sent event, data lines are joined with newlines
+#[derive(Debug, Clone, PartialEq)]
+pub struct SseEvent {
+    pub event: Option<String>,
+    pub data: String,
+}
+
+/// Line based parser for the text/event-stream format
+#[derive(Debug, Default)]
+pub struct SseParser {
+    event: Option<String>,
+    data: Vec<String>,
+}
+
+impl SseParser {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Feed a single line (without the line ending), returns an event when a blank line completes one
+    pub fn push_line(&mut self, line: &str) -> Option<SseEvent> {
+        let line = line.strip_suffix('\r').unwrap_or(line);
+
+        if line.is_empty() {
+            return self.dispatch();
+        }
+
+        // lines starting with a colon are comments, usually keep alive pings
+        if line.starts_with(':') {
+            return None;
+        }
+
+        let (field, value) = match line.split_once(':') {
+            Some((field, value)) => (field, value.strip_prefix(' ').unwrap_or(value)),
+            None => (line, ""),
+        };
+
+        match field {
+            "event" => self.event = Some(value.to_string()),
+            "data" => self.data.push(value.to_string()),
+            // id and retry aren't used by any provider
+            _ => {}
+        }
+
+        None
+    }
+
+    /// Returns the pending event if the stream ended without a trailing blank line
+    pub fn finish(&mut self) -> Option<SseEvent> {
+        self.dispatch()
+    }
+
+    fn dispatch(&mut self) -> Option<SseEvent> {
+        let event = self.event.take();
+        if self.data.is_empty() {
+            return None;
+        }
+
+        Some(SseEvent {
+            event,
+            data: std::mem::take(&mut self.data).join("\n"),
+        })
+    }
+}
+
+/// Split a streaming http response into lines, without their line endings
+pub fn response_lines(
+    response: reqwest::Response,
+) -> impl Stream<Item = Result<String, CoreError>> + Send {
+    try_stream! {
+        let mut bytes = response.bytes_stream();
+        // raw bytes are buffered so multi-byte characters split across chunks stay intact
+        let mut buffer: Vec<u8> = Vec::new();
+
+        while let Some(chunk) = bytes.next().await {
+            buffer.extend_from_slice(&chunk?);
+
+            while let Some(newline) = buffer.iter().position(|b| *b == b'\n') {
+                let line: Vec<u8> = buffer.drain(..=newline).collect();
+                yield String::from_utf8_lossy(&line[..line.len() - 1]).to_string();
+            }
+        }
+
+        if !buffer.is_empty() {
+            yield String::from_utf8_lossy(&buffer).to_string();
+        }
+    }
+}
+
+/// Turn a streaming http response into a stream of server-sent events
+pub fn sse_events(
+    response: reqwest::Response,
+) -> impl Stream<Item = Result<SseEvent, CoreError>> + Send {
+    try_stream! {
+        let mut lines = Box::pin(response_lines(response));
+        let mut parser = SseParser::new();
+
+        while let Some(line) = lines.next().await {
+            if let Some(event) = parser.push_line(&line?) {
+                yield event;
+            }
+        }
+
+        if let Some(event) = parser.finish() {
+            yield event;
+        }
+    }
+}
+
+/// Stream of completion chunks from a server-sent events response,
+/// `parse` returns the chunks carried by each event
+pub fn sse_chunks<F>(response: reqwest::Response, parse: F) -> TokenStream
+where
+    F: Fn(&SseEvent) -> Result<Vec<StreamChunk>, CoreError> + Send + 'static,
+{
+    Box::pin(try_stream! {
+        let mut events = Box::pin(sse_events(response));
+        while let Some(event) = events.next().await {
+            for chunk in parse(&event?)? {
+                yield chunk;
+            }
+        }
+    })
+}
+
+/// Stream of completion chunks from a newline delimited response,
+/// `parse` returns the chunks carried by each line
+pub fn line_chunks<F>(response: reqwest::Response, parse: F) -> TokenStream
+where
+    F: Fn(&str) -> Result<Vec<StreamChunk>, CoreError> + Send + 'static,
+{
+    Box::pin(try_stream! {
+        let mut lines = Box::pin(response_lines(response));
+        while let Some(line) = lines.next().await {
+            for chunk in parse(&line?)? {
+                yield chunk;
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(input: &str) -> Vec<SseEvent> {
+        let mut parser = SseParser::new();
+        let mut events: Vec<SseEvent> = input
+            .split('\n')
+            .filter_map(|l| parser.push_line(l))
+            .collect();
+        events.extend(parser.finish());
+        events
+    }
+
+    #[test]
+    fn test_parse_named_event() {
+        let events = parse("event: content_block_delta\ndata: {\"text\":\"hi\"}\n\n");
+        assert_eq!(
+            events,
+            vec![SseEvent {
+                event: Some("content_block_delta".to_string()),
+                data: "{\"text\":\"hi\"}".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_parse_data_only_events() {
+        let events = parse("data: one\n\ndata: two\n\n");
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].event, None);
+        assert_eq!(events[0].data, "one");
+        assert_eq!(events[1].data, "two");
+    }
+
+    #[test]
+    fn test_parse_multi_line_data() {
+        let events = parse("data: first\ndata: second\n\n");
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].data, "first\nsecond");
+    }
+
+    #[test]
+    fn test_parse_ignores_comments_and_crlf() {
+        let events = parse(": ping\r\ndata: value\r\n\r\n");
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].data, "value");
+    }
+
+    #[test]
+    fn test_parse_event_without_trailing_blank_line() {
+        let events = parse("data: [DONE]");
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].data, "[DONE]");
+    }
+
+    #[test]
+    fn test_blank_lines_without_data_are_ignored() {
+        let events = parse("\n\nevent: ping\n\n");
+        assert!(events.is_empty());
+    }
+}
@@ -0,0 +1,855 @@
+// load the cached response struct from the cache module
+use crate::cache::CachedResponse;
+// load the config struct and backend enum from the config module
+use crate::config::{CoreConfig, DatabaseBackend};
+// load the schema migration results from the migrations module
+use crate::migrations::{MigrationError, MigrationReport};
+// load these structs from the models module
+use crate::models::{
+    Conversation, ModelCatalogEntry, NewPrompt, Prompt, PromptCursor, PromptFilter, PromptPage,
+    UsageGroupBy, UsageSummary,
+};
+// load the search structs from the search module
+use crate::search::{PromptSearch, SearchHit};
+// load the task structs from the task module
+use crate::task::{NewTask, Task, TaskStatus};
+// load the template structs from the template module
+use crate::template::{NewPromptTemplate, PromptTemplate};
+// load error handling and result types
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+// date and time handling
+use chrono::{DateTime, NaiveDateTime, Timelike, Utc};
+use std::sync::Arc;
+use std::time::Duration;
+
+// builds a Prompt from a row of any backend, selected with PROMPT_COLUMNS.
+// the backends store max_tokens as different integer types so it is decoded by the caller
+macro_rules! prompt_from_row {
+    ($row:expr, $max_tokens:expr) => {{
+        let row = $row;
+        let created_at: chrono::NaiveDateTime = row.get("created_at");
+        crate::models::Prompt {
+            id: row.get("id"),
+            prompt: row.get("prompt"),
+            response: row.get("response"),
+            model: row.get("model"),
+            provider: row.get("provider"),
+            conversation_id: row.get("conversation_id"),
+            sequence: row.get("sequence"),
+            input_tokens: row.get("input_tokens"),
+            output_tokens: row.get("output_tokens"),
+            reasoning_tokens: row.get("reasoning_tokens"),
+            cached_tokens: row.get("cached_tokens"),
+            latency_ms: row.get("latency_ms"),
+            finish_reason: row.get("finish_reason"),
+            cost: row.get("cost"),
+            user_id: row.get("user_id"),
+            attempts: row.get("attempts"),
+            cache_hit: row.get("cache_hit"),
+            route: row.get("route"),
+            attempted_targets: crate::store::from_json_array(row.get("attempted_targets")),
+            requested_model: row.get("requested_model"),
+            params: crate::models::GenerationParams {
+                system: row.get("system_prompt"),
+                temperature: row.get("temperature"),
+                top_p: row.get("top_p"),
+                max_tokens: $max_tokens,
+                stop: crate::store::from_json_array(row.get("stop_sequences")),
+                seed: row.get("seed"),
+            },
+            template_id: row.get("template_id"),
+            comparison_id: row.get("comparison_id"),
+            comparison_index: row.get("comparison_index"),
+            tags: crate::store::from_json_array(row.get("tags")).unwrap_or_default(),
+            created_at: created_at.and_utc(),
+        }
+    }};
+}
+
+// builds a CachedResponse from a row of any backend, selected with CACHED_RESPONSE_COLUMNS
+macro_rules! cached_response_from_row {
+    ($row:expr) => {{
+        let row = $row;
+        let created_at: chrono::NaiveDateTime = row.get("created_at");
+        crate::cache::CachedResponse {
+            key: row.get("cache_key"),
+            provider: row.get("provider"),
+            model: row.get("model"),
+            response: row.get("response"),
+            usage: crate::models::TokenUsage {
+                input_tokens: row.get::<Option<i32>, _>("input_tokens").map(|n| n as u32),
+                output_tokens: row.get::<Option<i32>, _>("output_tokens").map(|n| n as u32),
+                reasoning_tokens: row
+                    .get::<Option<i32>, _>("reasoning_tokens")
+                    .map(|n| n as u32),
+                cached_tokens: row.get::<Option<i32>, _>("cached_tokens").map(|n| n as u32),
+            },
+            finish_reason: row.get("finish_reason"),
+            created_at: created_at.and_utc(),
+        }
+    }};
+}
+
+// builds a PromptTemplate from a row of any backend, selected with TEMPLATE_COLUMNS
+macro_rules! template_from_row {
+    ($row:expr) => {{
+        let row = $row;
+        let created_at: chrono::NaiveDateTime = row.get("created_at");
+        crate::template::PromptTemplate {
+            id: row.get("id"),
+            name: row.get("name"),
+            version: row.get("version"),
+            template: row.get("template"),
+            variables: serde_json::from_str(row.get("variables")).unwrap_or_default(),
+            provider: row.get("provider"),
+            model: row.get("model"),
+            created_at: created_at.and_utc(),
+        }
+    }};
+}
+
+// builds a Conversation from a row of any backend, selected with CONVERSATION_COLUMNS
+macro_rules! conversation_from_row {
+    ($row:expr) => {{
+        let row = $row;
+        let created_at: chrono::NaiveDateTime = row.get("created_at");
+        let updated_at: chrono::NaiveDateTime = row.get("updated_at");
+        crate::models::Conversation {
+            id: row.get("id"),
+            title: row.get("title"),
+            created_at: created_at.and_utc(),
+            updated_at: updated_at.and_utc(),
+        }
+    }};
+}
+
+// builds a Task from a row of any backend, selected with TASK_COLUMNS
+macro_rules! task_from_row {
+    ($row:expr) => {{
+        let row = $row;
+        let run_at: chrono::NaiveDateTime = row.get("run_at");
+        let created_at: chrono::NaiveDateTime = row.get("created_at");
+        let updated_at: chrono::NaiveDateTime = row.get("updated_at");
+        crate::task::Task {
+            id: row.get("id"),
+            task_type: row.get("task_type"),
+            payload: serde_json::from_str(row.get("payload")).unwrap_or_default(),
+            status: row.get::<String, _>("status").parse().unwrap_or_default(),
+            attempts: row.get("attempts"),
+            max_attempts: row.get("max_attempts"),
+            run_at: run_at.and_utc(),
+            worker_id: row.get("worker_id"),
+            lease_expires_at: row
+                .get::<Option<chrono::NaiveDateTime>, _>("lease_expires_at")
+                .map(|lease_expires_at| lease_expires_at.and_utc()),
+            result: row
+                .get::<Option<String>, _>("result")
+                .and_then(|result| serde_json::from_str(&result).ok()),
+            error: row.get("error"),
+            created_at: created_at.and_utc(),
+            updated_at: updated_at.and_utc(),
+        }
+    }};
+}
+
+mod mysql;
+mod postgres;
+mod sqlite;
+
+pub use mysql::MySqlStore;
+pub use postgres::PostgresStore;
+pub use sqlite::SqliteStore;
+
+// columns selected whenever a prompt is loaded, must match prompt_from_row
+pub(crate) const PROMPT_COLUMNS: &str = "id, prompt, response, model, provider, conversation_id, sequence, \
+    input_tokens, output_tokens, reasoning_tokens, cached_tokens, latency_ms, finish_reason, cost, user_id, attempts, cache_hit, route, attempted_targets, requested_model, \
+    system_prompt, temperature, top_p, max_tokens, stop_sequences, seed, template_id, comparison_id, comparison_index, tags, created_at";
+
+// columns selected whenever a prompt template is loaded, must match template_from_row
+pub(crate) const TEMPLATE_COLUMNS: &str =
+    "id, name, version, template, variables, provider, model, created_at";
+
+// columns selected whenever a cached response is loaded, must match cached_response_from_row
+pub(crate) const CACHED_RESPONSE_COLUMNS: &str = "cache_key, provider, model, response, \
+    input_tokens, output_tokens, reasoning_tokens, cached_tokens, finish_reason, created_at";
+
+// columns selected whenever a conversation is loaded, must match conversation_from_row
+pub(crate) const CONVERSATION_COLUMNS: &str = "id, title, created_at, updated_at";
+
+// columns selected whenever a task is loaded, must match task_from_row
+pub(crate) const TASK_COLUMNS: &str = "id, task_type, payload, status, attempts, max_attempts, \
+    run_at, worker_id, lease_expires_at, result, error, created_at, updated_at";
+
+/// Where prompts, conversations, templates and cached model lists are saved.
+/// There is a store for MySQL, PostgreSQL and SQLite, connect_store picks one by the
+/// scheme of the database url. Tests can use the MemoryStore from test_support instead.
+#[async_trait]
+pub trait PromptStore: Send + Sync {
+    /// Bring the database schema up to date by running any migrations it hasn't had
+    async fn migrate(&self) -> Result<MigrationReport, MigrationError>;
+
+    // fails when the database can't be reached
+    async fn ping(&self) -> Result<(), sqlx::Error>;
+
+    // prompts that are part of a conversation get the next sequence number in it
+    async fn create_prompt_record(&self, new_prompt: &NewPrompt) -> Result<Prompt, sqlx::Error>;
+
+    async fn get_prompt_by_id(&self, id: i64) -> Result<Option<Prompt>, sqlx::Error>;
+
+    // a page of prompts matching the filter, newest first
+    async fn get_prompts(&self, filter: &PromptFilter) -> Result<PromptPage, sqlx::Error>;
+
+    // prompts and responses matching a search, most relevant first
+    async fn search_prompts(&self, search: &PromptSearch) -> Result<Vec<SearchHit>, sqlx::Error>;
+
+    // prompt counts, tokens and cost grouped by provider, model or day, from is inclusive and to exclusive
+    async fn get_usage(
+        &self,
+        group_by: UsageGroupBy,
+        from: Option<DateTime<Utc>>,
+        to: Option<DateTime<Utc>>,
+    ) -> Result<Vec<UsageSummary>, sqlx::Error>;
+
+    // total cost of prompts since the given time, optionally only for one provider or user
+    async fn get_spend(
+        &self,
+        since: DateTime<Utc>,
+        provider: Option<&str>,
+        user_id: Option<&str>,
+    ) -> Result<f64, sqlx::Error>;
+
+    // the provider's cached model list, None if it has never been saved
+    async fn get_model_catalog(
+        &self,
+        provider: &str,
+    ) -> Result<Option<ModelCatalogEntry>, sqlx::Error>;
+
+    // replace the provider's cached model list
+    async fn save_model_catalog(&self, entry: &ModelCatalogEntry) -> Result<(), sqlx::Error>;
+
+    // the response cached under the key, None if there isn't one, expired or not
+    async fn get_cached_response(&self, key: &str) -> Result<Option<CachedResponse>, sqlx::Error>;
+
+    // replace the response cached under its key
+    async fn save_cached_response(&self, response: &CachedResponse) -> Result<(), sqlx::Error>;
+
+    async fn create_conversation(&self, title: &str) -> Result<Conversation, sqlx::Error>;
+
+    async fn get_conversation(&self, id: i64) -> Result<Option<Conversation>, sqlx::Error>;
+
+    async fn get_all_conversations(&self) -> Result<Vec<Conversation>, sqlx::Error>;
+
+    // the prompts in a conversation in the order they were asked
+    async fn get_conversation_prompts(
+        &self,
+        conversation_id: i64,
+    ) -> Result<Vec<Prompt>, sqlx::Error>;
+
+    // the answers of a comparison in the order its targets were given,
+    // targets that failed to answer weren't saved
+    async fn get_comparison_prompts(&self, comparison_id: &str)
+        -> Result<Vec<Prompt>, sqlx::Error>;
+
+    // saving a template under an existing name adds the next version of it
+    async fn create_prompt_template(
+        &self,
+        new_template: &NewPromptTemplate,
+    ) -> Result<PromptTemplate, sqlx::Error>;
+
+    // a single version of a template
+    async fn get_prompt_template(&self, id: i64) -> Result<Option<PromptTemplate>, sqlx::Error>;
+
+    // the latest version of the template with this name
+    async fn get_prompt_template_by_name(
+        &self,
+        name: &str,
+    ) -> Result<Option<PromptTemplate>, sqlx::Error>;
+
+    // the latest version of every template
+    async fn get_prompt_templates(&self) -> Result<Vec<PromptTemplate>, sqlx::Error>;
+
+    // add a task to the queue, it is claimed once its run_at time has come
+    async fn enqueue_task(&self, new_task: &NewTask) -> Result<Task, sqlx::Error>;
+
+    async fn get_task(&self, id: i64) -> Result<Option<Task>, sqlx::Error>;
+
+    // the newest tasks, optionally only those with the status
+    async fn get_tasks(
+        &self,
+        status: Option<TaskStatus>,
+        limit: u32,
+    ) -> Result<Vec<Task>, sqlx::Error>;
+
+    // claim the due task that has waited longest for the worker, a pending task or a running
+    // one whose lease has expired. concurrent workers never claim the same task
+    async fn claim_task(
+        &self,
+        worker_id: &str,
+        lease: Duration,
+    ) -> Result<Option<Task>, sqlx::Error>;
+
+    // extend the lease of a task the worker is running, false if the worker no longer holds it
+    async fn renew_task_lease(
+        &self,
+        id: i64,
+        worker_id: &str,
+        lease: Duration,
+    ) -> Result<bool, sqlx::Error>;
+
+    // save the result of a task the worker holds, false if it no longer holds it
+    async fn complete_task(
+        &self,
+        id: i64,
+        worker_id: &str,
+        result: &serde_json::Value,
+    ) -> Result<bool, sqlx::Error>;
+
+    // record a failed attempt of a task the worker holds, the task is pending again from
+    // retry_at or dead without one. false if the worker no longer holds it
+    async fn fail_task(
+        &self,
+        id: i64,
+        worker_id: &str,
+        error: &str,
+        retry_at: Option<DateTime<Utc>>,
+    ) -> Result<bool, sqlx::Error>;
+
+    // a template by id, or the latest version of the template with that name
+    async fn find_prompt_template(
+        &self,
+        id_or_name: &str,
+    ) -> Result<Option<PromptTemplate>, sqlx::Error> {
+        match id_or_name.parse::<i64>() {
+            Ok(id) => self.get_prompt_template(id).await,
+            Err(_) => self.get_prompt_template_by_name(id_or_name).await,
+        }
+    }
+}
+
+/// Connect to the database in the config's database url with the store for its backend
+pub async fn connect_store(config: &CoreConfig) -> Result<Arc<dyn PromptStore>> {
+    let url = &config.database_url;
+    let max_connections = config.max_connections;
+    let store: Arc<dyn PromptStore> = match config.database_backend {
+        DatabaseBackend::MySql => Arc::new(MySqlStore::connect(url, max_connections).await?),
+        DatabaseBackend::Postgres => Arc::new(PostgresStore::connect(url, max_connections).await?),
+        DatabaseBackend::Sqlite => Arc::new(SqliteStore::connect(url, max_connections).await?),
+    };
+
+    // check if a test query runs on the db
+    store.ping().await.context("Database health check failed")?;
+
+    Ok(store)
+}
+
+// timestamps are stored to the second, the paging cursor relies on them matching what was saved
+pub(crate) fn now() -> NaiveDateTime {
+    let now = Utc::now().naive_utc();
+    now.with_nanosecond(0).unwrap_or(now)
+}
+
+// when a lease taken now runs out
+pub(crate) fn lease_expiry(lease: Duration) -> NaiveDateTime {
+    now() + chrono::Duration::seconds(lease.as_secs() as i64)
+}
+
+// attempted targets, stop sequences and tags are stored as json arrays
+pub(crate) fn json_array(values: Option<&Vec<String>>) -> Option<String> {
+    values.map(|values| serde_json::to_string(values).unwrap_or_default())
+}
+
+pub(crate) fn from_json_array(value: Option<String>) -> Option<Vec<String>> {
+    value.and_then(|value| serde_json::from_str(&value).ok())
+}
+
+// the json columns of a new prompt, tags are left out when there are none
+pub(crate) struct PromptJson {
+    pub attempted_targets: Option<String>,
+    pub stop_sequences: Option<String>,
+    pub tags: Option<String>,
+}
+
+impl PromptJson {
+    pub fn new(new_prompt: &NewPrompt) -> Self {
+        Self {
+            attempted_targets: json_array(new_prompt.attempted_targets.as_ref()),
+            stop_sequences: json_array(new_prompt.params.stop.as_ref()),
+            tags: json_array(Some(&new_prompt.tags).filter(|tags| !tags.is_empty())),
+        }
+    }
+}
+
+// the page of prompts fetched with one extra row to tell whether there is another page
+pub(crate) fn prompt_page(mut prompts: Vec<Prompt>, limit: u32) -> PromptPage {
+    let next_cursor = if prompts.len() > limit as usize {
+        prompts.truncate(limit as usize);
+        prompts
+            .last()
+            .map(|prompt| PromptCursor::from(prompt).to_string())
+    } else {
+        None
+    };
+
+    PromptPage {
+        prompts,
+        next_cursor,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{GenerationParams, ModelInfo, TokenUsage};
+    use crate::template::TemplateVariable;
+    use crate::test_support::new_prompt;
+    use chrono::{Duration, TimeZone};
+
+    async fn memory_store() -> SqliteStore {
+        let store = SqliteStore::connect("sqlite::memory:", 1).await.unwrap();
+        store.migrate().await.unwrap();
+        store
+    }
+
+    #[tokio::test]
+    async fn test_sqlite_migrate() {
+        let store = SqliteStore::connect("sqlite::memory:", 1).await.unwrap();
+        let report = store.migrate().await.unwrap();
+        assert_eq!(
+            report.applied,
+            vec![
+                "0001_create_schema",
+                "0002_response_cache",
+                "0003_tasks",
+                "0004_prompt_comparison_index"
+            ]
+        );
+        assert_eq!(
+            report.version,
+            crate::migrations::schema_version(DatabaseBackend::Sqlite)
+        );
+
+        // running them again has nothing left to do
+        let report = store.migrate().await.unwrap();
+        assert!(report.applied.is_empty());
+        store.ping().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_sqlite_prompt_round_trip() {
+        let store = memory_store().await;
+
+        let mut prompt = new_prompt("What is Kafka?", "A distributed log", "Anthropic");
+        prompt.attempts = 2;
+        prompt.route = Some("primary".to_string());
+        prompt.attempted_targets = Some(vec!["OpenAI/gpt-4o".to_string()]);
+        prompt.params = GenerationParams {
+            system: Some("Be brief".to_string()),
+            temperature: Some(0.5),
+            top_p: None,
+            max_tokens: Some(256),
+            stop: Some(vec!["END".to_string()]),
+            seed: Some(42),
+        };
+        prompt.tags = vec!["eval".to_string()];
+
+        let saved = store.create_prompt_record(&prompt).await.unwrap();
+        assert_eq!(saved.prompt, "What is Kafka?");
+        assert_eq!(saved.input_tokens, Some(10));
+        assert_eq!(saved.cost, Some(0.5));
+        assert_eq!(saved.attempts, 2);
+        assert!(!saved.cache_hit);
+        assert_eq!(
+            saved.attempted_targets,
+            Some(vec!["OpenAI/gpt-4o".to_string()])
+        );
+        assert_eq!(saved.params.max_tokens, Some(256));
+        assert_eq!(saved.params.temperature, Some(0.5));
+        assert_eq!(saved.params.stop, Some(vec!["END".to_string()]));
+        assert_eq!(saved.tags, vec!["eval"]);
+        assert_eq!(saved.created_at.timestamp_subsec_nanos(), 0);
+
+        let loaded = store.get_prompt_by_id(saved.id).await.unwrap().unwrap();
+        assert_eq!(loaded.id, saved.id);
+        assert_eq!(loaded.created_at, saved.created_at);
+        assert!(store
+            .get_prompt_by_id(saved.id + 1)
+            .await
+            .unwrap()
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn test_sqlite_prompt_pages_and_filters() {
+        let store = memory_store().await;
+        for i in 0..5 {
+            let mut prompt = new_prompt(&format!("prompt {}", i), "answer", "Anthropic");
+            if i % 2 == 0 {
+                prompt.provider = "OpenAI".to_string();
+                prompt.tags = vec!["even".to_string()];
+            }
+            store.create_prompt_record(&prompt).await.unwrap();
+        }
+
+        let page = store
+            .get_prompts(&PromptFilter {
+                limit: 2,
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        let first: Vec<&str> = page.prompts.iter().map(|p| p.prompt.as_str()).collect();
+        assert_eq!(first, vec!["prompt 4", "prompt 3"]);
+
+        let cursor: PromptCursor = page.next_cursor.unwrap().parse().unwrap();
+        let page = store
+            .get_prompts(&PromptFilter {
+                limit: 5,
+                after: Some(cursor),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        let rest: Vec<&str> = page.prompts.iter().map(|p| p.prompt.as_str()).collect();
+        assert_eq!(rest, vec!["prompt 2", "prompt 1", "prompt 0"]);
+        assert_eq!(page.next_cursor, None);
+
+        let tagged = store
+            .get_prompts(&PromptFilter {
+                tag: Some("even".to_string()),
+                provider: Some("OpenAI".to_string()),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        assert_eq!(tagged.prompts.len(), 3);
+
+        let future = store
+            .get_prompts(&PromptFilter {
+                from: Some(Utc::now() + Duration::days(1)),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        assert!(future.prompts.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_sqlite_search() {
+        let store = memory_store().await;
+        store
+            .create_prompt_record(&new_prompt(
+                "Why is my Kafka consumer lagging?",
+                "The consumer group is rebalancing",
+                "Anthropic",
+            ))
+            .await
+            .unwrap();
+        store
+            .create_prompt_record(&new_prompt("Write a haiku", "Autumn moonlight", "OpenAI"))
+            .await
+            .unwrap();
+
+        let search = crate::search::SearchQuery {
+            q: "kafka \"consumers\"".to_string(),
+            limit: None,
+        }
+        .search()
+        .unwrap();
+        let hits = store.search_prompts(&search).await.unwrap();
+        assert_eq!(hits.len(), 1);
+        assert!(hits[0].score > 0.0);
+        // consumers matches consumer through stemming, snippets only highlight word starts
+        assert_eq!(
+            hits[0].prompt_snippet.as_deref(),
+            Some("Why is my **Kafka** consumer lagging?")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_sqlite_usage_and_spend() {
+        let store = memory_store().await;
+        store
+            .create_prompt_record(&new_prompt("a", "b", "Anthropic"))
+            .await
+            .unwrap();
+        store
+            .create_prompt_record(&new_prompt("c", "d", "Anthropic"))
+            .await
+            .unwrap();
+        let mut unpriced = new_prompt("e", "f", "Ollama");
+        unpriced.cost = None;
+        unpriced.user_id = None;
+        store.create_prompt_record(&unpriced).await.unwrap();
+
+        let usage = store
+            .get_usage(UsageGroupBy::Provider, None, None)
+            .await
+            .unwrap();
+        assert_eq!(usage.len(), 2);
+        assert_eq!(usage[0].key, "Anthropic");
+        assert_eq!(usage[0].prompts, 2);
+        assert_eq!(usage[0].input_tokens, 20);
+        assert_eq!(usage[0].cost, 1.0);
+        assert_eq!(usage[1].unpriced_prompts, 1);
+
+        let days = store
+            .get_usage(UsageGroupBy::Day, None, None)
+            .await
+            .unwrap();
+        assert_eq!(days[0].key, Utc::now().format("%Y-%m-%d").to_string());
+
+        let since = Utc::now() - Duration::hours(1);
+        assert_eq!(store.get_spend(since, None, None).await.unwrap(), 1.0);
+        assert_eq!(
+            store.get_spend(since, Some("Ollama"), None).await.unwrap(),
+            0.0
+        );
+        assert_eq!(
+            store.get_spend(since, None, Some("alice")).await.unwrap(),
+            1.0
+        );
+    }
+
+    #[tokio::test]
+    async fn test_sqlite_provider_ignores_case() {
+        let store = memory_store().await;
+        store
+            .create_prompt_record(&new_prompt("a", "b", "OpenAI"))
+            .await
+            .unwrap();
+
+        // budgets and filters name providers in any case, like BudgetScope and MySQL do
+        let since = Utc::now() - Duration::hours(1);
+        assert_eq!(
+            store.get_spend(since, Some("openai"), None).await.unwrap(),
+            0.5
+        );
+        let page = store
+            .get_prompts(&PromptFilter {
+                provider: Some("OPENAI".to_string()),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        assert_eq!(page.prompts.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_sqlite_comparison_order() {
+        let store = memory_store().await;
+        // the second target answered first
+        for (index, provider) in [(1, "OpenAI"), (0, "Anthropic")] {
+            let mut prompt = new_prompt("Explain Kafka", "answer", provider);
+            prompt.comparison_id = Some("c0ffee".to_string());
+            prompt.comparison_index = Some(index);
+            store.create_prompt_record(&prompt).await.unwrap();
+        }
+
+        let prompts = store.get_comparison_prompts("c0ffee").await.unwrap();
+        let providers: Vec<&str> = prompts.iter().map(|p| p.provider.as_str()).collect();
+        assert_eq!(providers, vec!["Anthropic", "OpenAI"]);
+        assert_eq!(prompts[1].comparison_index, Some(1));
+    }
+
+    #[tokio::test]
+    async fn test_sqlite_conversations() {
+        let store = memory_store().await;
+        let conversation = store.create_conversation("Kafka help").await.unwrap();
+
+        for question in ["first", "second"] {
+            let mut prompt = new_prompt(question, "answer", "Anthropic");
+            prompt.conversation_id = Some(conversation.id);
+            store.create_prompt_record(&prompt).await.unwrap();
+        }
+
+        let prompts = store
+            .get_conversation_prompts(conversation.id)
+            .await
+            .unwrap();
+        let sequences: Vec<Option<i32>> = prompts.iter().map(|p| p.sequence).collect();
+        assert_eq!(sequences, vec![Some(1), Some(2)]);
+
+        let loaded = store.get_conversation(conversation.id).await.unwrap();
+        assert_eq!(loaded.unwrap().title, "Kafka help");
+        assert_eq!(store.get_all_conversations().await.unwrap().len(), 1);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_sqlite_concurrent_turns() {
+        // an in-memory database has a single connection, a file lets the turns overlap
+        let path = std::env::temp_dir().join(format!("kubellm-turns-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let store = SqliteStore::connect(&format!("sqlite://{}", path.display()), 4)
+            .await
+            .unwrap();
+        store.migrate().await.unwrap();
+        let conversation = store.create_conversation("Kafka help").await.unwrap();
+
+        let turns = (0..8).map(|i| {
+            let mut prompt = new_prompt(&format!("turn {}", i), "answer", "Anthropic");
+            prompt.conversation_id = Some(conversation.id);
+            let store = &store;
+            async move { store.create_prompt_record(&prompt).await.unwrap() }
+        });
+        futures::future::join_all(turns).await;
+
+        let mut sequences: Vec<i32> = store
+            .get_conversation_prompts(conversation.id)
+            .await
+            .unwrap()
+            .iter()
+            .filter_map(|p| p.sequence)
+            .collect();
+        sequences.sort();
+        assert_eq!(sequences, (1..=8).collect::<Vec<_>>());
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[tokio::test]
+    async fn test_sqlite_templates_and_catalog() {
+        let store = memory_store().await;
+        let new_template = NewPromptTemplate {
+            name: "summary".to_string(),
+            template: "Summarize {{text}}".to_string(),
+            variables: vec![TemplateVariable {
+                name: "text".to_string(),
+                default: None,
+                description: None,
+            }],
+            provider: None,
+            model: None,
+        };
+        let first = store.create_prompt_template(&new_template).await.unwrap();
+        let second = store.create_prompt_template(&new_template).await.unwrap();
+        assert_eq!((first.version, second.version), (1, 2));
+        assert_eq!(second.variables.len(), 1);
+
+        let latest = store
+            .find_prompt_template("summary")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(latest.id, second.id);
+        let by_id = store
+            .find_prompt_template(&first.id.to_string())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(by_id.version, 1);
+        assert_eq!(store.get_prompt_templates().await.unwrap().len(), 1);
+
+        assert!(store.get_model_catalog("OpenAI").await.unwrap().is_none());
+        let entry = ModelCatalogEntry {
+            provider: "OpenAI".to_string(),
+            models: vec![ModelInfo::new("gpt-4o"), ModelInfo::new("gpt-5")],
+            fetched_at: Utc.with_ymd_and_hms(2025, 1, 1, 12, 0, 0).unwrap(),
+        };
+        store.save_model_catalog(&entry).await.unwrap();
+        store.save_model_catalog(&entry).await.unwrap();
+        let cached = store.get_model_catalog("OpenAI").await.unwrap().unwrap();
+        assert_eq!(cached.models.len(), 2);
+        assert_eq!(cached.fetched_at, entry.fetched_at);
+    }
+
+    #[tokio::test]
+    async fn test_sqlite_response_cache() {
+        let store = memory_store().await;
+        assert!(store.get_cached_response("abc").await.unwrap().is_none());
+
+        let mut response = CachedResponse {
+            key: "abc".to_string(),
+            provider: "Anthropic".to_string(),
+            model: "test-model".to_string(),
+            response: "A distributed log".to_string(),
+            usage: TokenUsage {
+                input_tokens: Some(10),
+                output_tokens: Some(20),
+                reasoning_tokens: None,
+                cached_tokens: None,
+            },
+            finish_reason: Some("end_turn".to_string()),
+            created_at: Utc.with_ymd_and_hms(2025, 1, 1, 12, 0, 0).unwrap(),
+        };
+        store.save_cached_response(&response).await.unwrap();
+        response.response = "A commit log".to_string();
+        store.save_cached_response(&response).await.unwrap();
+
+        let cached = store.get_cached_response("abc").await.unwrap().unwrap();
+        assert_eq!(cached, response);
+
+        let mut prompt = new_prompt("What is Kafka?", "A commit log", "Anthropic");
+        prompt.cache_hit = true;
+        prompt.attempts = 0;
+        let saved = store.create_prompt_record(&prompt).await.unwrap();
+        assert!(saved.cache_hit);
+        assert_eq!(saved.attempts, 0);
+    }
+
+    #[tokio::test]
+    async fn test_sqlite_tasks() {
+        let store = memory_store().await;
+        let lease = std::time::Duration::from_secs(60);
+        let later = NewTask::new("later", serde_json::json!({}), 3)
+            .with_run_at(Utc::now() + Duration::hours(1));
+        store.enqueue_task(&later).await.unwrap();
+        let queued = store
+            .enqueue_task(&NewTask::new("prompt", serde_json::json!({"n": 1}), 3))
+            .await
+            .unwrap();
+        assert_eq!(queued.status, TaskStatus::Pending);
+        assert_eq!(queued.payload["n"], 1);
+
+        // only the task that is due is claimed, and only by one worker
+        let task = store.claim_task("w1", lease).await.unwrap().unwrap();
+        assert_eq!(task.id, queued.id);
+        assert_eq!((task.status, task.attempts), (TaskStatus::Running, 1));
+        assert_eq!(task.worker_id.as_deref(), Some("w1"));
+        assert!(task.lease_expires_at.unwrap() > Utc::now());
+        assert!(store.claim_task("w2", lease).await.unwrap().is_none());
+
+        assert!(store.renew_task_lease(task.id, "w1", lease).await.unwrap());
+        assert!(!store.renew_task_lease(task.id, "w2", lease).await.unwrap());
+
+        // a retried task is pending again once its backoff has passed
+        let retry_at = Utc::now() - Duration::seconds(1);
+        assert!(store
+            .fail_task(task.id, "w1", "overloaded", Some(retry_at))
+            .await
+            .unwrap());
+        let task = store.claim_task("w2", lease).await.unwrap().unwrap();
+        assert_eq!(
+            (task.attempts, task.error.as_deref()),
+            (2, Some("overloaded"))
+        );
+
+        let result = serde_json::json!({"prompt_id": 7});
+        assert!(!store.complete_task(task.id, "w1", &result).await.unwrap());
+        assert!(store.complete_task(task.id, "w2", &result).await.unwrap());
+        let done = store.get_task(task.id).await.unwrap().unwrap();
+        assert_eq!(
+            (done.status, done.result),
+            (TaskStatus::Completed, Some(result))
+        );
+        assert_eq!(done.lease_expires_at, None);
+
+        // a lease that has run out lets another worker claim the task
+        store
+            .enqueue_task(&NewTask::new("prompt", serde_json::json!({}), 1))
+            .await
+            .unwrap();
+        let task = store
+            .claim_task("w1", std::time::Duration::ZERO)
+            .await
+            .unwrap()
+            .unwrap();
+        let reclaimed = store.claim_task("w2", lease).await.unwrap().unwrap();
+        assert_eq!((reclaimed.id, reclaimed.attempts), (task.id, 2));
+        assert!(store.fail_task(task.id, "w2", "boom", None).await.unwrap());
+
+        let dead = store.get_tasks(Some(TaskStatus::Dead), 10).await.unwrap();
+        assert_eq!(dead.len(), 1);
+        assert_eq!(dead[0].error.as_deref(), Some("boom"));
+        assert_eq!(store.get_tasks(None, 2).await.unwrap()[0].id, task.id);
+    }
+}
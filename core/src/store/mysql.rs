@@ -0,0 +1,841 @@
+use crate::cache::CachedResponse;
+use crate::migrations::{
+    pending_migrations, statements, Marker, MigrationError, MigrationReport, MIGRATION_LOCK,
+    MIGRATION_LOCK_TIMEOUT_SECS, MYSQL_MIGRATIONS,
+};
+use crate::models::{
+    Conversation, ModelCatalogEntry, ModelInfo, NewPrompt, Prompt, PromptFilter, PromptPage,
+    UsageGroupBy, UsageSummary,
+};
+use crate::search::{PromptSearch, SearchHit};
+use crate::store::{
+    lease_expiry, now, prompt_page, PromptJson, PromptStore, CACHED_RESPONSE_COLUMNS,
+    CONVERSATION_COLUMNS, PROMPT_COLUMNS, TASK_COLUMNS, TEMPLATE_COLUMNS,
+};
+use crate::task::{NewTask, Task, TaskStatus};
+use crate::template::{NewPromptTemplate, PromptTemplate};
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, NaiveDateTime, Utc};
+use sqlx::{
+    mysql::{MySqlConnection, MySqlPool, MySqlPoolOptions, MySqlRow},
+    Row,
+};
+use std::time::Duration;
+
+/// Prompts stored in MySQL, the database kubellm started out with
+pub struct MySqlStore {
+    pool: MySqlPool,
+}
+
+impl MySqlStore {
+    pub async fn connect(url: &str, max_connections: u32) -> Result<Self> {
+        // create a connection pool to the MySQL database using the URL from the config
+        let pool = MySqlPoolOptions::new()
+            .max_connections(max_connections)
+            .connect(url)
+            .await
+            .context("Failed to connect to MySQL database")?;
+
+        Ok(Self { pool })
+    }
+}
+
+#[async_trait]
+impl PromptStore for MySqlStore {
+    async fn migrate(&self) -> Result<MigrationReport, MigrationError> {
+        // the lock belongs to the connection, everything runs on the one that took it
+        let mut conn = self.pool.acquire().await?;
+        let locked: Option<i64> = sqlx::query("SELECT GET_LOCK(?, ?) AS locked")
+            .bind(MIGRATION_LOCK)
+            .bind(MIGRATION_LOCK_TIMEOUT_SECS)
+            .fetch_one(&mut *conn)
+            .await?
+            .get("locked");
+        if locked != Some(1) {
+            return Err(MigrationError::Locked);
+        }
+
+        let result = migrate(&mut conn).await;
+
+        sqlx::query("SELECT RELEASE_LOCK(?)")
+            .bind(MIGRATION_LOCK)
+            .execute(&mut *conn)
+            .await?;
+        result
+    }
+
+    async fn ping(&self) -> Result<(), sqlx::Error> {
+        sqlx::query("SELECT 1").execute(&self.pool).await?;
+        Ok(())
+    }
+
+    async fn create_prompt_record(&self, new_prompt: &NewPrompt) -> Result<Prompt, sqlx::Error> {
+        let now = now();
+
+        // the sequence is read and the prompt inserted in one transaction
+        let mut tx = self.pool.begin().await?;
+
+        let sequence: Option<i32> = match new_prompt.conversation_id {
+            Some(conversation_id) => {
+                // updating the conversation first locks its row until the prompt is inserted, so
+                // concurrent turns of a conversation take their sequence one after the other. the
+                // locking read sees the prompts of turns that committed while this one waited
+                sqlx::query("UPDATE conversations SET updated_at = ? WHERE id = ?")
+                    .bind(now)
+                    .bind(conversation_id)
+                    .execute(&mut *tx)
+                    .await?;
+
+                let row = sqlx::query(
+                    "SELECT CAST(COALESCE(MAX(sequence), 0) + 1 AS SIGNED) AS next_sequence FROM prompts WHERE conversation_id = ? FOR UPDATE",
+                )
+                .bind(conversation_id)
+                .fetch_one(&mut *tx)
+                .await?;
+                let next_sequence: i64 = row.get("next_sequence");
+
+                Some(next_sequence as i32)
+            }
+            None => None,
+        };
+
+        let usage = &new_prompt.usage;
+        let params = &new_prompt.params;
+        let json = PromptJson::new(new_prompt);
+        let insert_result = sqlx::query(
+            "INSERT INTO prompts (prompt, response, model, provider, conversation_id, sequence, input_tokens, output_tokens, reasoning_tokens, cached_tokens, latency_ms, finish_reason, cost, user_id, attempts, cache_hit, route, attempted_targets, requested_model, system_prompt, temperature, top_p, max_tokens, stop_sequences, seed, template_id, comparison_id, comparison_index, tags, created_at) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+        )
+        .bind(&new_prompt.prompt)
+        .bind(&new_prompt.response)
+        .bind(&new_prompt.model)
+        .bind(&new_prompt.provider)
+        .bind(new_prompt.conversation_id)
+        .bind(sequence)
+        .bind(usage.input_tokens)
+        .bind(usage.output_tokens)
+        .bind(usage.reasoning_tokens)
+        .bind(usage.cached_tokens)
+        .bind(new_prompt.latency_ms)
+        .bind(&new_prompt.finish_reason)
+        .bind(new_prompt.cost)
+        .bind(&new_prompt.user_id)
+        .bind(new_prompt.attempts)
+        .bind(new_prompt.cache_hit)
+        .bind(&new_prompt.route)
+        .bind(json.attempted_targets)
+        .bind(&new_prompt.requested_model)
+        .bind(&params.system)
+        .bind(params.temperature)
+        .bind(params.top_p)
+        .bind(params.max_tokens)
+        .bind(json.stop_sequences)
+        .bind(params.seed)
+        .bind(new_prompt.template_id)
+        .bind(&new_prompt.comparison_id)
+        .bind(new_prompt.comparison_index)
+        .bind(json.tags)
+        .bind(now)
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        let id = insert_result.last_insert_id() as i64;
+
+        self.get_prompt_by_id(id)
+            .await?
+            .ok_or(sqlx::Error::RowNotFound)
+    }
+
+    async fn get_prompt_by_id(&self, id: i64) -> Result<Option<Prompt>, sqlx::Error> {
+        let row = sqlx::query(&format!(
+            "SELECT {} FROM prompts WHERE id = ?",
+            PROMPT_COLUMNS
+        ))
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row.as_ref().map(prompt_from_row))
+    }
+
+    async fn get_prompts(&self, filter: &PromptFilter) -> Result<PromptPage, sqlx::Error> {
+        let from = filter.from.map(|from| from.naive_utc());
+        let to = filter.to.map(|to| to.naive_utc());
+        let after_created_at = filter.after.map(|after| after.created_at.naive_utc());
+        let after_id = filter.after.map(|after| after.id);
+
+        // one extra row is fetched to tell whether there is another page
+        let rows = sqlx::query(&format!(
+            "SELECT {} FROM prompts \
+            WHERE (? IS NULL OR LOWER(provider) = LOWER(?)) AND (? IS NULL OR model = ?) \
+                AND (? IS NULL OR created_at >= ?) AND (? IS NULL OR created_at < ?) \
+                AND (? IS NULL OR conversation_id = ?) \
+                AND (? IS NULL OR JSON_CONTAINS(tags, JSON_QUOTE(?))) \
+                AND (? IS NULL OR created_at < ? OR (created_at = ? AND id < ?)) \
+            ORDER BY created_at DESC, id DESC LIMIT ?",
+            PROMPT_COLUMNS
+        ))
+        .bind(&filter.provider)
+        .bind(&filter.provider)
+        .bind(&filter.model)
+        .bind(&filter.model)
+        .bind(from)
+        .bind(from)
+        .bind(to)
+        .bind(to)
+        .bind(filter.conversation_id)
+        .bind(filter.conversation_id)
+        .bind(&filter.tag)
+        .bind(&filter.tag)
+        .bind(after_created_at)
+        .bind(after_created_at)
+        .bind(after_created_at)
+        .bind(after_id)
+        .bind(filter.limit as i64 + 1)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(prompt_page(
+            rows.iter().map(prompt_from_row).collect(),
+            filter.limit,
+        ))
+    }
+
+    async fn search_prompts(&self, search: &PromptSearch) -> Result<Vec<SearchHit>, sqlx::Error> {
+        let rows = sqlx::query(&format!(
+            "SELECT {}, MATCH(prompt, response) AGAINST (? IN NATURAL LANGUAGE MODE) AS score \
+            FROM prompts \
+            WHERE MATCH(prompt, response) AGAINST (? IN NATURAL LANGUAGE MODE) \
+            ORDER BY score DESC, created_at DESC, id DESC LIMIT ?",
+            PROMPT_COLUMNS
+        ))
+        .bind(&search.text)
+        .bind(&search.text)
+        .bind(search.limit)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows
+            .iter()
+            .map(|row| SearchHit::new(prompt_from_row(row), row.get("score"), &search.terms))
+            .collect())
+    }
+
+    async fn get_usage(
+        &self,
+        group_by: UsageGroupBy,
+        from: Option<DateTime<Utc>>,
+        to: Option<DateTime<Utc>>,
+    ) -> Result<Vec<UsageSummary>, sqlx::Error> {
+        let (group_key, order_by) = match group_by {
+            UsageGroupBy::Provider => ("provider", "cost DESC, group_key"),
+            UsageGroupBy::Model => ("model", "cost DESC, group_key"),
+            UsageGroupBy::Day => ("DATE_FORMAT(created_at, '%Y-%m-%d')", "group_key"),
+        };
+
+        let from = from.map(|from| from.naive_utc());
+        let to = to.map(|to| to.naive_utc());
+
+        let rows = sqlx::query(&format!(
+            "SELECT {} AS group_key, COUNT(*) AS prompts, \
+                CAST(COALESCE(SUM(input_tokens), 0) AS SIGNED) AS input_tokens, \
+                CAST(COALESCE(SUM(output_tokens), 0) AS SIGNED) AS output_tokens, \
+                CAST(COALESCE(SUM(cached_tokens), 0) AS SIGNED) AS cached_tokens, \
+                COALESCE(SUM(cost), 0) AS cost, \
+                CAST(SUM(cost IS NULL) AS SIGNED) AS unpriced_prompts \
+            FROM prompts \
+            WHERE (? IS NULL OR created_at >= ?) AND (? IS NULL OR created_at < ?) \
+            GROUP BY group_key ORDER BY {}",
+            group_key, order_by
+        ))
+        .bind(from)
+        .bind(from)
+        .bind(to)
+        .bind(to)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.iter().map(usage_from_row).collect())
+    }
+
+    async fn get_spend(
+        &self,
+        since: DateTime<Utc>,
+        provider: Option<&str>,
+        user_id: Option<&str>,
+    ) -> Result<f64, sqlx::Error> {
+        let row = sqlx::query(
+            "SELECT COALESCE(SUM(cost), 0) AS spent FROM prompts \
+            WHERE created_at >= ? AND (? IS NULL OR LOWER(provider) = LOWER(?)) AND (? IS NULL OR user_id = ?)",
+        )
+        .bind(since.naive_utc())
+        .bind(provider)
+        .bind(provider)
+        .bind(user_id)
+        .bind(user_id)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(row.get("spent"))
+    }
+
+    async fn get_model_catalog(
+        &self,
+        provider: &str,
+    ) -> Result<Option<ModelCatalogEntry>, sqlx::Error> {
+        let rows = sqlx::query(
+            "SELECT model_id, display_name, created_at, fetched_at FROM model_catalog \
+            WHERE provider = ? ORDER BY model_id",
+        )
+        .bind(provider)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let Some(first) = rows.first() else {
+            return Ok(None);
+        };
+        let fetched_at: NaiveDateTime = first.get("fetched_at");
+
+        Ok(Some(ModelCatalogEntry {
+            provider: provider.to_string(),
+            models: rows.iter().map(model_from_row).collect(),
+            fetched_at: fetched_at.and_utc(),
+        }))
+    }
+
+    async fn save_model_catalog(&self, entry: &ModelCatalogEntry) -> Result<(), sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query("DELETE FROM model_catalog WHERE provider = ?")
+            .bind(&entry.provider)
+            .execute(&mut *tx)
+            .await?;
+
+        for model in &entry.models {
+            sqlx::query(
+                "INSERT INTO model_catalog (provider, model_id, display_name, created_at, fetched_at) \
+                VALUES (?, ?, ?, ?, ?)",
+            )
+            .bind(&entry.provider)
+            .bind(&model.id)
+            .bind(&model.display_name)
+            .bind(model.created_at.map(|created_at| created_at.naive_utc()))
+            .bind(entry.fetched_at.naive_utc())
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await
+    }
+
+    async fn get_cached_response(&self, key: &str) -> Result<Option<CachedResponse>, sqlx::Error> {
+        let row = sqlx::query(&format!(
+            "SELECT {} FROM response_cache WHERE cache_key = ?",
+            CACHED_RESPONSE_COLUMNS
+        ))
+        .bind(key)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row.as_ref().map(cached_response_from_row))
+    }
+
+    async fn save_cached_response(&self, response: &CachedResponse) -> Result<(), sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query("DELETE FROM response_cache WHERE cache_key = ?")
+            .bind(&response.key)
+            .execute(&mut *tx)
+            .await?;
+
+        let usage = &response.usage;
+        sqlx::query(&format!(
+            "INSERT INTO response_cache ({}) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            CACHED_RESPONSE_COLUMNS
+        ))
+        .bind(&response.key)
+        .bind(&response.provider)
+        .bind(&response.model)
+        .bind(&response.response)
+        .bind(usage.input_tokens.map(|n| n as i32))
+        .bind(usage.output_tokens.map(|n| n as i32))
+        .bind(usage.reasoning_tokens.map(|n| n as i32))
+        .bind(usage.cached_tokens.map(|n| n as i32))
+        .bind(&response.finish_reason)
+        .bind(response.created_at.naive_utc())
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await
+    }
+
+    async fn create_conversation(&self, title: &str) -> Result<Conversation, sqlx::Error> {
+        let now = now();
+
+        let insert_result = sqlx::query(
+            "INSERT INTO conversations (title, created_at, updated_at) VALUES (?, ?, ?)",
+        )
+        .bind(title)
+        .bind(now)
+        .bind(now)
+        .execute(&self.pool)
+        .await?;
+
+        let id = insert_result.last_insert_id() as i64;
+
+        self.get_conversation(id)
+            .await?
+            .ok_or(sqlx::Error::RowNotFound)
+    }
+
+    async fn get_conversation(&self, id: i64) -> Result<Option<Conversation>, sqlx::Error> {
+        let row = sqlx::query(&format!(
+            "SELECT {} FROM conversations WHERE id = ?",
+            CONVERSATION_COLUMNS
+        ))
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row.as_ref().map(conversation_from_row))
+    }
+
+    async fn get_all_conversations(&self) -> Result<Vec<Conversation>, sqlx::Error> {
+        let rows = sqlx::query(&format!(
+            "SELECT {} FROM conversations ORDER BY updated_at DESC, id DESC",
+            CONVERSATION_COLUMNS
+        ))
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.iter().map(conversation_from_row).collect())
+    }
+
+    async fn get_conversation_prompts(
+        &self,
+        conversation_id: i64,
+    ) -> Result<Vec<Prompt>, sqlx::Error> {
+        let rows = sqlx::query(&format!(
+            "SELECT {} FROM prompts WHERE conversation_id = ? ORDER BY sequence ASC",
+            PROMPT_COLUMNS
+        ))
+        .bind(conversation_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.iter().map(prompt_from_row).collect())
+    }
+
+    async fn get_comparison_prompts(
+        &self,
+        comparison_id: &str,
+    ) -> Result<Vec<Prompt>, sqlx::Error> {
+        let rows = sqlx::query(&format!(
+            "SELECT {} FROM prompts WHERE comparison_id = ? ORDER BY comparison_index ASC, id ASC",
+            PROMPT_COLUMNS
+        ))
+        .bind(comparison_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.iter().map(prompt_from_row).collect())
+    }
+
+    async fn create_prompt_template(
+        &self,
+        new_template: &NewPromptTemplate,
+    ) -> Result<PromptTemplate, sqlx::Error> {
+        let now = now();
+        let variables = serde_json::to_string(&new_template.variables).unwrap_or_default();
+
+        let mut tx = self.pool.begin().await?;
+
+        let row = sqlx::query(
+            "SELECT CAST(COALESCE(MAX(version), 0) + 1 AS SIGNED) AS next_version FROM prompt_templates WHERE name = ? FOR UPDATE",
+        )
+        .bind(&new_template.name)
+        .fetch_one(&mut *tx)
+        .await?;
+        let next_version: i64 = row.get("next_version");
+
+        let insert_result = sqlx::query(
+            "INSERT INTO prompt_templates (name, version, template, variables, provider, model, created_at) VALUES (?, ?, ?, ?, ?, ?, ?)",
+        )
+        .bind(&new_template.name)
+        .bind(next_version as i32)
+        .bind(&new_template.template)
+        .bind(variables)
+        .bind(&new_template.provider)
+        .bind(&new_template.model)
+        .bind(now)
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        let id = insert_result.last_insert_id() as i64;
+
+        self.get_prompt_template(id)
+            .await?
+            .ok_or(sqlx::Error::RowNotFound)
+    }
+
+    async fn get_prompt_template(&self, id: i64) -> Result<Option<PromptTemplate>, sqlx::Error> {
+        let row = sqlx::query(&format!(
+            "SELECT {} FROM prompt_templates WHERE id = ?",
+            TEMPLATE_COLUMNS
+        ))
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row.as_ref().map(template_from_row))
+    }
+
+    async fn get_prompt_template_by_name(
+        &self,
+        name: &str,
+    ) -> Result<Option<PromptTemplate>, sqlx::Error> {
+        let row = sqlx::query(&format!(
+            "SELECT {} FROM prompt_templates WHERE name = ? ORDER BY version DESC LIMIT 1",
+            TEMPLATE_COLUMNS
+        ))
+        .bind(name)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row.as_ref().map(template_from_row))
+    }
+
+    async fn get_prompt_templates(&self) -> Result<Vec<PromptTemplate>, sqlx::Error> {
+        let rows = sqlx::query(&format!(
+            "SELECT {} FROM prompt_templates t \
+            WHERE version = (SELECT MAX(version) FROM prompt_templates WHERE name = t.name) \
+            ORDER BY name",
+            TEMPLATE_COLUMNS
+        ))
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.iter().map(template_from_row).collect())
+    }
+
+    async fn enqueue_task(&self, new_task: &NewTask) -> Result<Task, sqlx::Error> {
+        let now = now();
+
+        let insert_result = sqlx::query(
+            "INSERT INTO tasks (task_type, payload, status, attempts, max_attempts, run_at, created_at, updated_at) \
+            VALUES (?, ?, ?, 0, ?, ?, ?, ?)",
+        )
+        .bind(&new_task.task_type)
+        .bind(new_task.payload.to_string())
+        .bind(TaskStatus::Pending.to_string())
+        .bind(new_task.max_attempts)
+        .bind(new_task.run_at.naive_utc())
+        .bind(now)
+        .bind(now)
+        .execute(&self.pool)
+        .await?;
+
+        let id = insert_result.last_insert_id() as i64;
+        self.get_task(id).await?.ok_or(sqlx::Error::RowNotFound)
+    }
+
+    async fn get_task(&self, id: i64) -> Result<Option<Task>, sqlx::Error> {
+        let row = sqlx::query(&format!("SELECT {} FROM tasks WHERE id = ?", TASK_COLUMNS))
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(row.as_ref().map(task_from_row))
+    }
+
+    async fn get_tasks(
+        &self,
+        status: Option<TaskStatus>,
+        limit: u32,
+    ) -> Result<Vec<Task>, sqlx::Error> {
+        let status = status.map(|status| status.to_string());
+        let rows = sqlx::query(&format!(
+            "SELECT {} FROM tasks WHERE (? IS NULL OR status = ?) ORDER BY id DESC LIMIT ?",
+            TASK_COLUMNS
+        ))
+        .bind(&status)
+        .bind(&status)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.iter().map(task_from_row).collect())
+    }
+
+    async fn claim_task(
+        &self,
+        worker_id: &str,
+        lease: Duration,
+    ) -> Result<Option<Task>, sqlx::Error> {
+        let now = now();
+        let mut tx = self.pool.begin().await?;
+
+        // SKIP LOCKED passes over a task another worker has locked instead of waiting for it,
+        // so every worker claims a different task. the lock is held until the commit
+        let row = sqlx::query(
+            "SELECT id FROM tasks \
+            WHERE (status = ? AND run_at <= ?) OR (status = ? AND lease_expires_at <= ?) \
+            ORDER BY run_at, id LIMIT 1 FOR UPDATE SKIP LOCKED",
+        )
+        .bind(TaskStatus::Pending.to_string())
+        .bind(now)
+        .bind(TaskStatus::Running.to_string())
+        .bind(now)
+        .fetch_optional(&mut *tx)
+        .await?;
+        let Some(row) = row else {
+            return Ok(None);
+        };
+        let id: i64 = row.get("id");
+
+        sqlx::query(
+            "UPDATE tasks SET status = ?, worker_id = ?, lease_expires_at = ?, \
+                attempts = attempts + 1, updated_at = ? WHERE id = ?",
+        )
+        .bind(TaskStatus::Running.to_string())
+        .bind(worker_id)
+        .bind(lease_expiry(lease))
+        .bind(now)
+        .bind(id)
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        self.get_task(id).await
+    }
+
+    async fn renew_task_lease(
+        &self,
+        id: i64,
+        worker_id: &str,
+        lease: Duration,
+    ) -> Result<bool, sqlx::Error> {
+        // sqlx connects with CLIENT_FOUND_ROWS, rows are counted even when nothing changed
+        let result = sqlx::query(
+            "UPDATE tasks SET lease_expires_at = ?, updated_at = ? \
+            WHERE id = ? AND worker_id = ? AND status = ?",
+        )
+        .bind(lease_expiry(lease))
+        .bind(now())
+        .bind(id)
+        .bind(worker_id)
+        .bind(TaskStatus::Running.to_string())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() == 1)
+    }
+
+    async fn complete_task(
+        &self,
+        id: i64,
+        worker_id: &str,
+        result: &serde_json::Value,
+    ) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query(
+            "UPDATE tasks SET status = ?, result = ?, lease_expires_at = NULL, updated_at = ? \
+            WHERE id = ? AND worker_id = ? AND status = ?",
+        )
+        .bind(TaskStatus::Completed.to_string())
+        .bind(result.to_string())
+        .bind(now())
+        .bind(id)
+        .bind(worker_id)
+        .bind(TaskStatus::Running.to_string())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() == 1)
+    }
+
+    async fn fail_task(
+        &self,
+        id: i64,
+        worker_id: &str,
+        error: &str,
+        retry_at: Option<DateTime<Utc>>,
+    ) -> Result<bool, sqlx::Error> {
+        let status = match retry_at {
+            Some(_) => TaskStatus::Pending,
+            None => TaskStatus::Dead,
+        };
+        let result = sqlx::query(
+            "UPDATE tasks SET status = ?, run_at = COALESCE(?, run_at), error = ?, \
+                lease_expires_at = NULL, updated_at = ? \
+            WHERE id = ? AND worker_id = ? AND status = ?",
+        )
+        .bind(status.to_string())
+        .bind(retry_at.map(|retry_at| retry_at.naive_utc()))
+        .bind(error)
+        .bind(now())
+        .bind(id)
+        .bind(worker_id)
+        .bind(TaskStatus::Running.to_string())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() == 1)
+    }
+}
+
+async fn migrate(conn: &mut MySqlConnection) -> Result<MigrationReport, MigrationError> {
+    // a prompts table without a migrations table was set up by init_database before migrations
+    let unversioned =
+        !table_exists(conn, "schema_migrations").await? && table_exists(conn, "prompts").await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS schema_migrations (
+            version BIGINT PRIMARY KEY,
+            name VARCHAR(255) NOT NULL,
+            applied_at DATETIME NOT NULL
+        );
+        "#,
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    let applied_versions: Vec<i64> = sqlx::query("SELECT version FROM schema_migrations")
+        .fetch_all(&mut *conn)
+        .await?
+        .iter()
+        .map(|row| row.get("version"))
+        .collect();
+
+    let (mut report, pending) = pending_migrations(MYSQL_MIGRATIONS, &applied_versions)?;
+    for migration in pending {
+        let adopt = match &migration.marker {
+            Some(marker) if unversioned => marker_exists(conn, marker).await?,
+            _ => false,
+        };
+        if adopt {
+            report.adopted.push(migration.name);
+        } else {
+            for statement in statements(migration.sql) {
+                sqlx::query(&statement)
+                    .execute(&mut *conn)
+                    .await
+                    .map_err(|error| MigrationError::Failed {
+                        name: migration.name,
+                        error,
+                    })?;
+            }
+            report.applied.push(migration.name);
+        }
+
+        sqlx::query("INSERT INTO schema_migrations (version, name, applied_at) VALUES (?, ?, ?)")
+            .bind(migration.version)
+            .bind(migration.name)
+            .bind(now())
+            .execute(&mut *conn)
+            .await?;
+        report.version = report.version.max(migration.version);
+    }
+
+    Ok(report)
+}
+
+async fn marker_exists(conn: &mut MySqlConnection, marker: &Marker) -> Result<bool, sqlx::Error> {
+    match marker {
+        Marker::Table(table) => table_exists(conn, table).await,
+        Marker::Column(table, column) => column_exists(conn, table, column).await,
+        Marker::Index(table, index) => index_exists(conn, table, index).await,
+    }
+}
+
+async fn table_exists(conn: &mut MySqlConnection, table: &str) -> Result<bool, sqlx::Error> {
+    let count: i64 = sqlx::query(
+        "SELECT COUNT(*) AS count FROM information_schema.tables WHERE table_schema = DATABASE() AND table_name = ?",
+    )
+    .bind(table)
+    .fetch_one(conn)
+    .await?
+    .get("count");
+
+    Ok(count > 0)
+}
+
+async fn column_exists(
+    conn: &mut MySqlConnection,
+    table: &str,
+    column: &str,
+) -> Result<bool, sqlx::Error> {
+    let count: i64 = sqlx::query(
+        "SELECT COUNT(*) AS count FROM information_schema.columns WHERE table_schema = DATABASE() AND table_name = ? AND column_name = ?",
+    )
+    .bind(table)
+    .bind(column)
+    .fetch_one(conn)
+    .await?
+    .get("count");
+
+    Ok(count > 0)
+}
+
+async fn index_exists(
+    conn: &mut MySqlConnection,
+    table: &str,
+    index: &str,
+) -> Result<bool, sqlx::Error> {
+    let count: i64 = sqlx::query(
+        "SELECT COUNT(*) AS count FROM information_schema.statistics WHERE table_schema = DATABASE() AND table_name = ? AND index_name = ?",
+    )
+    .bind(table)
+    .bind(index)
+    .fetch_one(conn)
+    .await?
+    .get("count");
+
+    Ok(count > 0)
+}
+
+fn prompt_from_row(row: &MySqlRow) -> Prompt {
+    prompt_from_row!(row, row.get("max_tokens"))
+}
+
+fn template_from_row(row: &MySqlRow) -> PromptTemplate {
+    template_from_row!(row)
+}
+
+fn conversation_from_row(row: &MySqlRow) -> Conversation {
+    conversation_from_row!(row)
+}
+
+fn cached_response_from_row(row: &MySqlRow) -> CachedResponse {
+    cached_response_from_row!(row)
+}
+
+fn task_from_row(row: &MySqlRow) -> Task {
+    task_from_row!(row)
+}
+
+fn usage_from_row(row: &MySqlRow) -> UsageSummary {
+    UsageSummary {
+        key: row.get("group_key"),
+        prompts: row.get("prompts"),
+        input_tokens: row.get("input_tokens"),
+        output_tokens: row.get("output_tokens"),
+        cached_tokens: row.get("cached_tokens"),
+        cost: row.get("cost"),
+        unpriced_prompts: row.get("unpriced_prompts"),
+    }
+}
+
+fn model_from_row(row: &MySqlRow) -> ModelInfo {
+    ModelInfo {
+        id: row.get("model_id"),
+        display_name: row.get("display_name"),
+        created_at: row
+            .get::<Option<NaiveDateTime>, _>("created_at")
+            .map(|created_at| created_at.and_utc()),
+    }
+}
@@ -0,0 +1,766 @@
+use crate::cache::CachedResponse;
+use crate::migrations::{
+    pending_migrations, statements, MigrationError, MigrationReport, MIGRATION_LOCK,
+    MIGRATION_LOCK_TIMEOUT_SECS, POSTGRES_MIGRATIONS,
+};
+use crate::models::{
+    Conversation, ModelCatalogEntry, ModelInfo, NewPrompt, Prompt, PromptFilter, PromptPage,
+    UsageGroupBy, UsageSummary,
+};
+use crate::search::{PromptSearch, SearchHit};
+use crate::store::{
+    lease_expiry, now, prompt_page, PromptJson, PromptStore, CACHED_RESPONSE_COLUMNS,
+    CONVERSATION_COLUMNS, PROMPT_COLUMNS, TASK_COLUMNS, TEMPLATE_COLUMNS,
+};
+use crate::task::{NewTask, Task, TaskStatus};
+use crate::template::{NewPromptTemplate, PromptTemplate};
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, NaiveDateTime, Utc};
+use sqlx::{
+    postgres::{PgConnection, PgPool, PgPoolOptions, PgRow},
+    Row,
+};
+use std::time::Duration;
+
+/// Prompts stored in PostgreSQL.
+/// Postgres has no unsigned integers so token counts and max_tokens are stored signed.
+pub struct PostgresStore {
+    pool: PgPool,
+}
+
+impl PostgresStore {
+    pub async fn connect(url: &str, max_connections: u32) -> Result<Self> {
+        let pool = PgPoolOptions::new()
+            .max_connections(max_connections)
+            .connect(url)
+            .await
+            .context("Failed to connect to PostgreSQL database")?;
+
+        Ok(Self { pool })
+    }
+}
+
+#[async_trait]
+impl PromptStore for PostgresStore {
+    async fn migrate(&self) -> Result<MigrationReport, MigrationError> {
+        // advisory locks belong to the session, everything runs on the connection that took it
+        let mut conn = self.pool.acquire().await?;
+        let mut waited_secs = 0;
+        loop {
+            let locked: bool = sqlx::query("SELECT pg_try_advisory_lock(hashtext($1)) AS locked")
+                .bind(MIGRATION_LOCK)
+                .fetch_one(&mut *conn)
+                .await?
+                .get("locked");
+            if locked {
+                break;
+            }
+            if waited_secs >= MIGRATION_LOCK_TIMEOUT_SECS {
+                return Err(MigrationError::Locked);
+            }
+            tokio::time::sleep(Duration::from_secs(1)).await;
+            waited_secs += 1;
+        }
+
+        let result = migrate(&mut conn).await;
+
+        sqlx::query("SELECT pg_advisory_unlock(hashtext($1))")
+            .bind(MIGRATION_LOCK)
+            .execute(&mut *conn)
+            .await?;
+        result
+    }
+
+    async fn ping(&self) -> Result<(), sqlx::Error> {
+        sqlx::query("SELECT 1").execute(&self.pool).await?;
+        Ok(())
+    }
+
+    async fn create_prompt_record(&self, new_prompt: &NewPrompt) -> Result<Prompt, sqlx::Error> {
+        let now = now();
+
+        // the sequence is read and the prompt inserted in one transaction
+        let mut tx = self.pool.begin().await?;
+
+        let sequence: Option<i32> = match new_prompt.conversation_id {
+            Some(conversation_id) => {
+                // updating the conversation first locks its row until the prompt is inserted, so
+                // concurrent turns of a conversation take their sequence one after the other
+                sqlx::query("UPDATE conversations SET updated_at = $1 WHERE id = $2")
+                    .bind(now)
+                    .bind(conversation_id)
+                    .execute(&mut *tx)
+                    .await?;
+
+                let row = sqlx::query(
+                    "SELECT COALESCE(MAX(sequence), 0) + 1 AS next_sequence FROM prompts WHERE conversation_id = $1",
+                )
+                .bind(conversation_id)
+                .fetch_one(&mut *tx)
+                .await?;
+                let next_sequence: i32 = row.get("next_sequence");
+
+                Some(next_sequence)
+            }
+            None => None,
+        };
+
+        let usage = &new_prompt.usage;
+        let params = &new_prompt.params;
+        let json = PromptJson::new(new_prompt);
+        let signed = |count: Option<u32>| count.map(|count| count as i32);
+        let row = sqlx::query(
+            "INSERT INTO prompts (prompt, response, model, provider, conversation_id, sequence, input_tokens, output_tokens, reasoning_tokens, cached_tokens, latency_ms, finish_reason, cost, user_id, attempts, cache_hit, route, attempted_targets, requested_model, system_prompt, temperature, top_p, max_tokens, stop_sequences, seed, template_id, comparison_id, comparison_index, tags, created_at) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18, $19, $20, $21, $22, $23, $24, $25, $26, $27, $28, $29, $30) RETURNING id",
+        )
+        .bind(&new_prompt.prompt)
+        .bind(&new_prompt.response)
+        .bind(&new_prompt.model)
+        .bind(&new_prompt.provider)
+        .bind(new_prompt.conversation_id)
+        .bind(sequence)
+        .bind(signed(usage.input_tokens))
+        .bind(signed(usage.output_tokens))
+        .bind(signed(usage.reasoning_tokens))
+        .bind(signed(usage.cached_tokens))
+        .bind(new_prompt.latency_ms)
+        .bind(&new_prompt.finish_reason)
+        .bind(new_prompt.cost)
+        .bind(&new_prompt.user_id)
+        .bind(new_prompt.attempts as i32)
+        .bind(new_prompt.cache_hit)
+        .bind(&new_prompt.route)
+        .bind(json.attempted_targets)
+        .bind(&new_prompt.requested_model)
+        .bind(&params.system)
+        .bind(params.temperature)
+        .bind(params.top_p)
+        .bind(params.max_tokens.map(i64::from))
+        .bind(json.stop_sequences)
+        .bind(params.seed)
+        .bind(new_prompt.template_id)
+        .bind(&new_prompt.comparison_id)
+        .bind(new_prompt.comparison_index)
+        .bind(json.tags)
+        .bind(now)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        self.get_prompt_by_id(row.get("id"))
+            .await?
+            .ok_or(sqlx::Error::RowNotFound)
+    }
+
+    async fn get_prompt_by_id(&self, id: i64) -> Result<Option<Prompt>, sqlx::Error> {
+        let row = sqlx::query(&format!(
+            "SELECT {} FROM prompts WHERE id = $1",
+            PROMPT_COLUMNS
+        ))
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row.as_ref().map(prompt_from_row))
+    }
+
+    async fn get_prompts(&self, filter: &PromptFilter) -> Result<PromptPage, sqlx::Error> {
+        let from = filter.from.map(|from| from.naive_utc());
+        let to = filter.to.map(|to| to.naive_utc());
+        let after_created_at = filter.after.map(|after| after.created_at.naive_utc());
+        let after_id = filter.after.map(|after| after.id);
+
+        // one extra row is fetched to tell whether there is another page
+        let rows = sqlx::query(&format!(
+            "SELECT {} FROM prompts \
+            WHERE ($1::text IS NULL OR LOWER(provider) = LOWER($1)) AND ($2::text IS NULL OR model = $2) \
+                AND ($3::timestamp IS NULL OR created_at >= $3) \
+                AND ($4::timestamp IS NULL OR created_at < $4) \
+                AND ($5::bigint IS NULL OR conversation_id = $5) \
+                AND ($6::text IS NULL OR tags::jsonb @> jsonb_build_array($6::text)) \
+                AND ($7::timestamp IS NULL OR created_at < $7 OR (created_at = $7 AND id < $8)) \
+            ORDER BY created_at DESC, id DESC LIMIT $9",
+            PROMPT_COLUMNS
+        ))
+        .bind(&filter.provider)
+        .bind(&filter.model)
+        .bind(from)
+        .bind(to)
+        .bind(filter.conversation_id)
+        .bind(&filter.tag)
+        .bind(after_created_at)
+        .bind(after_id)
+        .bind(filter.limit as i64 + 1)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(prompt_page(
+            rows.iter().map(prompt_from_row).collect(),
+            filter.limit,
+        ))
+    }
+
+    async fn search_prompts(&self, search: &PromptSearch) -> Result<Vec<SearchHit>, sqlx::Error> {
+        // the tsvector expression matches the ft_prompts_text index, any of the terms is a match
+        let rows = sqlx::query(&format!(
+            "SELECT {}, ts_rank(to_tsvector('english', prompt || ' ' || response), search_query)::float8 AS score \
+            FROM prompts, websearch_to_tsquery('english', $1) search_query \
+            WHERE to_tsvector('english', prompt || ' ' || response) @@ search_query \
+            ORDER BY score DESC, created_at DESC, id DESC LIMIT $2",
+            PROMPT_COLUMNS
+        ))
+        .bind(search.terms.join(" or "))
+        .bind(i64::from(search.limit))
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows
+            .iter()
+            .map(|row| SearchHit::new(prompt_from_row(row), row.get("score"), &search.terms))
+            .collect())
+    }
+
+    async fn get_usage(
+        &self,
+        group_by: UsageGroupBy,
+        from: Option<DateTime<Utc>>,
+        to: Option<DateTime<Utc>>,
+    ) -> Result<Vec<UsageSummary>, sqlx::Error> {
+        let (group_key, order_by) = match group_by {
+            UsageGroupBy::Provider => ("provider", "cost DESC, group_key"),
+            UsageGroupBy::Model => ("model", "cost DESC, group_key"),
+            UsageGroupBy::Day => ("to_char(created_at, 'YYYY-MM-DD')", "group_key"),
+        };
+
+        let from = from.map(|from| from.naive_utc());
+        let to = to.map(|to| to.naive_utc());
+
+        let rows = sqlx::query(&format!(
+            "SELECT {} AS group_key, COUNT(*) AS prompts, \
+                COALESCE(SUM(input_tokens), 0)::bigint AS input_tokens, \
+                COALESCE(SUM(output_tokens), 0)::bigint AS output_tokens, \
+                COALESCE(SUM(cached_tokens), 0)::bigint AS cached_tokens, \
+                COALESCE(SUM(cost), 0)::float8 AS cost, \
+                COUNT(*) - COUNT(cost) AS unpriced_prompts \
+            FROM prompts \
+            WHERE ($1::timestamp IS NULL OR created_at >= $1) \
+                AND ($2::timestamp IS NULL OR created_at < $2) \
+            GROUP BY group_key ORDER BY {}",
+            group_key, order_by
+        ))
+        .bind(from)
+        .bind(to)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.iter().map(usage_from_row).collect())
+    }
+
+    async fn get_spend(
+        &self,
+        since: DateTime<Utc>,
+        provider: Option<&str>,
+        user_id: Option<&str>,
+    ) -> Result<f64, sqlx::Error> {
+        let row = sqlx::query(
+            "SELECT COALESCE(SUM(cost), 0)::float8 AS spent FROM prompts \
+            WHERE created_at >= $1 AND ($2::text IS NULL OR LOWER(provider) = LOWER($2)) \
+                AND ($3::text IS NULL OR user_id = $3)",
+        )
+        .bind(since.naive_utc())
+        .bind(provider)
+        .bind(user_id)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(row.get("spent"))
+    }
+
+    async fn get_model_catalog(
+        &self,
+        provider: &str,
+    ) -> Result<Option<ModelCatalogEntry>, sqlx::Error> {
+        let rows = sqlx::query(
+            "SELECT model_id, display_name, created_at, fetched_at FROM model_catalog \
+            WHERE provider = $1 ORDER BY model_id",
+        )
+        .bind(provider)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let Some(first) = rows.first() else {
+            return Ok(None);
+        };
+        let fetched_at: NaiveDateTime = first.get("fetched_at");
+
+        Ok(Some(ModelCatalogEntry {
+            provider: provider.to_string(),
+            models: rows.iter().map(model_from_row).collect(),
+            fetched_at: fetched_at.and_utc(),
+        }))
+    }
+
+    async fn save_model_catalog(&self, entry: &ModelCatalogEntry) -> Result<(), sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query("DELETE FROM model_catalog WHERE provider = $1")
+            .bind(&entry.provider)
+            .execute(&mut *tx)
+            .await?;
+
+        for model in &entry.models {
+            sqlx::query(
+                "INSERT INTO model_catalog (provider, model_id, display_name, created_at, fetched_at) \
+                VALUES ($1, $2, $3, $4, $5)",
+            )
+            .bind(&entry.provider)
+            .bind(&model.id)
+            .bind(&model.display_name)
+            .bind(model.created_at.map(|created_at| created_at.naive_utc()))
+            .bind(entry.fetched_at.naive_utc())
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await
+    }
+
+    async fn get_cached_response(&self, key: &str) -> Result<Option<CachedResponse>, sqlx::Error> {
+        let row = sqlx::query(&format!(
+            "SELECT {} FROM response_cache WHERE cache_key = $1",
+            CACHED_RESPONSE_COLUMNS
+        ))
+        .bind(key)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row.as_ref().map(cached_response_from_row))
+    }
+
+    async fn save_cached_response(&self, response: &CachedResponse) -> Result<(), sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query("DELETE FROM response_cache WHERE cache_key = $1")
+            .bind(&response.key)
+            .execute(&mut *tx)
+            .await?;
+
+        let usage = &response.usage;
+        sqlx::query(&format!(
+            "INSERT INTO response_cache ({}) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)",
+            CACHED_RESPONSE_COLUMNS
+        ))
+        .bind(&response.key)
+        .bind(&response.provider)
+        .bind(&response.model)
+        .bind(&response.response)
+        .bind(usage.input_tokens.map(|n| n as i32))
+        .bind(usage.output_tokens.map(|n| n as i32))
+        .bind(usage.reasoning_tokens.map(|n| n as i32))
+        .bind(usage.cached_tokens.map(|n| n as i32))
+        .bind(&response.finish_reason)
+        .bind(response.created_at.naive_utc())
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await
+    }
+
+    async fn create_conversation(&self, title: &str) -> Result<Conversation, sqlx::Error> {
+        let now = now();
+
+        let row = sqlx::query(
+            "INSERT INTO conversations (title, created_at, updated_at) VALUES ($1, $2, $3) RETURNING id",
+        )
+        .bind(title)
+        .bind(now)
+        .bind(now)
+        .fetch_one(&self.pool)
+        .await?;
+
+        self.get_conversation(row.get("id"))
+            .await?
+            .ok_or(sqlx::Error::RowNotFound)
+    }
+
+    async fn get_conversation(&self, id: i64) -> Result<Option<Conversation>, sqlx::Error> {
+        let row = sqlx::query(&format!(
+            "SELECT {} FROM conversations WHERE id = $1",
+            CONVERSATION_COLUMNS
+        ))
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row.as_ref().map(conversation_from_row))
+    }
+
+    async fn get_all_conversations(&self) -> Result<Vec<Conversation>, sqlx::Error> {
+        let rows = sqlx::query(&format!(
+            "SELECT {} FROM conversations ORDER BY updated_at DESC, id DESC",
+            CONVERSATION_COLUMNS
+        ))
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.iter().map(conversation_from_row).collect())
+    }
+
+    async fn get_conversation_prompts(
+        &self,
+        conversation_id: i64,
+    ) -> Result<Vec<Prompt>, sqlx::Error> {
+        let rows = sqlx::query(&format!(
+            "SELECT {} FROM prompts WHERE conversation_id = $1 ORDER BY sequence ASC",
+            PROMPT_COLUMNS
+        ))
+        .bind(conversation_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.iter().map(prompt_from_row).collect())
+    }
+
+    async fn get_comparison_prompts(
+        &self,
+        comparison_id: &str,
+    ) -> Result<Vec<Prompt>, sqlx::Error> {
+        let rows = sqlx::query(&format!(
+            "SELECT {} FROM prompts WHERE comparison_id = $1 ORDER BY comparison_index ASC, id ASC",
+            PROMPT_COLUMNS
+        ))
+        .bind(comparison_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.iter().map(prompt_from_row).collect())
+    }
+
+    async fn create_prompt_template(
+        &self,
+        new_template: &NewPromptTemplate,
+    ) -> Result<PromptTemplate, sqlx::Error> {
+        let now = now();
+        let variables = serde_json::to_string(&new_template.variables).unwrap_or_default();
+
+        let mut tx = self.pool.begin().await?;
+
+        // FOR UPDATE can't be used with MAX, the name is locked instead until the transaction ends
+        sqlx::query("SELECT pg_advisory_xact_lock(hashtext($1))")
+            .bind(&new_template.name)
+            .execute(&mut *tx)
+            .await?;
+
+        let row = sqlx::query(
+            "SELECT COALESCE(MAX(version), 0) + 1 AS next_version FROM prompt_templates WHERE name = $1",
+        )
+        .bind(&new_template.name)
+        .fetch_one(&mut *tx)
+        .await?;
+        let next_version: i32 = row.get("next_version");
+
+        let row = sqlx::query(
+            "INSERT INTO prompt_templates (name, version, template, variables, provider, model, created_at) VALUES ($1, $2, $3, $4, $5, $6, $7) RETURNING id",
+        )
+        .bind(&new_template.name)
+        .bind(next_version)
+        .bind(&new_template.template)
+        .bind(variables)
+        .bind(&new_template.provider)
+        .bind(&new_template.model)
+        .bind(now)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        self.get_prompt_template(row.get("id"))
+            .await?
+            .ok_or(sqlx::Error::RowNotFound)
+    }
+
+    async fn get_prompt_template(&self, id: i64) -> Result<Option<PromptTemplate>, sqlx::Error> {
+        let row = sqlx::query(&format!(
+            "SELECT {} FROM prompt_templates WHERE id = $1",
+            TEMPLATE_COLUMNS
+        ))
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row.as_ref().map(template_from_row))
+    }
+
+    async fn get_prompt_template_by_name(
+        &self,
+        name: &str,
+    ) -> Result<Option<PromptTemplate>, sqlx::Error> {
+        let row = sqlx::query(&format!(
+            "SELECT {} FROM prompt_templates WHERE name = $1 ORDER BY version DESC LIMIT 1",
+            TEMPLATE_COLUMNS
+        ))
+        .bind(name)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row.as_ref().map(template_from_row))
+    }
+
+    async fn get_prompt_templates(&self) -> Result<Vec<PromptTemplate>, sqlx::Error> {
+        let rows = sqlx::query(&format!(
+            "SELECT {} FROM prompt_templates t \
+            WHERE version = (SELECT MAX(version) FROM prompt_templates WHERE name = t.name) \
+            ORDER BY name",
+            TEMPLATE_COLUMNS
+        ))
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.iter().map(template_from_row).collect())
+    }
+
+    async fn enqueue_task(&self, new_task: &NewTask) -> Result<Task, sqlx::Error> {
+        let now = now();
+
+        let row = sqlx::query(
+            "INSERT INTO tasks (task_type, payload, status, attempts, max_attempts, run_at, created_at, updated_at) \
+            VALUES ($1, $2, $3, 0, $4, $5, $6, $7) RETURNING id",
+        )
+        .bind(&new_task.task_type)
+        .bind(new_task.payload.to_string())
+        .bind(TaskStatus::Pending.to_string())
+        .bind(new_task.max_attempts)
+        .bind(new_task.run_at.naive_utc())
+        .bind(now)
+        .bind(now)
+        .fetch_one(&self.pool)
+        .await?;
+
+        self.get_task(row.get("id"))
+            .await?
+            .ok_or(sqlx::Error::RowNotFound)
+    }
+
+    async fn get_task(&self, id: i64) -> Result<Option<Task>, sqlx::Error> {
+        let row = sqlx::query(&format!("SELECT {} FROM tasks WHERE id = $1", TASK_COLUMNS))
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(row.as_ref().map(task_from_row))
+    }
+
+    async fn get_tasks(
+        &self,
+        status: Option<TaskStatus>,
+        limit: u32,
+    ) -> Result<Vec<Task>, sqlx::Error> {
+        let rows = sqlx::query(&format!(
+            "SELECT {} FROM tasks WHERE ($1::text IS NULL OR status = $1) ORDER BY id DESC LIMIT $2",
+            TASK_COLUMNS
+        ))
+        .bind(status.map(|status| status.to_string()))
+        .bind(i64::from(limit))
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.iter().map(task_from_row).collect())
+    }
+
+    async fn claim_task(
+        &self,
+        worker_id: &str,
+        lease: Duration,
+    ) -> Result<Option<Task>, sqlx::Error> {
+        let now = now();
+
+        // SKIP LOCKED passes over a task another worker is claiming instead of waiting for it,
+        // so every worker claims a different task
+        let row = sqlx::query(
+            "UPDATE tasks SET status = $1, worker_id = $2, lease_expires_at = $3, \
+                attempts = attempts + 1, updated_at = $4 \
+            WHERE id = ( \
+                SELECT id FROM tasks \
+                WHERE (status = $5 AND run_at <= $4) OR (status = $1 AND lease_expires_at <= $4) \
+                ORDER BY run_at, id LIMIT 1 FOR UPDATE SKIP LOCKED \
+            ) RETURNING id",
+        )
+        .bind(TaskStatus::Running.to_string())
+        .bind(worker_id)
+        .bind(lease_expiry(lease))
+        .bind(now)
+        .bind(TaskStatus::Pending.to_string())
+        .fetch_optional(&self.pool)
+        .await?;
+
+        match row {
+            Some(row) => self.get_task(row.get("id")).await,
+            None => Ok(None),
+        }
+    }
+
+    async fn renew_task_lease(
+        &self,
+        id: i64,
+        worker_id: &str,
+        lease: Duration,
+    ) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query(
+            "UPDATE tasks SET lease_expires_at = $1, updated_at = $2 \
+            WHERE id = $3 AND worker_id = $4 AND status = $5",
+        )
+        .bind(lease_expiry(lease))
+        .bind(now())
+        .bind(id)
+        .bind(worker_id)
+        .bind(TaskStatus::Running.to_string())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() == 1)
+    }
+
+    async fn complete_task(
+        &self,
+        id: i64,
+        worker_id: &str,
+        result: &serde_json::Value,
+    ) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query(
+            "UPDATE tasks SET status = $1, result = $2, lease_expires_at = NULL, updated_at = $3 \
+            WHERE id = $4 AND worker_id = $5 AND status = $6",
+        )
+        .bind(TaskStatus::Completed.to_string())
+        .bind(result.to_string())
+        .bind(now())
+        .bind(id)
+        .bind(worker_id)
+        .bind(TaskStatus::Running.to_string())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() == 1)
+    }
+
+    async fn fail_task(
+        &self,
+        id: i64,
+        worker_id: &str,
+        error: &str,
+        retry_at: Option<DateTime<Utc>>,
+    ) -> Result<bool, sqlx::Error> {
+        let status = match retry_at {
+            Some(_) => TaskStatus::Pending,
+            None => TaskStatus::Dead,
+        };
+        let result = sqlx::query(
+            "UPDATE tasks SET status = $1, run_at = COALESCE($2, run_at), error = $3, \
+                lease_expires_at = NULL, updated_at = $4 \
+            WHERE id = $5 AND worker_id = $6 AND status = $7",
+        )
+        .bind(status.to_string())
+        .bind(retry_at.map(|retry_at| retry_at.naive_utc()))
+        .bind(error)
+        .bind(now())
+        .bind(id)
+        .bind(worker_id)
+        .bind(TaskStatus::Running.to_string())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() == 1)
+    }
+}
+
+async fn migrate(conn: &mut PgConnection) -> Result<MigrationReport, MigrationError> {
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS schema_migrations (
+            version BIGINT PRIMARY KEY,
+            name VARCHAR(255) NOT NULL,
+            applied_at TIMESTAMP NOT NULL
+        )",
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    let applied_versions: Vec<i64> = sqlx::query("SELECT version FROM schema_migrations")
+        .fetch_all(&mut *conn)
+        .await?
+        .iter()
+        .map(|row| row.get("version"))
+        .collect();
+
+    let (mut report, pending) = pending_migrations(POSTGRES_MIGRATIONS, &applied_versions)?;
+    for migration in pending {
+        // postgres schema changes are transactional, a failed migration leaves nothing behind
+        let mut tx = sqlx::Connection::begin(&mut *conn).await?;
+        for statement in statements(migration.sql) {
+            sqlx::query(&statement)
+                .execute(&mut *tx)
+                .await
+                .map_err(|error| MigrationError::Failed {
+                    name: migration.name,
+                    error,
+                })?;
+        }
+
+        sqlx::query(
+            "INSERT INTO schema_migrations (version, name, applied_at) VALUES ($1, $2, $3)",
+        )
+        .bind(migration.version)
+        .bind(migration.name)
+        .bind(now())
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        report.applied.push(migration.name);
+        report.version = report.version.max(migration.version);
+    }
+
+    Ok(report)
+}
+
+fn prompt_from_row(row: &PgRow) -> Prompt {
+    let max_tokens: Option<i64> = row.get("max_tokens");
+    prompt_from_row!(row, max_tokens.map(|max_tokens| max_tokens as u32))
+}
+
+fn template_from_row(row: &PgRow) -> PromptTemplate {
+    template_from_row!(row)
+}
+
+fn conversation_from_row(row: &PgRow) -> Conversation {
+    conversation_from_row!(row)
+}
+
+fn cached_response_from_row(row: &PgRow) -> CachedResponse {
+    cached_response_from_row!(row)
+}
+
+fn task_from_row(row: &PgRow) -> Task {
+    task_from_row!(row)
+}
+
+fn usage_from_row(row: &PgRow) -> UsageSummary {
+    UsageSummary {
+        key: row.get("group_key"),
+        prompts: row.get("prompts"),
+        input_tokens: row.get("input_tokens"),
+        output_tokens: row.get("output_tokens"),
+        cached_tokens: row.get("cached_tokens"),
+        cost: row.get("cost"),
+        unpriced_prompts: row.get("unpriced_prompts"),
+    }
+}
+
+fn model_from_row(row: &PgRow) -> ModelInfo {
+    ModelInfo {
+        id: row.get("model_id"),
+        display_name: row.get("display_name"),
+        created_at: row
+            .get::<Option<NaiveDateTime>, _>("created_at")
+            .map(|created_at| created_at.and_utc()),
+    }
+}
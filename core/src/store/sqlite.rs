@@ -0,0 +1,782 @@
+use crate::cache::CachedResponse;
+use crate::migrations::{pending_migrations, MigrationError, MigrationReport, SQLITE_MIGRATIONS};
+use crate::models::{
+    Conversation, ModelCatalogEntry, ModelInfo, NewPrompt, Prompt, PromptFilter, PromptPage,
+    UsageGroupBy, UsageSummary,
+};
+use crate::search::{PromptSearch, SearchHit};
+use crate::store::{
+    lease_expiry, now, prompt_page, PromptJson, PromptStore, CACHED_RESPONSE_COLUMNS,
+    CONVERSATION_COLUMNS, PROMPT_COLUMNS, TASK_COLUMNS, TEMPLATE_COLUMNS,
+};
+use crate::task::{NewTask, Task, TaskStatus};
+use crate::template::{NewPromptTemplate, PromptTemplate};
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, NaiveDateTime, Utc};
+use sqlx::{
+    sqlite::{SqliteConnectOptions, SqliteConnection, SqlitePool, SqlitePoolOptions, SqliteRow},
+    Executor, Row,
+};
+use std::str::FromStr;
+use std::time::Duration;
+
+/// Prompts stored in a SQLite file, or in memory with `sqlite::memory:`, so nothing
+/// has to be running to use the CLI or run the tests
+pub struct SqliteStore {
+    pool: SqlitePool,
+}
+
+impl SqliteStore {
+    pub async fn connect(url: &str, max_connections: u32) -> Result<Self> {
+        let options = SqliteConnectOptions::from_str(url)
+            .context("Invalid SQLite database url")?
+            .create_if_missing(true);
+
+        // an in-memory database only lives as long as a connection to it, so exactly one
+        // connection is kept open for good
+        let in_memory = url.contains(":memory:") || url.contains("mode=memory");
+        let pool_options = if in_memory {
+            SqlitePoolOptions::new()
+                .max_connections(1)
+                .min_connections(1)
+                .idle_timeout(None)
+                .max_lifetime(None)
+        } else {
+            SqlitePoolOptions::new().max_connections(max_connections)
+        };
+        let pool = pool_options
+            .connect_with(options)
+            .await
+            .context("Failed to open SQLite database")?;
+
+        Ok(Self { pool })
+    }
+}
+
+#[async_trait]
+impl PromptStore for SqliteStore {
+    async fn migrate(&self) -> Result<MigrationReport, MigrationError> {
+        // an immediate transaction takes the write lock up front, so a second process
+        // migrating the same file waits for this one and then finds nothing left to do
+        let mut conn = self.pool.acquire().await?;
+        sqlx::query("BEGIN IMMEDIATE").execute(&mut *conn).await?;
+
+        match migrate(&mut conn).await {
+            Ok(report) => {
+                sqlx::query("COMMIT").execute(&mut *conn).await?;
+                Ok(report)
+            }
+            Err(error) => {
+                sqlx::query("ROLLBACK").execute(&mut *conn).await?;
+                Err(error)
+            }
+        }
+    }
+
+    async fn ping(&self) -> Result<(), sqlx::Error> {
+        sqlx::query("SELECT 1").execute(&self.pool).await?;
+        Ok(())
+    }
+
+    async fn create_prompt_record(&self, new_prompt: &NewPrompt) -> Result<Prompt, sqlx::Error> {
+        let now = now();
+
+        // the sequence is read and the prompt inserted in one transaction
+        let mut tx = self.pool.begin().await?;
+
+        let sequence: Option<i32> = match new_prompt.conversation_id {
+            Some(conversation_id) => {
+                // writing first takes the database's write lock, so concurrent turns of a
+                // conversation take their sequence one after the other
+                sqlx::query("UPDATE conversations SET updated_at = ? WHERE id = ?")
+                    .bind(now)
+                    .bind(conversation_id)
+                    .execute(&mut *tx)
+                    .await?;
+
+                let row = sqlx::query(
+                    "SELECT COALESCE(MAX(sequence), 0) + 1 AS next_sequence FROM prompts WHERE conversation_id = ?",
+                )
+                .bind(conversation_id)
+                .fetch_one(&mut *tx)
+                .await?;
+                let next_sequence: i64 = row.get("next_sequence");
+
+                Some(next_sequence as i32)
+            }
+            None => None,
+        };
+
+        let usage = &new_prompt.usage;
+        let params = &new_prompt.params;
+        let json = PromptJson::new(new_prompt);
+        let insert_result = sqlx::query(
+            "INSERT INTO prompts (prompt, response, model, provider, conversation_id, sequence, input_tokens, output_tokens, reasoning_tokens, cached_tokens, latency_ms, finish_reason, cost, user_id, attempts, cache_hit, route, attempted_targets, requested_model, system_prompt, temperature, top_p, max_tokens, stop_sequences, seed, template_id, comparison_id, comparison_index, tags, created_at) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+        )
+        .bind(&new_prompt.prompt)
+        .bind(&new_prompt.response)
+        .bind(&new_prompt.model)
+        .bind(&new_prompt.provider)
+        .bind(new_prompt.conversation_id)
+        .bind(sequence)
+        .bind(usage.input_tokens)
+        .bind(usage.output_tokens)
+        .bind(usage.reasoning_tokens)
+        .bind(usage.cached_tokens)
+        .bind(new_prompt.latency_ms)
+        .bind(&new_prompt.finish_reason)
+        .bind(new_prompt.cost)
+        .bind(&new_prompt.user_id)
+        .bind(new_prompt.attempts)
+        .bind(new_prompt.cache_hit)
+        .bind(&new_prompt.route)
+        .bind(json.attempted_targets)
+        .bind(&new_prompt.requested_model)
+        .bind(&params.system)
+        .bind(params.temperature)
+        .bind(params.top_p)
+        .bind(params.max_tokens)
+        .bind(json.stop_sequences)
+        .bind(params.seed)
+        .bind(new_prompt.template_id)
+        .bind(&new_prompt.comparison_id)
+        .bind(new_prompt.comparison_index)
+        .bind(json.tags)
+        .bind(now)
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        self.get_prompt_by_id(insert_result.last_insert_rowid())
+            .await?
+            .ok_or(sqlx::Error::RowNotFound)
+    }
+
+    async fn get_prompt_by_id(&self, id: i64) -> Result<Option<Prompt>, sqlx::Error> {
+        let row = sqlx::query(&format!(
+            "SELECT {} FROM prompts WHERE id = ?",
+            PROMPT_COLUMNS
+        ))
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row.as_ref().map(prompt_from_row))
+    }
+
+    async fn get_prompts(&self, filter: &PromptFilter) -> Result<PromptPage, sqlx::Error> {
+        let from = filter.from.map(|from| from.naive_utc());
+        let to = filter.to.map(|to| to.naive_utc());
+        let after_created_at = filter.after.map(|after| after.created_at.naive_utc());
+        let after_id = filter.after.map(|after| after.id);
+
+        // one extra row is fetched to tell whether there is another page
+        let rows = sqlx::query(&format!(
+            "SELECT {} FROM prompts \
+            WHERE (? IS NULL OR LOWER(provider) = LOWER(?)) AND (? IS NULL OR model = ?) \
+                AND (? IS NULL OR created_at >= ?) AND (? IS NULL OR created_at < ?) \
+                AND (? IS NULL OR conversation_id = ?) \
+                AND (? IS NULL OR EXISTS (SELECT 1 FROM json_each(tags) WHERE value = ?)) \
+                AND (? IS NULL OR created_at < ? OR (created_at = ? AND id < ?)) \
+            ORDER BY created_at DESC, id DESC LIMIT ?",
+            PROMPT_COLUMNS
+        ))
+        .bind(&filter.provider)
+        .bind(&filter.provider)
+        .bind(&filter.model)
+        .bind(&filter.model)
+        .bind(from)
+        .bind(from)
+        .bind(to)
+        .bind(to)
+        .bind(filter.conversation_id)
+        .bind(filter.conversation_id)
+        .bind(&filter.tag)
+        .bind(&filter.tag)
+        .bind(after_created_at)
+        .bind(after_created_at)
+        .bind(after_created_at)
+        .bind(after_id)
+        .bind(filter.limit as i64 + 1)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(prompt_page(
+            rows.iter().map(prompt_from_row).collect(),
+            filter.limit,
+        ))
+    }
+
+    async fn search_prompts(&self, search: &PromptSearch) -> Result<Vec<SearchHit>, sqlx::Error> {
+        // bm25 ranks better matches lower, it is negated so the best match has the highest score
+        let rows = sqlx::query(&format!(
+            "SELECT {}, score FROM prompts \
+            JOIN (SELECT rowid AS match_id, -bm25(prompts_fts) AS score FROM prompts_fts \
+                WHERE prompts_fts MATCH ?) matches ON prompts.id = matches.match_id \
+            ORDER BY score DESC, created_at DESC, id DESC LIMIT ?",
+            PROMPT_COLUMNS
+        ))
+        .bind(match_query(&search.terms))
+        .bind(search.limit)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows
+            .iter()
+            .map(|row| SearchHit::new(prompt_from_row(row), row.get("score"), &search.terms))
+            .collect())
+    }
+
+    async fn get_usage(
+        &self,
+        group_by: UsageGroupBy,
+        from: Option<DateTime<Utc>>,
+        to: Option<DateTime<Utc>>,
+    ) -> Result<Vec<UsageSummary>, sqlx::Error> {
+        let (group_key, order_by) = match group_by {
+            UsageGroupBy::Provider => ("provider", "cost DESC, group_key"),
+            UsageGroupBy::Model => ("model", "cost DESC, group_key"),
+            UsageGroupBy::Day => ("strftime('%Y-%m-%d', created_at)", "group_key"),
+        };
+
+        let from = from.map(|from| from.naive_utc());
+        let to = to.map(|to| to.naive_utc());
+
+        let rows = sqlx::query(&format!(
+            "SELECT {} AS group_key, COUNT(*) AS prompts, \
+                COALESCE(SUM(input_tokens), 0) AS input_tokens, \
+                COALESCE(SUM(output_tokens), 0) AS output_tokens, \
+                COALESCE(SUM(cached_tokens), 0) AS cached_tokens, \
+                COALESCE(SUM(cost), 0.0) AS cost, \
+                SUM(cost IS NULL) AS unpriced_prompts \
+            FROM prompts \
+            WHERE (? IS NULL OR created_at >= ?) AND (? IS NULL OR created_at < ?) \
+            GROUP BY group_key ORDER BY {}",
+            group_key, order_by
+        ))
+        .bind(from)
+        .bind(from)
+        .bind(to)
+        .bind(to)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.iter().map(usage_from_row).collect())
+    }
+
+    async fn get_spend(
+        &self,
+        since: DateTime<Utc>,
+        provider: Option<&str>,
+        user_id: Option<&str>,
+    ) -> Result<f64, sqlx::Error> {
+        let row = sqlx::query(
+            "SELECT COALESCE(SUM(cost), 0.0) AS spent FROM prompts \
+            WHERE created_at >= ? AND (? IS NULL OR LOWER(provider) = LOWER(?)) AND (? IS NULL OR user_id = ?)",
+        )
+        .bind(since.naive_utc())
+        .bind(provider)
+        .bind(provider)
+        .bind(user_id)
+        .bind(user_id)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(row.get("spent"))
+    }
+
+    async fn get_model_catalog(
+        &self,
+        provider: &str,
+    ) -> Result<Option<ModelCatalogEntry>, sqlx::Error> {
+        let rows = sqlx::query(
+            "SELECT model_id, display_name, created_at, fetched_at FROM model_catalog \
+            WHERE provider = ? ORDER BY model_id",
+        )
+        .bind(provider)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let Some(first) = rows.first() else {
+            return Ok(None);
+        };
+        let fetched_at: NaiveDateTime = first.get("fetched_at");
+
+        Ok(Some(ModelCatalogEntry {
+            provider: provider.to_string(),
+            models: rows.iter().map(model_from_row).collect(),
+            fetched_at: fetched_at.and_utc(),
+        }))
+    }
+
+    async fn save_model_catalog(&self, entry: &ModelCatalogEntry) -> Result<(), sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query("DELETE FROM model_catalog WHERE provider = ?")
+            .bind(&entry.provider)
+            .execute(&mut *tx)
+            .await?;
+
+        for model in &entry.models {
+            sqlx::query(
+                "INSERT INTO model_catalog (provider, model_id, display_name, created_at, fetched_at) \
+                VALUES (?, ?, ?, ?, ?)",
+            )
+            .bind(&entry.provider)
+            .bind(&model.id)
+            .bind(&model.display_name)
+            .bind(model.created_at.map(|created_at| created_at.naive_utc()))
+            .bind(entry.fetched_at.naive_utc())
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await
+    }
+
+    async fn get_cached_response(&self, key: &str) -> Result<Option<CachedResponse>, sqlx::Error> {
+        let row = sqlx::query(&format!(
+            "SELECT {} FROM response_cache WHERE cache_key = ?",
+            CACHED_RESPONSE_COLUMNS
+        ))
+        .bind(key)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row.as_ref().map(cached_response_from_row))
+    }
+
+    async fn save_cached_response(&self, response: &CachedResponse) -> Result<(), sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query("DELETE FROM response_cache WHERE cache_key = ?")
+            .bind(&response.key)
+            .execute(&mut *tx)
+            .await?;
+
+        let usage = &response.usage;
+        sqlx::query(&format!(
+            "INSERT INTO response_cache ({}) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            CACHED_RESPONSE_COLUMNS
+        ))
+        .bind(&response.key)
+        .bind(&response.provider)
+        .bind(&response.model)
+        .bind(&response.response)
+        .bind(usage.input_tokens.map(|n| n as i32))
+        .bind(usage.output_tokens.map(|n| n as i32))
+        .bind(usage.reasoning_tokens.map(|n| n as i32))
+        .bind(usage.cached_tokens.map(|n| n as i32))
+        .bind(&response.finish_reason)
+        .bind(response.created_at.naive_utc())
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await
+    }
+
+    async fn create_conversation(&self, title: &str) -> Result<Conversation, sqlx::Error> {
+        let now = now();
+
+        let insert_result = sqlx::query(
+            "INSERT INTO conversations (title, created_at, updated_at) VALUES (?, ?, ?)",
+        )
+        .bind(title)
+        .bind(now)
+        .bind(now)
+        .execute(&self.pool)
+        .await?;
+
+        self.get_conversation(insert_result.last_insert_rowid())
+            .await?
+            .ok_or(sqlx::Error::RowNotFound)
+    }
+
+    async fn get_conversation(&self, id: i64) -> Result<Option<Conversation>, sqlx::Error> {
+        let row = sqlx::query(&format!(
+            "SELECT {} FROM conversations WHERE id = ?",
+            CONVERSATION_COLUMNS
+        ))
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row.as_ref().map(conversation_from_row))
+    }
+
+    async fn get_all_conversations(&self) -> Result<Vec<Conversation>, sqlx::Error> {
+        let rows = sqlx::query(&format!(
+            "SELECT {} FROM conversations ORDER BY updated_at DESC, id DESC",
+            CONVERSATION_COLUMNS
+        ))
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.iter().map(conversation_from_row).collect())
+    }
+
+    async fn get_conversation_prompts(
+        &self,
+        conversation_id: i64,
+    ) -> Result<Vec<Prompt>, sqlx::Error> {
+        let rows = sqlx::query(&format!(
+            "SELECT {} FROM prompts WHERE conversation_id = ? ORDER BY sequence ASC",
+            PROMPT_COLUMNS
+        ))
+        .bind(conversation_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.iter().map(prompt_from_row).collect())
+    }
+
+    async fn get_comparison_prompts(
+        &self,
+        comparison_id: &str,
+    ) -> Result<Vec<Prompt>, sqlx::Error> {
+        let rows = sqlx::query(&format!(
+            "SELECT {} FROM prompts WHERE comparison_id = ? ORDER BY comparison_index ASC, id ASC",
+            PROMPT_COLUMNS
+        ))
+        .bind(comparison_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.iter().map(prompt_from_row).collect())
+    }
+
+    async fn create_prompt_template(
+        &self,
+        new_template: &NewPromptTemplate,
+    ) -> Result<PromptTemplate, sqlx::Error> {
+        let now = now();
+        let variables = serde_json::to_string(&new_template.variables).unwrap_or_default();
+
+        // writes to a SQLite database are serialized, the unique (name, version) catches the rest
+        let mut tx = self.pool.begin().await?;
+
+        let row = sqlx::query(
+            "SELECT COALESCE(MAX(version), 0) + 1 AS next_version FROM prompt_templates WHERE name = ?",
+        )
+        .bind(&new_template.name)
+        .fetch_one(&mut *tx)
+        .await?;
+        let next_version: i32 = row.get("next_version");
+
+        let insert_result = sqlx::query(
+            "INSERT INTO prompt_templates (name, version, template, variables, provider, model, created_at) VALUES (?, ?, ?, ?, ?, ?, ?)",
+        )
+        .bind(&new_template.name)
+        .bind(next_version)
+        .bind(&new_template.template)
+        .bind(variables)
+        .bind(&new_template.provider)
+        .bind(&new_template.model)
+        .bind(now)
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        self.get_prompt_template(insert_result.last_insert_rowid())
+            .await?
+            .ok_or(sqlx::Error::RowNotFound)
+    }
+
+    async fn get_prompt_template(&self, id: i64) -> Result<Option<PromptTemplate>, sqlx::Error> {
+        let row = sqlx::query(&format!(
+            "SELECT {} FROM prompt_templates WHERE id = ?",
+            TEMPLATE_COLUMNS
+        ))
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row.as_ref().map(template_from_row))
+    }
+
+    async fn get_prompt_template_by_name(
+        &self,
+        name: &str,
+    ) -> Result<Option<PromptTemplate>, sqlx::Error> {
+        let row = sqlx::query(&format!(
+            "SELECT {} FROM prompt_templates WHERE name = ? ORDER BY version DESC LIMIT 1",
+            TEMPLATE_COLUMNS
+        ))
+        .bind(name)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row.as_ref().map(template_from_row))
+    }
+
+    async fn get_prompt_templates(&self) -> Result<Vec<PromptTemplate>, sqlx::Error> {
+        let rows = sqlx::query(&format!(
+            "SELECT {} FROM prompt_templates t \
+            WHERE version = (SELECT MAX(version) FROM prompt_templates WHERE name = t.name) \
+            ORDER BY name",
+            TEMPLATE_COLUMNS
+        ))
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.iter().map(template_from_row).collect())
+    }
+
+    async fn enqueue_task(&self, new_task: &NewTask) -> Result<Task, sqlx::Error> {
+        let now = now();
+
+        let insert_result = sqlx::query(
+            "INSERT INTO tasks (task_type, payload, status, attempts, max_attempts, run_at, created_at, updated_at) \
+            VALUES (?, ?, ?, 0, ?, ?, ?, ?)",
+        )
+        .bind(&new_task.task_type)
+        .bind(new_task.payload.to_string())
+        .bind(TaskStatus::Pending.to_string())
+        .bind(new_task.max_attempts)
+        .bind(new_task.run_at.naive_utc())
+        .bind(now)
+        .bind(now)
+        .execute(&self.pool)
+        .await?;
+
+        self.get_task(insert_result.last_insert_rowid())
+            .await?
+            .ok_or(sqlx::Error::RowNotFound)
+    }
+
+    async fn get_task(&self, id: i64) -> Result<Option<Task>, sqlx::Error> {
+        let row = sqlx::query(&format!("SELECT {} FROM tasks WHERE id = ?", TASK_COLUMNS))
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(row.as_ref().map(task_from_row))
+    }
+
+    async fn get_tasks(
+        &self,
+        status: Option<TaskStatus>,
+        limit: u32,
+    ) -> Result<Vec<Task>, sqlx::Error> {
+        let rows = sqlx::query(&format!(
+            "SELECT {} FROM tasks WHERE (?1 IS NULL OR status = ?1) ORDER BY id DESC LIMIT ?2",
+            TASK_COLUMNS
+        ))
+        .bind(status.map(|status| status.to_string()))
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.iter().map(task_from_row).collect())
+    }
+
+    async fn claim_task(
+        &self,
+        worker_id: &str,
+        lease: Duration,
+    ) -> Result<Option<Task>, sqlx::Error> {
+        let now = now();
+
+        // sqlite has no row locks, a single statement holds the database's write lock
+        // from picking the task until it is claimed
+        let row = sqlx::query(
+            "UPDATE tasks SET status = ?1, worker_id = ?2, lease_expires_at = ?3, \
+                attempts = attempts + 1, updated_at = ?4 \
+            WHERE id = ( \
+                SELECT id FROM tasks \
+                WHERE (status = ?5 AND run_at <= ?4) OR (status = ?1 AND lease_expires_at <= ?4) \
+                ORDER BY run_at, id LIMIT 1 \
+            ) RETURNING id",
+        )
+        .bind(TaskStatus::Running.to_string())
+        .bind(worker_id)
+        .bind(lease_expiry(lease))
+        .bind(now)
+        .bind(TaskStatus::Pending.to_string())
+        .fetch_optional(&self.pool)
+        .await?;
+
+        match row {
+            Some(row) => self.get_task(row.get("id")).await,
+            None => Ok(None),
+        }
+    }
+
+    async fn renew_task_lease(
+        &self,
+        id: i64,
+        worker_id: &str,
+        lease: Duration,
+    ) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query(
+            "UPDATE tasks SET lease_expires_at = ?, updated_at = ? \
+            WHERE id = ? AND worker_id = ? AND status = ?",
+        )
+        .bind(lease_expiry(lease))
+        .bind(now())
+        .bind(id)
+        .bind(worker_id)
+        .bind(TaskStatus::Running.to_string())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() == 1)
+    }
+
+    async fn complete_task(
+        &self,
+        id: i64,
+        worker_id: &str,
+        result: &serde_json::Value,
+    ) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query(
+            "UPDATE tasks SET status = ?, result = ?, lease_expires_at = NULL, updated_at = ? \
+            WHERE id = ? AND worker_id = ? AND status = ?",
+        )
+        .bind(TaskStatus::Completed.to_string())
+        .bind(result.to_string())
+        .bind(now())
+        .bind(id)
+        .bind(worker_id)
+        .bind(TaskStatus::Running.to_string())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() == 1)
+    }
+
+    async fn fail_task(
+        &self,
+        id: i64,
+        worker_id: &str,
+        error: &str,
+        retry_at: Option<DateTime<Utc>>,
+    ) -> Result<bool, sqlx::Error> {
+        let status = match retry_at {
+            Some(_) => TaskStatus::Pending,
+            None => TaskStatus::Dead,
+        };
+        let result = sqlx::query(
+            "UPDATE tasks SET status = ?, run_at = COALESCE(?, run_at), error = ?, \
+                lease_expires_at = NULL, updated_at = ? \
+            WHERE id = ? AND worker_id = ? AND status = ?",
+        )
+        .bind(status.to_string())
+        .bind(retry_at.map(|retry_at| retry_at.naive_utc()))
+        .bind(error)
+        .bind(now())
+        .bind(id)
+        .bind(worker_id)
+        .bind(TaskStatus::Running.to_string())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() == 1)
+    }
+}
+
+async fn migrate(conn: &mut SqliteConnection) -> Result<MigrationReport, MigrationError> {
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS schema_migrations (
+            version INTEGER PRIMARY KEY,
+            name TEXT NOT NULL,
+            applied_at DATETIME NOT NULL
+        )",
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    let applied_versions: Vec<i64> = sqlx::query("SELECT version FROM schema_migrations")
+        .fetch_all(&mut *conn)
+        .await?
+        .iter()
+        .map(|row| row.get("version"))
+        .collect();
+
+    let (mut report, pending) = pending_migrations(SQLITE_MIGRATIONS, &applied_versions)?;
+    for migration in pending {
+        // run as a whole, trigger bodies have semicolons of their own
+        conn.execute(migration.sql)
+            .await
+            .map_err(|error| MigrationError::Failed {
+                name: migration.name,
+                error,
+            })?;
+        report.applied.push(migration.name);
+
+        sqlx::query("INSERT INTO schema_migrations (version, name, applied_at) VALUES (?, ?, ?)")
+            .bind(migration.version)
+            .bind(migration.name)
+            .bind(now())
+            .execute(&mut *conn)
+            .await?;
+        report.version = report.version.max(migration.version);
+    }
+
+    Ok(report)
+}
+
+// an fts5 query matching any of the terms, each quoted so it is searched for as written
+fn match_query(terms: &[String]) -> String {
+    terms
+        .iter()
+        .map(|term| format!("\"{}\"", term.replace('"', "\"\"")))
+        .collect::<Vec<_>>()
+        .join(" OR ")
+}
+
+fn prompt_from_row(row: &SqliteRow) -> Prompt {
+    prompt_from_row!(row, row.get("max_tokens"))
+}
+
+fn template_from_row(row: &SqliteRow) -> PromptTemplate {
+    template_from_row!(row)
+}
+
+fn conversation_from_row(row: &SqliteRow) -> Conversation {
+    conversation_from_row!(row)
+}
+
+fn cached_response_from_row(row: &SqliteRow) -> CachedResponse {
+    cached_response_from_row!(row)
+}
+
+fn task_from_row(row: &SqliteRow) -> Task {
+    task_from_row!(row)
+}
+
+fn usage_from_row(row: &SqliteRow) -> UsageSummary {
+    UsageSummary {
+        key: row.get("group_key"),
+        prompts: row.get("prompts"),
+        input_tokens: row.get("input_tokens"),
+        output_tokens: row.get("output_tokens"),
+        cached_tokens: row.get("cached_tokens"),
+        cost: row.get("cost"),
+        unpriced_prompts: row.get("unpriced_prompts"),
+    }
+}
+
+fn model_from_row(row: &SqliteRow) -> ModelInfo {
+    ModelInfo {
+        id: row.get("model_id"),
+        display_name: row.get("display_name"),
+        created_at: row
+            .get::<Option<NaiveDateTime>, _>("created_at")
+            .map(|created_at| created_at.and_utc()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_match_query() {
+        let terms = vec!["kafka".to_string(), "say\"hi".to_string()];
+        assert_eq!(match_query(&terms), "\"kafka\" OR \"say\"\"hi\"");
+    }
+}
@@ -0,0 +1,395 @@
+use crate::config::CoreConfig;
+use crate::error::CoreError;
+use crate::models::CreatePromptRequest;
+use crate::prompt::prompt_model;
+use crate::retry::RetryPolicy;
+use crate::store::{now, PromptStore};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::str::FromStr;
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+use strum::Display;
+
+// task type of a prompt queued to be run by a worker, its payload is a PromptTask
+pub const PROMPT_TASK: &str = "prompt";
+
+// where a task is in the queue
+#[derive(Display, Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub enum TaskStatus {
+    // waiting for its run_at time and a worker to claim it
+    #[default]
+    Pending,
+    // claimed by a worker, which keeps it for as long as it renews the lease
+    Running,
+    Completed,
+    // failed with an error retrying won't fix or used up its attempts, it is never run again
+    Dead,
+}
+
+impl FromStr for TaskStatus {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "pending" => Ok(TaskStatus::Pending),
+            "running" => Ok(TaskStatus::Running),
+            "completed" => Ok(TaskStatus::Completed),
+            "dead" => Ok(TaskStatus::Dead),
+            _ => Err(format!(
+                "Unknown task status: {} (expected pending, running, completed or dead)",
+                s
+            )),
+        }
+    }
+}
+
+/// Work queued in the tasks table for a worker.
+/// Workers claim a task with a lease they renew while running it, a task whose lease runs
+/// out because its worker died is claimed again by another worker. Tasks run at least once,
+/// a worker dying after finishing the work but before recording it means it is run again.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Task {
+    pub id: i64,
+    pub task_type: String,
+    pub payload: Value,
+    pub status: TaskStatus,
+    // times the task has been claimed, including a run still going
+    pub attempts: i32,
+    pub max_attempts: i32,
+    // the task isn't claimed before this, failed attempts push it back
+    pub run_at: DateTime<Utc>,
+    // the worker running the task, or that ran it last
+    pub worker_id: Option<String>,
+    pub lease_expires_at: Option<DateTime<Utc>>,
+    pub result: Option<Value>,
+    // error of the last failed attempt
+    pub error: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct NewTask {
+    pub task_type: String,
+    pub payload: Value,
+    pub max_attempts: i32,
+    pub run_at: DateTime<Utc>,
+}
+
+impl NewTask {
+    pub fn new(task_type: &str, payload: Value, max_attempts: u32) -> Self {
+        Self {
+            task_type: task_type.to_string(),
+            payload,
+            max_attempts: max_attempts.max(1) as i32,
+            // to the second like the stores' clock, so a task is due as soon as it is queued
+            run_at: now().and_utc(),
+        }
+    }
+
+    pub fn with_run_at(mut self, run_at: DateTime<Utc>) -> Self {
+        self.run_at = run_at;
+        self
+    }
+}
+
+// payload of a PROMPT_TASK, the prompt is made for the user that queued it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PromptTask {
+    pub request: CreatePromptRequest,
+    pub user: Option<String>,
+}
+
+/// How long workers hold the tasks they claim and how failed tasks are retried.
+/// Failed attempts are retried with the same doubling backoff as provider calls, only
+/// longer, errors that retrying won't fix dead-letter the task straight away.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TaskPolicy {
+    pub lease: Duration,
+    pub max_attempts: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl TaskPolicy {
+    pub fn from_config(config: &CoreConfig) -> Self {
+        Self {
+            lease: Duration::from_secs(config.task_lease_secs.max(1)),
+            max_attempts: config.task_max_attempts.max(1),
+            base_delay: Duration::from_secs(config.task_retry_base_delay_secs),
+            max_delay: Duration::from_secs(config.task_retry_max_delay_secs),
+        }
+    }
+
+    pub fn new_task(&self, task_type: &str, payload: Value) -> NewTask {
+        NewTask::new(task_type, payload, self.max_attempts)
+    }
+
+    /// Wait before the task runs again after its latest attempt failed with `error`,
+    /// None when it should be dead-lettered
+    pub fn retry_delay(&self, task: &Task, error: &CoreError) -> Option<Duration> {
+        if !is_task_retryable(error) {
+            return None;
+        }
+
+        let retry = RetryPolicy {
+            max_attempts: task.max_attempts.max(1) as u32,
+            base_delay: self.base_delay,
+            max_delay: self.max_delay,
+            jitter: true,
+        };
+        retry.backoff(task.attempts.max(1) as u32, error)
+    }
+
+    /// Claim the next task that is due for the worker. Tasks that used up their attempts
+    /// because the workers running them kept dying are dead-lettered instead of run again
+    pub async fn claim(
+        &self,
+        worker_id: &str,
+        store: &dyn PromptStore,
+    ) -> Result<Option<Task>, CoreError> {
+        loop {
+            let Some(task) = store.claim_task(worker_id, self.lease).await? else {
+                return Ok(None);
+            };
+            if task.attempts <= task.max_attempts {
+                return Ok(Some(task));
+            }
+
+            let error = format!(
+                "Lease expired on all {} attempts, the workers running the task stopped",
+                task.max_attempts
+            );
+            store.fail_task(task.id, worker_id, &error, None).await?;
+        }
+    }
+
+    /// Record the outcome of a task's attempt, failures are retried or dead-lettered.
+    /// False when the worker lost the task's lease and the outcome was dropped
+    pub async fn finish(
+        &self,
+        task: &Task,
+        worker_id: &str,
+        outcome: Result<Value, CoreError>,
+        store: &dyn PromptStore,
+    ) -> Result<bool, CoreError> {
+        let recorded = match outcome {
+            Ok(result) => store.complete_task(task.id, worker_id, &result).await?,
+            Err(e) => {
+                let retry_at = self.retry_delay(task, &e).map(|delay| {
+                    Utc::now()
+                        + chrono::Duration::from_std(delay).unwrap_or(chrono::Duration::zero())
+                });
+                store
+                    .fail_task(task.id, worker_id, &e.to_string(), retry_at)
+                    .await?
+            }
+        };
+        Ok(recorded)
+    }
+}
+
+// whether a later attempt at a task could succeed. Unlike a provider call, which is retried
+// within the request, a task is retried minutes later, so database errors and failures of
+// providers registered outside kubellm-core are worth another go. Only failures that would
+// happen again the same way dead-letter the task straight away
+fn is_task_retryable(error: &CoreError) -> bool {
+    match error {
+        CoreError::MissingCredentials { .. }
+        | CoreError::NotConfigured { .. }
+        | CoreError::UnknownProvider(_)
+        | CoreError::InvalidModel { .. }
+        | CoreError::UnsupportedParams { .. }
+        | CoreError::NoModels { .. }
+        | CoreError::ConversationNotFound(_)
+        | CoreError::TemplateNotFound(_)
+        | CoreError::Template(_)
+        | CoreError::Cassette(_)
+        | CoreError::SafetyBlock(_)
+        | CoreError::BudgetExceeded(_) => false,
+        // a bad request is refused again, overloaded and failing servers may recover
+        CoreError::ProviderStatus { .. } => error.is_retryable(),
+        _ => true,
+    }
+}
+
+static TASK_POLICY: OnceLock<TaskPolicy> = OnceLock::new();
+
+/// Lease and retry settings of every task, from the TASK_* settings
+pub fn task_policy() -> &'static TaskPolicy {
+    TASK_POLICY.get_or_init(|| TaskPolicy::from_config(CoreConfig::get()))
+}
+
+// queue a prompt for a worker, for prompts that take longer than a client wants to wait
+pub async fn enqueue_prompt(
+    request: &CreatePromptRequest,
+    user: Option<&str>,
+    store: &Arc<dyn PromptStore>,
+) -> Result<Task, CoreError> {
+    let payload = PromptTask {
+        request: request.clone(),
+        user: user.map(str::to_string),
+    };
+    let payload = serde_json::to_value(&payload).map_err(|e| CoreError::Other(e.to_string()))?;
+
+    Ok(store
+        .enqueue_task(&task_policy().new_task(PROMPT_TASK, payload))
+        .await?)
+}
+
+// do a claimed task's work, returns the result saved with the task
+pub async fn run_task(task: &Task, store: &Arc<dyn PromptStore>) -> Result<Value, CoreError> {
+    match task.task_type.as_str() {
+        PROMPT_TASK => {
+            let prompt_task: PromptTask = serde_json::from_value(task.payload.clone())
+                .map_err(|e| CoreError::Other(format!("Invalid prompt task: {}", e)))?;
+            let prompt =
+                prompt_model(&prompt_task.request, prompt_task.user.as_deref(), store).await?;
+            Ok(json!({ "prompt_id": prompt.id }))
+        }
+        other => Err(CoreError::Other(format!("Unknown task type: {}", other))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::MemoryStore;
+
+    fn policy() -> TaskPolicy {
+        TaskPolicy {
+            lease: Duration::from_secs(60),
+            max_attempts: 3,
+            base_delay: Duration::from_secs(10),
+            max_delay: Duration::from_secs(600),
+        }
+    }
+
+    fn overloaded() -> CoreError {
+        CoreError::ProviderStatus {
+            provider: "Anthropic".to_string(),
+            status: 529,
+            retry_after: None,
+            body: "overloaded_error".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_task_status() {
+        assert_eq!("Dead".parse::<TaskStatus>().unwrap(), TaskStatus::Dead);
+        assert_eq!(TaskStatus::Running.to_string(), "running");
+        assert!("done".parse::<TaskStatus>().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_failed_tasks_are_retried_then_dead_lettered() {
+        let store = MemoryStore::new();
+        let policy = policy();
+        let queued = store
+            .enqueue_task(&policy.new_task("test", json!({})))
+            .await
+            .unwrap();
+        assert_eq!(
+            (queued.status, queued.max_attempts),
+            (TaskStatus::Pending, 3)
+        );
+
+        // a retryable failure puts the task back with a backoff
+        let task = policy.claim("w1", &store).await.unwrap().unwrap();
+        assert_eq!((task.status, task.attempts), (TaskStatus::Running, 1));
+        assert!(policy
+            .finish(&task, "w1", Err(overloaded()), &store)
+            .await
+            .unwrap());
+        let retried = store.get_task(task.id).await.unwrap().unwrap();
+        assert_eq!(retried.status, TaskStatus::Pending);
+        assert!(retried.run_at >= Utc::now() + chrono::Duration::seconds(4));
+        assert!(retried.error.unwrap().contains("overloaded_error"));
+        assert!(policy.claim("w1", &store).await.unwrap().is_none());
+
+        // one that won't go away is dead-lettered without using up the attempts
+        let unknown = CoreError::UnknownProvider("nope".to_string());
+        assert_eq!(policy.retry_delay(&task, &unknown), None);
+        let last = Task {
+            attempts: 3,
+            ..task.clone()
+        };
+        assert_eq!(policy.retry_delay(&last, &overloaded()), None);
+    }
+
+    #[test]
+    fn test_transient_errors_are_retried() {
+        let task = Task {
+            id: 1,
+            task_type: "test".to_string(),
+            payload: json!({}),
+            status: TaskStatus::Running,
+            attempts: 1,
+            max_attempts: 3,
+            run_at: Utc::now(),
+            worker_id: Some("w1".to_string()),
+            lease_expires_at: None,
+            result: None,
+            error: None,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        };
+        let policy = policy();
+
+        // a provider call would give up on these, a task waits and tries again
+        for error in [
+            CoreError::Database(sqlx::Error::PoolTimedOut),
+            CoreError::Timeout("read timed out".to_string()),
+            CoreError::Connection("connection reset".to_string()),
+            CoreError::Other("plugin unavailable".to_string()),
+        ] {
+            let delay = policy.retry_delay(&task, &error).unwrap();
+            assert!(delay >= Duration::from_secs(5) && delay <= Duration::from_secs(10));
+        }
+
+        let bad_request = CoreError::ProviderStatus {
+            provider: "OpenAI".to_string(),
+            status: 400,
+            retry_after: None,
+            body: "invalid_request_error".to_string(),
+        };
+        assert_eq!(policy.retry_delay(&task, &bad_request), None);
+    }
+
+    #[tokio::test]
+    async fn test_expired_leases() {
+        let store = MemoryStore::new();
+        let policy = TaskPolicy {
+            lease: Duration::ZERO,
+            max_attempts: 1,
+            ..policy()
+        };
+        store
+            .enqueue_task(&policy.new_task("test", json!({"n": 1})))
+            .await
+            .unwrap();
+
+        // a worker that stops renewing its lease loses the task to the next worker
+        let task = policy.claim("w1", &store).await.unwrap().unwrap();
+        assert!(!store
+            .renew_task_lease(task.id, "w2", policy.lease)
+            .await
+            .unwrap());
+        let reclaimed = store.claim_task("w2", policy.lease).await.unwrap().unwrap();
+        assert_eq!(reclaimed.worker_id.as_deref(), Some("w2"));
+        assert!(!policy
+            .finish(&task, "w1", Ok(json!({})), &store)
+            .await
+            .unwrap());
+
+        // once it has been claimed more often than its attempts allow it is dead-lettered
+        assert!(policy.claim("w3", &store).await.unwrap().is_none());
+        let dead = store.get_task(task.id).await.unwrap().unwrap();
+        assert_eq!((dead.status, dead.attempts), (TaskStatus::Dead, 3));
+        assert!(dead.error.unwrap().contains("Lease expired"));
+    }
+}
@@ -0,0 +1,396 @@
+use crate::models::{CreatePromptRequest, GenerationParams, ModelMode};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+
+/// A variable that fills a template's `{{name}}` placeholders
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TemplateVariable {
+    pub name: String,
+    // used when a run doesn't set the variable, variables without one are required
+    #[serde(default)]
+    pub default: Option<String>,
+    #[serde(default)]
+    pub description: Option<String>,
+}
+
+impl TemplateVariable {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            default: None,
+            description: None,
+        }
+    }
+
+    pub fn required(&self) -> bool {
+        self.default.is_none()
+    }
+}
+
+/// A saved version of a prompt template.
+/// Saving a template under a name that exists adds a new version, earlier versions are kept
+/// so prompts can link back to the exact template they were run with.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PromptTemplate {
+    pub id: i64,
+    pub name: String,
+    pub version: i32,
+    pub template: String,
+    // every placeholder in the template, in the order they first appear
+    pub variables: Vec<TemplateVariable>,
+    // used by runs that don't ask for a provider or model
+    pub provider: Option<String>,
+    pub model: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+// body of POST /templates
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct NewPromptTemplate {
+    pub name: String,
+    pub template: String,
+    // only variables with a default or description need to be listed,
+    // any other placeholder becomes a required variable
+    #[serde(default)]
+    pub variables: Vec<TemplateVariable>,
+    pub provider: Option<String>,
+    pub model: Option<String>,
+}
+
+impl NewPromptTemplate {
+    /// Check the template's placeholders and list a variable for each of them
+    pub fn validate(mut self) -> Result<Self, TemplateError> {
+        self.name = self.name.trim().to_string();
+        if self.name.is_empty() {
+            return Err(TemplateError::EmptyName);
+        }
+
+        let names = placeholder_names(&self.template)?;
+        if let Some(unused) = self.variables.iter().find(|v| !names.contains(&v.name)) {
+            return Err(TemplateError::UnusedVariable(unused.name.clone()));
+        }
+
+        self.variables = names
+            .iter()
+            .map(|name| {
+                self.variables
+                    .iter()
+                    .find(|v| &v.name == name)
+                    .cloned()
+                    .unwrap_or_else(|| TemplateVariable::new(name))
+            })
+            .collect();
+        Ok(self)
+    }
+}
+
+// body of POST /templates/{id}/run, provider and model fall back to the template's
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RunTemplateRequest {
+    #[serde(default)]
+    pub variables: HashMap<String, String>,
+    pub provider: Option<String>,
+    pub model: Option<String>,
+    #[serde(default)]
+    pub model_mode: ModelMode,
+    #[serde(flatten)]
+    pub params: GenerationParams,
+}
+
+impl PromptTemplate {
+    /// The template with its placeholders replaced, fails if a required variable is missing
+    /// or a value is given for a variable the template doesn't have
+    pub fn render(&self, values: &HashMap<String, String>) -> Result<String, TemplateError> {
+        let mut unknown: Vec<String> = values
+            .keys()
+            .filter(|name| !self.variables.iter().any(|v| &v.name == *name))
+            .cloned()
+            .collect();
+        if !unknown.is_empty() {
+            unknown.sort();
+            return Err(TemplateError::UnknownVariables(unknown));
+        }
+
+        let missing: Vec<String> = self
+            .variables
+            .iter()
+            .filter(|v| v.required() && !values.contains_key(&v.name))
+            .map(|v| v.name.clone())
+            .collect();
+        if !missing.is_empty() {
+            return Err(TemplateError::MissingVariables(missing));
+        }
+
+        let mut rendered = String::with_capacity(self.template.len());
+        let mut rest = 0;
+        for placeholder in placeholders(&self.template)? {
+            rendered.push_str(&self.template[rest..placeholder.start]);
+            let value = values.get(placeholder.name).or_else(|| {
+                self.variables
+                    .iter()
+                    .find(|v| v.name == placeholder.name)
+                    .and_then(|v| v.default.as_ref())
+            });
+            rendered.push_str(value.map(String::as_str).unwrap_or_default());
+            rest = placeholder.end;
+        }
+        rendered.push_str(&self.template[rest..]);
+        Ok(rendered)
+    }
+
+    /// The prompt a run of this template sends.
+    /// The template's model is only used when the run doesn't pick another provider.
+    pub fn prompt_request(
+        &self,
+        run: &RunTemplateRequest,
+    ) -> Result<CreatePromptRequest, TemplateError> {
+        let prompt = self.render(&run.variables)?;
+
+        let (provider, model) = match (&run.provider, &self.provider) {
+            (Some(provider), Some(template_provider))
+                if !provider.eq_ignore_ascii_case(template_provider) =>
+            {
+                (provider, run.model.clone())
+            }
+            (Some(provider), _) | (None, Some(provider)) => {
+                (provider, run.model.clone().or_else(|| self.model.clone()))
+            }
+            (None, None) => return Err(TemplateError::NoProvider),
+        };
+
+        Ok(CreatePromptRequest::new(&prompt, provider)
+            .with_model(model.as_deref())
+            .with_model_mode(run.model_mode)
+            .with_params(run.params.clone()))
+    }
+}
+
+/// Why a template couldn't be saved or run
+#[derive(Debug, Clone, PartialEq)]
+pub enum TemplateError {
+    EmptyName,
+    /// A `{{` without a closing `}}` or a placeholder name that isn't letters, digits and underscores
+    InvalidPlaceholder(String),
+    /// A variable was listed that no placeholder uses
+    UnusedVariable(String),
+    MissingVariables(Vec<String>),
+    UnknownVariables(Vec<String>),
+    /// Neither the run nor the template says which provider to prompt
+    NoProvider,
+}
+
+impl fmt::Display for TemplateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TemplateError::EmptyName => write!(f, "Template name cannot be empty"),
+            TemplateError::InvalidPlaceholder(placeholder) => {
+                write!(f, "Invalid template placeholder: {}", placeholder)
+            }
+            TemplateError::UnusedVariable(name) => {
+                write!(f, "Variable {} is not used in the template", name)
+            }
+            TemplateError::MissingVariables(names) => {
+                write!(f, "Missing template variables: {}", names.join(", "))
+            }
+            TemplateError::UnknownVariables(names) => {
+                write!(f, "Unknown template variables: {}", names.join(", "))
+            }
+            TemplateError::NoProvider => {
+                write!(f, "The template has no provider, the run needs to set one")
+            }
+        }
+    }
+}
+
+impl std::error::Error for TemplateError {}
+
+// a `{{name}}` in a template, start and end are the byte range of the braces
+struct Placeholder<'a> {
+    start: usize,
+    end: usize,
+    name: &'a str,
+}
+
+fn placeholders(template: &str) -> Result<Vec<Placeholder<'_>>, TemplateError> {
+    let mut found = Vec::new();
+    let mut offset = 0;
+
+    while let Some(open) = template[offset..].find("{{") {
+        let start = offset + open;
+        let Some(close) = template[start + 2..].find("}}") else {
+            return Err(TemplateError::InvalidPlaceholder(
+                template[start..].chars().take(20).collect(),
+            ));
+        };
+        let end = start + 2 + close + 2;
+        let name = template[start + 2..end - 2].trim();
+
+        let valid = !name.is_empty()
+            && !name.starts_with(|c: char| c.is_ascii_digit())
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+        if !valid {
+            return Err(TemplateError::InvalidPlaceholder(
+                template[start..end].to_string(),
+            ));
+        }
+
+        found.push(Placeholder { start, end, name });
+        offset = end;
+    }
+
+    Ok(found)
+}
+
+/// Names of the variables a template uses, in the order they first appear
+pub fn placeholder_names(template: &str) -> Result<Vec<String>, TemplateError> {
+    let mut names: Vec<String> = Vec::new();
+    for placeholder in placeholders(template)? {
+        if !names.iter().any(|name| name == placeholder.name) {
+            names.push(placeholder.name.to_string());
+        }
+    }
+    Ok(names)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn template() -> PromptTemplate {
+        let new_template = NewPromptTemplate {
+            name: " summarise ".to_string(),
+            template: "Summarise this {{ kind }} in {{words}} words for {{audience}}:\n{{kind}}"
+                .to_string(),
+            variables: vec![TemplateVariable {
+                name: "words".to_string(),
+                default: Some("50".to_string()),
+                description: None,
+            }],
+            provider: Some("Anthropic".to_string()),
+            model: Some("claude-sonnet-4-20250514".to_string()),
+        }
+        .validate()
+        .unwrap();
+
+        PromptTemplate {
+            id: 1,
+            name: new_template.name,
+            version: 1,
+            template: new_template.template,
+            variables: new_template.variables,
+            provider: new_template.provider,
+            model: new_template.model,
+            created_at: Utc::now(),
+        }
+    }
+
+    fn values(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_validate_lists_variables() {
+        let template = template();
+        assert_eq!(template.name, "summarise");
+
+        let variables: Vec<(&str, bool)> = template
+            .variables
+            .iter()
+            .map(|v| (v.name.as_str(), v.required()))
+            .collect();
+        assert_eq!(
+            variables,
+            vec![("kind", true), ("words", false), ("audience", true)]
+        );
+    }
+
+    #[test]
+    fn test_validate_errors() {
+        let validate = |template: &str, variables: Vec<TemplateVariable>| {
+            NewPromptTemplate {
+                name: "test".to_string(),
+                template: template.to_string(),
+                variables,
+                ..Default::default()
+            }
+            .validate()
+            .unwrap_err()
+        };
+
+        assert!(matches!(
+            validate("Hello {{name", vec![]),
+            TemplateError::InvalidPlaceholder(_)
+        ));
+        assert!(matches!(
+            validate("Hello {{first name}}", vec![]),
+            TemplateError::InvalidPlaceholder(_)
+        ));
+        assert_eq!(
+            validate("Hello {{name}}", vec![TemplateVariable::new("nmae")]),
+            TemplateError::UnusedVariable("nmae".to_string())
+        );
+        assert!(NewPromptTemplate::default().validate().is_err());
+    }
+
+    #[test]
+    fn test_render() {
+        let template = template();
+
+        let rendered = template
+            .render(&values(&[("kind", "report"), ("audience", "engineers")]))
+            .unwrap();
+        assert_eq!(
+            rendered,
+            "Summarise this report in 50 words for engineers:\nreport"
+        );
+
+        assert_eq!(
+            template.render(&values(&[("kind", "report")])),
+            Err(TemplateError::MissingVariables(
+                vec!["audience".to_string()]
+            ))
+        );
+        assert_eq!(
+            template.render(&values(&[
+                ("kind", "report"),
+                ("audience", "engineers"),
+                ("tone", "formal")
+            ])),
+            Err(TemplateError::UnknownVariables(vec!["tone".to_string()]))
+        );
+    }
+
+    #[test]
+    fn test_prompt_request_provider_and_model() {
+        let template = template();
+        let run = |provider: Option<&str>, model: Option<&str>| RunTemplateRequest {
+            variables: values(&[("kind", "report"), ("audience", "engineers")]),
+            provider: provider.map(str::to_string),
+            model: model.map(str::to_string),
+            ..Default::default()
+        };
+
+        let request = template.prompt_request(&run(None, None)).unwrap();
+        assert_eq!(request.provider, "Anthropic");
+        assert_eq!(request.model.as_deref(), Some("claude-sonnet-4-20250514"));
+
+        // the template's model belongs to its own provider
+        let request = template.prompt_request(&run(Some("OpenAI"), None)).unwrap();
+        assert_eq!(request.provider, "OpenAI");
+        assert_eq!(request.model, None);
+
+        let template = PromptTemplate {
+            provider: None,
+            ..template
+        };
+        assert_eq!(
+            template.prompt_request(&run(None, None)).unwrap_err(),
+            TemplateError::NoProvider
+        );
+    }
+}
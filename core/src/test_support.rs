@@ -0,0 +1,64 @@
+// helpers for testing code built on kubellm-core without a database or provider accounts,
+// compiled for the core tests and for other crates with the test-support feature
+mod memory;
+mod mock_llm;
+
+pub use memory::MemoryStore;
+pub use mock_llm::{mock_server, MockFormat, MockLlmServer, MockReply, MockRequest, MOCK_API_KEY};
+
+use crate::models::{GenerationParams, NewPrompt, TokenUsage};
+use std::env;
+use std::sync::Once;
+
+/// Settings for `CoreConfig::get` that need nothing running: an in-memory SQLite url,
+/// retries after a millisecond and model lists that are fetched again for every prompt,
+/// so each test sees its own mock server's models. Variables that are already set are kept.
+/// Has to be called before anything loads the config, it is only read once per process.
+pub fn use_test_config() {
+    static SET: Once = Once::new();
+    SET.call_once(|| {
+        for (name, value) in [
+            ("DATABASE_URL", "sqlite::memory:"),
+            ("RETRY_BASE_DELAY_MS", "1"),
+            ("RETRY_MAX_DELAY_MS", "10"),
+            ("MODEL_CACHE_TTL_SECS", "0"),
+            // tests that repeat a prompt get the cached answer unless they bypass the cache
+            ("RESPONSE_CACHE_TTL_SECS", "3600"),
+        ] {
+            if env::var_os(name).is_none() {
+                env::set_var(name, value);
+            }
+        }
+    });
+}
+
+/// A prompt from alice costing 0.5, for tests of the stores to change what they need
+pub fn new_prompt(prompt: &str, response: &str, provider: &str) -> NewPrompt {
+    NewPrompt {
+        prompt: prompt.to_string(),
+        response: response.to_string(),
+        model: "test-model".to_string(),
+        provider: provider.to_string(),
+        conversation_id: None,
+        usage: TokenUsage {
+            input_tokens: Some(10),
+            output_tokens: Some(20),
+            reasoning_tokens: None,
+            cached_tokens: Some(2),
+        },
+        latency_ms: Some(150),
+        finish_reason: Some("stop".to_string()),
+        cost: Some(0.5),
+        user_id: Some("alice".to_string()),
+        attempts: 1,
+        cache_hit: false,
+        route: None,
+        attempted_targets: None,
+        requested_model: None,
+        params: GenerationParams::default(),
+        template_id: None,
+        comparison_id: None,
+        comparison_index: None,
+        tags: Vec::new(),
+    }
+}
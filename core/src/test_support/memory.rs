@@ -0,0 +1,662 @@
+use crate::cache::CachedResponse;
+use crate::migrations::{MigrationError, MigrationReport};
+use crate::models::{
+    Conversation, ModelCatalogEntry, NewPrompt, Prompt, PromptFilter, PromptPage, UsageGroupBy,
+    UsageSummary,
+};
+use crate::search::{PromptSearch, SearchHit};
+use crate::store::{lease_expiry, now, prompt_page, PromptStore};
+use crate::task::{NewTask, Task, TaskStatus};
+use crate::template::{NewPromptTemplate, PromptTemplate};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Prompts kept in memory for as long as the store lives, for tests that shouldn't need a
+/// database. Queries behave like the SQL stores', searches match words starting with a term.
+#[derive(Default)]
+pub struct MemoryStore {
+    data: Mutex<MemoryData>,
+}
+
+#[derive(Default)]
+struct MemoryData {
+    prompts: Vec<Prompt>,
+    conversations: Vec<Conversation>,
+    templates: Vec<PromptTemplate>,
+    catalog: HashMap<String, ModelCatalogEntry>,
+    cached_responses: HashMap<String, CachedResponse>,
+    tasks: Vec<Task>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // every saved prompt, oldest first
+    pub fn prompts(&self) -> Vec<Prompt> {
+        self.data.lock().unwrap().prompts.clone()
+    }
+
+    // change a task the worker is running, false if it isn't running it
+    fn update_held_task(&self, id: i64, worker_id: &str, update: impl FnOnce(&mut Task)) -> bool {
+        let mut data = self.data.lock().unwrap();
+        let held = data.tasks.iter_mut().find(|t| {
+            t.id == id
+                && t.status == TaskStatus::Running
+                && t.worker_id.as_deref() == Some(worker_id)
+        });
+
+        match held {
+            Some(task) => {
+                update(task);
+                task.updated_at = now().and_utc();
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+#[async_trait]
+impl PromptStore for MemoryStore {
+    async fn migrate(&self) -> Result<MigrationReport, MigrationError> {
+        // there is no schema to build
+        Ok(MigrationReport {
+            version: 0,
+            applied: vec![],
+            adopted: vec![],
+        })
+    }
+
+    async fn ping(&self) -> Result<(), sqlx::Error> {
+        Ok(())
+    }
+
+    async fn create_prompt_record(&self, new_prompt: &NewPrompt) -> Result<Prompt, sqlx::Error> {
+        let now = now().and_utc();
+        let mut data = self.data.lock().unwrap();
+
+        let sequence = match new_prompt.conversation_id {
+            Some(conversation_id) => {
+                let conversation = data
+                    .conversations
+                    .iter_mut()
+                    .find(|c| c.id == conversation_id)
+                    .ok_or(sqlx::Error::RowNotFound)?;
+                conversation.updated_at = now;
+
+                let last_sequence = data
+                    .prompts
+                    .iter()
+                    .filter(|p| p.conversation_id == Some(conversation_id))
+                    .filter_map(|p| p.sequence)
+                    .max();
+                Some(last_sequence.unwrap_or(0) + 1)
+            }
+            None => None,
+        };
+
+        let usage = &new_prompt.usage;
+        let prompt = Prompt {
+            id: data.prompts.len() as i64 + 1,
+            prompt: new_prompt.prompt.clone(),
+            response: new_prompt.response.clone(),
+            model: new_prompt.model.clone(),
+            provider: new_prompt.provider.clone(),
+            conversation_id: new_prompt.conversation_id,
+            sequence,
+            input_tokens: usage.input_tokens.map(|tokens| tokens as i32),
+            output_tokens: usage.output_tokens.map(|tokens| tokens as i32),
+            reasoning_tokens: usage.reasoning_tokens.map(|tokens| tokens as i32),
+            cached_tokens: usage.cached_tokens.map(|tokens| tokens as i32),
+            latency_ms: new_prompt.latency_ms,
+            finish_reason: new_prompt.finish_reason.clone(),
+            cost: new_prompt.cost,
+            user_id: new_prompt.user_id.clone(),
+            attempts: new_prompt.attempts as i32,
+            cache_hit: new_prompt.cache_hit,
+            route: new_prompt.route.clone(),
+            attempted_targets: new_prompt.attempted_targets.clone(),
+            requested_model: new_prompt.requested_model.clone(),
+            params: new_prompt.params.clone(),
+            template_id: new_prompt.template_id,
+            comparison_id: new_prompt.comparison_id.clone(),
+            comparison_index: new_prompt.comparison_index,
+            tags: new_prompt.tags.clone(),
+            created_at: now,
+        };
+        data.prompts.push(prompt.clone());
+
+        Ok(prompt)
+    }
+
+    async fn get_prompt_by_id(&self, id: i64) -> Result<Option<Prompt>, sqlx::Error> {
+        let data = self.data.lock().unwrap();
+        Ok(data.prompts.iter().find(|p| p.id == id).cloned())
+    }
+
+    async fn get_prompts(&self, filter: &PromptFilter) -> Result<PromptPage, sqlx::Error> {
+        let data = self.data.lock().unwrap();
+        let mut prompts: Vec<Prompt> = data
+            .prompts
+            .iter()
+            .filter(|p| {
+                filter
+                    .provider
+                    .as_ref()
+                    .is_none_or(|v| p.provider.eq_ignore_ascii_case(v))
+            })
+            .filter(|p| filter.model.as_ref().is_none_or(|v| &p.model == v))
+            .filter(|p| filter.from.is_none_or(|from| p.created_at >= from))
+            .filter(|p| filter.to.is_none_or(|to| p.created_at < to))
+            .filter(|p| {
+                filter
+                    .conversation_id
+                    .is_none_or(|id| p.conversation_id == Some(id))
+            })
+            .filter(|p| filter.tag.as_ref().is_none_or(|tag| p.tags.contains(tag)))
+            .filter(|p| {
+                filter
+                    .after
+                    .is_none_or(|after| (p.created_at, p.id) < (after.created_at, after.id))
+            })
+            .cloned()
+            .collect();
+        newest_first(&mut prompts);
+
+        // one extra prompt is kept to tell whether there is another page
+        prompts.truncate(filter.limit as usize + 1);
+        Ok(prompt_page(prompts, filter.limit))
+    }
+
+    async fn search_prompts(&self, search: &PromptSearch) -> Result<Vec<SearchHit>, sqlx::Error> {
+        let data = self.data.lock().unwrap();
+        let mut hits: Vec<SearchHit> = data
+            .prompts
+            .iter()
+            .filter_map(|p| {
+                let score =
+                    match_count(&p.prompt, &search.terms) + match_count(&p.response, &search.terms);
+                (score > 0).then(|| SearchHit::new(p.clone(), score as f64, &search.terms))
+            })
+            .collect();
+
+        hits.sort_by(|a, b| {
+            b.score
+                .total_cmp(&a.score)
+                .then(b.prompt.created_at.cmp(&a.prompt.created_at))
+                .then(b.prompt.id.cmp(&a.prompt.id))
+        });
+        hits.truncate(search.limit as usize);
+
+        Ok(hits)
+    }
+
+    async fn get_usage(
+        &self,
+        group_by: UsageGroupBy,
+        from: Option<DateTime<Utc>>,
+        to: Option<DateTime<Utc>>,
+    ) -> Result<Vec<UsageSummary>, sqlx::Error> {
+        let data = self.data.lock().unwrap();
+        let mut groups: Vec<UsageSummary> = Vec::new();
+
+        let prompts = data
+            .prompts
+            .iter()
+            .filter(|p| from.is_none_or(|from| p.created_at >= from))
+            .filter(|p| to.is_none_or(|to| p.created_at < to));
+        for prompt in prompts {
+            let key = match group_by {
+                UsageGroupBy::Provider => prompt.provider.clone(),
+                UsageGroupBy::Model => prompt.model.clone(),
+                UsageGroupBy::Day => prompt.created_at.format("%Y-%m-%d").to_string(),
+            };
+            let index = match groups.iter().position(|group| group.key == key) {
+                Some(index) => index,
+                None => {
+                    groups.push(UsageSummary {
+                        key,
+                        prompts: 0,
+                        input_tokens: 0,
+                        output_tokens: 0,
+                        cached_tokens: 0,
+                        cost: 0.0,
+                        unpriced_prompts: 0,
+                    });
+                    groups.len() - 1
+                }
+            };
+
+            let group = &mut groups[index];
+            group.prompts += 1;
+            group.input_tokens += prompt.input_tokens.unwrap_or(0) as i64;
+            group.output_tokens += prompt.output_tokens.unwrap_or(0) as i64;
+            group.cached_tokens += prompt.cached_tokens.unwrap_or(0) as i64;
+            match prompt.cost {
+                Some(cost) => group.cost += cost,
+                None => group.unpriced_prompts += 1,
+            }
+        }
+
+        match group_by {
+            UsageGroupBy::Day => groups.sort_by(|a, b| a.key.cmp(&b.key)),
+            _ => groups.sort_by(|a, b| b.cost.total_cmp(&a.cost).then(a.key.cmp(&b.key))),
+        }
+
+        Ok(groups)
+    }
+
+    async fn get_spend(
+        &self,
+        since: DateTime<Utc>,
+        provider: Option<&str>,
+        user_id: Option<&str>,
+    ) -> Result<f64, sqlx::Error> {
+        let data = self.data.lock().unwrap();
+        Ok(data
+            .prompts
+            .iter()
+            .filter(|p| p.created_at >= since)
+            .filter(|p| provider.is_none_or(|provider| p.provider.eq_ignore_ascii_case(provider)))
+            .filter(|p| user_id.is_none_or(|user_id| p.user_id.as_deref() == Some(user_id)))
+            .filter_map(|p| p.cost)
+            .sum())
+    }
+
+    async fn get_model_catalog(
+        &self,
+        provider: &str,
+    ) -> Result<Option<ModelCatalogEntry>, sqlx::Error> {
+        let data = self.data.lock().unwrap();
+        Ok(data.catalog.get(provider).cloned())
+    }
+
+    async fn save_model_catalog(&self, entry: &ModelCatalogEntry) -> Result<(), sqlx::Error> {
+        let mut entry = entry.clone();
+        // the sql stores load a provider's models ordered by id
+        entry.models.sort_by(|a, b| a.id.cmp(&b.id));
+
+        let mut data = self.data.lock().unwrap();
+        data.catalog.insert(entry.provider.clone(), entry);
+        Ok(())
+    }
+
+    async fn get_cached_response(&self, key: &str) -> Result<Option<CachedResponse>, sqlx::Error> {
+        let data = self.data.lock().unwrap();
+        Ok(data.cached_responses.get(key).cloned())
+    }
+
+    async fn save_cached_response(&self, response: &CachedResponse) -> Result<(), sqlx::Error> {
+        let mut data = self.data.lock().unwrap();
+        data.cached_responses
+            .insert(response.key.clone(), response.clone());
+        Ok(())
+    }
+
+    async fn create_conversation(&self, title: &str) -> Result<Conversation, sqlx::Error> {
+        let now = now().and_utc();
+        let mut data = self.data.lock().unwrap();
+
+        let conversation = Conversation {
+            id: data.conversations.len() as i64 + 1,
+            title: title.to_string(),
+            created_at: now,
+            updated_at: now,
+        };
+        data.conversations.push(conversation.clone());
+
+        Ok(conversation)
+    }
+
+    async fn get_conversation(&self, id: i64) -> Result<Option<Conversation>, sqlx::Error> {
+        let data = self.data.lock().unwrap();
+        Ok(data.conversations.iter().find(|c| c.id == id).cloned())
+    }
+
+    async fn get_all_conversations(&self) -> Result<Vec<Conversation>, sqlx::Error> {
+        let data = self.data.lock().unwrap();
+        let mut conversations = data.conversations.clone();
+        conversations.sort_by(|a, b| b.updated_at.cmp(&a.updated_at).then(b.id.cmp(&a.id)));
+        Ok(conversations)
+    }
+
+    async fn get_conversation_prompts(
+        &self,
+        conversation_id: i64,
+    ) -> Result<Vec<Prompt>, sqlx::Error> {
+        let data = self.data.lock().unwrap();
+        let mut prompts: Vec<Prompt> = data
+            .prompts
+            .iter()
+            .filter(|p| p.conversation_id == Some(conversation_id))
+            .cloned()
+            .collect();
+        prompts.sort_by_key(|p| p.sequence);
+        Ok(prompts)
+    }
+
+    async fn get_comparison_prompts(
+        &self,
+        comparison_id: &str,
+    ) -> Result<Vec<Prompt>, sqlx::Error> {
+        let data = self.data.lock().unwrap();
+        let mut prompts: Vec<Prompt> = data
+            .prompts
+            .iter()
+            .filter(|p| p.comparison_id.as_deref() == Some(comparison_id))
+            .cloned()
+            .collect();
+        prompts.sort_by_key(|p| (p.comparison_index, p.id));
+        Ok(prompts)
+    }
+
+    async fn create_prompt_template(
+        &self,
+        new_template: &NewPromptTemplate,
+    ) -> Result<PromptTemplate, sqlx::Error> {
+        let mut data = self.data.lock().unwrap();
+
+        let last_version = data
+            .templates
+            .iter()
+            .filter(|t| t.name == new_template.name)
+            .map(|t| t.version)
+            .max();
+        let template = PromptTemplate {
+            id: data.templates.len() as i64 + 1,
+            name: new_template.name.clone(),
+            version: last_version.unwrap_or(0) + 1,
+            template: new_template.template.clone(),
+            variables: new_template.variables.clone(),
+            provider: new_template.provider.clone(),
+            model: new_template.model.clone(),
+            created_at: now().and_utc(),
+        };
+        data.templates.push(template.clone());
+
+        Ok(template)
+    }
+
+    async fn get_prompt_template(&self, id: i64) -> Result<Option<PromptTemplate>, sqlx::Error> {
+        let data = self.data.lock().unwrap();
+        Ok(data.templates.iter().find(|t| t.id == id).cloned())
+    }
+
+    async fn get_prompt_template_by_name(
+        &self,
+        name: &str,
+    ) -> Result<Option<PromptTemplate>, sqlx::Error> {
+        let data = self.data.lock().unwrap();
+        Ok(data
+            .templates
+            .iter()
+            .filter(|t| t.name == name)
+            .max_by_key(|t| t.version)
+            .cloned())
+    }
+
+    async fn get_prompt_templates(&self) -> Result<Vec<PromptTemplate>, sqlx::Error> {
+        let data = self.data.lock().unwrap();
+        let mut latest: Vec<PromptTemplate> = data
+            .templates
+            .iter()
+            .filter(|t| {
+                !data
+                    .templates
+                    .iter()
+                    .any(|other| other.name == t.name && other.version > t.version)
+            })
+            .cloned()
+            .collect();
+        latest.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(latest)
+    }
+
+    async fn enqueue_task(&self, new_task: &NewTask) -> Result<Task, sqlx::Error> {
+        let now = now().and_utc();
+        let mut data = self.data.lock().unwrap();
+
+        let task = Task {
+            id: data.tasks.len() as i64 + 1,
+            task_type: new_task.task_type.clone(),
+            payload: new_task.payload.clone(),
+            status: TaskStatus::Pending,
+            attempts: 0,
+            max_attempts: new_task.max_attempts,
+            run_at: new_task.run_at,
+            worker_id: None,
+            lease_expires_at: None,
+            result: None,
+            error: None,
+            created_at: now,
+            updated_at: now,
+        };
+        data.tasks.push(task.clone());
+
+        Ok(task)
+    }
+
+    async fn get_task(&self, id: i64) -> Result<Option<Task>, sqlx::Error> {
+        let data = self.data.lock().unwrap();
+        Ok(data.tasks.iter().find(|t| t.id == id).cloned())
+    }
+
+    async fn get_tasks(
+        &self,
+        status: Option<TaskStatus>,
+        limit: u32,
+    ) -> Result<Vec<Task>, sqlx::Error> {
+        let data = self.data.lock().unwrap();
+        Ok(data
+            .tasks
+            .iter()
+            .rev()
+            .filter(|t| status.is_none_or(|status| t.status == status))
+            .take(limit as usize)
+            .cloned()
+            .collect())
+    }
+
+    async fn claim_task(
+        &self,
+        worker_id: &str,
+        lease: Duration,
+    ) -> Result<Option<Task>, sqlx::Error> {
+        let now = now().and_utc();
+        let mut data = self.data.lock().unwrap();
+
+        let due = |t: &&mut Task| match t.status {
+            TaskStatus::Pending => t.run_at <= now,
+            TaskStatus::Running => t.lease_expires_at.is_some_and(|expiry| expiry <= now),
+            _ => false,
+        };
+        let Some(task) = data
+            .tasks
+            .iter_mut()
+            .filter(due)
+            .min_by_key(|t| (t.run_at, t.id))
+        else {
+            return Ok(None);
+        };
+
+        task.status = TaskStatus::Running;
+        task.worker_id = Some(worker_id.to_string());
+        task.lease_expires_at = Some(lease_expiry(lease).and_utc());
+        task.attempts += 1;
+        task.updated_at = now;
+        Ok(Some(task.clone()))
+    }
+
+    async fn renew_task_lease(
+        &self,
+        id: i64,
+        worker_id: &str,
+        lease: Duration,
+    ) -> Result<bool, sqlx::Error> {
+        Ok(self.update_held_task(id, worker_id, |task| {
+            task.lease_expires_at = Some(lease_expiry(lease).and_utc());
+        }))
+    }
+
+    async fn complete_task(
+        &self,
+        id: i64,
+        worker_id: &str,
+        result: &serde_json::Value,
+    ) -> Result<bool, sqlx::Error> {
+        Ok(self.update_held_task(id, worker_id, |task| {
+            task.status = TaskStatus::Completed;
+            task.result = Some(result.clone());
+            task.lease_expires_at = None;
+        }))
+    }
+
+    async fn fail_task(
+        &self,
+        id: i64,
+        worker_id: &str,
+        error: &str,
+        retry_at: Option<DateTime<Utc>>,
+    ) -> Result<bool, sqlx::Error> {
+        Ok(self.update_held_task(id, worker_id, |task| {
+            task.status = match retry_at {
+                Some(_) => TaskStatus::Pending,
+                None => TaskStatus::Dead,
+            };
+            task.run_at = retry_at.unwrap_or(task.run_at);
+            task.error = Some(error.to_string());
+            task.lease_expires_at = None;
+        }))
+    }
+}
+
+fn newest_first(prompts: &mut [Prompt]) {
+    prompts.sort_by_key(|p| std::cmp::Reverse((p.created_at, p.id)));
+}
+
+// words of the text starting with one of the terms, the search terms are already lowercased
+fn match_count(text: &str, terms: &[String]) -> usize {
+    text.split(|c: char| !c.is_alphanumeric())
+        .map(str::to_lowercase)
+        .filter(|word| terms.iter().any(|term| word.starts_with(term.as_str())))
+        .count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::PromptCursor;
+    use crate::test_support::new_prompt;
+
+    #[tokio::test]
+    async fn test_prompt_pages_and_filters() {
+        let store = MemoryStore::new();
+        for i in 0..5 {
+            let mut prompt = new_prompt(&format!("prompt {}", i), "answer", "Anthropic");
+            if i % 2 == 0 {
+                prompt.provider = "OpenAI".to_string();
+                prompt.tags = vec!["even".to_string()];
+            }
+            store.create_prompt_record(&prompt).await.unwrap();
+        }
+
+        let page = store
+            .get_prompts(&PromptFilter {
+                limit: 2,
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        let first: Vec<&str> = page.prompts.iter().map(|p| p.prompt.as_str()).collect();
+        assert_eq!(first, vec!["prompt 4", "prompt 3"]);
+
+        let cursor: PromptCursor = page.next_cursor.unwrap().parse().unwrap();
+        let page = store
+            .get_prompts(&PromptFilter {
+                limit: 5,
+                after: Some(cursor),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        let rest: Vec<&str> = page.prompts.iter().map(|p| p.prompt.as_str()).collect();
+        assert_eq!(rest, vec!["prompt 2", "prompt 1", "prompt 0"]);
+        assert_eq!(page.next_cursor, None);
+
+        let tagged = store
+            .get_prompts(&PromptFilter {
+                tag: Some("even".to_string()),
+                provider: Some("OpenAI".to_string()),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        assert_eq!(tagged.prompts.len(), 3);
+
+        let usage = store
+            .get_usage(UsageGroupBy::Provider, None, None)
+            .await
+            .unwrap();
+        let keys: Vec<(&str, i64)> = usage.iter().map(|u| (u.key.as_str(), u.prompts)).collect();
+        assert_eq!(keys, vec![("OpenAI", 3), ("Anthropic", 2)]);
+    }
+
+    #[tokio::test]
+    async fn test_conversations_and_templates() {
+        let store = MemoryStore::new();
+        let conversation = store.create_conversation("Kafka").await.unwrap();
+        for text in ["first", "second"] {
+            let mut prompt = new_prompt(text, "answer", "Anthropic");
+            prompt.conversation_id = Some(conversation.id);
+            store.create_prompt_record(&prompt).await.unwrap();
+        }
+        let prompts = store
+            .get_conversation_prompts(conversation.id)
+            .await
+            .unwrap();
+        let sequences: Vec<Option<i32>> = prompts.iter().map(|p| p.sequence).collect();
+        assert_eq!(sequences, vec![Some(1), Some(2)]);
+
+        // prompts can't join a conversation that doesn't exist
+        let mut prompt = new_prompt("lost", "answer", "Anthropic");
+        prompt.conversation_id = Some(99);
+        assert!(store.create_prompt_record(&prompt).await.is_err());
+
+        let new_template = |template: &str| NewPromptTemplate {
+            name: "summary".to_string(),
+            template: template.to_string(),
+            variables: vec![],
+            provider: None,
+            model: None,
+        };
+        store
+            .create_prompt_template(&new_template("Summarize {{text}}"))
+            .await
+            .unwrap();
+        let second = store
+            .create_prompt_template(&new_template("Briefly summarize {{text}}"))
+            .await
+            .unwrap();
+        assert_eq!(second.version, 2);
+
+        let latest = store
+            .find_prompt_template("summary")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(latest.id, second.id);
+        assert_eq!(store.get_prompt_templates().await.unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_match_count() {
+        let terms = vec!["kafka".to_string(), "lag".to_string()];
+        assert_eq!(match_count("Why is my Kafka consumer lagging?", &terms), 2);
+        assert_eq!(match_count("a flag is not a match", &terms), 0);
+    }
+}
@@ -0,0 +1,581 @@
+use crate::anthropic::AnthropicProvider;
+use crate::cassette::Cassette;
+use crate::openai::OpenAIProvider;
+use axum::body::Body;
+use axum::extract::State;
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use serde_json::{json, Value};
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Api key the providers from `MockLlmServer` send, requests without a key are refused
+pub const MOCK_API_KEY: &str = "mock-api-key";
+
+// models listed until set_models is called, the first is the provider's default model
+const ANTHROPIC_MODELS: &[&str] = &["claude-mock-1", "claude-mock-2"];
+const OPENAI_MODELS: &[&str] = &["gpt-4o-mock", "gpt-4o-mini-mock"];
+
+/// The provider api a request to the mock server was made to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MockFormat {
+    Anthropic,
+    OpenAI,
+}
+
+/// A scripted answer to one completion request
+#[derive(Debug, Clone)]
+pub struct MockReply {
+    outcome: MockOutcome,
+    input_tokens: u32,
+    output_tokens: u32,
+    delay: Duration,
+    retry_after: Option<u64>,
+}
+
+#[derive(Debug, Clone)]
+enum MockOutcome {
+    Text(String),
+    Error { status: u16, message: String },
+}
+
+impl MockReply {
+    /// Answer with the text, streamed a word at a time when the request streams
+    pub fn text(text: &str) -> Self {
+        Self {
+            outcome: MockOutcome::Text(text.to_string()),
+            input_tokens: 10,
+            output_tokens: 20,
+            delay: Duration::ZERO,
+            retry_after: None,
+        }
+    }
+
+    /// Fail with the status and an error body in the provider's format
+    pub fn error(status: u16, message: &str) -> Self {
+        Self {
+            outcome: MockOutcome::Error {
+                status,
+                message: message.to_string(),
+            },
+            ..Self::text("")
+        }
+    }
+
+    pub fn with_usage(mut self, input_tokens: u32, output_tokens: u32) -> Self {
+        self.input_tokens = input_tokens;
+        self.output_tokens = output_tokens;
+        self
+    }
+
+    // wait before answering, to test timeouts and latency
+    pub fn with_delay(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+
+    // send a Retry-After header with an error
+    pub fn with_retry_after(mut self, seconds: u64) -> Self {
+        self.retry_after = Some(seconds);
+        self
+    }
+}
+
+/// A request the mock server received, body is Null for model listings
+#[derive(Debug, Clone)]
+pub struct MockRequest {
+    pub format: MockFormat,
+    pub path: String,
+    pub body: Value,
+}
+
+/// Local server speaking the Anthropic messages and OpenAI chat completions apis.
+/// Completion requests are answered with the scripted replies in order, once the script
+/// runs out every request gets a reply echoing its prompt.
+pub struct MockLlmServer {
+    url: String,
+    state: Arc<MockState>,
+}
+
+#[derive(Default)]
+struct MockState {
+    replies: Mutex<HashMap<MockFormat, VecDeque<MockReply>>>,
+    models: Mutex<HashMap<MockFormat, Vec<String>>>,
+    requests: Mutex<Vec<MockRequest>>,
+}
+
+/// Serve the router on a free local port until the test's runtime shuts down, returns its
+/// base url, for tests that need a provider api MockLlmServer doesn't mock
+pub async fn mock_server(app: Router) -> String {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    format!("http://{}", addr)
+}
+
+impl MockLlmServer {
+    /// Start the server on a free local port, it runs until the test's runtime shuts down
+    pub async fn start() -> Self {
+        let state = Arc::new(MockState::default());
+        let app = Router::new()
+            .route("/anthropic/messages", post(anthropic_messages))
+            .route("/anthropic/models", get(anthropic_models))
+            .route("/openai/chat/completions", post(openai_chat_completions))
+            .route("/openai/models", get(openai_models))
+            .with_state(state.clone());
+
+        Self {
+            url: mock_server(app).await,
+            state,
+        }
+    }
+
+    /// Base url to use in place of https://api.anthropic.com/v1
+    pub fn anthropic_url(&self) -> String {
+        format!("{}/anthropic", self.url)
+    }
+
+    /// Base url to use in place of https://api.openai.com/v1
+    pub fn openai_url(&self) -> String {
+        format!("{}/openai", self.url)
+    }
+
+    /// An Anthropic provider sending its requests to this server, never through a cassette
+    pub fn anthropic_provider(&self) -> AnthropicProvider {
+        AnthropicProvider::new()
+            .with_base_url(&self.anthropic_url())
+            .with_api_key(MOCK_API_KEY)
+            .with_default_model(&self.state.models(MockFormat::Anthropic)[0])
+            .with_cassette(Arc::new(Cassette::off()))
+    }
+
+    /// An OpenAI provider sending its requests to this server, never through a cassette
+    pub fn openai_provider(&self) -> OpenAIProvider {
+        OpenAIProvider::new()
+            .with_base_url(&self.openai_url())
+            .with_api_key(MOCK_API_KEY)
+            .with_default_model(&self.state.models(MockFormat::OpenAI)[0])
+            .with_cassette(Arc::new(Cassette::off()))
+    }
+
+    /// Answer the next unanswered completion request in this format with the reply
+    pub fn push(&self, format: MockFormat, reply: MockReply) {
+        self.state
+            .replies
+            .lock()
+            .unwrap()
+            .entry(format)
+            .or_default()
+            .push_back(reply);
+    }
+
+    /// Replace the models listed for the format, the first becomes the providers' default
+    pub fn set_models(&self, format: MockFormat, models: &[&str]) {
+        self.state.models.lock().unwrap().insert(
+            format,
+            models.iter().map(|model| model.to_string()).collect(),
+        );
+    }
+
+    /// Every request received so far, in the order they arrived
+    pub fn requests(&self) -> Vec<MockRequest> {
+        self.state.requests.lock().unwrap().clone()
+    }
+
+    /// Bodies of the completion requests made in the format
+    pub fn completion_requests(&self, format: MockFormat) -> Vec<Value> {
+        self.requests()
+            .into_iter()
+            .filter(|request| request.format == format && !request.body.is_null())
+            .map(|request| request.body)
+            .collect()
+    }
+}
+
+impl MockState {
+    fn record(&self, format: MockFormat, path: &str, body: Value) {
+        self.requests.lock().unwrap().push(MockRequest {
+            format,
+            path: path.to_string(),
+            body,
+        });
+    }
+
+    fn models(&self, format: MockFormat) -> Vec<String> {
+        let defaults = match format {
+            MockFormat::Anthropic => ANTHROPIC_MODELS,
+            MockFormat::OpenAI => OPENAI_MODELS,
+        };
+        self.models
+            .lock()
+            .unwrap()
+            .get(&format)
+            .cloned()
+            .unwrap_or_else(|| defaults.iter().map(|model| model.to_string()).collect())
+    }
+
+    // the next scripted reply, or one echoing the request's last message
+    fn next_reply(&self, format: MockFormat, body: &Value) -> MockReply {
+        let scripted = self
+            .replies
+            .lock()
+            .unwrap()
+            .get_mut(&format)
+            .and_then(|replies| replies.pop_front());
+        scripted.unwrap_or_else(|| {
+            let prompt = body["messages"]
+                .as_array()
+                .and_then(|messages| messages.last())
+                .and_then(|message| message["content"].as_str())
+                .unwrap_or_default();
+            MockReply::text(&format!("Mock response to: {}", prompt))
+        })
+    }
+}
+
+async fn anthropic_messages(
+    State(state): State<Arc<MockState>>,
+    headers: HeaderMap,
+    Json(body): Json<Value>,
+) -> Response {
+    state.record(MockFormat::Anthropic, "/messages", body.clone());
+    if !headers.contains_key("x-api-key") {
+        return error_response(MockFormat::Anthropic, 401, "missing x-api-key header", None);
+    }
+
+    let reply = state.next_reply(MockFormat::Anthropic, &body);
+    respond(MockFormat::Anthropic, reply, &body).await
+}
+
+async fn openai_chat_completions(
+    State(state): State<Arc<MockState>>,
+    headers: HeaderMap,
+    Json(body): Json<Value>,
+) -> Response {
+    state.record(MockFormat::OpenAI, "/chat/completions", body.clone());
+    if !headers.contains_key(header::AUTHORIZATION) {
+        return error_response(MockFormat::OpenAI, 401, "missing bearer token", None);
+    }
+
+    let reply = state.next_reply(MockFormat::OpenAI, &body);
+    respond(MockFormat::OpenAI, reply, &body).await
+}
+
+async fn anthropic_models(State(state): State<Arc<MockState>>) -> Json<Value> {
+    state.record(MockFormat::Anthropic, "/models", Value::Null);
+    let models = state.models(MockFormat::Anthropic);
+    Json(json!({
+        "data": models.iter().map(|id| json!({
+            "id": id,
+            "type": "model",
+            "display_name": id,
+            "created_at": "2025-01-01T00:00:00Z",
+        })).collect::<Vec<_>>(),
+        "has_more": false,
+        "first_id": models.first(),
+        "last_id": models.last(),
+    }))
+}
+
+async fn openai_models(State(state): State<Arc<MockState>>) -> Json<Value> {
+    state.record(MockFormat::OpenAI, "/models", Value::Null);
+    let models = state.models(MockFormat::OpenAI);
+    Json(json!({
+        "object": "list",
+        "data": models.iter().map(|id| json!({
+            "id": id,
+            "object": "model",
+            "created": 1735689600,
+            "owned_by": "kubellm",
+        })).collect::<Vec<_>>(),
+    }))
+}
+
+async fn respond(format: MockFormat, reply: MockReply, body: &Value) -> Response {
+    tokio::time::sleep(reply.delay).await;
+
+    let text = match reply.outcome {
+        MockOutcome::Text(ref text) => text.clone(),
+        MockOutcome::Error { status, message } => {
+            return error_response(format, status, &message, reply.retry_after)
+        }
+    };
+    let model = body["model"].as_str().unwrap_or_default();
+
+    if body["stream"].as_bool() == Some(true) {
+        let events = match format {
+            MockFormat::Anthropic => anthropic_events(&reply, model, &text),
+            MockFormat::OpenAI => openai_events(&reply, model, &text),
+        };
+        return (
+            [(header::CONTENT_TYPE, "text/event-stream")],
+            Body::from(events),
+        )
+            .into_response();
+    }
+
+    let response = match format {
+        MockFormat::Anthropic => json!({
+            "id": "msg_mock",
+            "type": "message",
+            "role": "assistant",
+            "model": model,
+            "content": [{"type": "text", "text": text}],
+            "stop_reason": "end_turn",
+            "usage": {"input_tokens": reply.input_tokens, "output_tokens": reply.output_tokens},
+        }),
+        MockFormat::OpenAI => json!({
+            "id": "chatcmpl-mock",
+            "object": "chat.completion",
+            "created": 1735689600,
+            "model": model,
+            "choices": [{
+                "index": 0,
+                "message": {"role": "assistant", "content": text},
+                "finish_reason": "stop",
+            }],
+            "usage": openai_usage(&reply),
+        }),
+    };
+    Json(response).into_response()
+}
+
+// the error body each provider sends with a failed request
+fn error_response(
+    format: MockFormat,
+    status: u16,
+    message: &str,
+    retry_after: Option<u64>,
+) -> Response {
+    let error_type = match status {
+        400 => "invalid_request_error",
+        401 => "authentication_error",
+        429 => "rate_limit_error",
+        529 => "overloaded_error",
+        _ => "api_error",
+    };
+    let body = match format {
+        MockFormat::Anthropic => {
+            json!({"type": "error", "error": {"type": error_type, "message": message}})
+        }
+        MockFormat::OpenAI => {
+            json!({"error": {"type": error_type, "message": message, "code": null}})
+        }
+    };
+
+    let status = StatusCode::from_u16(status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+    let mut response = (status, Json(body)).into_response();
+    if let Some(seconds) = retry_after {
+        response
+            .headers_mut()
+            .insert(header::RETRY_AFTER, seconds.into());
+    }
+    response
+}
+
+fn anthropic_events(reply: &MockReply, model: &str, text: &str) -> String {
+    let mut events = vec![sse_event(
+        Some("message_start"),
+        json!({
+            "type": "message_start",
+            "message": {
+                "id": "msg_mock",
+                "type": "message",
+                "role": "assistant",
+                "content": [],
+                "model": model,
+                "usage": {"input_tokens": reply.input_tokens, "output_tokens": 1},
+            },
+        }),
+    )];
+    for word in text.split_inclusive(' ') {
+        events.push(sse_event(
+            Some("content_block_delta"),
+            json!({
+                "type": "content_block_delta",
+                "index": 0,
+                "delta": {"type": "text_delta", "text": word},
+            }),
+        ));
+    }
+    events.push(sse_event(
+        Some("message_delta"),
+        json!({
+            "type": "message_delta",
+            "delta": {"stop_reason": "end_turn", "stop_sequence": null},
+            "usage": {"output_tokens": reply.output_tokens},
+        }),
+    ));
+    events.push(sse_event(
+        Some("message_stop"),
+        json!({"type": "message_stop"}),
+    ));
+    events.concat()
+}
+
+fn openai_events(reply: &MockReply, model: &str, text: &str) -> String {
+    let chunk = |choices: Value, usage: Value| {
+        sse_event(
+            None,
+            json!({
+                "id": "chatcmpl-mock",
+                "object": "chat.completion.chunk",
+                "created": 1735689600,
+                "model": model,
+                "choices": choices,
+                "usage": usage,
+            }),
+        )
+    };
+
+    let mut events: Vec<String> = text
+        .split_inclusive(' ')
+        .map(|word| {
+            chunk(
+                json!([{"index": 0, "delta": {"content": word}, "finish_reason": null}]),
+                Value::Null,
+            )
+        })
+        .collect();
+    events.push(chunk(
+        json!([{"index": 0, "delta": {}, "finish_reason": "stop"}]),
+        Value::Null,
+    ));
+    // asked for with stream_options.include_usage, sent last without any choices
+    events.push(chunk(json!([]), openai_usage(reply)));
+    events.push("data: [DONE]\n\n".to_string());
+    events.concat()
+}
+
+fn openai_usage(reply: &MockReply) -> Value {
+    json!({
+        "prompt_tokens": reply.input_tokens,
+        "completion_tokens": reply.output_tokens,
+        "total_tokens": reply.input_tokens + reply.output_tokens,
+    })
+}
+
+fn sse_event(event: Option<&str>, data: Value) -> String {
+    match event {
+        Some(event) => format!("event: {}\ndata: {}\n\n", event, data),
+        None => format!("data: {}\n\n", data),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider::{CompletionRequest, LlmProvider, StreamChunk};
+    use crate::CoreError;
+    use futures::StreamExt;
+
+    #[tokio::test]
+    async fn test_scripted_replies_in_both_formats() {
+        let server = MockLlmServer::start().await;
+        server.push(
+            MockFormat::Anthropic,
+            MockReply::text("Kafka is a log").with_usage(12, 4),
+        );
+
+        let anthropic = server.anthropic_provider();
+        assert_eq!(anthropic.default_model(), "claude-mock-1");
+        assert_eq!(
+            anthropic.list_models().await.unwrap(),
+            vec!["claude-mock-1", "claude-mock-2"]
+        );
+        let completion = anthropic
+            .complete(&CompletionRequest::new("claude-mock-1", "What is Kafka?"))
+            .await
+            .unwrap();
+        assert_eq!(completion.text, "Kafka is a log");
+        assert_eq!(completion.usage.input_tokens, Some(12));
+        assert_eq!(completion.finish_reason, Some("end_turn".to_string()));
+
+        // once the script runs out the prompt is echoed
+        let openai = server.openai_provider();
+        let completion = openai
+            .complete(&CompletionRequest::new("gpt-4o-mock", "hello"))
+            .await
+            .unwrap();
+        assert_eq!(completion.text, "Mock response to: hello");
+        assert_eq!(completion.model, "gpt-4o-mock");
+
+        let bodies = server.completion_requests(MockFormat::Anthropic);
+        assert_eq!(bodies.len(), 1);
+        assert_eq!(bodies[0]["messages"][0]["content"], "What is Kafka?");
+    }
+
+    #[tokio::test]
+    async fn test_streamed_replies() {
+        let server = MockLlmServer::start().await;
+        for format in [MockFormat::Anthropic, MockFormat::OpenAI] {
+            server.push(format, MockReply::text("one two three").with_usage(5, 3));
+        }
+
+        let providers: Vec<Box<dyn LlmProvider>> = vec![
+            Box::new(server.anthropic_provider()),
+            Box::new(server.openai_provider()),
+        ];
+        for provider in providers {
+            let request = CompletionRequest::new(&provider.default_model(), "count");
+            let chunks: Vec<StreamChunk> = provider
+                .stream(&request)
+                .await
+                .unwrap()
+                .map(|chunk| chunk.unwrap())
+                .collect()
+                .await;
+
+            let text: String = chunks
+                .iter()
+                .cloned()
+                .filter_map(StreamChunk::into_text)
+                .collect();
+            assert_eq!(text, "one two three");
+            assert!(chunks.iter().any(|chunk| matches!(
+                chunk,
+                StreamChunk::Usage(usage) if usage.output_tokens == Some(3)
+            )));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_injected_errors_and_latency() {
+        let server = MockLlmServer::start().await;
+        server.push(
+            MockFormat::Anthropic,
+            MockReply::error(429, "slow down").with_retry_after(7),
+        );
+        server.push(
+            MockFormat::OpenAI,
+            MockReply::text("late").with_delay(Duration::from_millis(50)),
+        );
+
+        let error = server
+            .anthropic_provider()
+            .complete(&CompletionRequest::new("claude-mock-1", "hi"))
+            .await
+            .unwrap_err();
+        match error {
+            CoreError::RateLimited {
+                retry_after, body, ..
+            } => {
+                assert_eq!(retry_after, Some(Duration::from_secs(7)));
+                assert!(body.contains("rate_limit_error"));
+            }
+            other => panic!("expected a rate limit, got {:?}", other),
+        }
+
+        let started = std::time::Instant::now();
+        let completion = server
+            .openai_provider()
+            .complete(&CompletionRequest::new("gpt-4o-mock", "hi"))
+            .await
+            .unwrap();
+        assert_eq!(completion.text, "late");
+        assert!(started.elapsed() >= Duration::from_millis(50));
+    }
+}
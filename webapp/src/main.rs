@@ -3,8 +3,7 @@ mod config;
 use crate::config::WebConfig;
 use anyhow::{Context, Result};
 use axum::{response::Html, routing::get, Router};
-use kubellm_core::{create_database_pool, init_database, CoreConfig};
-use std::sync::Arc;
+use kubellm_core::{connect_store, CoreConfig};
 use tower_http::{cors::CorsLayer, services::ServeDir};
 
 // serve the contents of the html file
@@ -23,6 +22,10 @@ async fn serve_response() -> Html<&'static str> {
     Html(include_str!("../static/response.html"))
 }
 
+async fn serve_compare() -> Html<&'static str> {
+    Html(include_str!("../static/compare.html"))
+}
+
 async fn health_check() -> &'static str {
     "Web app is running!"
 }
@@ -37,24 +40,27 @@ async fn main() -> Result<()> {
         "   Server: {}:{}",
         &web_config.app_server_host, &web_config.app_server_port
     );
+    println!("   Database: {}", core_config.database_backend);
     println!("   Max DB connections: {}", core_config.max_connections);
 
-    let pool = create_database_pool(core_config).await?;
+    let store = connect_store(core_config).await?;
 
-    init_database(&pool)
+    // bring the schema up to date, refuses to start against a schema newer than this build
+    let migrations = store
+        .migrate()
         .await
         .context("Failed to initialize database")?;
-
-    let db_connection = Arc::new(pool);
+    println!("   Schema version: {}", migrations.version);
 
     let app = Router::new()
         .route("/", get(serve_index)) // serve html content
         .route("/prompts", get(serve_prompts)) // serve html content
         .route("/response", get(serve_response)) // serve html content
+        .route("/compare", get(serve_compare)) // serve html content
         .route("/health", get(health_check)) // rest endpoint
         .nest_service("/static", ServeDir::new("static"))
         .layer(CorsLayer::permissive()) // this is a bad idea for prod
-        .with_state(db_connection); // store the Arc<MySqlPool> in the state (DatabaseConnection)
+        .with_state(store); // store the Arc<dyn PromptStore> in the state (DatabaseConnection)
 
     let bind_address = format!(
         "{}:{}",
@@ -67,6 +73,7 @@ async fn main() -> Result<()> {
     println!("🚀 Web app running on http://{}", bind_address);
     println!("🌐 Open your browser to view the interface");
     println!("📂 View all prompts at /prompts");
+    println!("⚖️  Compare models at /compare");
     println!("❤️  GET /health for health check");
 
     axum::serve(listener, app).await.context("Server error")?;
@@ -101,6 +108,14 @@ mod tests {
         assert!(html_content.contains("html"));
     }
 
+    #[tokio::test]
+    async fn test_serve_compare() {
+        let response = serve_compare().await;
+        let html_content = response.0;
+        assert!(html_content.contains("html"));
+        assert!(html_content.contains("/compare"));
+    }
+
     #[tokio::test]
     async fn test_health_check() {
         let response = health_check().await;
@@ -113,6 +128,7 @@ mod tests {
             .route("/", get(serve_index))
             .route("/prompts", get(serve_prompts))
             .route("/response", get(serve_response))
+            .route("/compare", get(serve_compare))
             .route("/health", get(health_check))
             .layer(CorsLayer::permissive());
 
@@ -133,6 +149,10 @@ mod tests {
         let response = server.get("/response").await;
         response.assert_status(StatusCode::OK);
         response.assert_header("content-type", "text/html; charset=utf-8");
+
+        let response = server.get("/compare").await;
+        response.assert_status(StatusCode::OK);
+        response.assert_header("content-type", "text/html; charset=utf-8");
     }
 
     #[tokio::test]
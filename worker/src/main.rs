@@ -1,92 +1,110 @@
-use anyhow::Result;
-use kubellm_core::{CoreConfig, create_database_pool};
-use serde::{Deserialize, Serialize};
+use anyhow::{Context, Result};
+use kubellm_core::{
+    cassette, connect_store, load_budgets, load_price_table, load_routes, retry_policy, run_task,
+    task_policy, CoreConfig, CoreError, PromptStore, Task,
+};
+use serde_json::Value;
+use std::sync::Arc;
 use std::time::Duration;
 use tokio::time;
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct Task {
-    pub id: String,
-    pub task_type: String,
-    pub payload: serde_json::Value,
-    pub status: TaskStatus,
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-pub enum TaskStatus {
-    Pending,
-    Processing,
-    Completed,
-    Failed,
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-pub struct ProcessingResult {
-    pub task_id: String,
-    pub success: bool,
-    pub result: Option<serde_json::Value>,
-    pub error: Option<String>,
-}
-
+/// Runs the tasks queued in the database, e.g. prompts sent to POST /prompt/async.
+/// Any number of workers can share a database, a task is only held by one worker at a time
+/// and a worker that dies has its task picked up by another once the lease runs out.
 pub struct Worker {
     pub id: String,
+    store: Arc<dyn PromptStore>,
+    poll_interval: Duration,
 }
 
 impl Worker {
-    pub fn new(id: String) -> Self {
-        Self { id }
+    pub fn new(id: String, store: Arc<dyn PromptStore>) -> Self {
+        Self {
+            id,
+            store,
+            poll_interval: Duration::from_secs(1),
+        }
+    }
+
+    pub fn with_poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
     }
 
     pub async fn start(&self) -> Result<()> {
         println!("🚀 Worker {} starting...", self.id);
-        
+
         loop {
-            println!("⏳ Worker {} polling for tasks...", self.id);
-            
-            // Simulate task processing
-            if let Some(task) = self.poll_for_task().await? {
-                println!("📋 Worker {} processing task: {}", self.id, task.id);
-                let result = self.process_task(task).await?;
-                println!("✅ Worker {} completed task: {}", self.id, result.task_id);
+            match self.run_next().await {
+                // keep going without waiting while tasks are queued
+                Ok(true) => continue,
+                Ok(false) => {}
+                Err(e) => eprintln!("❌ Worker {} couldn't reach the task queue: {}", self.id, e),
             }
-            
-            // Wait before next poll
-            time::sleep(Duration::from_secs(5)).await;
+
+            time::sleep(self.poll_interval).await;
         }
     }
 
-    async fn poll_for_task(&self) -> Result<Option<Task>> {
-        // TODO: Implement actual task queue polling
-        // For now, return None to simulate no tasks
-        Ok(None)
+    // claim a task that is due and run it, false when there was none
+    async fn run_next(&self) -> Result<bool, CoreError> {
+        let policy = task_policy();
+        let Some(task) = policy.claim(&self.id, self.store.as_ref()).await? else {
+            return Ok(false);
+        };
+        println!(
+            "📋 Worker {} running {} task {} (attempt {} of {})",
+            self.id, task.task_type, task.id, task.attempts, task.max_attempts
+        );
+
+        let Some(outcome) = self.run_with_lease(&task).await else {
+            eprintln!(
+                "⚠️ Worker {} lost the lease on task {}, it was left to another worker",
+                self.id, task.id
+            );
+            return Ok(true);
+        };
+        if let Err(e) = &outcome {
+            eprintln!("❌ Worker {} failed task {}: {}", self.id, task.id, e);
+        }
+
+        let succeeded = outcome.is_ok();
+        if !policy
+            .finish(&task, &self.id, outcome, self.store.as_ref())
+            .await?
+        {
+            eprintln!(
+                "⚠️ Worker {} lost the lease on task {} before it finished",
+                self.id, task.id
+            );
+        } else if succeeded {
+            println!("✅ Worker {} completed task {}", self.id, task.id);
+        }
+        Ok(true)
     }
 
-    async fn process_task(&self, task: Task) -> Result<ProcessingResult> {
-        // Simulate processing time
-        time::sleep(Duration::from_secs(2)).await;
-        
-        match task.task_type.as_str() {
-            "prompt_analysis" => {
-                // Simulate prompt analysis
-                let result = serde_json::json!({
-                    "analysis": "Task completed successfully",
-                    "processed_at": chrono::Utc::now()
-                });
-                
-                Ok(ProcessingResult {
-                    task_id: task.id,
-                    success: true,
-                    result: Some(result),
-                    error: None,
-                })
-            }
-            _ => {
-                Ok(ProcessingResult {
-                    task_id: task.id,
-                    success: false,
-                    result: None,
-                    error: Some(format!("Unknown task type: {}", task.task_type)),
-                })
+    // run the task while renewing its lease, None when the lease was lost and the run dropped
+    async fn run_with_lease(&self, task: &Task) -> Option<Result<Value, CoreError>> {
+        let lease = task_policy().lease;
+        let run = run_task(task, &self.store);
+        tokio::pin!(run);
+
+        // renewed well before it runs out so a slow database doesn't cost the task
+        let mut renewal = time::interval(lease / 3);
+        renewal.tick().await;
+        loop {
+            tokio::select! {
+                outcome = &mut run => return Some(outcome),
+                _ = renewal.tick() => {
+                    match self.store.renew_task_lease(task.id, &self.id, lease).await {
+                        Ok(true) => {}
+                        Ok(false) => return None,
+                        // the lease is held until it runs out, the next renewal tries again
+                        Err(e) => {
+                            eprintln!("❌ Failed to renew the lease on task {}: {}", task.id, e)
+                        }
+                    }
+                }
             }
         }
     }
@@ -95,16 +113,110 @@ impl Worker {
 #[tokio::main]
 async fn main() -> Result<()> {
     let config = CoreConfig::get();
-    
-    // Verify database connection
-    let _pool = create_database_pool(&config).await?;
+
+    println!("🔧 Configuration loaded");
+    println!("   Database: {}", config.database_backend);
+    // load the price table and budgets now so a broken file stops the worker from starting
+    println!("   Priced models: {}", load_price_table()?.models.len());
+    println!("   Budgets: {}", load_budgets()?.budgets.len());
+    println!("   Provider attempts: {}", retry_policy().max_attempts);
+    println!("   Routes: {}", load_routes()?);
+    println!("   Provider cassette: {}", cassette().mode());
+    println!("   Task lease: {}s", task_policy().lease.as_secs());
+    println!("   Task attempts: {}", task_policy().max_attempts);
+
+    let store = connect_store(config).await?;
     println!("✅ Connected to database");
-    
-    let worker_id = std::env::var("WORKER_ID")
-        .unwrap_or_else(|_| format!("worker-{}", uuid::Uuid::new_v4()));
-    
-    let worker = Worker::new(worker_id);
+
+    // bring the schema up to date, refuses to start against a schema newer than this build
+    let migrations = store
+        .migrate()
+        .await
+        .context("Failed to initialize database")?;
+    println!("   Schema version: {}", migrations.version);
+
+    let worker_id =
+        std::env::var("WORKER_ID").unwrap_or_else(|_| format!("worker-{}", uuid::Uuid::new_v4()));
+
+    let worker = Worker::new(worker_id, store)
+        .with_poll_interval(Duration::from_millis(config.worker_poll_interval_ms));
     worker.start().await?;
-    
+
     Ok(())
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use kubellm_core::test_support::{
+        use_test_config, MemoryStore, MockFormat, MockLlmServer, MockReply,
+    };
+    use kubellm_core::{enqueue_prompt, register_provider, CreatePromptRequest, TaskStatus};
+    use serial_test::serial;
+
+    // the providers are registered process wide, so tests using them run one at a time
+    async fn test_worker() -> (Worker, MockLlmServer, Arc<dyn PromptStore>) {
+        use_test_config();
+        let mock = MockLlmServer::start().await;
+        register_provider(Arc::new(mock.anthropic_provider()));
+
+        let store: Arc<dyn PromptStore> = Arc::new(MemoryStore::new());
+        let worker = Worker::new("test-worker".to_string(), store.clone());
+        (worker, mock, store)
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn test_worker_runs_queued_prompt() {
+        let (worker, mock, store) = test_worker().await;
+        mock.push(
+            MockFormat::Anthropic,
+            MockReply::text("Partitions and offsets"),
+        );
+        let request = CreatePromptRequest::new("Explain Kafka consumer offsets", "Anthropic");
+        let task = enqueue_prompt(&request, Some("alice"), &store)
+            .await
+            .unwrap();
+
+        assert!(worker.run_next().await.unwrap());
+        assert!(!worker.run_next().await.unwrap());
+
+        let done = store.get_task(task.id).await.unwrap().unwrap();
+        assert_eq!(done.status, TaskStatus::Completed);
+        assert_eq!(done.worker_id.as_deref(), Some("test-worker"));
+        let prompt_id = done.result.unwrap()["prompt_id"].as_i64().unwrap();
+        let prompt = store.get_prompt_by_id(prompt_id).await.unwrap().unwrap();
+        assert_eq!(prompt.response, "Partitions and offsets");
+        assert_eq!(prompt.user_id.as_deref(), Some("alice"));
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn test_worker_retries_and_dead_letters() {
+        let (worker, mock, store) = test_worker().await;
+
+        // a provider that keeps failing after its own retries leaves the task to retry later
+        for _ in 0..retry_policy().max_attempts {
+            mock.push(MockFormat::Anthropic, MockReply::error(503, "unavailable"));
+        }
+        let request = CreatePromptRequest::new("Explain Kafka rebalancing", "Anthropic");
+        let task = enqueue_prompt(&request, None, &store).await.unwrap();
+        assert!(worker.run_next().await.unwrap());
+
+        let retried = store.get_task(task.id).await.unwrap().unwrap();
+        assert_eq!((retried.status, retried.attempts), (TaskStatus::Pending, 1));
+        assert!(retried.run_at > retried.updated_at);
+        assert!(retried.error.unwrap().contains("unavailable"));
+        // its backoff hasn't passed yet
+        assert!(!worker.run_next().await.unwrap());
+
+        // a request that can never succeed is dead-lettered on its first attempt
+        let request = CreatePromptRequest::new("hello", "NoSuchProvider");
+        let task = enqueue_prompt(&request, None, &store).await.unwrap();
+        assert!(worker.run_next().await.unwrap());
+
+        let dead = store.get_task(task.id).await.unwrap().unwrap();
+        assert_eq!((dead.status, dead.attempts), (TaskStatus::Dead, 1));
+        assert!(dead.error.unwrap().contains("NoSuchProvider"));
+    }
+}